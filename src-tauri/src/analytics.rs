@@ -0,0 +1,237 @@
+// Opt-in record of how searches and streams go on this install: which providers find
+// things, how long streams take to start, how often ffmpeg has to step in and which
+// errors come up. It stays in the app data folder; nothing is sent anywhere.
+
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+const ANALYTICS_FILE: &str = "analytics.json";
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct ProviderStats {
+    searches: u64,
+    // Searches that found at least one result
+    found: u64,
+    errors: u64,
+    results: u64,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct AnalyticsData {
+    // When recording started, or was last cleared
+    since: Option<String>,
+    #[serde(default)]
+    providers: BTreeMap<String, ProviderStats>,
+    #[serde(default)]
+    streams_started: u64,
+    // Time from a stream's first request to the player reporting its position
+    #[serde(default)]
+    buffering_samples: u64,
+    #[serde(default)]
+    buffering_total_ms: u64,
+    #[serde(default)]
+    buffering_max_ms: u64,
+    // ffmpeg transcodes started, by kind ("audio", "remux", "tonemap")
+    #[serde(default)]
+    transcodes: BTreeMap<String, u64>,
+    // Stream server error codes, as sent in X-Magnolia-Error
+    #[serde(default)]
+    failures: BTreeMap<String, u64>,
+}
+
+struct Store {
+    path: PathBuf,
+    data: AnalyticsData,
+    // Changed since the last write
+    dirty: bool,
+}
+
+// Set from settings; off until the user opts in
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+static STORE: Mutex<Option<Store>> = Mutex::new(None);
+
+/// Loads what was recorded in earlier sessions
+pub fn init(app_data_dir: PathBuf) {
+    let path = app_data_dir.join(ANALYTICS_FILE);
+    let data = fs::read_to_string(&path)
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default();
+    if let Ok(mut store) = STORE.lock() {
+        *store = Some(Store { path, data, dirty: false });
+    }
+}
+
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+// Applies `f` when recording is on. Writing to disk is left to `flush`, as some of these
+// run on every request.
+fn record(f: impl FnOnce(&mut AnalyticsData)) {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return;
+    }
+    if let Ok(mut store) = STORE.lock() {
+        if let Some(store) = store.as_mut() {
+            store.data.since.get_or_insert_with(|| Local::now().to_rfc3339());
+            f(&mut store.data);
+            store.dirty = true;
+        }
+    }
+}
+
+/// One provider's part of a search: how many results it returned, or None if it failed
+pub fn record_search(provider: &str, results: Option<usize>) {
+    record(|data| {
+        let stats = data.providers.entry(provider.to_string()).or_default();
+        stats.searches += 1;
+        match results {
+            Some(count) => {
+                stats.results += count as u64;
+                if count > 0 {
+                    stats.found += 1;
+                }
+            }
+            None => stats.errors += 1,
+        }
+    });
+}
+
+pub fn record_stream_started() {
+    record(|data| data.streams_started += 1);
+}
+
+pub fn record_buffering(duration: Duration) {
+    let ms = duration.as_millis() as u64;
+    record(|data| {
+        data.buffering_samples += 1;
+        data.buffering_total_ms += ms;
+        data.buffering_max_ms = data.buffering_max_ms.max(ms);
+    });
+}
+
+pub fn record_transcode(kind: &str) {
+    record(|data| *data.transcodes.entry(kind.to_string()).or_default() += 1);
+}
+
+pub fn record_failure(code: &str) {
+    record(|data| *data.failures.entry(code.to_string()).or_default() += 1);
+}
+
+/// Writes out anything recorded since the last call. Run from the watchdog and on exit.
+pub fn flush() {
+    let Ok(mut store) = STORE.lock() else { return };
+    let Some(store) = store.as_mut().filter(|s| s.dirty) else { return };
+    match serde_json::to_string_pretty(&store.data) {
+        Ok(content) => match fs::write(&store.path, content) {
+            Ok(()) => store.dirty = false,
+            Err(e) => tracing::error!("failed to write {:?}: {}", store.path, e),
+        },
+        Err(e) => tracing::error!("failed to serialize analytics: {}", e),
+    }
+}
+
+/// Forgets everything recorded so far
+pub fn clear() -> Result<(), String> {
+    let mut store = STORE.lock().map_err(|e| e.to_string())?;
+    if let Some(store) = store.as_mut() {
+        store.data = AnalyticsData::default();
+        store.dirty = false;
+        if store.path.exists() {
+            fs::remove_file(&store.path).map_err(|e| format!("Failed to remove {:?}: {}", store.path, e))?;
+        }
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ProviderReport {
+    pub provider: String,
+    pub searches: u64,
+    // Share of searches that found anything, 0-1
+    pub success_rate: f64,
+    pub errors: u64,
+    pub average_results: f64,
+}
+
+/// What `get_local_analytics` returns
+#[derive(Debug, Clone, Serialize)]
+pub struct LocalAnalytics {
+    pub enabled: bool,
+    pub since: Option<String>,
+    // Most searched first
+    pub providers: Vec<ProviderReport>,
+    pub streams_started: u64,
+    pub average_buffering_ms: Option<u64>,
+    pub max_buffering_ms: Option<u64>,
+    pub transcodes: BTreeMap<String, u64>,
+    // Most frequent first
+    pub failures: Vec<(String, u64)>,
+}
+
+pub fn report() -> LocalAnalytics {
+    let data = STORE.lock().ok()
+        .and_then(|store| store.as_ref().map(|s| s.data.clone()))
+        .unwrap_or_default();
+    build_report(data)
+}
+
+/// The report as JSON for crash reports, when recording is on. Doesn't wait for the
+/// store, since the panic may have happened while it was held.
+pub fn report_for_crash() -> Option<String> {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return None;
+    }
+    let data = STORE.try_lock().ok()?.as_ref()?.data.clone();
+    serde_json::to_string_pretty(&build_report(data)).ok()
+}
+
+fn build_report(data: AnalyticsData) -> LocalAnalytics {
+    let mut providers: Vec<ProviderReport> = data.providers
+        .into_iter()
+        .map(|(provider, stats)| {
+            let searches = stats.searches.max(1) as f64;
+            ProviderReport {
+                provider,
+                searches: stats.searches,
+                success_rate: stats.found as f64 / searches,
+                errors: stats.errors,
+                average_results: stats.results as f64 / searches,
+            }
+        })
+        .collect();
+    providers.sort_by(|a, b| b.searches.cmp(&a.searches));
+
+    let mut failures: Vec<(String, u64)> = data.failures.into_iter().collect();
+    failures.sort_by(|a, b| b.1.cmp(&a.1));
+
+    let buffered = data.buffering_samples > 0;
+    LocalAnalytics {
+        enabled: ENABLED.load(Ordering::Relaxed),
+        since: data.since,
+        providers,
+        streams_started: data.streams_started,
+        average_buffering_ms: buffered.then(|| data.buffering_total_ms / data.buffering_samples),
+        max_buffering_ms: buffered.then_some(data.buffering_max_ms),
+        transcodes: data.transcodes,
+        failures,
+    }
+}
+
+#[tauri::command]
+pub fn get_local_analytics() -> LocalAnalytics {
+    report()
+}
+
+#[tauri::command]
+pub fn clear_local_analytics() -> Result<(), String> {
+    clear()
+}
@@ -0,0 +1,208 @@
+use crate::imdb_resolver::ImdbIdData;
+use crate::library::LibraryData;
+use crate::settings::Settings;
+use crate::track_preferences::PreferencesData;
+use crate::tracking::HistoryData;
+use crate::watch_history::WatchHistoryData;
+use chrono::{Local, NaiveDateTime};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// Bump when the backup layout changes and add a step to `migrate`
+const BACKUP_VERSION: u32 = 1;
+const BACKUP_PREFIX: &str = "magnolia-backup-";
+const TIMESTAMP_FORMAT: &str = "%Y%m%d-%H%M%S";
+
+// Login tokens (scrobble.json) are left out on purpose since backups usually end up in
+// a cloud folder
+const BACKED_UP_FILES: &[&str] = &[
+    "settings.json",
+    "history.json",
+    "watch_history.json",
+    "track_preferences.json",
+    "cache_metadata.json",
+    "library.json",
+    "imdb_ids.json",
+];
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Backup {
+    version: u32,
+    app_version: String,
+    created_at: String,
+    files: HashMap<String, Value>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BackupInfo {
+    pub path: String,
+    pub created_at: String,
+    pub size: u64,
+}
+
+/// Exports the persisted JSON state to a single timestamped file, and restores it
+pub struct BackupManager {
+    app_data_dir: PathBuf,
+}
+
+// Round-trips a file through its current type so fields added since the backup was
+// made get their defaults and unknown ones are dropped
+fn normalize<T: Serialize + DeserializeOwned>(value: Value) -> Result<Value, String> {
+    let data: T = serde_json::from_value(value).map_err(|e| e.to_string())?;
+    serde_json::to_value(data).map_err(|e| e.to_string())
+}
+
+/// Brings an older backup up to the current layout
+fn migrate(mut backup: Value) -> Result<Backup, String> {
+    // Backups from before versioning are treated as version 1
+    let version = backup.get("version").and_then(|v| v.as_u64()).unwrap_or(1) as u32;
+    if version > BACKUP_VERSION {
+        return Err(format!("Backup version {} is newer than this app supports", version));
+    }
+    if let Some(obj) = backup.as_object_mut() {
+        obj.insert("version".to_string(), Value::from(BACKUP_VERSION));
+    }
+    serde_json::from_value(backup).map_err(|e| format!("Invalid backup file: {}", e))
+}
+
+fn backup_created_at(path: &Path) -> Option<NaiveDateTime> {
+    let stem = path.file_stem()?.to_str()?;
+    let timestamp = stem.strip_prefix(BACKUP_PREFIX)?;
+    NaiveDateTime::parse_from_str(timestamp, TIMESTAMP_FORMAT).ok()
+}
+
+impl BackupManager {
+    pub fn new(app_data_dir: PathBuf) -> Self {
+        Self { app_data_dir }
+    }
+
+    // Restoring an old backup shouldn't be a way around the parental controls PIN
+    fn keep_parental_controls(&self, mut settings: Value) -> Value {
+        let current = fs::read_to_string(self.app_data_dir.join("settings.json"))
+            .ok()
+            .and_then(|content| serde_json::from_str::<Settings>(&content).ok());
+        if let (Some(current), Some(obj)) = (current, settings.as_object_mut()) {
+            if let Ok(parental) = serde_json::to_value(current.parental) {
+                obj.insert("parental".to_string(), parental);
+            }
+        }
+        settings
+    }
+
+    /// Backups in `dir`, newest first
+    pub fn list_backups(&self, dir: &Path) -> Vec<BackupInfo> {
+        let mut backups: Vec<(NaiveDateTime, BackupInfo)> = fs::read_dir(dir)
+            .map(|entries| {
+                entries
+                    .flatten()
+                    .filter_map(|entry| {
+                        let path = entry.path();
+                        let created_at = backup_created_at(&path)?;
+                        Some((created_at, BackupInfo {
+                            path: path.to_string_lossy().to_string(),
+                            created_at: created_at.format("%Y-%m-%d %H:%M:%S").to_string(),
+                            size: entry.metadata().map(|m| m.len()).unwrap_or(0),
+                        }))
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        backups.sort_by(|a, b| b.0.cmp(&a.0));
+        backups.into_iter().map(|(_, info)| info).collect()
+    }
+
+    /// Whether the newest backup in `dir` is older than `interval_hours`
+    pub fn is_backup_due(&self, dir: &Path, interval_hours: u32) -> bool {
+        let newest = fs::read_dir(dir)
+            .map(|entries| entries.flatten().filter_map(|e| backup_created_at(&e.path())).max())
+            .ok()
+            .flatten();
+
+        match newest {
+            Some(created_at) => {
+                let age = Local::now().naive_local() - created_at;
+                age.num_hours() >= interval_hours as i64
+            }
+            None => true,
+        }
+    }
+
+    /// Writes a new backup to `dir` and removes the oldest ones beyond `keep`
+    pub fn create_backup(&self, dir: &Path, keep: usize) -> Result<PathBuf, String> {
+        fs::create_dir_all(dir).map_err(|e| format!("Failed to create backup folder: {}", e))?;
+
+        let mut files = HashMap::new();
+        for name in BACKED_UP_FILES {
+            let path = self.app_data_dir.join(name);
+            let Ok(content) = fs::read_to_string(&path) else { continue };
+            match serde_json::from_str::<Value>(&content) {
+                Ok(value) => {
+                    files.insert(name.to_string(), value);
+                }
+                Err(e) => tracing::warn!("skipping {} in backup: {}", name, e),
+            }
+        }
+
+        let now = Local::now();
+        let backup = Backup {
+            version: BACKUP_VERSION,
+            app_version: env!("CARGO_PKG_VERSION").to_string(),
+            created_at: now.to_rfc3339(),
+            files,
+        };
+
+        let path = dir.join(format!("{}{}.json", BACKUP_PREFIX, now.format(TIMESTAMP_FORMAT)));
+        let content = serde_json::to_string_pretty(&backup).map_err(|e| e.to_string())?;
+        fs::write(&path, content).map_err(|e| format!("Failed to write backup: {}", e))?;
+        tracing::info!("wrote backup to {:?}", path);
+
+        if keep > 0 {
+            for old in self.list_backups(dir).into_iter().skip(keep) {
+                let _ = fs::remove_file(&old.path);
+            }
+        }
+
+        Ok(path)
+    }
+
+    /// Replaces the app data files with the ones in the backup. The managers keep their
+    /// data in memory, so the app has to restart afterwards.
+    pub fn restore_backup(&self, path: &Path) -> Result<(), String> {
+        let content = fs::read_to_string(path).map_err(|e| format!("Failed to read backup: {}", e))?;
+        let value: Value = serde_json::from_str(&content).map_err(|e| format!("Invalid backup file: {}", e))?;
+        let backup = migrate(value)?;
+
+        // Validate everything before touching any file so a bad backup can't leave a mix
+        let mut restored = Vec::new();
+        for (name, value) in backup.files {
+            if !BACKED_UP_FILES.contains(&name.as_str()) {
+                continue;
+            }
+            let value = match name.as_str() {
+                "settings.json" => normalize::<Settings>(value).map(|v| self.keep_parental_controls(v)),
+                "history.json" => normalize::<HistoryData>(value),
+                "watch_history.json" => normalize::<WatchHistoryData>(value),
+                "track_preferences.json" => normalize::<PreferencesData>(value),
+                "library.json" => normalize::<LibraryData>(value),
+                "imdb_ids.json" => normalize::<ImdbIdData>(value),
+                _ => Ok(value),
+            }
+            .map_err(|e| format!("{} in backup is invalid: {}", name, e))?;
+            restored.push((name, value));
+        }
+
+        for (name, value) in restored {
+            let content = serde_json::to_string_pretty(&value).map_err(|e| e.to_string())?;
+            fs::write(self.app_data_dir.join(&name), content)
+                .map_err(|e| format!("Failed to restore {}: {}", name, e))?;
+        }
+
+        tracing::info!("restored backup from {:?}", path);
+        Ok(())
+    }
+}
@@ -8,6 +8,11 @@ use tauri::{AppHandle, Manager};
 pub struct CacheMetadata {
     pub tmdb_id: u32,
     pub media_type: String,
+    // Set when the torrent was picked for a specific episode
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub season: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub episode: Option<u32>,
 }
 
 pub struct CacheMetadataManager {
@@ -46,10 +51,48 @@ impl CacheMetadataManager {
     }
     
     pub fn set_mapping(&mut self, hash: String, tmdb_id: u32, media_type: String) -> Result<(), String> {
-        self.mappings.insert(hash.to_lowercase(), CacheMetadata {
+        let hash = hash.to_lowercase();
+        // Keep the episode recorded when streaming started if it's the same title
+        let (season, episode) = match self.mappings.get(&hash) {
+            Some(existing) if existing.tmdb_id == tmdb_id => (existing.season, existing.episode),
+            _ => (None, None),
+        };
+        self.mappings.insert(hash, CacheMetadata {
+            tmdb_id,
+            media_type,
+            season,
+            episode,
+        });
+        self.save()
+    }
+
+    /// Records which title (and episode) a torrent was picked for; unchanged mappings
+    /// aren't written again
+    pub fn set_episode_mapping(
+        &mut self,
+        hash: &str,
+        tmdb_id: u32,
+        media_type: String,
+        season: Option<u32>,
+        episode: Option<u32>,
+    ) -> Result<(), String> {
+        let hash = hash.to_lowercase();
+        let mapping = CacheMetadata {
             tmdb_id,
             media_type,
+            season,
+            episode,
+        };
+        let unchanged = self.mappings.get(&hash).map(|m| {
+            m.tmdb_id == mapping.tmdb_id
+                && m.media_type == mapping.media_type
+                && m.season == mapping.season
+                && m.episode == mapping.episode
         });
+        if unchanged == Some(true) {
+            return Ok(());
+        }
+        self.mappings.insert(hash, mapping);
         self.save()
     }
     
@@ -0,0 +1,163 @@
+use serde::Serialize;
+use std::backtrace::Backtrace;
+use std::fs;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use chrono::Local;
+use crate::log_error;
+
+// Holds the file name of a crash report the user hasn't seen yet
+const PENDING_FLAG: &str = "pending_crash";
+// Crash reports kept in the logs folder
+const MAX_CRASH_REPORTS: usize = 5;
+
+// Set once the logs folder is known; panics before that only reach stderr
+static CRASH_DIR: OnceLock<PathBuf> = OnceLock::new();
+
+/// Writes a crash report for every panic, on any thread. Only a panic on the main thread
+/// takes the app down, so only that one is flagged for the user on the next start;
+/// panics in background tasks and worker threads are recovered from. The default hook
+/// still runs, so the panic is printed as before.
+pub fn install_panic_hook() {
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        let message = info
+            .payload()
+            .downcast_ref::<&str>()
+            .map(|s| s.to_string())
+            .or_else(|| info.payload().downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "unknown panic".to_string());
+        let location = info
+            .location()
+            .map(|l| format!("{}:{}:{}", l.file(), l.line(), l.column()))
+            .unwrap_or_else(|| "unknown location".to_string());
+
+        if let Some(dir) = CRASH_DIR.get() {
+            match write_report(dir, &message, &location) {
+                Ok(path) => eprintln!("crash report written to {}", path.display()),
+                Err(e) => eprintln!("failed to write crash report: {}", e),
+            }
+        }
+        default_hook(info);
+    }));
+}
+
+/// Where crash reports go, normally the logs folder
+pub fn set_crash_dir(dir: PathBuf) {
+    let _ = CRASH_DIR.set(dir);
+}
+
+fn write_report(dir: &Path, message: &str, location: &str) -> std::io::Result<PathBuf> {
+    let thread = std::thread::current();
+    let fatal = thread.name() == Some("main");
+    let mut report = String::new();
+    report.push_str("Magnolia crash report\n\n");
+    report.push_str(&format!("Time: {}\n", Local::now().to_rfc3339()));
+    report.push_str(&format!("Version: {}\n", env!("CARGO_PKG_VERSION")));
+    report.push_str(&format!("OS: {} ({})\n", std::env::consts::OS, std::env::consts::ARCH));
+    report.push_str(&format!("Thread: {}\n", thread.name().unwrap_or("unnamed")));
+    report.push_str(&format!("Panic: {}\n", message));
+    report.push_str(&format!("Location: {}\n", location));
+    report.push_str(&format!("\nBacktrace:\n{}\n", Backtrace::force_capture()));
+    report.push_str("\nRecent log:\n");
+    for line in crate::logger::recent_lines() {
+        report.push_str(&line);
+        report.push('\n');
+    }
+    if let Some(analytics) = crate::analytics::report_for_crash() {
+        report.push_str(&format!("\nLocal analytics:\n{}\n", analytics));
+    }
+
+    fs::create_dir_all(dir)?;
+    let file_name = format!("crash_{}.txt", Local::now().format("%Y%m%d_%H%M%S_%3f"));
+    let path = dir.join(&file_name);
+    fs::write(&path, report)?;
+    if fatal {
+        fs::write(dir.join(PENDING_FLAG), &file_name)?;
+    }
+    cleanup_old_reports(dir);
+    Ok(path)
+}
+
+fn cleanup_old_reports(dir: &Path) {
+    let Ok(entries) = fs::read_dir(dir) else { return };
+    let mut reports: Vec<PathBuf> = entries
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| {
+            let name = p.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+            name.starts_with("crash_") && name.ends_with(".txt")
+        })
+        .collect();
+    // Timestamped names sort oldest first
+    reports.sort();
+    while reports.len() > MAX_CRASH_REPORTS {
+        let _ = fs::remove_file(reports.remove(0));
+    }
+}
+
+/// Spawns a background task and logs it if the task panics, which tokio otherwise only
+/// reports to whoever awaits the handle
+pub fn spawn_logged<F>(name: &'static str, future: F)
+where
+    F: Future<Output = ()> + Send + 'static,
+{
+    let handle = tauri::async_runtime::spawn(future);
+    tauri::async_runtime::spawn(async move {
+        if let Err(e) = handle.await {
+            log_error!("Background task '{}' stopped: {}", name, e);
+        }
+    });
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CrashReport {
+    pub file_name: String,
+    pub path: String,
+    pub contents: String,
+}
+
+fn crash_dir() -> Result<&'static PathBuf, String> {
+    CRASH_DIR.get().ok_or_else(|| "crash reports aren't set up yet".to_string())
+}
+
+/// The report of the last crash, if the user hasn't exported or dismissed it yet
+#[tauri::command]
+pub fn get_pending_crash_report() -> Result<Option<CrashReport>, String> {
+    let dir = crash_dir()?;
+    let Ok(file_name) = fs::read_to_string(dir.join(PENDING_FLAG)) else {
+        return Ok(None);
+    };
+    let file_name = file_name.trim().to_string();
+    let path = dir.join(&file_name);
+    match fs::read_to_string(&path) {
+        Ok(contents) => Ok(Some(CrashReport {
+            file_name,
+            path: path.to_string_lossy().to_string(),
+            contents,
+        })),
+        // The report was cleaned up; nothing left to offer
+        Err(_) => {
+            let _ = fs::remove_file(dir.join(PENDING_FLAG));
+            Ok(None)
+        }
+    }
+}
+
+/// Copies the pending report to `destination` and clears the flag
+#[tauri::command]
+pub fn export_crash_report(destination: String) -> Result<(), String> {
+    let report = get_pending_crash_report()?.ok_or("no crash report to export")?;
+    fs::copy(&report.path, &destination).map_err(|e| format!("failed to export crash report: {}", e))?;
+    dismiss_crash_report()
+}
+
+#[tauri::command]
+pub fn dismiss_crash_report() -> Result<(), String> {
+    let flag = crash_dir()?.join(PENDING_FLAG);
+    if flag.exists() {
+        fs::remove_file(&flag).map_err(|e| format!("failed to clear crash report: {}", e))?;
+    }
+    Ok(())
+}
@@ -0,0 +1,117 @@
+use crate::search::{parse_release_title, parse_season_episode};
+use crate::settings::Settings;
+use crate::torrent::FinishedTorrent;
+use serde::Serialize;
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+
+/// How long the download-complete command may run before it's killed
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+
+/// Sent to the webhook as the request body and to the command on stdin
+#[derive(Debug, Clone, Serialize)]
+pub struct DownloadCompletePayload {
+    pub title: String,
+    pub path: String,
+    pub show: Option<String>,
+    pub season: Option<u32>,
+    pub episode: Option<u32>,
+}
+
+impl DownloadCompletePayload {
+    pub fn from_finished(torrent: &FinishedTorrent) -> Self {
+        let file_name = torrent.main_file
+            .file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or(&torrent.name)
+            .to_string();
+        let (season, episode) = parse_season_episode(&file_name);
+        let (show, _) = parse_release_title(&file_name);
+
+        Self {
+            title: torrent.name.clone(),
+            path: torrent.main_file.to_string_lossy().to_string(),
+            show: Some(show).filter(|s| !s.is_empty()),
+            season: if episode.is_some() { season.or(Some(1)) } else { None },
+            episode,
+        }
+    }
+}
+
+/// Runs the user's download-complete command and webhook, if configured. Failures are only
+/// logged since there's nobody to report them to.
+pub async fn run(settings: &Settings, payload: &DownloadCompletePayload) {
+    let Ok(json) = serde_json::to_string(payload) else { return };
+
+    if let Some(url) = settings.download_complete_webhook.as_deref().filter(|u| !u.trim().is_empty()) {
+        let result = reqwest::Client::new()
+            .post(url.trim())
+            .header("Content-Type", "application/json")
+            .body(json.clone())
+            .timeout(Duration::from_secs(15))
+            .send()
+            .await;
+        match result {
+            Ok(res) if !res.status().is_success() => {
+                tracing::warn!("download webhook returned {}", res.status());
+            }
+            Ok(_) => tracing::info!("download webhook sent for {}", payload.title),
+            Err(e) => tracing::warn!("download webhook failed: {}", e),
+        }
+    }
+
+    if let Some(command) = settings.download_complete_command.as_deref().filter(|c| !c.trim().is_empty()) {
+        if let Err(e) = run_command(command, &json).await {
+            tracing::error!("download complete command failed: {}", e);
+        }
+    }
+}
+
+// The command goes through the shell so users can write pipelines and pass arguments.
+// The payload is on stdin and in MAGNOLIA_DOWNLOAD for scripts that don't read stdin.
+async fn run_command(command: &str, json: &str) -> std::io::Result<()> {
+    #[cfg(target_os = "windows")]
+    let mut cmd = {
+        let mut cmd = Command::new("cmd");
+        cmd.args(["/C", command]);
+        cmd.creation_flags(0x08000000);
+        cmd
+    };
+
+    #[cfg(not(target_os = "windows"))]
+    let mut cmd = {
+        let mut cmd = Command::new("sh");
+        cmd.args(["-c", command]);
+        cmd
+    };
+
+    cmd.env("MAGNOLIA_DOWNLOAD", json)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        // Don't leave the command behind if the app exits while it's running
+        .kill_on_drop(true);
+
+    let mut child = cmd.spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        // A script that ignores stdin closes the pipe early, which isn't an error
+        let _ = stdin.write_all(json.as_bytes()).await;
+    }
+
+    let status = match tokio::time::timeout(COMMAND_TIMEOUT, child.wait()).await {
+        Ok(status) => status?,
+        Err(_) => {
+            let _ = child.kill().await;
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::TimedOut,
+                format!("killed after {}s", COMMAND_TIMEOUT.as_secs()),
+            ));
+        }
+    };
+    if !status.success() {
+        tracing::warn!("download complete command exited with {}", status);
+    }
+    Ok(())
+}
@@ -0,0 +1,268 @@
+use serde::Serialize;
+use std::collections::BTreeSet;
+use std::path::PathBuf;
+use std::process::Command;
+use std::sync::Mutex;
+use tauri::{AppHandle, Emitter};
+#[cfg(target_os = "windows")]
+use std::os::windows::process::CommandExt;
+
+// Encoder name suffixes of the hardware encoders ffmpeg can be built with
+const HW_ENCODER_SUFFIXES: &[&str] = &["_nvenc", "_qsv", "_amf", "_vaapi", "_videotoolbox", "_mf", "_v4l2m2m"];
+
+/// ffprobe next to the sidecar ffmpeg if it was installed there, otherwise whatever is
+/// on PATH. Mirrors `ffmpeg_sidecar::paths::ffmpeg_path`.
+pub fn ffprobe_path() -> PathBuf {
+    sidecar_ffprobe().unwrap_or_else(|| PathBuf::from("ffprobe"))
+}
+
+fn sidecar_ffprobe() -> Option<PathBuf> {
+    let path = ffmpeg_sidecar::paths::sidecar_dir()
+        .ok()?
+        .join(format!("ffprobe{}", std::env::consts::EXE_SUFFIX));
+    path.exists().then_some(path)
+}
+
+fn command(program: impl AsRef<std::ffi::OsStr>) -> Command {
+    #[allow(unused_mut)]
+    let mut cmd = Command::new(program);
+    #[cfg(target_os = "windows")]
+    cmd.creation_flags(0x08000000);
+    cmd
+}
+
+// Process ids of ffmpeg/ffprobe children still running, killed when the app exits
+static RUNNING: Mutex<BTreeSet<u32>> = Mutex::new(BTreeSet::new());
+
+/// Async command for ffmpeg or ffprobe with no console window on Windows. The child is
+/// killed when its handle is dropped and, on Unix, gets a process group of its own so
+/// `kill_running` takes anything it started down with it.
+fn tool_command(program: impl AsRef<std::ffi::OsStr>) -> tokio::process::Command {
+    let mut cmd = tokio::process::Command::new(program);
+    #[cfg(target_os = "windows")]
+    cmd.creation_flags(0x08000000);
+    #[cfg(unix)]
+    cmd.process_group(0);
+    cmd.kill_on_drop(true);
+    cmd
+}
+
+pub fn ffmpeg_command() -> tokio::process::Command {
+    tool_command(ffmpeg_sidecar::paths::ffmpeg_path())
+}
+
+pub fn ffprobe_command() -> tokio::process::Command {
+    tool_command(ffprobe_path())
+}
+
+// Keeps a child's id in RUNNING until dropped
+struct Registration(Option<u32>);
+
+impl Registration {
+    fn new(pid: Option<u32>) -> Self {
+        if let (Some(pid), Ok(mut running)) = (pid, RUNNING.lock()) {
+            running.insert(pid);
+        }
+        Self(pid)
+    }
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        if let (Some(pid), Ok(mut running)) = (self.0, RUNNING.lock()) {
+            running.remove(&pid);
+        }
+    }
+}
+
+/// A spawned ffmpeg/ffprobe, registered for `kill_running` for as long as it's held
+pub struct ToolChild {
+    child: tokio::process::Child,
+    _registration: Registration,
+}
+
+impl std::ops::Deref for ToolChild {
+    type Target = tokio::process::Child;
+
+    fn deref(&self) -> &Self::Target {
+        &self.child
+    }
+}
+
+impl std::ops::DerefMut for ToolChild {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.child
+    }
+}
+
+pub fn spawn(cmd: &mut tokio::process::Command) -> std::io::Result<ToolChild> {
+    let child = cmd.spawn()?;
+    let registration = Registration::new(child.id());
+    Ok(ToolChild { child, _registration: registration })
+}
+
+/// Runs the command to completion like `Command::output`, registered while it runs
+pub async fn output(cmd: &mut tokio::process::Command) -> std::io::Result<std::process::Output> {
+    cmd.stdin(std::process::Stdio::null())
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped());
+    let child = cmd.spawn()?;
+    let _registration = Registration::new(child.id());
+    child.wait_with_output().await
+}
+
+/// Kills every ffmpeg/ffprobe still running. Called on exit, where the tasks holding
+/// the children never get to drop them.
+pub fn kill_running() {
+    let pids: Vec<u32> = match RUNNING.lock() {
+        Ok(mut running) => std::mem::take(&mut *running).into_iter().collect(),
+        Err(_) => return,
+    };
+    if pids.is_empty() {
+        return;
+    }
+    tracing::info!("killing {} media tool processes", pids.len());
+    for pid in pids {
+        #[cfg(target_os = "windows")]
+        let _ = command("taskkill").args(["/F", "/T", "/PID", &pid.to_string()]).output();
+        // The negative id signals the whole process group
+        #[cfg(unix)]
+        let _ = command("kill").args(["-KILL", "--", &format!("-{}", pid)]).output();
+    }
+}
+
+fn on_system_path(name: &str) -> bool {
+    let finder = if cfg!(target_os = "windows") { "where" } else { "which" };
+    command(finder)
+        .arg(name)
+        .output()
+        .map(|output| output.status.success())
+        .unwrap_or(false)
+}
+
+pub fn is_ffmpeg_installed() -> bool {
+    if on_system_path("ffmpeg") {
+        tracing::debug!("ffmpeg found in system PATH");
+        return true;
+    }
+
+    let sidecar_exists = ffmpeg_sidecar::paths::ffmpeg_path().exists();
+    if sidecar_exists {
+        tracing::debug!("ffmpeg found in sidecar directory");
+    }
+
+    sidecar_exists
+}
+
+pub fn is_ffprobe_installed() -> bool {
+    if on_system_path("ffprobe") {
+        tracing::debug!("ffprobe found in system PATH");
+        return true;
+    }
+
+    let sidecar_exists = sidecar_ffprobe().is_some();
+    if sidecar_exists {
+        tracing::debug!("ffprobe found in sidecar directory");
+    }
+
+    sidecar_exists
+}
+
+// "ffmpeg version 7.1-full_build-www.gyan.dev Copyright ..." -> "7.1-full_build-www.gyan.dev"
+fn tool_version(program: PathBuf) -> Option<String> {
+    let output = command(program).arg("-version").output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let first_line = stdout.lines().next()?;
+    first_line.split_whitespace().nth(2).map(|v| v.to_string())
+}
+
+/// Hardware video encoders compiled into ffmpeg. Being listed doesn't mean the GPU
+/// driver is there, only that ffmpeg can try.
+fn hw_encoders() -> Vec<String> {
+    let Ok(output) = command(ffmpeg_sidecar::paths::ffmpeg_path()).args(["-hide_banner", "-encoders"]).output() else {
+        return Vec::new();
+    };
+    // Lines look like " V....D h264_nvenc           NVIDIA NVENC H.264 encoder"
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| {
+            let mut parts = line.split_whitespace();
+            let flags = parts.next()?;
+            let name = parts.next()?;
+            (flags.starts_with('V') && HW_ENCODER_SUFFIXES.iter().any(|s| name.ends_with(s)))
+                .then(|| name.to_string())
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ToolVersions {
+    pub ffmpeg: Option<String>,
+    pub ffprobe: Option<String>,
+}
+
+/// What the transcoding and metadata code can rely on
+#[derive(Debug, Clone, Serialize)]
+pub struct FfmpegCapabilities {
+    pub ffmpeg: bool,
+    pub ffprobe: bool,
+    pub versions: ToolVersions,
+    pub hw_encoders: Vec<String>,
+}
+
+/// Runs each binary, so call it off the async runtime
+pub fn capabilities() -> FfmpegCapabilities {
+    let ffmpeg = is_ffmpeg_installed();
+    let ffprobe = is_ffprobe_installed();
+    FfmpegCapabilities {
+        ffmpeg,
+        ffprobe,
+        versions: ToolVersions {
+            ffmpeg: ffmpeg.then(|| tool_version(ffmpeg_sidecar::paths::ffmpeg_path())).flatten(),
+            ffprobe: ffprobe.then(|| tool_version(ffprobe_path())).flatten(),
+        },
+        hw_encoders: if ffmpeg { hw_encoders() } else { Vec::new() },
+    }
+}
+
+/// Where the startup ffmpeg setup is. Moves checking -> downloading -> ready, or ends in
+/// failed/cancelled until the user retries from onboarding.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BootstrapState {
+    Checking,
+    Downloading,
+    Ready,
+    Failed,
+    Cancelled,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FfmpegBootstrap {
+    pub state: BootstrapState,
+    pub error: Option<String>,
+}
+
+static BOOTSTRAP: Mutex<FfmpegBootstrap> = Mutex::new(FfmpegBootstrap {
+    state: BootstrapState::Checking,
+    error: None,
+});
+
+pub fn bootstrap_status() -> FfmpegBootstrap {
+    BOOTSTRAP.lock().map(|b| b.clone()).unwrap_or(FfmpegBootstrap {
+        state: BootstrapState::Checking,
+        error: None,
+    })
+}
+
+/// Records the new state and sends it to the UI as "ffmpeg-bootstrap"
+pub fn set_bootstrap_state(app: &AppHandle, state: BootstrapState, error: Option<String>) {
+    let status = FfmpegBootstrap { state, error };
+    if let Ok(mut current) = BOOTSTRAP.lock() {
+        *current = status.clone();
+    }
+    let _ = app.emit("ffmpeg-bootstrap", status);
+}
@@ -1,17 +1,74 @@
+use std::collections::{HashMap, HashSet};
 use std::fs;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, SystemTime};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use tauri::{AppHandle, Manager};
 
+// Fonts no release has asked for in this long are dropped; every save or serve of a font
+// counts as a use
+const FONT_UNUSED_DAYS: u64 = 60;
+
+// Size cap of the fonts folder in bytes, set from settings; 0 keeps everything
+static FONT_LIMIT_BYTES: AtomicU64 = AtomicU64::new(0);
+
+pub fn set_font_limit(mb: u64) {
+    FONT_LIMIT_BYTES.store(mb * 1_048_576, Ordering::Relaxed);
+}
+
+/// Marks a stored font as used now. The modification time doubles as the last-used time.
+pub fn touch_font(path: &Path) {
+    if let Ok(file) = fs::File::options().write(true).open(path) {
+        let _ = file.set_modified(SystemTime::now());
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FontInfo {
     pub filename: String,
+    // sha256 of the file contents
     pub hash: String,
     pub path: String,
+    #[serde(default)]
+    pub families: Vec<String>,
+}
+
+// Kept next to the fonts folder so it isn't served or counted as a font
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct FontIndex {
+    // content hash -> stored filename
+    by_hash: HashMap<String, String>,
+    // lowercased family name -> stored filenames
+    by_family: HashMap<String, Vec<String>>,
+}
+
+impl FontIndex {
+    fn remove(&mut self, filename: &str) {
+        self.by_hash.retain(|_, stored| stored != filename);
+        for files in self.by_family.values_mut() {
+            files.retain(|f| f != filename);
+        }
+        self.by_family.retain(|_, files| !files.is_empty());
+    }
+
+    fn insert(&mut self, filename: &str, hash: String, families: &[String]) {
+        self.by_hash.insert(hash, filename.to_string());
+        for family in families {
+            let files = self.by_family.entry(family.to_lowercase()).or_default();
+            if !files.iter().any(|f| f == filename) {
+                files.push(filename.to_string());
+            }
+        }
+    }
 }
 
 pub struct FontManager {
     fonts_dir: PathBuf,
+    index_path: PathBuf,
+    index: Mutex<FontIndex>,
 }
 
 impl FontManager {
@@ -27,27 +84,158 @@ impl FontManager {
             fs::create_dir_all(&fonts_dir)
                 .map_err(|e| format!("Failed to create fonts directory: {}", e))?;
         }
-        
-        Ok(Self { fonts_dir })
+
+        let index_path = app_data.join("font_index.json");
+        let index = match fs::read_to_string(&index_path).ok().and_then(|json| serde_json::from_str(&json).ok()) {
+            Some(index) => index,
+            // First run with the index, or it was lost: rebuild it from the fonts on disk
+            None => Self::build_index(&fonts_dir),
+        };
+
+        let manager = Self {
+            fonts_dir,
+            index_path,
+            index: Mutex::new(index),
+        };
+        manager.save_index();
+        if let Err(e) = manager.cleanup() {
+            tracing::error!("failed to clean up fonts: {}", e);
+        }
+        Ok(manager)
+    }
+
+    fn build_index(fonts_dir: &Path) -> FontIndex {
+        let mut index = FontIndex::default();
+        if let Ok(entries) = fs::read_dir(fonts_dir) {
+            for entry in entries.flatten() {
+                let path = entry.path();
+                let (Some(filename), Ok(data)) = (path.file_name().and_then(|n| n.to_str()), fs::read(&path)) else {
+                    continue;
+                };
+                index.insert(filename, content_hash(&data), &font_families(&data));
+            }
+        }
+        index
+    }
+
+    fn save_index(&self) {
+        let Ok(index) = self.index.lock() else { return };
+        match serde_json::to_string_pretty(&*index) {
+            Ok(json) => {
+                if let Err(e) = fs::write(&self.index_path, json) {
+                    tracing::error!("failed to save font index: {}", e);
+                }
+            }
+            Err(e) => tracing::error!("failed to serialize font index: {}", e),
+        }
     }
     
+    /// Stores a font unless the same file is already there under any name. Returns the
+    /// path of the stored copy, whose file name may differ from `filename`.
     pub fn save_font(&self, filename: &str, data: &[u8]) -> Result<PathBuf, String> {
         let sanitized_name = sanitize_filename(filename);
-        let font_path = self.fonts_dir.join(&sanitized_name);
-        
-        // Check if font already exists
-        if font_path.exists() {
-            println!("Font already exists: {}", sanitized_name);
-            return Ok(font_path);
+        let hash = content_hash(data);
+
+        {
+            let index = self.index.lock().map_err(|e| e.to_string())?;
+            if let Some(stored) = index.by_hash.get(&hash) {
+                let stored_path = self.fonts_dir.join(stored);
+                if stored_path.exists() {
+                    touch_font(&stored_path);
+                    tracing::info!("Font already exists: {} (as {})", sanitized_name, stored);
+                    return Ok(stored_path);
+                }
+            }
+        }
+
+        // A different font already has this name, so keep both
+        let mut stored_name = sanitized_name.clone();
+        if self.fonts_dir.join(&stored_name).exists() {
+            let path = Path::new(&sanitized_name);
+            let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or(&sanitized_name);
+            stored_name = match path.extension().and_then(|e| e.to_str()) {
+                Some(ext) => format!("{}-{}.{}", stem, &hash[..8], ext),
+                None => format!("{}-{}", stem, &hash[..8]),
+            };
         }
+        let font_path = self.fonts_dir.join(&stored_name);
         
         fs::write(&font_path, data)
             .map_err(|e| format!("Failed to write font file: {}", e))?;
+
+        let families = font_families(data);
+        self.index.lock().map_err(|e| e.to_string())?.insert(&stored_name, hash, &families);
+        self.save_index();
         
-        println!("saved font: {} ({} bytes)", sanitized_name, data.len());
+        tracing::info!("saved font: {} ({} bytes, families: {:?})", stored_name, data.len(), families);
+        if let Err(e) = self.cleanup() {
+            tracing::error!("failed to clean up fonts: {}", e);
+        }
         Ok(font_path)
     }
 
+    /// Removes fonts that haven't been used for a while, then the least recently used ones
+    /// until the folder fits the size cap. Returns how many files and bytes were removed.
+    pub fn cleanup(&self) -> Result<(usize, u64), String> {
+        let mut fonts: Vec<(PathBuf, u64, SystemTime)> = fs::read_dir(&self.fonts_dir)
+            .map_err(|e| format!("Failed to read fonts directory: {}", e))?
+            .flatten()
+            .filter_map(|entry| {
+                let metadata = entry.metadata().ok()?;
+                if !metadata.is_file() {
+                    return None;
+                }
+                Some((entry.path(), metadata.len(), metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH)))
+            })
+            .collect();
+        fonts.sort_by_key(|(_, _, used)| *used);
+
+        let unused_before = SystemTime::now() - Duration::from_secs(FONT_UNUSED_DAYS * 24 * 60 * 60);
+        let limit = FONT_LIMIT_BYTES.load(Ordering::Relaxed);
+        let mut total: u64 = fonts.iter().map(|(_, size, _)| size).sum();
+        let mut removed = Vec::new();
+        let mut removed_bytes = 0;
+
+        for (path, size, used) in fonts {
+            let over_limit = limit > 0 && total > limit;
+            if used >= unused_before && !over_limit {
+                break;
+            }
+            if fs::remove_file(&path).is_ok() {
+                total -= size;
+                removed_bytes += size;
+                if let Some(filename) = path.file_name().and_then(|n| n.to_str()) {
+                    removed.push(filename.to_string());
+                }
+            }
+        }
+
+        if !removed.is_empty() {
+            if let Ok(mut index) = self.index.lock() {
+                for filename in &removed {
+                    index.remove(filename);
+                }
+            }
+            self.save_index();
+            tracing::info!("removed {} unused fonts ({} bytes)", removed.len(), removed_bytes);
+        }
+        Ok((removed.len(), removed_bytes))
+    }
+
+    pub fn clear_fonts(&self) -> Result<(), String> {
+        let entries = fs::read_dir(&self.fonts_dir)
+            .map_err(|e| format!("Failed to read fonts directory: {}", e))?;
+        for entry in entries.flatten() {
+            if entry.path().is_file() {
+                fs::remove_file(entry.path())
+                    .map_err(|e| format!("Failed to remove font: {}", e))?;
+            }
+        }
+        *self.index.lock().map_err(|e| e.to_string())? = FontIndex::default();
+        self.save_index();
+        Ok(())
+    }
+
     pub fn get_stats(&self) -> Result<(usize, u64), String> {
         let mut count = 0;
         let mut size = 0;
@@ -68,22 +256,30 @@ impl FontManager {
     
     pub fn list_fonts(&self) -> Result<Vec<FontInfo>, String> {
         let mut fonts = Vec::new();
+        let index = self.index.lock().map_err(|e| e.to_string())?;
         
         let entries = fs::read_dir(&self.fonts_dir)
             .map_err(|e| format!("Failed to read fonts directory: {}", e))?;
         
-        for entry in entries {
-            if let Ok(entry) = entry {
-                let path = entry.path();
-                if path.is_file() {
-                    if let Some(filename) = path.file_name().and_then(|n| n.to_str()) {
-                        let hash = format!("{:x}", md5::compute(filename));
-                        fonts.push(FontInfo {
-                            filename: filename.to_string(),
-                            hash,
-                            path: path.to_string_lossy().to_string(),
-                        });
-                    }
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.is_file() {
+                if let Some(filename) = path.file_name().and_then(|n| n.to_str()) {
+                    let hash = index.by_hash.iter()
+                        .find(|(_, stored)| stored.as_str() == filename)
+                        .map(|(hash, _)| hash.clone())
+                        .unwrap_or_default();
+                    let mut families: Vec<String> = index.by_family.iter()
+                        .filter(|(_, files)| files.iter().any(|f| f == filename))
+                        .map(|(family, _)| family.clone())
+                        .collect();
+                    families.sort();
+                    fonts.push(FontInfo {
+                        filename: filename.to_string(),
+                        hash,
+                        path: path.to_string_lossy().to_string(),
+                        families,
+                    });
                 }
             }
         }
@@ -96,6 +292,32 @@ impl FontManager {
     }
 }
 
+fn content_hash(data: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(data))
+}
+
+/// Family names from a font's name table, including every face of a collection. The
+/// typographic family is preferred by renderers but older fonts only have the legacy one,
+/// so both are returned.
+pub fn font_families(data: &[u8]) -> Vec<String> {
+    let faces = ttf_parser::fonts_in_collection(data).unwrap_or(1);
+    let mut families = Vec::new();
+    for face_index in 0..faces {
+        let Ok(face) = ttf_parser::Face::parse(data, face_index) else { continue };
+        for name in face.names() {
+            if name.name_id != ttf_parser::name_id::FAMILY && name.name_id != ttf_parser::name_id::TYPOGRAPHIC_FAMILY {
+                continue;
+            }
+            if let Some(family) = name.to_string().map(|f| f.trim().to_string()).filter(|f| !f.is_empty()) {
+                if !families.iter().any(|known: &String| known.eq_ignore_ascii_case(&family)) {
+                    families.push(family);
+                }
+            }
+        }
+    }
+    families
+}
+
 fn sanitize_filename(filename: &str) -> String {
     filename
         .chars()
@@ -106,75 +328,107 @@ fn sanitize_filename(filename: &str) -> String {
         .collect()
 }
 
-#[cfg(windows)]
-pub fn is_font_installed(font_name: &str) -> bool {
-    // Check Windows fonts directory
-    let windows_fonts = PathBuf::from("C:\\Windows\\Fonts");
-    
-    // Extract base filename without extension for comparison
-    let base_name = Path::new(font_name)
-        .file_stem()
-        .and_then(|s| s.to_str())
-        .unwrap_or(font_name)
-        .to_lowercase();
-    
-    if let Ok(entries) = fs::read_dir(&windows_fonts) {
-        for entry in entries.flatten() {
-            if let Some(filename) = entry.file_name().to_str() {
-                let installed_base = Path::new(filename)
-                    .file_stem()
-                    .and_then(|s| s.to_str())
-                    .unwrap_or("")
-                    .to_lowercase();
-                
-                if installed_base == base_name {
-                    println!("Font {} already installed in system", font_name);
-                    return true;
-                }
+/// An installed face matching a requested font
+#[derive(Debug, Clone, Serialize)]
+pub struct SystemFont {
+    pub family: String,
+    // "normal", "italic" or "oblique"
+    pub style: String,
+    // 400 is regular, 700 bold
+    pub weight: u16,
+    pub path: Option<String>,
+}
+
+// Installed faces, plus the file stems that attachments are sometimes named after
+struct SystemFonts {
+    stems: HashSet<String>,
+    faces: Vec<SystemFont>,
+}
+
+fn style_name(style: fontdb::Style) -> &'static str {
+    match style {
+        fontdb::Style::Normal => "normal",
+        fontdb::Style::Italic => "italic",
+        fontdb::Style::Oblique => "oblique",
+    }
+}
+
+// Enumerating the system fonts takes a moment, so it's done once per run. fontdb follows
+// the fontconfig configuration on Linux and includes per-user fonts on Windows and macOS.
+fn system_fonts() -> &'static SystemFonts {
+    static SYSTEM_FONTS: OnceLock<SystemFonts> = OnceLock::new();
+    SYSTEM_FONTS.get_or_init(|| {
+        let mut db = fontdb::Database::new();
+        db.load_system_fonts();
+
+        let mut fonts = SystemFonts {
+            stems: HashSet::new(),
+            faces: Vec::new(),
+        };
+        for face in db.faces() {
+            let path = match &face.source {
+                fontdb::Source::File(path) | fontdb::Source::SharedFile(path, _) => Some(path.clone()),
+                fontdb::Source::Binary(_) => None,
+            };
+            if let Some(stem) = path.as_ref().and_then(|p| p.file_stem()).and_then(|s| s.to_str()) {
+                fonts.stems.insert(stem.to_lowercase());
+            }
+            for (family, _) in &face.families {
+                fonts.faces.push(SystemFont {
+                    family: family.clone(),
+                    style: style_name(face.style).to_string(),
+                    weight: face.weight.0,
+                    path: path.as_ref().map(|p| p.to_string_lossy().to_string()),
+                });
             }
         }
-    }
-    
-    false
+        tracing::info!("indexed {} system font faces", fonts.faces.len());
+        fonts
+    })
 }
 
-#[cfg(not(windows))]
+pub fn warm_system_fonts() {
+    system_fonts();
+}
+
+/// Installed faces of a family, matched case-insensitively
+pub fn find_system_fonts(family: &str) -> Vec<SystemFont> {
+    system_fonts().faces.iter()
+        .filter(|face| face.family.eq_ignore_ascii_case(family))
+        .cloned()
+        .collect()
+}
+
+/// Whether the system has a font matching an attachment's file name, or the family name
+/// it was given as
 pub fn is_font_installed(font_name: &str) -> bool {
-    // For non-Windows, check common font directories
-    let font_dirs = [
-        "/usr/share/fonts",
-        "/usr/local/share/fonts",
-        "~/.fonts",
-        "~/.local/share/fonts",
-    ];
-    
     let base_name = Path::new(font_name)
         .file_stem()
         .and_then(|s| s.to_str())
         .unwrap_or(font_name)
         .to_lowercase();
-    
-    for dir in &font_dirs {
-        let path = PathBuf::from(dir);
-        if path.exists() {
-            if let Ok(entries) = fs::read_dir(&path) {
-                for entry in entries.flatten() {
-                    if let Some(filename) = entry.file_name().to_str() {
-                        let installed_base = Path::new(filename)
-                            .file_stem()
-                            .and_then(|s| s.to_str())
-                            .unwrap_or("")
-                            .to_lowercase();
-                        
-                        if installed_base == base_name {
-                            println!("Font {} already installed in system", font_name);
-                            return true;
-                        }
-                    }
-                }
-            }
-        }
+
+    if system_fonts().stems.contains(&base_name) || !find_system_fonts(font_name).is_empty() {
+        tracing::info!("Font {} already installed in system", font_name);
+        return true;
     }
-    
     false
 }
+
+/// Whether every face in this font file is already installed with the same family, style
+/// and weight, so a bold or italic cut isn't skipped just because the regular one exists
+pub fn is_face_installed(data: &[u8]) -> bool {
+    let mut db = fontdb::Database::new();
+    db.load_font_data(data.to_vec());
+    let mut faces = db.faces().peekable();
+    if faces.peek().is_none() {
+        return false;
+    }
+    faces.all(|face| {
+        face.families.iter().any(|(family, _)| {
+            find_system_fonts(family).iter().any(|installed| {
+                installed.style == style_name(face.style) && installed.weight == face.weight.0
+            })
+        })
+    })
+}
@@ -0,0 +1,162 @@
+// Translations for text the backend puts in front of the user itself: command errors the
+// frontend shows as they are, and native notifications. Log output stays in English.
+
+use std::fmt::Display;
+use std::sync::RwLock;
+
+/// Languages with translations; the `language` setting is one of these codes
+pub const LANGUAGES: &[&str] = &["en", "es", "fr", "de"];
+
+static LANGUAGE: RwLock<usize> = RwLock::new(0);
+
+/// Switches to `code`, or back to English if there are no translations for it
+pub fn set_language(code: &str) {
+    let index = LANGUAGES.iter().position(|l| *l == code).unwrap_or(0);
+    if let Ok(mut current) = LANGUAGE.write() {
+        *current = index;
+    }
+}
+
+// Key, then its text in each language in the order of LANGUAGES. `{name}` placeholders
+// are filled in by t_args.
+const STRINGS: &[(&str, [&str; 4])] = &[
+    ("notification.ffmpeg_installed.title", [
+        "ffmpeg installed",
+        "ffmpeg instalado",
+        "ffmpeg installé",
+        "ffmpeg installiert",
+    ]),
+    ("notification.ffmpeg_installed.body", [
+        "Audio transcoding and subtitle extraction are ready",
+        "La transcodificación de audio y la extracción de subtítulos están listas",
+        "Le transcodage audio et l'extraction des sous-titres sont prêts",
+        "Audio-Transkodierung und Untertitel-Extraktion sind bereit",
+    ]),
+    ("notification.stream_stalled.title", [
+        "Stream stalled",
+        "Transmisión detenida",
+        "Flux bloqué",
+        "Stream hängt",
+    ]),
+    ("notification.stream_stalled.body", [
+        "No data received for a while, try another release",
+        "No llegan datos desde hace un rato, prueba otra versión",
+        "Aucune donnée reçue depuis un moment, essayez une autre version",
+        "Seit einer Weile kommen keine Daten an, versuche ein anderes Release",
+    ]),
+    ("notification.download_complete.title", [
+        "Download complete",
+        "Descarga completada",
+        "Téléchargement terminé",
+        "Download abgeschlossen",
+    ]),
+    ("notification.audio_track_ready.title", [
+        "Audio track ready",
+        "Pista de audio lista",
+        "Piste audio prête",
+        "Audiospur bereit",
+    ]),
+    ("error.ffmpeg_installing", [
+        "ffmpeg is already being installed",
+        "ffmpeg ya se está instalando",
+        "ffmpeg est déjà en cours d'installation",
+        "ffmpeg wird bereits installiert",
+    ]),
+    ("error.ffmpeg_install_cancelled", [
+        "ffmpeg install cancelled",
+        "Instalación de ffmpeg cancelada",
+        "Installation de ffmpeg annulée",
+        "ffmpeg-Installation abgebrochen",
+    ]),
+    ("error.unsupported_player", [
+        "Unsupported player: {player}",
+        "Reproductor no compatible: {player}",
+        "Lecteur non pris en charge : {player}",
+        "Nicht unterstützter Player: {player}",
+    ]),
+    ("error.player_launch_failed", [
+        "Failed to launch {player}: {error}",
+        "No se pudo iniciar {player}: {error}",
+        "Impossible de lancer {player} : {error}",
+        "{player} konnte nicht gestartet werden: {error}",
+    ]),
+    ("error.library_entry_not_found", [
+        "Library entry not found",
+        "No se encontró la entrada de la biblioteca",
+        "Entrée de bibliothèque introuvable",
+        "Bibliothekseintrag nicht gefunden",
+    ]),
+    ("error.file_missing", [
+        "File no longer exists on disk",
+        "El archivo ya no existe en el disco",
+        "Le fichier n'existe plus sur le disque",
+        "Die Datei existiert nicht mehr auf dem Datenträger",
+    ]),
+    ("error.no_season_episodes", [
+        "No episodes of season {season} in the library",
+        "No hay episodios de la temporada {season} en la biblioteca",
+        "Aucun épisode de la saison {season} dans la bibliothèque",
+        "Keine Folgen von Staffel {season} in der Bibliothek",
+    ]),
+    ("error.no_backup_folder", [
+        "No backup folder set",
+        "No hay ninguna carpeta de copias de seguridad configurada",
+        "Aucun dossier de sauvegarde défini",
+        "Kein Sicherungsordner festgelegt",
+    ]),
+    ("error.parental_blocked", [
+        "Blocked by parental controls (rated {rating})",
+        "Bloqueado por el control parental (clasificación {rating})",
+        "Bloqué par le contrôle parental (classé {rating})",
+        "Durch die Kindersicherung gesperrt (Freigabe {rating})",
+    ]),
+    ("error.incorrect_pin", [
+        "Incorrect PIN",
+        "PIN incorrecto",
+        "Code PIN incorrect",
+        "Falsche PIN",
+    ]),
+    ("error.pin_backoff", [
+        "Too many wrong PINs, try again in {seconds}s",
+        "Demasiados PIN incorrectos, inténtalo de nuevo en {seconds} s",
+        "Trop de codes PIN incorrects, réessayez dans {seconds} s",
+        "Zu viele falsche PINs, versuche es in {seconds} s erneut",
+    ]),
+    ("error.parental_not_cleared", [
+        "This stream hasn't been checked by parental controls",
+        "El control parental no ha comprobado esta transmisión",
+        "Ce flux n'a pas été vérifié par le contrôle parental",
+        "Dieser Stream wurde nicht von der Kindersicherung geprüft",
+    ]),
+    ("error.parental_blocked_adult", [
+        "Blocked by parental controls (adult content)",
+        "Bloqueado por el control parental (contenido para adultos)",
+        "Bloqué par le contrôle parental (contenu pour adultes)",
+        "Durch die Kindersicherung gesperrt (Inhalte für Erwachsene)",
+    ]),
+    ("error.too_many_streams", [
+        "{count} streams are already playing; close one to start another",
+        "Ya se están reproduciendo {count} transmisiones; cierra una para iniciar otra",
+        "{count} flux sont déjà en lecture ; fermez-en un pour en lancer un autre",
+        "Es laufen bereits {count} Streams; schließe einen, um einen weiteren zu starten",
+    ]),
+];
+
+/// `key` in the app language. Falls back to English, and to the key itself for a key
+/// that isn't in the table.
+pub fn t(key: &str) -> String {
+    t_args(key, &[])
+}
+
+/// Like `t`, filling in the `{name}` placeholders
+pub fn t_args(key: &str, args: &[(&str, &dyn Display)]) -> String {
+    let language = LANGUAGE.read().map(|l| *l).unwrap_or(0);
+    let mut text = match STRINGS.iter().find(|(k, _)| *k == key) {
+        Some((_, texts)) => texts[language].to_string(),
+        None => key.to_string(),
+    };
+    for (name, value) in args {
+        text = text.replace(&format!("{{{}}}", name), &value.to_string());
+    }
+    text
+}
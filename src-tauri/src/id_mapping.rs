@@ -0,0 +1,130 @@
+use serde::{Deserialize, Deserializer, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tokio::sync::RwLock;
+
+const MAPPING_URL: &str = "https://raw.githubusercontent.com/Fribb/anime-lists/master/anime-list-full.json";
+// The list is updated upstream roughly weekly
+const MAPPING_MAX_AGE: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AnimeIds {
+    pub anidb_id: Option<u32>,
+    pub anilist_id: Option<u32>,
+    pub mal_id: Option<u32>,
+}
+
+#[derive(Deserialize)]
+struct MappingEntry {
+    #[serde(default, deserialize_with = "lenient_id")]
+    anidb_id: Option<u32>,
+    #[serde(default, deserialize_with = "lenient_id")]
+    anilist_id: Option<u32>,
+    #[serde(default, deserialize_with = "lenient_id")]
+    mal_id: Option<u32>,
+    #[serde(default, deserialize_with = "lenient_id")]
+    themoviedb_id: Option<u32>,
+}
+
+// Ids show up as numbers, numeric strings or "unknown" depending on the entry
+fn lenient_id<'de, D>(deserializer: D) -> Result<Option<u32>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let value = serde_json::Value::deserialize(deserializer)?;
+    Ok(match value {
+        serde_json::Value::Number(n) => n.as_u64().and_then(|n| u32::try_from(n).ok()),
+        serde_json::Value::String(s) => s.parse().ok(),
+        _ => None,
+    })
+}
+
+/// Maps TMDB ids to AniDB/AniList/MAL ids using the anime-lists mapping file,
+/// which is downloaded on first use and cached in the app data dir
+pub struct IdMappingManager {
+    file_path: PathBuf,
+    by_tmdb: Arc<RwLock<Option<HashMap<u32, Vec<AnimeIds>>>>>,
+    client: reqwest::Client,
+}
+
+impl IdMappingManager {
+    pub fn new(app_data_dir: PathBuf) -> Self {
+        Self {
+            file_path: app_data_dir.join("anime-list-full.json"),
+            by_tmdb: Arc::new(RwLock::new(None)),
+            client: reqwest::Client::builder()
+                .timeout(Duration::from_secs(60))
+                .build()
+                .unwrap(),
+        }
+    }
+
+    /// Returns every mapping entry for a TMDB id. A TMDB show usually maps to several
+    /// AniDB entries since AniDB splits seasons into separate anime.
+    pub async fn lookup_tmdb(&self, tmdb_id: u32) -> Vec<AnimeIds> {
+        if self.by_tmdb.read().await.is_none() {
+            self.load().await;
+        }
+
+        self.by_tmdb.read().await
+            .as_ref()
+            .and_then(|m| m.get(&tmdb_id).cloned())
+            .unwrap_or_default()
+    }
+
+    async fn load(&self) {
+        let mut by_tmdb = self.by_tmdb.write().await;
+        if by_tmdb.is_some() {
+            return;
+        }
+
+        let is_stale = fs::metadata(&self.file_path)
+            .and_then(|m| m.modified())
+            .ok()
+            .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+            .map(|age| age > MAPPING_MAX_AGE)
+            .unwrap_or(true);
+
+        if is_stale {
+            match self.download().await {
+                Ok(content) => {
+                    if let Err(e) = fs::write(&self.file_path, &content) {
+                        tracing::warn!("failed to write anime id mapping: {}", e);
+                    }
+                }
+                // Keep using the old file if there is one
+                Err(e) => tracing::warn!("failed to download anime id mapping: {}", e),
+            }
+        }
+
+        let entries: Vec<MappingEntry> = match fs::read_to_string(&self.file_path) {
+            Ok(content) => serde_json::from_str(&content).unwrap_or_else(|e| {
+                tracing::warn!("failed to parse anime id mapping: {}", e);
+                Vec::new()
+            }),
+            Err(_) => Vec::new(),
+        };
+
+        let mut map: HashMap<u32, Vec<AnimeIds>> = HashMap::new();
+        for entry in entries {
+            if let Some(tmdb_id) = entry.themoviedb_id {
+                map.entry(tmdb_id).or_default().push(AnimeIds {
+                    anidb_id: entry.anidb_id,
+                    anilist_id: entry.anilist_id,
+                    mal_id: entry.mal_id,
+                });
+            }
+        }
+
+        tracing::info!("loaded anime id mapping with {} TMDB entries", map.len());
+        *by_tmdb = Some(map);
+    }
+
+    async fn download(&self) -> Result<String, reqwest::Error> {
+        tracing::info!("downloading anime id mapping from {}", MAPPING_URL);
+        self.client.get(MAPPING_URL).send().await?.error_for_status()?.text().await
+    }
+}
@@ -0,0 +1,347 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::RwLock;
+
+const TMDB_BASE_URL: &str = "https://api.themoviedb.org/3";
+const TOKEN_ENDPOINT: &str = "https://magnolia-tmdb.netlify.app/tmdb-proxy";
+const IMDB_SUGGESTION_URL: &str = "https://v3.sg.media-imdb.com/suggestion/x";
+// Titles TMDB couldn't match are retried after this, doubling up to a day
+const TMDB_MISS_BACKOFF: Duration = Duration::from_secs(5 * 60);
+const TMDB_MISS_BACKOFF_MAX: Duration = Duration::from_secs(24 * 60 * 60);
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct ImdbIdData {
+    // Keyed by "{media_type}:{tmdb_id}", e.g. "tv:1399"
+    pub mappings: HashMap<String, String>,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    token: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ExternalIds {
+    imdb_id: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct TmdbSearchResponse {
+    #[serde(default)]
+    results: Vec<TmdbSearchResult>,
+}
+
+#[derive(Deserialize)]
+struct TmdbSearchResult {
+    id: u32,
+    // "title" for movies, "name" for shows
+    title: Option<String>,
+    name: Option<String>,
+}
+
+// /tv/{id}/season/{n}
+#[derive(Deserialize)]
+struct SeasonDetails {
+    #[serde(default)]
+    name: Option<String>,
+}
+
+// /movie/{id}/release_dates and /tv/{id}/content_ratings
+#[derive(Deserialize)]
+struct RatingsResponse {
+    #[serde(default)]
+    results: Vec<CountryRating>,
+}
+
+#[derive(Deserialize)]
+struct CountryRating {
+    iso_3166_1: String,
+    // TV
+    rating: Option<String>,
+    // Movies, one per release type
+    #[serde(default)]
+    release_dates: Vec<ReleaseDate>,
+}
+
+#[derive(Deserialize)]
+struct ReleaseDate {
+    #[serde(default)]
+    certification: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TmdbMatch {
+    pub tmdb_id: u32,
+    pub title: String,
+}
+
+#[derive(Deserialize)]
+struct SuggestionResponse {
+    #[serde(default)]
+    d: Vec<Suggestion>,
+}
+
+#[derive(Deserialize)]
+struct Suggestion {
+    id: String,
+    #[serde(default)]
+    l: String,
+    // "feature", "tvSeries", "tvMiniSeries", ...
+    qid: Option<String>,
+    y: Option<u32>,
+}
+
+pub struct ImdbResolver {
+    file_path: PathBuf,
+    data: Arc<RwLock<ImdbIdData>>,
+    client: reqwest::Client,
+    token: RwLock<Option<String>>,
+    // US certifications by "{media_type}:{tmdb_id}", kept for the session
+    certifications: RwLock<HashMap<String, Option<String>>>,
+    // Failed title searches by "{media_type}:{title}:{year}": misses so far and when to retry
+    tmdb_misses: RwLock<HashMap<String, (u32, Instant)>>,
+}
+
+impl ImdbResolver {
+    pub fn new(app_data_dir: PathBuf) -> Self {
+        let file_path = app_data_dir.join("imdb_ids.json");
+        let data = if file_path.exists() {
+            let content = fs::read_to_string(&file_path).unwrap_or_default();
+            serde_json::from_str(&content).unwrap_or_default()
+        } else {
+            ImdbIdData::default()
+        };
+
+        Self {
+            file_path,
+            data: Arc::new(RwLock::new(data)),
+            client: reqwest::Client::builder()
+                .timeout(std::time::Duration::from_secs(10))
+                .build()
+                .unwrap(),
+            token: RwLock::new(None),
+            certifications: RwLock::new(HashMap::new()),
+            tmdb_misses: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Maps a TMDB id to an IMDB id ("tt..."), using the on-disk cache first, then TMDB
+    /// external_ids, then the IMDB suggestion API when a title is available.
+    pub async fn resolve(&self, tmdb_id: u32, media_type: &str, title: Option<&str>, year: Option<u32>) -> Option<String> {
+        let media_type = if media_type == "movie" { "movie" } else { "tv" };
+        let key = format!("{}:{}", media_type, tmdb_id);
+
+        if let Some(imdb_id) = self.data.read().await.mappings.get(&key) {
+            return Some(imdb_id.clone());
+        }
+
+        let resolved = match self.fetch_from_tmdb(tmdb_id, media_type).await {
+            Ok(Some(id)) => Some(id),
+            Ok(None) => None,
+            Err(e) => {
+                tracing::warn!("imdb resolver: TMDB lookup failed for {}: {}", key, e);
+                None
+            }
+        };
+
+        let resolved = match (resolved, title) {
+            (Some(id), _) => Some(id),
+            (None, Some(title)) => match self.fetch_from_suggestions(title, media_type, year).await {
+                Ok(id) => id,
+                Err(e) => {
+                    tracing::warn!("imdb resolver: suggestion lookup failed for '{}': {}", title, e);
+                    None
+                }
+            },
+            (None, None) => None,
+        };
+
+        if let Some(ref imdb_id) = resolved {
+            tracing::info!("imdb resolver: {} -> {}", key, imdb_id);
+            let mut data = self.data.write().await;
+            data.mappings.insert(key, imdb_id.clone());
+            if let Ok(content) = serde_json::to_string_pretty(&*data) {
+                let _ = fs::write(&self.file_path, content);
+            }
+        }
+
+        resolved
+    }
+
+    async fn bearer_token(&self) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+        if let Some(token) = self.token.read().await.clone() {
+            return Ok(token);
+        }
+
+        let response: TokenResponse = self.client.get(TOKEN_ENDPOINT).send().await?.json().await?;
+        let token = response.token.ok_or("no token in response")?;
+        *self.token.write().await = Some(token.clone());
+        Ok(token)
+    }
+
+    async fn fetch_from_tmdb(&self, tmdb_id: u32, media_type: &str) -> Result<Option<String>, Box<dyn std::error::Error + Send + Sync>> {
+        let token = self.bearer_token().await?;
+        let url = format!("{}/{}/{}/external_ids", TMDB_BASE_URL, media_type, tmdb_id);
+
+        let response = self.client.get(&url).bearer_auth(token).send().await?;
+        if !response.status().is_success() {
+            return Err(format!("TMDB returned status {}", response.status()).into());
+        }
+
+        let ids: ExternalIds = response.json().await?;
+        Ok(ids.imdb_id.filter(|id| id.starts_with("tt")))
+    }
+
+    /// Best TMDB match for a parsed release title, used to index local files
+    /// Titles that found nothing (or failed) aren't looked up again until their backoff ends.
+    pub async fn search_tmdb(&self, title: &str, media_type: &str, year: Option<u32>) -> Option<TmdbMatch> {
+        let media_type = if media_type == "movie" { "movie" } else { "tv" };
+        let miss_key = format!("{}:{}:{}", media_type, title.to_lowercase(), year.map(|y| y.to_string()).unwrap_or_default());
+        if let Some((_, retry_at)) = self.tmdb_misses.read().await.get(&miss_key) {
+            if Instant::now() < *retry_at {
+                return None;
+            }
+        }
+
+        let result: Result<Option<TmdbMatch>, Box<dyn std::error::Error + Send + Sync>> = async {
+            let token = self.bearer_token().await?;
+            let mut url = format!("{}/search/{}?query={}", TMDB_BASE_URL, media_type, urlencoding::encode(title));
+            if let Some(year) = year {
+                let year_param = if media_type == "movie" { "year" } else { "first_air_date_year" };
+                url.push_str(&format!("&{}={}", year_param, year));
+            }
+
+            let response = self.client.get(&url).bearer_auth(token).send().await?;
+            if !response.status().is_success() {
+                return Err(format!("TMDB returned status {}", response.status()).into());
+            }
+
+            let search: TmdbSearchResponse = response.json().await?;
+            Ok(search.results.into_iter().next().map(|r| TmdbMatch {
+                tmdb_id: r.id,
+                title: r.title.or(r.name).unwrap_or_default(),
+            }))
+        }.await;
+
+        let found = match result {
+            Ok(found) => found,
+            Err(e) => {
+                tracing::warn!("tmdb search failed for '{}': {}", title, e);
+                None
+            }
+        };
+
+        let mut misses = self.tmdb_misses.write().await;
+        if found.is_some() {
+            misses.remove(&miss_key);
+        } else {
+            let attempts = misses.get(&miss_key).map(|(n, _)| n + 1).unwrap_or(1);
+            let backoff = TMDB_MISS_BACKOFF
+                .saturating_mul(1 << (attempts - 1).min(16))
+                .min(TMDB_MISS_BACKOFF_MAX);
+            misses.insert(miss_key, (attempts, Instant::now() + backoff));
+        }
+        found
+    }
+
+    /// TMDB's name for a season when it's a title of its own (a sequel or arc name),
+    /// None for the generic "Season 2"
+    pub async fn season_name(&self, tmdb_id: u32, season: u32) -> Option<String> {
+        let result: Result<Option<String>, Box<dyn std::error::Error + Send + Sync>> = async {
+            let token = self.bearer_token().await?;
+            let url = format!("{}/tv/{}/season/{}", TMDB_BASE_URL, tmdb_id, season);
+
+            let response = self.client.get(&url).bearer_auth(token).send().await?;
+            if !response.status().is_success() {
+                return Err(format!("TMDB returned status {}", response.status()).into());
+            }
+
+            let details: SeasonDetails = response.json().await?;
+            Ok(details.name)
+        }.await;
+
+        match result {
+            Ok(name) => name.filter(|n| {
+                let generic = format!("season {}", season);
+                !n.trim().is_empty() && n.trim().to_lowercase() != generic
+            }),
+            Err(e) => {
+                tracing::warn!("tmdb season lookup failed for {} season {}: {}", tmdb_id, season, e);
+                None
+            }
+        }
+    }
+
+    /// US certification for a title ("PG-13", "TV-MA", ...), None when it's unrated
+    pub async fn certification(&self, tmdb_id: u32, media_type: &str) -> Result<Option<String>, String> {
+        let media_type = if media_type == "movie" { "movie" } else { "tv" };
+        let key = format!("{}:{}", media_type, tmdb_id);
+
+        if let Some(certification) = self.certifications.read().await.get(&key) {
+            return Ok(certification.clone());
+        }
+
+        let result: Result<Option<String>, Box<dyn std::error::Error + Send + Sync>> = async {
+            let token = self.bearer_token().await?;
+            let endpoint = if media_type == "movie" { "release_dates" } else { "content_ratings" };
+            let url = format!("{}/{}/{}/{}", TMDB_BASE_URL, media_type, tmdb_id, endpoint);
+
+            let response = self.client.get(&url).bearer_auth(token).send().await?;
+            if !response.status().is_success() {
+                return Err(format!("TMDB returned status {}", response.status()).into());
+            }
+
+            let ratings: RatingsResponse = response.json().await?;
+            Ok(ratings.results.into_iter()
+                .find(|r| r.iso_3166_1 == "US")
+                .and_then(|r| {
+                    r.rating.or_else(|| r.release_dates.into_iter().map(|d| d.certification).find(|c| !c.is_empty()))
+                })
+                .filter(|c| !c.is_empty()))
+        }.await;
+
+        let certification = result.map_err(|e| format!("Failed to look up the rating for {}: {}", key, e))?;
+        self.certifications.write().await.insert(key, certification.clone());
+        Ok(certification)
+    }
+
+    async fn fetch_from_suggestions(&self, title: &str, media_type: &str, year: Option<u32>) -> Result<Option<String>, Box<dyn std::error::Error + Send + Sync>> {
+        let query = title.trim().to_lowercase();
+        if query.is_empty() {
+            return Ok(None);
+        }
+
+        let url = format!("{}/{}.json", IMDB_SUGGESTION_URL, urlencoding::encode(&query));
+        let response: SuggestionResponse = self.client.get(&url).send().await?.json().await?;
+
+        let wants_series = media_type == "tv";
+        let best = response.d.into_iter()
+            .filter(|s| s.id.starts_with("tt"))
+            .filter(|s| {
+                let is_series = s.qid.as_deref().map(|q| q.starts_with("tvSeries") || q == "tvMiniSeries").unwrap_or(false);
+                is_series == wants_series
+            })
+            .filter(|s| s.l.to_lowercase() == query)
+            .find(|s| match (year, s.y) {
+                (Some(wanted), Some(found)) => wanted.abs_diff(found) <= 1,
+                _ => true,
+            });
+
+        Ok(best.map(|s| s.id))
+    }
+}
+
+#[tauri::command]
+pub async fn resolve_imdb_id(
+    resolver: tauri::State<'_, ImdbResolver>,
+    tmdb_id: u32,
+    media_type: String,
+    title: Option<String>,
+    year: Option<u32>,
+) -> Result<Option<String>, String> {
+    Ok(resolver.resolve(tmdb_id, &media_type, title.as_deref(), year).await)
+}
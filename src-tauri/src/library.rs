@@ -0,0 +1,438 @@
+use crate::imdb_resolver::{ImdbResolver, TmdbMatch};
+use crate::search::{parse_release_title, parse_season_episode};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::sync::RwLock;
+
+const VIDEO_EXTENSIONS: &[&str] = &["mkv", "mp4", "avi", "mov", "webm", "m4v"];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LibraryEntry {
+    pub id: String,
+    pub path: String,
+    pub file_name: String,
+    pub size: u64,
+    pub tmdb_id: Option<u32>,
+    pub media_type: Option<String>, // "tv" or "movie"
+    pub title: Option<String>,
+    pub season: Option<u32>,
+    pub episode: Option<u32>,
+    pub source: String, // "download", "folder", "import", "organized"
+    pub added_at: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct LibraryData {
+    pub entries: Vec<LibraryEntry>,
+    // Extra folders the user asked to index alongside completed downloads
+    #[serde(default)]
+    pub folders: Vec<String>,
+    // Completed downloads already linked into the organized layout
+    #[serde(default)]
+    pub organized: Vec<String>,
+}
+
+/// Index of media files on disk, so finished downloads and the user's own files can be
+/// played without the torrent session
+pub struct LibraryManager {
+    file_path: PathBuf,
+    // Imported files are linked or copied here
+    library_dir: PathBuf,
+    data: Arc<RwLock<LibraryData>>,
+}
+
+fn library_id(path: &Path) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(path.to_string_lossy().as_bytes());
+    format!("{:x}", hasher.finalize())[..16].to_string()
+}
+
+fn is_video_file(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .map(|e| VIDEO_EXTENSIONS.contains(&e.to_lowercase().as_str()))
+        .unwrap_or(false)
+}
+
+/// Fills in an organizer template like "{show}/Season {season}/{show} - S{season}E{episode}".
+/// Season and episode are zero padded; each path component is stripped of characters
+/// that aren't allowed in file names.
+fn render_organize_template(template: &str, show: &str, season: u32, episode: u32) -> PathBuf {
+    let rendered = template
+        .replace("{show}", show)
+        .replace("{season}", &format!("{:02}", season))
+        .replace("{episode}", &format!("{:02}", episode));
+
+    rendered
+        .split(['/', '\\'])
+        .map(|part| {
+            part.chars()
+                .filter(|c| !matches!(c, '<' | '>' | ':' | '"' | '|' | '?' | '*'))
+                .collect::<String>()
+                .trim()
+                .trim_end_matches('.')
+                .to_string()
+        })
+        .filter(|part| !part.is_empty() && part != "..")
+        .collect()
+}
+
+// Symlinked directories aren't followed, so a link back up the tree can't loop forever.
+// Symlinked files are still picked up.
+// Whether two paths are the same file, e.g. an earlier import hard linked from the source
+fn same_file(a: &Path, b: &Path) -> bool {
+    let (Ok(a), Ok(b)) = (fs::metadata(a), fs::metadata(b)) else {
+        return false;
+    };
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        if a.dev() == b.dev() && a.ino() == b.ino() {
+            return true;
+        }
+    }
+    // A copy from another volume keeps the size and, mostly, the modification time
+    a.len() == b.len() && a.modified().ok() == b.modified().ok()
+}
+
+/// Where an imported file goes in the library: its own name, or with a suffix from its
+/// source path when a different file already has that name
+fn import_destination(library_dir: &Path, source: &Path) -> Result<PathBuf, String> {
+    let file_name = source.file_name().and_then(|n| n.to_str()).unwrap_or("unknown");
+    let plain = library_dir.join(file_name);
+    if !plain.exists() || same_file(source, &plain) {
+        return Ok(plain);
+    }
+
+    let stem = source.file_stem().and_then(|n| n.to_str()).unwrap_or("unknown");
+    let mut suffixed = format!("{} [{}]", stem, &library_id(source)[..8]);
+    if let Some(ext) = source.extension().and_then(|e| e.to_str()) {
+        suffixed.push('.');
+        suffixed.push_str(ext);
+    }
+    let suffixed = library_dir.join(suffixed);
+    if !suffixed.exists() || same_file(source, &suffixed) {
+        return Ok(suffixed);
+    }
+    Err(format!("A different file named {} is already in the library", file_name))
+}
+
+fn collect_video_files(dir: &Path, files: &mut Vec<PathBuf>) {
+    if let Ok(entries) = fs::read_dir(dir) {
+        for entry in entries.flatten() {
+            let Ok(file_type) = entry.file_type() else { continue };
+            let path = entry.path();
+            if file_type.is_dir() {
+                collect_video_files(&path, files);
+            } else if is_video_file(&path) && (file_type.is_file() || path.is_file()) {
+                files.push(path);
+            }
+        }
+    }
+}
+
+impl LibraryManager {
+    pub fn new(app_data_dir: PathBuf) -> Self {
+        let file_path = app_data_dir.join("library.json");
+        let data = if file_path.exists() {
+            let content = fs::read_to_string(&file_path).unwrap_or_default();
+            serde_json::from_str(&content).unwrap_or_default()
+        } else {
+            LibraryData::default()
+        };
+
+        Self {
+            file_path,
+            library_dir: app_data_dir.join("library"),
+            data: Arc::new(RwLock::new(data)),
+        }
+    }
+
+    fn persist(&self, data: &LibraryData) {
+        if let Ok(content) = serde_json::to_string_pretty(data) {
+            let _ = fs::write(&self.file_path, content);
+        }
+    }
+
+    pub async fn get_library(&self) -> Vec<LibraryEntry> {
+        self.data.read().await.entries.clone()
+    }
+
+    pub async fn get_entry(&self, id: &str) -> Option<LibraryEntry> {
+        self.data.read().await.entries.iter().find(|e| e.id == id).cloned()
+    }
+
+    pub async fn add_folder(&self, folder: String) {
+        let mut data = self.data.write().await;
+        if !data.folders.contains(&folder) {
+            data.folders.push(folder);
+            self.persist(&data);
+        }
+    }
+
+    /// Stops indexing a folder and drops the entries found in it, unless another indexed
+    /// folder still covers them
+    pub async fn remove_folder(&self, folder: &str) {
+        let mut data = self.data.write().await;
+        data.folders.retain(|f| f != folder);
+        let remaining = data.folders.clone();
+        data.entries.retain(|e| {
+            let path = Path::new(&e.path);
+            e.source != "folder"
+                || !path.starts_with(folder)
+                || remaining.iter().any(|f| path.starts_with(f))
+        });
+        self.persist(&data);
+    }
+
+    /// Brings an existing file into the library. A hard link is used when the file is on the
+    /// same volume, otherwise it is copied. Season/episode are parsed from the file name and
+    /// the show looked up on TMDB when they aren't supplied.
+    pub async fn import_file(
+        &self,
+        path: String,
+        tmdb_id: Option<u32>,
+        season: Option<u32>,
+        episode: Option<u32>,
+        resolver: &ImdbResolver,
+    ) -> Result<LibraryEntry, String> {
+        let source = PathBuf::from(&path);
+        if !source.is_file() {
+            return Err(format!("File not found: {}", path));
+        }
+        if !is_video_file(&source) {
+            return Err("Only video files can be imported".to_string());
+        }
+
+        // Parsed from the original name, which a suffix in the library would throw off
+        let source_name = source.file_name().and_then(|n| n.to_str()).unwrap_or("unknown").to_string();
+        let library_dir = self.library_dir.clone();
+        let dest = tokio::task::spawn_blocking(move || -> Result<PathBuf, String> {
+            fs::create_dir_all(&library_dir).map_err(|e| format!("Failed to import file: {}", e))?;
+            let dest = import_destination(&library_dir, &source)?;
+            if !dest.exists() && fs::hard_link(&source, &dest).is_err() {
+                fs::copy(&source, &dest).map_err(|e| format!("Failed to import file: {}", e))?;
+            }
+            Ok(dest)
+        })
+        .await
+        .map_err(|e| e.to_string())??;
+        let file_name = dest.file_name().and_then(|n| n.to_str()).unwrap_or("unknown").to_string();
+
+        let (parsed_season, parsed_episode) = parse_season_episode(&source_name);
+        let episode = episode.or(parsed_episode);
+        let season = if episode.is_some() { season.or(parsed_season).or(Some(1)) } else { None };
+        let media_type = if episode.is_some() { "tv" } else { "movie" };
+        let (title, year) = parse_release_title(&source_name);
+
+        let tmdb_match = match tmdb_id {
+            Some(_) => None,
+            None if title.is_empty() => None,
+            None => resolver.search_tmdb(&title, media_type, year).await,
+        };
+
+        let entry = LibraryEntry {
+            id: library_id(&dest),
+            path: dest.to_string_lossy().to_string(),
+            file_name,
+            size: fs::metadata(&dest).map(|m| m.len()).unwrap_or(0),
+            tmdb_id: tmdb_id.or(tmdb_match.as_ref().map(|m| m.tmdb_id)),
+            media_type: Some(media_type.to_string()),
+            title: tmdb_match.map(|m| m.title).or(Some(title).filter(|t| !t.is_empty())),
+            season,
+            episode,
+            source: "import".to_string(),
+            added_at: SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0),
+        };
+
+        tracing::info!("imported {} into library", entry.path);
+        let mut data = self.data.write().await;
+        data.entries.retain(|e| e.id != entry.id);
+        data.entries.push(entry.clone());
+        self.persist(&data);
+        Ok(entry)
+    }
+
+    /// Hard links completed episodes into `target_root` following `template` and adds them to
+    /// the library. Falls back to copying when the target is on another volume. The original
+    /// stays in place so the torrent keeps seeding; each download is organized once.
+    pub async fn organize_downloads(
+        &self,
+        completed_downloads: Vec<PathBuf>,
+        template: &str,
+        target_root: Option<PathBuf>,
+        resolver: &ImdbResolver,
+    ) -> Vec<LibraryEntry> {
+        let target_root = target_root.unwrap_or_else(|| self.library_dir.clone());
+        let already_organized = self.data.read().await.organized.clone();
+        let mut organized = Vec::new();
+
+        for source in completed_downloads {
+            let source_str = source.to_string_lossy().to_string();
+            if !is_video_file(&source) || already_organized.contains(&source_str) {
+                continue;
+            }
+
+            let file_name = source.file_name().and_then(|n| n.to_str()).unwrap_or("unknown").to_string();
+            let (season, episode) = parse_season_episode(&file_name);
+            // Movies have no layout to follow
+            let Some(episode) = episode else { continue };
+            let season = season.unwrap_or(1);
+
+            let (title, year) = parse_release_title(&file_name);
+            if title.is_empty() {
+                continue;
+            }
+            let tmdb_match = resolver.search_tmdb(&title, "tv", year).await;
+            let show = tmdb_match.as_ref().map(|m| m.title.clone()).unwrap_or_else(|| title.clone());
+
+            let mut dest = target_root.join(render_organize_template(template, &show, season, episode));
+            if let Some(ext) = source.extension() {
+                let mut name = dest.file_name().map(|n| n.to_os_string()).unwrap_or_default();
+                name.push(".");
+                name.push(ext);
+                dest.set_file_name(name);
+            }
+
+            let link_source = source.clone();
+            let link_dest = dest.clone();
+            let linked = tokio::task::spawn_blocking(move || -> std::io::Result<()> {
+                if let Some(parent) = link_dest.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                if link_dest.exists() {
+                    return Ok(());
+                }
+                if fs::hard_link(&link_source, &link_dest).is_err() {
+                    fs::copy(&link_source, &link_dest)?;
+                }
+                Ok(())
+            }).await;
+
+            match linked {
+                Ok(Ok(())) => {}
+                Ok(Err(e)) => {
+                    tracing::error!("failed to organize {:?} into {:?}: {}", source, dest, e);
+                    continue;
+                }
+                Err(e) => {
+                    tracing::error!("organize task failed: {}", e);
+                    continue;
+                }
+            }
+
+            tracing::info!("organized {:?} -> {:?}", source, dest);
+            organized.push((source_str, LibraryEntry {
+                id: library_id(&dest),
+                path: dest.to_string_lossy().to_string(),
+                file_name: dest.file_name().and_then(|n| n.to_str()).unwrap_or("unknown").to_string(),
+                size: fs::metadata(&dest).map(|m| m.len()).unwrap_or(0),
+                tmdb_id: tmdb_match.as_ref().map(|m| m.tmdb_id),
+                media_type: Some("tv".to_string()),
+                title: Some(show),
+                season: Some(season),
+                episode: Some(episode),
+                source: "organized".to_string(),
+                added_at: SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0),
+            }));
+        }
+
+        if organized.is_empty() {
+            return Vec::new();
+        }
+
+        let mut data = self.data.write().await;
+        let mut entries = Vec::new();
+        for (source, entry) in organized {
+            data.organized.push(source);
+            data.entries.retain(|e| e.id != entry.id);
+            data.entries.push(entry.clone());
+            entries.push(entry);
+        }
+        self.persist(&data);
+        entries
+    }
+
+    /// Rebuilds the index from completed downloads and the user's folders. Entries whose
+    /// file is gone are dropped; existing matches are kept so only new files hit TMDB.
+    pub async fn rescan(&self, completed_downloads: Vec<PathBuf>, resolver: &ImdbResolver) -> Vec<LibraryEntry> {
+        let (existing, folders, organized) = {
+            let data = self.data.read().await;
+            (data.entries.clone(), data.folders.clone(), data.organized.clone())
+        };
+
+        let found: Vec<(PathBuf, &'static str)> = tokio::task::spawn_blocking(move || {
+            let mut found: Vec<(PathBuf, &'static str)> = completed_downloads
+                .into_iter()
+                // Organized downloads are already listed under their linked path
+                .filter(|p| is_video_file(p) && !organized.contains(&p.to_string_lossy().to_string()))
+                .map(|p| (p, "download"))
+                .collect();
+            for folder in folders {
+                let mut files = Vec::new();
+                collect_video_files(Path::new(&folder), &mut files);
+                found.extend(files.into_iter().map(|p| (p, "folder")));
+            }
+            found
+        }).await.unwrap_or_default();
+
+        let mut entries: Vec<LibraryEntry> = existing
+            .into_iter()
+            .filter(|e| Path::new(&e.path).exists())
+            .collect();
+        let mut matches: HashMap<(String, Option<u32>, &str), Option<TmdbMatch>> = HashMap::new();
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+
+        for (path, source) in found {
+            let id = library_id(&path);
+            if entries.iter().any(|e| e.id == id) {
+                continue;
+            }
+
+            let file_name = path.file_name().and_then(|n| n.to_str()).unwrap_or("unknown").to_string();
+            let (season, episode) = parse_season_episode(&file_name);
+            let (title, year) = parse_release_title(&file_name);
+            let media_type = if episode.is_some() { "tv" } else { "movie" };
+
+            // Episodes of one show share a title, so look each title up once per scan
+            let tmdb_match = if title.is_empty() {
+                None
+            } else {
+                let key = (title.clone(), year, media_type);
+                match matches.get(&key) {
+                    Some(m) => m.clone(),
+                    None => {
+                        let m = resolver.search_tmdb(&title, media_type, year).await;
+                        matches.insert(key, m.clone());
+                        m
+                    }
+                }
+            };
+
+            entries.push(LibraryEntry {
+                id,
+                path: path.to_string_lossy().to_string(),
+                file_name,
+                size: fs::metadata(&path).map(|m| m.len()).unwrap_or(0),
+                tmdb_id: tmdb_match.as_ref().map(|m| m.tmdb_id),
+                media_type: Some(media_type.to_string()),
+                title: tmdb_match.map(|m| m.title).or(Some(title).filter(|t| !t.is_empty())),
+                season: if episode.is_some() { season.or(Some(1)) } else { None },
+                episode,
+                source: source.to_string(),
+                added_at: now,
+            });
+        }
+
+        tracing::info!("library scan found {} entries", entries.len());
+        let mut data = self.data.write().await;
+        data.entries = entries.clone();
+        self.persist(&data);
+        entries
+    }
+}
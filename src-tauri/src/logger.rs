@@ -1,12 +1,20 @@
+use std::collections::VecDeque;
 use std::fs::{self, File, OpenOptions};
 use std::io::Write;
 use std::path::PathBuf;
-use std::sync::{Mutex, Arc};
+use std::sync::{Mutex, Arc, OnceLock};
 use std::thread;
 use chrono::Local;
 use tauri::{AppHandle, Manager};
 
 const MAX_LOG_LENGTH: usize = 1000;
+// Log lines kept in memory for crash reports
+const RECENT_LINES_CAPACITY: usize = 200;
+
+// The backend log file, for code that has no handle on the managed Logger
+static BACKEND_LOG: OnceLock<Arc<Mutex<Option<File>>>> = OnceLock::new();
+
+static RECENT_LINES: Mutex<VecDeque<String>> = Mutex::new(VecDeque::new());
 
 pub struct Logger {
     current_log_file: Mutex<Option<File>>,
@@ -33,6 +41,7 @@ impl Logger {
         
         logger.start_new_session()?;
         logger.start_backend_session()?;
+        let _ = BACKEND_LOG.set(Arc::clone(&logger.backend_log_file));
         logger.start_capturing_output();
         logger.cleanup_old_logs()?;
         
@@ -101,13 +110,10 @@ impl Logger {
                     if let Ok(mut file_guard) = stdout_log.lock() {
                         if let Some(file) = file_guard.as_mut() {
                             for line in lines {
-                                let truncated = if line.len() > MAX_LOG_LENGTH {
-                                    format!("{}... (truncated {} chars)", &line[..MAX_LOG_LENGTH], line.len() - MAX_LOG_LENGTH)
-                                } else {
-                                    line.to_string()
-                                };
-                                let log_line = format!("[{}] [STDOUT] {}\n", timestamp, truncated);
-                                let _ = file.write_all(log_line.as_bytes());
+                                let truncated = truncate_line(line);
+                                let log_line = format!("[{}] [STDOUT] {}", timestamp, truncated);
+                                let _ = file.write_all(format!("{}\n", log_line).as_bytes());
+                                remember_line(log_line);
                             }
                             let _ = file.flush();
                         }
@@ -140,13 +146,10 @@ impl Logger {
                     if let Ok(mut file_guard) = backend_log.lock() {
                         if let Some(file) = file_guard.as_mut() {
                             for line in lines {
-                                let truncated = if line.len() > MAX_LOG_LENGTH {
-                                    format!("{}... (truncated {} chars)", &line[..MAX_LOG_LENGTH], line.len() - MAX_LOG_LENGTH)
-                                } else {
-                                    line.to_string()
-                                };
-                                let log_line = format!("[{}] [STDERR] {}\n", timestamp, truncated);
-                                let _ = file.write_all(log_line.as_bytes());
+                                let truncated = truncate_line(line);
+                                let log_line = format!("[{}] [STDERR] {}", timestamp, truncated);
+                                let _ = file.write_all(format!("{}\n", log_line).as_bytes());
+                                remember_line(log_line);
                             }
                             let _ = file.flush();
                         }
@@ -215,38 +218,120 @@ impl Logger {
     }
     
     pub fn log(&self, level: &str, message: &str) {
-        let timestamp = Local::now().format("%Y-%m-%d %H:%M:%S%.3f");
-        let truncated_msg = if message.len() > MAX_LOG_LENGTH {
-            format!("{}... (truncated {} chars)", &message[..MAX_LOG_LENGTH], message.len() - MAX_LOG_LENGTH)
-        } else {
-            message.to_string()
-        };
-        let log_line = format!("[{}] [{}] {}\n", timestamp, level, truncated_msg);
-        
-        if let Ok(mut file_guard) = self.current_log_file.lock() {
-            if let Some(file) = file_guard.as_mut() {
-                let _ = file.write_all(log_line.as_bytes());
-                let _ = file.flush();
-            }
-        }
+        write_log_line(&self.current_log_file, level, message);
     }
     
     #[allow(dead_code)]
     pub fn log_backend(&self, level: &str, message: &str) {
-        let timestamp = Local::now().format("%Y-%m-%d %H:%M:%S%.3f");
-        let truncated_msg = if message.len() > MAX_LOG_LENGTH {
-            format!("{}... (truncated {} chars)", &message[..MAX_LOG_LENGTH], message.len() - MAX_LOG_LENGTH)
-        } else {
-            message.to_string()
-        };
-        let log_line = format!("[{}] [{}] {}\n", timestamp, level, truncated_msg);
-        
-        if let Ok(mut file_guard) = self.backend_log_file.lock() {
-            if let Some(file) = file_guard.as_mut() {
-                let _ = file.write_all(log_line.as_bytes());
-                let _ = file.flush();
-            }
+        write_log_line(&self.backend_log_file, level, message);
+    }
+}
+
+fn write_log_line(log_file: &Mutex<Option<File>>, level: &str, message: &str) {
+    let timestamp = Local::now().format("%Y-%m-%d %H:%M:%S%.3f");
+    let truncated_msg = truncate_line(message);
+    let log_line = format!("[{}] [{}] {}", timestamp, level, truncated_msg);
+    
+    if let Ok(mut file_guard) = log_file.lock() {
+        if let Some(file) = file_guard.as_mut() {
+            let _ = file.write_all(format!("{}\n", log_line).as_bytes());
+            let _ = file.flush();
+        }
+    }
+    remember_line(log_line);
+}
+
+// Cuts a line down to MAX_LOG_LENGTH bytes, backing off to the start of a character
+// that would otherwise be split
+fn truncate_line(line: &str) -> String {
+    if line.len() <= MAX_LOG_LENGTH {
+        return line.to_string();
+    }
+    let mut end = MAX_LOG_LENGTH;
+    while !line.is_char_boundary(end) {
+        end -= 1;
+    }
+    format!("{}... (truncated {} bytes)", &line[..end], line.len() - end)
+}
+
+fn remember_line(line: String) {
+    if let Ok(mut lines) = RECENT_LINES.lock() {
+        if lines.len() >= RECENT_LINES_CAPACITY {
+            lines.pop_front();
+        }
+        lines.push_back(line);
+    }
+}
+
+/// The last lines written to either log, oldest first. Doesn't block, since the panic
+/// hook calls it and the panicking thread may hold the lock.
+pub fn recent_lines() -> Vec<String> {
+    match RECENT_LINES.try_lock() {
+        Ok(lines) => lines.iter().cloned().collect(),
+        Err(_) => Vec::new(),
+    }
+}
+
+/// Writes to the backend log of the current session. Before the Logger is set up this
+/// falls back to stderr so nothing is lost during startup.
+pub fn backend(level: &str, message: &str) {
+    match BACKEND_LOG.get() {
+        Some(log_file) => write_log_line(log_file, level, message),
+        None => eprintln!("[{}] {}", level, message),
+    }
+}
+
+/// Sends `tracing` events to the backend log. The app's own events are kept from INFO up,
+/// dependencies' (librqbit logs every peer) only from WARN. Spans aren't tracked.
+struct BackendSubscriber;
+
+impl tracing::Subscriber for BackendSubscriber {
+    fn enabled(&self, metadata: &tracing::Metadata<'_>) -> bool {
+        let own = metadata.target().starts_with(env!("CARGO_CRATE_NAME"));
+        *metadata.level() <= if own { tracing::Level::INFO } else { tracing::Level::WARN }
+    }
+
+    fn new_span(&self, _: &tracing::span::Attributes<'_>) -> tracing::span::Id {
+        tracing::span::Id::from_u64(1)
+    }
+
+    fn record(&self, _: &tracing::span::Id, _: &tracing::span::Record<'_>) {}
+
+    fn record_follows_from(&self, _: &tracing::span::Id, _: &tracing::span::Id) {}
+
+    fn event(&self, event: &tracing::Event<'_>) {
+        let mut message = EventMessage::default();
+        event.record(&mut message);
+        backend(event.metadata().level().as_str(), &message.0);
+    }
+
+    fn enter(&self, _: &tracing::span::Id) {}
+
+    fn exit(&self, _: &tracing::span::Id) {}
+}
+
+// An event's message followed by its other fields as name=value
+#[derive(Default)]
+struct EventMessage(String);
+
+impl tracing::field::Visit for EventMessage {
+    fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn std::fmt::Debug) {
+        use std::fmt::Write as _;
+        if !self.0.is_empty() {
+            self.0.push(' ');
         }
+        let _ = match field.name() {
+            "message" => write!(self.0, "{:?}", value),
+            name => write!(self.0, "{}={:?}", name, value),
+        };
+    }
+}
+
+/// Routes `tracing` output to the backend log. Called first thing in main, so events from
+/// before the Logger exists go to stderr instead.
+pub fn install_tracing() {
+    if tracing::subscriber::set_global_default(BackendSubscriber).is_err() {
+        eprintln!("a tracing subscriber was already installed");
     }
 }
 
@@ -262,3 +347,24 @@ macro_rules! log_backend {
         $logger.log_backend($level, &format!($($arg)*))
     };
 }
+
+#[macro_export]
+macro_rules! log_info {
+    ($($arg:tt)*) => {
+        $crate::logger::backend("INFO", &format!($($arg)*))
+    };
+}
+
+#[macro_export]
+macro_rules! log_warn {
+    ($($arg:tt)*) => {
+        $crate::logger::backend("WARN", &format!($($arg)*))
+    };
+}
+
+#[macro_export]
+macro_rules! log_error {
+    ($($arg:tt)*) => {
+        $crate::logger::backend("ERROR", &format!($($arg)*))
+    };
+}
@@ -12,10 +12,31 @@ mod track_preferences;
 mod settings;
 mod logger;
 mod cache_metadata;
+mod imdb_resolver;
+mod id_mapping;
+mod power_state;
+mod watch_folder;
+mod library;
+mod download_hooks;
+mod notifications;
+mod scrobble;
+mod backup;
+mod parental;
+mod secrets;
+mod provider_config;
+mod web_seed;
+mod stream_log;
+mod stream_session;
+mod ffmpeg_tools;
+mod crash_report;
+mod updater;
+mod i18n;
+mod analytics;
 
 use search::{nyaa::NyaaProvider, limetorrents::LimeTorrentsProvider, piratebay::PirateBayProvider, 
              SearchProvider};
 use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
 #[cfg(target_os = "windows")]
 use std::os::windows::process::CommandExt;
 use tauri::{Manager, State};
@@ -28,160 +49,196 @@ use track_preferences::TrackPreferencesManager;
 use settings::{SettingsManager, Settings};
 use logger::Logger;
 use cache_metadata::CacheMetadataManager;
-use ffmpeg_sidecar::download::{check_latest_version, download_ffmpeg_package, unpack_ffmpeg};
+use imdb_resolver::ImdbResolver;
+use id_mapping::IdMappingManager;
+use scrobble::ScrobbleManager;
+use backup::BackupManager;
+use provider_config::ProviderConfigManager;
+use watch_folder::WatchFolder;
+use library::{LibraryManager, LibraryEntry};
+
+// Metadata extraction needs ffprobe as much as transcoding needs ffmpeg, so an install
+// only counts once both are in the sidecar folder
+fn verify_sidecar_binaries(sidecar_dir: &std::path::Path) -> Result<(), String> {
+    for name in ["ffmpeg", "ffprobe"] {
+        let path = sidecar_dir.join(format!("{}{}", name, std::env::consts::EXE_SUFFIX));
+        if !path.exists() {
+            tracing::error!("{} not found after unpacking, expected at {:?}", name, path);
+            return Err(format!("{} installation failed - binary not found after unpacking at {:?}", name, path));
+        }
+    }
+    Ok(())
+}
+
+#[tauri::command]
+fn check_ffmpeg() -> bool {
+    ffmpeg_tools::is_ffmpeg_installed()
+}
+
+#[tauri::command]
+fn check_ffprobe() -> bool {
+    ffmpeg_tools::is_ffprobe_installed()
+}
 
-fn is_ffmpeg_installed() -> bool {
+#[tauri::command]
+async fn get_ffmpeg_capabilities() -> Result<ffmpeg_tools::FfmpegCapabilities, String> {
+    tokio::task::spawn_blocking(ffmpeg_tools::capabilities)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+// Windows builds that ship both ffmpeg.exe and ffprobe.exe under bin/, tried in order
+#[cfg(target_os = "windows")]
+const FFMPEG_MIRRORS: &[&str] = &[
+    "https://github.com/BtbN/FFmpeg-Builds/releases/download/latest/ffmpeg-master-latest-win64-gpl.zip",
+    "https://www.gyan.dev/ffmpeg/builds/ffmpeg-release-essentials.zip",
+];
+// Attempts per mirror before moving on; each one resumes where the last stopped
+const FFMPEG_DOWNLOAD_ATTEMPTS: u32 = 3;
+// A mirror that sends nothing for this long has stalled, and the attempt is retried
+const FFMPEG_READ_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(30);
+
+static FFMPEG_INSTALL_CANCELLED: AtomicBool = AtomicBool::new(false);
+// Claimed with compare_exchange so a startup bootstrap and an install from the UI can't overlap
+static FFMPEG_INSTALLING: AtomicBool = AtomicBool::new(false);
+
+fn ffmpeg_mirrors() -> Result<Vec<String>, String> {
     #[cfg(target_os = "windows")]
-    let system_check = std::process::Command::new("where")
-        .arg("ffmpeg")
-        .creation_flags(0x08000000)
-        .output()
-        .map(|output| output.status.success())
-        .unwrap_or(false);
-    
-    #[cfg(not(target_os = "windows"))]
-    let system_check = std::process::Command::new("which")
-        .arg("ffmpeg")
-        .output()
-        .map(|output| output.status.success())
-        .unwrap_or(false);
-    
-    if system_check {
-        println!("ffmpeg found in system PATH");
-        return true;
+    {
+        Ok(FFMPEG_MIRRORS.iter().map(|url| url.to_string()).collect())
     }
-    
-    let sidecar_exists = ffmpeg_sidecar::paths::ffmpeg_path().exists();
-    if sidecar_exists {
-        println!("ffmpeg found in sidecar directory");
+
+    #[cfg(not(target_os = "windows"))]
+    {
+        let url = ffmpeg_sidecar::download::ffmpeg_download_url().map_err(|e| e.to_string())?;
+        Ok(vec![url.to_string()])
     }
-    
-    sidecar_exists
 }
 
-#[allow(dead_code)]
-async fn ensure_ffmpeg_installed() -> Result<(), Box<dyn std::error::Error>> {
-    if is_ffmpeg_installed() {
-        println!("ffmpeg is already available");
-        return Ok(());
+// "ffmpeg-download.zip" or "ffmpeg-download.tar.xz", after the archive the url points to;
+// unpacking goes by the extension
+fn ffmpeg_archive_name(url: &str) -> &'static str {
+    if url.ends_with(".tar.xz") {
+        "ffmpeg-download.tar.xz"
+    } else {
+        "ffmpeg-download.zip"
     }
-    
-    println!("============================================");
-    println!("ffmpeg not found, downloading and installing...");
-    println!("this may take a few minutes (approximately 80MB download)");
-    println!("============================================");
-    
-    tokio::task::spawn_blocking(|| {
-        let sidecar_dir = ffmpeg_sidecar::paths::sidecar_dir()
-            .map_err(|e| {
-                eprintln!("failed to get sidecar directory: {}", e);
-                format!("failed to get sidecar directory: {}", e)
-            })?;
-        
-        println!("sidecar directory: {:?}", sidecar_dir);
-        
-        std::fs::create_dir_all(&sidecar_dir)
-            .map_err(|e| {
-                eprintln!("failed to create sidecar directory: {}", e);
-                format!("failed to create sidecar directory: {}", e)
-            })?;
-        
-        // Download ffmpeg
-        println!("checking latest version...");
-        let download_url = check_latest_version()
-            .map_err(|e| {
-                eprintln!("failed to check latest version: {}", e);
-                format!("failed to check latest version: {}", e)
-            })?;
-        
-        println!("download URL: {}", download_url);
-        
-        let destination = sidecar_dir.join("ffmpeg-download.zip");
-        println!("downloading to: {:?}", destination);
-        
-        download_ffmpeg_package(&download_url, &destination)
-            .map_err(|e| {
-                eprintln!("failed to download ffmpeg package: {}", e);
-                format!("failed to download ffmpeg package: {}", e)
-            })?;
-        
-        println!("download complete, unpacking...");
-        
-        unpack_ffmpeg(&destination, &sidecar_dir)
-            .map_err(|e| {
-                eprintln!("failed to unpack ffmpeg: {}", e);
-                // Clean up partial download
-                let _ = std::fs::remove_file(&destination);
-                format!("failed to unpack ffmpeg: {}", e)
-            })?;
-        
-        let _ = std::fs::remove_file(&destination);
-        
-        println!("ffmpeg installed successfully to {:?}", sidecar_dir);
-        
-        let ffmpeg_exe = ffmpeg_sidecar::paths::ffmpeg_path();
-        if ffmpeg_exe.exists() {
-            println!("ffmpeg installation verified at: {:?}", ffmpeg_exe);
-            println!("============================================");
-            Ok(())
-        } else {
-            eprintln!("ffmpeg installation failed - binary not found after unpacking");
-            eprintln!("expected location: {:?}", ffmpeg_exe);
-            Err(format!("ffmpeg installation failed - binary not found after unpacking at {:?}", ffmpeg_exe))
+}
+
+// Mirror builds keep ffmpeg and ffprobe under bin/ in a zip
+#[cfg(target_os = "windows")]
+fn unpack_ffmpeg_archive(archive_path: &std::path::Path, sidecar_dir: &std::path::Path) -> Result<(), String> {
+    use std::fs::File;
+
+    let file = File::open(archive_path).map_err(|e| e.to_string())?;
+    let mut archive = zip::ZipArchive::new(file).map_err(|e| e.to_string())?;
+
+    for i in 0..archive.len() {
+        let mut file = archive.by_index(i).map_err(|e| e.to_string())?;
+        let name = file.name().to_string();
+        if name.ends_with("bin/ffmpeg.exe") || name.ends_with("bin/ffprobe.exe") {
+            let file_name = std::path::Path::new(&name).file_name().unwrap();
+            let out_path = sidecar_dir.join(file_name);
+
+            tracing::info!("Extracting {:?} to {:?}", name, out_path);
+
+            let mut outfile = File::create(&out_path).map_err(|e| e.to_string())?;
+            std::io::copy(&mut file, &mut outfile).map_err(|e| e.to_string())?;
         }
-    })
-    .await
-    .map_err(|e| -> Box<dyn std::error::Error> { Box::new(std::io::Error::new(std::io::ErrorKind::Other, format!("ffmpeg installation task panicked: {}", e))) })?
-    .map_err(|e| -> Box<dyn std::error::Error> { e.into() })?;
-    
+    }
     Ok(())
 }
 
-#[tauri::command]
-fn check_ffmpeg() -> bool {
-    is_ffmpeg_installed()
+// ffmpeg-sidecar's own builds: zips on macOS, tar.xz on Linux, which its unpacker
+// handles along with the executable bits
+#[cfg(not(target_os = "windows"))]
+fn unpack_ffmpeg_archive(archive_path: &std::path::Path, sidecar_dir: &std::path::Path) -> Result<(), String> {
+    ffmpeg_sidecar::download::unpack_ffmpeg(&archive_path.to_path_buf(), sidecar_dir).map_err(|e| e.to_string())
 }
 
-#[tauri::command]
-async fn install_ffmpeg(app: tauri::AppHandle) -> Result<(), String> {
+enum FfmpegDownloadError {
+    Cancelled,
+    Failed(String),
+}
+
+/// Downloads `url` to `destination` through a .part file. A partial file left by an
+/// earlier attempt from the same url is resumed with a range request guarded by the
+/// ETag it was started with; one from another mirror, or a file the server has since
+/// replaced, starts over.
+async fn download_ffmpeg_archive(app: &tauri::AppHandle, url: &str, destination: &std::path::Path) -> Result<(), FfmpegDownloadError> {
     use tauri::Emitter;
     use std::io::Write;
-    use std::fs::File;
-    
-    if is_ffmpeg_installed() {
-        return Ok(());
+    use FfmpegDownloadError::{Cancelled, Failed};
+
+    let name = destination.file_name().unwrap_or_default().to_string_lossy().to_string();
+    let partial = destination.with_file_name(format!("{}.part", name));
+    let source = destination.with_file_name(format!("{}.url", name));
+    let validator = destination.with_file_name(format!("{}.etag", name));
+    if std::fs::read_to_string(&source).ok().as_deref() != Some(url) {
+        let _ = std::fs::remove_file(&partial);
+        let _ = std::fs::remove_file(&validator);
+        std::fs::write(&source, url).map_err(|e| Failed(e.to_string()))?;
+    }
+    let etag = std::fs::read_to_string(&validator).ok();
+    // Without an ETag there's no telling whether the partial file is still the same build
+    let existing = match etag {
+        Some(_) => std::fs::metadata(&partial).map(|m| m.len()).unwrap_or(0),
+        None => 0,
+    };
+    if FFMPEG_INSTALL_CANCELLED.load(Ordering::SeqCst) {
+        return Err(Cancelled);
     }
 
-    let sidecar_dir = ffmpeg_sidecar::paths::sidecar_dir()
-        .map_err(|e| e.to_string())?;
-    
-    std::fs::create_dir_all(&sidecar_dir)
-        .map_err(|e| e.to_string())?;
-    #[cfg(target_os = "windows")]
-    let download_url = "https://github.com/BtbN/FFmpeg-Builds/releases/download/latest/ffmpeg-master-latest-win64-gpl.zip".to_string();
-    
-    #[cfg(not(target_os = "windows"))]
-    let download_url = check_latest_version()
-        .map_err(|e| e.to_string())?;
-    
-    let destination = sidecar_dir.join("ffmpeg-download.zip");
-    
     let client = reqwest::Client::new();
-    let mut response = client.get(&download_url)
-        .header("User-Agent", "Magnolia/1.0")
-        .send()
-        .await
-        .map_err(|e| e.to_string())?;
-        
-    let total_size = response.content_length().unwrap_or(0);
-    println!("Download started. Total size: {}", total_size);
+    let mut request = client.get(url).header("User-Agent", "Magnolia/1.0");
+    if let (true, Some(etag)) = (existing > 0, &etag) {
+        // A server whose file changed answers with the whole new file instead
+        request = request
+            .header(reqwest::header::RANGE, format!("bytes={}-", existing))
+            .header(reqwest::header::IF_RANGE, etag.as_str());
+    }
+    let mut response = request.send().await.map_err(|e| Failed(e.to_string()))?;
+    if response.status() == reqwest::StatusCode::RANGE_NOT_SATISFIABLE {
+        // The file changed on the server since the partial download
+        let _ = std::fs::remove_file(&partial);
+        return Err(Failed("partial download no longer matches, starting over".to_string()));
+    }
+    response = response.error_for_status().map_err(|e| Failed(e.to_string()))?;
+
+    // A server that ignores the range, or whose file changed, sends the whole file again
+    let resumed = existing > 0 && response.status() == reqwest::StatusCode::PARTIAL_CONTENT;
+    if !resumed {
+        match response.headers().get(reqwest::header::ETAG).and_then(|v| v.to_str().ok()) {
+            Some(etag) => std::fs::write(&validator, etag).map_err(|e| Failed(e.to_string()))?,
+            None => { let _ = std::fs::remove_file(&validator); }
+        }
+    }
+    let mut downloaded: u64 = if resumed { existing } else { 0 };
+    let total_size = response.content_length().map(|len| len + downloaded).unwrap_or(0);
+    if resumed {
+        tracing::info!("Resuming ffmpeg download from {} at {} of {} bytes", url, existing, total_size);
+    } else {
+        tracing::info!("Download started from {}. Total size: {}", url, total_size);
+    }
 
-    let mut file = std::fs::File::create(&destination).map_err(|e| e.to_string())?;
-    let mut downloaded: u64 = 0;
+    let mut options = std::fs::OpenOptions::new();
+    if resumed {
+        options.append(true);
+    } else {
+        options.write(true).create(true).truncate(true);
+    }
+    let mut file = options.open(&partial).map_err(|e| Failed(e.to_string()))?;
     let mut last_emit_time = std::time::Instant::now();
-    
-    while let Some(chunk) = response.chunk().await.map_err(|e| e.to_string())? {
-        file.write_all(&chunk).map_err(|e| e.to_string())?;
+
+    loop {
+        let chunk = tokio::time::timeout(FFMPEG_READ_TIMEOUT, response.chunk()).await
+            .map_err(|_| Failed(format!("no data for {} seconds", FFMPEG_READ_TIMEOUT.as_secs())))?
+            .map_err(|e| Failed(e.to_string()))?;
+        let Some(chunk) = chunk else { break };
+        if FFMPEG_INSTALL_CANCELLED.load(Ordering::SeqCst) {
+            return Err(Cancelled);
+        }
+        file.write_all(&chunk).map_err(|e| Failed(e.to_string()))?;
         downloaded += chunk.len() as u64;
         
         // Emit progress at most every 100ms to avoid flooding the frontend
@@ -195,70 +252,204 @@ async fn install_ffmpeg(app: tauri::AppHandle) -> Result<(), String> {
             last_emit_time = std::time::Instant::now();
         }
     }
+    file.flush().map_err(|e| Failed(e.to_string()))?;
+    drop(file);
+
+    std::fs::rename(&partial, destination).map_err(|e| Failed(e.to_string()))?;
+    let _ = std::fs::remove_file(&source);
+    let _ = std::fs::remove_file(&validator);
+    Ok(())
+}
+
+/// Stops a running install_ffmpeg. The partial download is kept so the next install resumes it.
+#[tauri::command]
+fn cancel_ffmpeg_install() {
+    FFMPEG_INSTALL_CANCELLED.store(true, Ordering::SeqCst);
+}
+
+async fn download_and_unpack_ffmpeg(app: &tauri::AppHandle) -> Result<(), String> {
+    use tauri::Emitter;
     
-    let _ = app.emit("ffmpeg-install-progress", 100.0);
-    
-    println!("Unpacking ffmpeg and ffprobe...");
-    let file = File::open(&destination).map_err(|e| e.to_string())?;
-    let mut archive = zip::ZipArchive::new(file).map_err(|e| e.to_string())?;
+    if ffmpeg_tools::is_ffmpeg_installed() && ffmpeg_tools::is_ffprobe_installed() {
+        return Ok(());
+    }
+
+    let sidecar_dir = ffmpeg_sidecar::paths::sidecar_dir()
+        .map_err(|e| e.to_string())?;
     
-    for i in 0..archive.len() {
-        let mut file = archive.by_index(i).map_err(|e| e.to_string())?;
-        let name = file.name().to_string();
-        
-        let is_bin = if cfg!(target_os = "windows") {
-            name.ends_with("bin/ffmpeg.exe") || name.ends_with("bin/ffprobe.exe")
-        } else {
-            name.ends_with("bin/ffmpeg") || name.ends_with("bin/ffprobe")
-        };
-        
-        if is_bin {
-            let file_name = std::path::Path::new(&name).file_name().unwrap();
-            let out_path = sidecar_dir.join(file_name);
-            
-            println!("Extracting {:?} to {:?}", name, out_path);
-            
-            let mut outfile = File::create(&out_path).map_err(|e| e.to_string())?;
-            std::io::copy(&mut file, &mut outfile).map_err(|e| e.to_string())?;
-            
-            #[cfg(not(target_os = "windows"))]
-            {
-                use std::os::unix::fs::PermissionsExt;
-                let mut perms = std::fs::metadata(&out_path).map_err(|e| e.to_string())?.permissions();
-                perms.set_mode(0o755);
-                std::fs::set_permissions(&out_path, perms).map_err(|e| e.to_string())?;
+    std::fs::create_dir_all(&sidecar_dir)
+        .map_err(|e| e.to_string())?;
+    FFMPEG_INSTALL_CANCELLED.store(false, Ordering::SeqCst);
+
+    let mut last_error = String::from("no download mirrors");
+    let mut downloaded = None;
+    'mirrors: for url in ffmpeg_mirrors()? {
+        let destination = sidecar_dir.join(ffmpeg_archive_name(&url));
+        for attempt in 1..=FFMPEG_DOWNLOAD_ATTEMPTS {
+            match download_ffmpeg_archive(app, &url, &destination).await {
+                Ok(()) => {
+                    downloaded = Some(destination);
+                    break 'mirrors;
+                }
+                Err(FfmpegDownloadError::Cancelled) => {
+                    tracing::info!("ffmpeg download cancelled, keeping the partial file to resume later");
+                    return Err(i18n::t("error.ffmpeg_install_cancelled"));
+                }
+                Err(FfmpegDownloadError::Failed(e)) => {
+                    tracing::error!("ffmpeg download from {} failed (attempt {}/{}): {}", url, attempt, FFMPEG_DOWNLOAD_ATTEMPTS, e);
+                    last_error = e;
+                    tokio::time::sleep(std::time::Duration::from_secs(2)).await;
+                }
             }
         }
     }
+    let Some(destination) = downloaded else {
+        return Err(format!("failed to download ffmpeg: {}", last_error));
+    };
+    
+    let _ = app.emit("ffmpeg-install-progress", 100.0);
+    
+    tracing::info!("Unpacking ffmpeg and ffprobe...");
+    let unpacked = {
+        let (archive, dir) = (destination.clone(), sidecar_dir.clone());
+        tokio::task::spawn_blocking(move || unpack_ffmpeg_archive(&archive, &dir))
+            .await
+            .map_err(|e| e.to_string())?
+    };
+    if let Err(e) = unpacked {
+        let _ = std::fs::remove_file(&destination);
+        return Err(format!("failed to unpack ffmpeg: {}", e));
+    }
         
     let _ = std::fs::remove_file(&destination);
+    verify_sidecar_binaries(&sidecar_dir)?;
+
+    notifications::notify(
+        app,
+        notifications::NotificationKind::FfmpegInstalled,
+        &i18n::t("notification.ffmpeg_installed.title"),
+        &i18n::t("notification.ffmpeg_installed.body"),
+    ).await;
     
     Ok(())
 }
 
+// Installs ffmpeg and ffprobe, keeping the bootstrap state in step so onboarding follows along
+async fn run_ffmpeg_install(app: &tauri::AppHandle) -> Result<(), String> {
+    use ffmpeg_tools::BootstrapState;
+
+    if FFMPEG_INSTALLING.compare_exchange(false, true, Ordering::SeqCst, Ordering::SeqCst).is_err() {
+        return Err(i18n::t("error.ffmpeg_installing"));
+    }
+    ffmpeg_tools::set_bootstrap_state(app, BootstrapState::Downloading, None);
+    let result = download_and_unpack_ffmpeg(app).await;
+    FFMPEG_INSTALLING.store(false, Ordering::SeqCst);
+    match &result {
+        Ok(()) => ffmpeg_tools::set_bootstrap_state(app, BootstrapState::Ready, None),
+        Err(_) if FFMPEG_INSTALL_CANCELLED.load(Ordering::SeqCst) => {
+            ffmpeg_tools::set_bootstrap_state(app, BootstrapState::Cancelled, None)
+        }
+        Err(e) => ffmpeg_tools::set_bootstrap_state(app, BootstrapState::Failed, Some(e.clone())),
+    }
+    result
+}
+
+/// Startup step replacing the old build-time download: checks for ffmpeg and ffprobe and
+/// downloads them when missing, so a build made offline still ends up with both
+async fn bootstrap_ffmpeg(app: tauri::AppHandle) {
+    use ffmpeg_tools::BootstrapState;
+
+    ffmpeg_tools::set_bootstrap_state(&app, BootstrapState::Checking, None);
+    let installed = tokio::task::spawn_blocking(|| {
+        ffmpeg_tools::is_ffmpeg_installed() && ffmpeg_tools::is_ffprobe_installed()
+    })
+    .await
+    .unwrap_or(false);
+
+    if installed {
+        ffmpeg_tools::set_bootstrap_state(&app, BootstrapState::Ready, None);
+        return;
+    }
+    tracing::info!("ffmpeg or ffprobe missing, downloading...");
+    if let Err(e) = run_ffmpeg_install(&app).await {
+        tracing::error!("ffmpeg bootstrap failed: {}", e);
+    }
+}
+
+#[tauri::command]
+async fn install_ffmpeg(app: tauri::AppHandle) -> Result<(), String> {
+    run_ffmpeg_install(&app).await
+}
+
+#[tauri::command]
+fn get_ffmpeg_bootstrap() -> ffmpeg_tools::FfmpegBootstrap {
+    ffmpeg_tools::bootstrap_status()
+}
+
 #[tauri::command]
 async fn search_nyaa(query: String) -> Result<Vec<search::SearchResult>, String> {
     let provider = NyaaProvider::new();
-    provider.search(&query).await.map_err(|e| e.to_string())
+    provider.search(&search::normalize_query(&query, true)).await.map_err(|e| e.to_string())
+}
+
+#[derive(Clone, serde::Serialize)]
+struct SearchPartialPayload {
+    search_id: Option<String>,
+    provider: String,
+    // Cumulative deduplicated results so far, so the frontend can simply replace its list
+    results: Vec<search::SearchResult>,
+}
+
+#[derive(Clone, serde::Serialize)]
+struct SearchProviderDegradedPayload {
+    search_id: Option<String>,
+    provider: String,
+    reason: String,
+}
+
+#[derive(Clone, serde::Serialize)]
+struct StreamStalledPayload {
+    handle_id: usize,
+    file_index: usize,
+    stalled_seconds: u64,
+    auto_switch: bool,
+}
+
+#[derive(Clone, serde::Serialize)]
+struct DownloadsAutoPausedPayload {
+    paused: bool,
+    reason: Option<String>,
+}
+
+#[derive(Clone, serde::Serialize)]
+struct SearchCompletePayload {
+    search_id: Option<String>,
+    total: usize,
 }
 
 #[tauri::command]
 async fn search_nyaa_filtered(
+    app: tauri::AppHandle,
     query: String,
-    _season: Option<u32>,
+    season: Option<u32>,
     _episode: Option<u32>,
-    _is_movie: bool,
+    is_movie: bool,
     media_type: Option<String>, // "anime", "tv", "movie"
     tracker_preference: Option<Vec<String>>, // ["nyaa", "limetorrents", ...] or None for auto
     imdb_id: Option<String>, // For EZTV: pass IMDB ID like "tt1234567" or "1234567"
+    search_id: Option<String>, // Echoed back in search-results-partial/complete events
+    tmdb_id: Option<u32>, // Used to resolve imdb_id in the backend when it isn't passed
+    release_year: Option<u32>, // Movies: exact year matches are ranked first
+    group_releases: Option<bool>, // Overrides the group_search_results setting
+    min_similarity: Option<f64>, // 0-100; title-searched results matching the query less are dropped
+    imdb_resolver: State<'_, ImdbResolver>,
+    id_mapping: State<'_, IdMappingManager>,
+    settings_manager: State<'_, SettingsManager>,
+    provider_configs: State<'_, ProviderConfigManager>,
 ) -> Result<Vec<search::SearchResult>, String> {
-    println!("search_nyaa_filtered called with tracker_preference: {:?}, imdb_id: {:?}", tracker_preference, imdb_id);
-    
-    // Normalize query
-    let normalized_query = query
-        .replace("-", " ")
-        .replace(":", " ")
-        .replace("_", " ");
+    use tauri::Emitter;
+
+    tracing::info!("search_nyaa_filtered called with tracker_preference: {:?}, imdb_id: {:?}", tracker_preference, imdb_id);
     
     // Determine if this is auto mode
     let is_auto_mode = match &tracker_preference {
@@ -267,14 +458,74 @@ async fn search_nyaa_filtered(
     };
     
     let is_anime = media_type.as_deref() == Some("anime");
+    // Anime titles keep their romaji long vowels spelled out, as fansub releases write them
+    let normalized_query = search::normalize_query(&query, is_anime);
+    // Anime movies still go to Nyaa first, but with the movie wording stripped from the
+    // query, and fall back to the movie trackers rather than the TV ones
+    let is_anime_movie = is_anime && is_movie;
+    let tmdb_media_type = if is_movie { "movie" } else { "tv" };
+    
+    let settings = settings_manager.get().await;
+    let parental = settings.parental.clone();
+    if let Some(id) = tmdb_id {
+        parental::check_title(&parental, &imdb_resolver, id, tmdb_media_type).await?;
+    }
+    
+    // Anime goes to Nyaa first, so only resolve up front for trackers that use the IMDB ID
+    let mut imdb_id = imdb_id;
+    if imdb_id.is_none() && !is_anime {
+        if let Some(id) = tmdb_id {
+            imdb_id = imdb_resolver.resolve(id, tmdb_media_type, Some(&query), None).await;
+        }
+    }
+    // EZTV is TV-only; without an IMDB ID it falls back to scraping its title search
+    let mut use_eztv = imdb_id.is_some() || media_type.as_deref() == Some("tv");
     
-    let trackers: Vec<String> = if let Some(prefs) = tracker_preference {
+    // AniDB ids let AnimeTosho do exact lookups instead of fuzzy title matching
+    let anidb_ids: Vec<u32> = match (is_anime, tmdb_id) {
+        (true, Some(id)) => id_mapping.lookup_tmdb(id).await
+            .into_iter()
+            .filter_map(|ids| ids.anidb_id)
+            .collect(),
+        _ => Vec::new(),
+    };
+    if !anidb_ids.is_empty() {
+        tracing::info!("Mapped TMDB {:?} to AniDB ids {:?}", tmdb_id, anidb_ids);
+    }
+    let anime_trackers = || {
+        let mut t = vec!["nyaa".to_string()];
+        if !anidb_ids.is_empty() {
+            t.push("animetosho".to_string());
+        }
+        t
+    };
+    
+    // Torznab indexers (configured or synced from Prowlarr), keyed "torznab:{id}"
+    let indexer_media_type = if is_movie { "movie" } else { media_type.as_deref().unwrap_or("tv") };
+    let indexers: std::collections::HashMap<String, TorznabIndexer> = provider_configs.list().await
+        .into_iter()
+        .filter(|p| p.supports(indexer_media_type))
+        .map(|p| {
+            let api_key = p.api_key.clone().or_else(|| match p.synced_from.as_deref() {
+                Some("prowlarr") => settings.prowlarr_api_key.clone(),
+                _ => None,
+            });
+            (format!("torznab:{}", p.id), TorznabIndexer {
+                categories: p.categories_for(indexer_media_type),
+                name: p.name,
+                url: p.torznab_url.unwrap_or_default(),
+                api_key,
+            })
+        })
+        .collect();
+    
+    let mut trackers: Vec<String> = if let Some(prefs) = tracker_preference {
         if prefs.is_empty() {
             match media_type.as_deref() {
-                Some("anime") => vec!["nyaa".to_string()],
+                Some("anime") => anime_trackers(),
                 _ => {
                     let mut t = vec!["limetorrents".to_string(), "thepiratebay".to_string()];
-                    if imdb_id.is_some() {
+                    if use_eztv {
                         t.push("eztv".to_string());
                     }
                     t
@@ -286,10 +537,10 @@ async fn search_nyaa_filtered(
     } else {
         // null/undefined means auto mode
         match media_type.as_deref() {
-            Some("anime") => vec!["nyaa".to_string()],
+            Some("anime") => anime_trackers(),
             _ => {
                 let mut t = vec!["limetorrents".to_string(), "thepiratebay".to_string()];
-                if imdb_id.is_some() {
+                if use_eztv {
                     t.push("eztv".to_string());
                 }
                 t
@@ -297,32 +548,151 @@ async fn search_nyaa_filtered(
         }
     };
     
-    println!("Using trackers: {:?}", trackers);
+    // Providers described by definition files in the app data folder, keyed "custom:{id}"
+    let definitions_dir = app.path().app_data_dir().map_err(|e| e.to_string())?.join(PROVIDER_DEFINITIONS_DIR);
+    let definitions: std::collections::HashMap<String, search::definition::ProviderDefinition> =
+        search::definition::load_definitions(&definitions_dir)
+            .into_iter()
+            .filter(|d| d.supports(indexer_media_type))
+            .map(|d| (format!("custom:{}", d.id), d))
+            .collect();
+    
+    if is_auto_mode {
+        trackers.extend(indexers.keys().cloned());
+        trackers.extend(definitions.keys().cloned());
+    }
     
-    // Helper function to search trackers
+    tracing::info!("Using trackers: {:?}", trackers);
+    
+    // Accumulates deduplicated results across providers as they finish
+    #[derive(Clone)]
+    struct TorznabIndexer {
+        name: String,
+        url: String,
+        api_key: Option<String>,
+        categories: Vec<u32>,
+    }
+    
+    struct SearchAccumulator {
+        results: Vec<search::SearchResult>,
+        seen_hashes: std::collections::HashSet<String>,
+        is_movie: bool,
+        release_year: Option<u32>,
+        block_adult: bool,
+        group_releases: bool,
+        min_similarity: f64,
+        // While retrying a season under another name: that pattern's label and the season,
+        // stamped on everything found
+        season_pattern: Option<(String, u32)>,
+    }
+    
+    impl SearchAccumulator {
+        // Results are scored against `query`, the one that found them: a season searched
+        // under its sequel title or a movie with "the movie" stripped would otherwise be
+        // measured against a title they were never meant to match. `exact_lookup` marks
+        // results found by id, which are the right show even when they're titled in
+        // another language, so they're scored but never dropped.
+        fn extend(&mut self, results: Vec<search::SearchResult>, query: &str, exact_lookup: bool) -> usize {
+            let mut added = 0;
+            for mut result in results {
+                if self.block_adult && result.is_adult {
+                    continue;
+                }
+                let similarity = search::title_similarity(query, &result.title);
+                if !exact_lookup && similarity < self.min_similarity {
+                    continue;
+                }
+                result.similarity = Some(similarity);
+                result.release_group = search::parse_release_group(&result.title);
+                if self.is_movie {
+                    search::apply_movie_metadata(&mut result);
+                }
+                if let Some((label, season)) = &self.season_pattern {
+                    result.season_pattern = Some(label.clone());
+                    result.season.get_or_insert(*season);
+                }
+                let is_new = match extract_info_hash(&result.magnet_link) {
+                    Some(hash) => self.seen_hashes.insert(hash),
+                    None => true,
+                };
+                if is_new {
+                    self.results.push(result);
+                    added += 1;
+                }
+            }
+            if self.is_movie && self.release_year.is_some() {
+                let release_year = self.release_year;
+                self.results.sort_by_key(|r| std::cmp::Reverse(search::movie_year_score(r, release_year)));
+            }
+            added
+        }
+        
+        // Counts DHT peers for the best-seeded results, all at once
+        async fn verify_peers(&mut self, manager: &TorrentManager, top: usize) {
+            let mut by_seeds: Vec<usize> = (0..self.results.len()).collect();
+            by_seeds.sort_by_key(|&i| std::cmp::Reverse(self.results[i].seeds));
+            let lookups: Vec<_> = by_seeds
+                .into_iter()
+                .take(top)
+                .filter_map(|i| {
+                    let hash = extract_info_hash(&self.results[i].magnet_link)?;
+                    Some(async move { (i, manager.count_dht_peers(&hash, DHT_VERIFY_TIMEOUT).await) })
+                })
+                .collect();
+            for (i, peers) in futures::future::join_all(lookups).await {
+                self.results[i].verified_peers = peers;
+            }
+        }
+        
+        // What gets sent to the frontend; grouping runs on the whole set each time so a
+        // later provider can join a group formed earlier
+        fn view(&self) -> Vec<search::SearchResult> {
+            if self.group_releases {
+                search::group_releases(&self.results)
+            } else {
+                self.results.clone()
+            }
+        }
+    }
+    
+    // Helper function to search trackers, emitting partial results as each provider finishes
     async fn search_trackers(
+        app: &tauri::AppHandle,
+        search_id: &Option<String>,
+        accumulator: &mut SearchAccumulator,
         trackers: Vec<String>,
         query: String,
         imdb_id: Option<String>,
-    ) -> Vec<search::SearchResult> {
-        let mut handles = vec![];
+        anidb_ids: Vec<u32>,
+        indexers: &std::collections::HashMap<String, TorznabIndexer>,
+        definitions: &std::collections::HashMap<String, search::definition::ProviderDefinition>,
+    ) {
+        let mut tasks = tokio::task::JoinSet::new();
         
         for tracker in trackers {
             let query_clone = query.clone();
             let imdb_clone = imdb_id.clone();
+            let anidb_clone = anidb_ids.clone();
+            let indexer = indexers.get(&tracker).cloned();
+            let definition = definitions.get(&tracker).cloned();
+            let exact_lookup = match tracker.as_str() {
+                "animetosho" => !anidb_ids.is_empty(),
+                "eztv" | "yts" => imdb_id.is_some(),
+                _ => false,
+            };
             
-            let handle = tokio::spawn(async move {
+            tasks.spawn(async move {
                 let result: Result<Vec<search::SearchResult>, Box<dyn std::error::Error + Send + Sync>> = match tracker.as_str() {
                     "nyaa" => {
-                        println!("Searching Nyaa...");
+                        tracing::info!("Searching Nyaa...");
                         NyaaProvider::new().search(&query_clone).await
                     }
                     "limetorrents" => {
-                        println!("Searching LimeTorrents...");
+                        tracing::info!("Searching LimeTorrents...");
                         LimeTorrentsProvider::new().search(&query_clone).await
                     }
                     "thepiratebay" => {
-                        println!("Searching ThePirateBay...");
+                        tracing::info!("Searching ThePirateBay...");
                         let provider = PirateBayProvider::new();
                         if let Some(ref imdb) = imdb_clone {
                             provider.search_with_imdb(&query_clone, Some(imdb)).await
@@ -330,74 +700,235 @@ async fn search_nyaa_filtered(
                             provider.search(&query_clone).await
                         }
                     }
+                    "animetosho" => {
+                        let provider = search::animetosho::AnimeToshoProvider::new();
+                        if anidb_clone.is_empty() {
+                            tracing::info!("Searching AnimeTosho by title...");
+                            provider.search(&query_clone).await
+                        } else {
+                            tracing::info!("Searching AnimeTosho with AniDB ids: {:?}", anidb_clone);
+                            let mut combined = Vec::new();
+                            let mut last_error = None;
+                            for aid in &anidb_clone {
+                                match provider.search_by_anidb(*aid).await {
+                                    Ok(results) => combined.extend(results),
+                                    Err(e) => last_error = Some(e),
+                                }
+                            }
+                            match last_error {
+                                Some(e) if combined.is_empty() => Err(e),
+                                _ => Ok(combined),
+                            }
+                        }
+                    }
+                    "yts" => {
+                        if let Some(ref imdb) = imdb_clone {
+                            tracing::info!("Searching YTS with IMDB ID: {}", imdb);
+                            search::yts::YTSProvider::new().search_by_imdb(imdb).await
+                        } else {
+                            tracing::info!("Searching YTS by title...");
+                            search::yts::YTSProvider::new().search(&query_clone).await
+                        }
+                    }
                     "eztv" => {
                         if let Some(ref imdb) = imdb_clone {
-                            println!("Searching EZTV with IMDB ID: {}", imdb);
+                            tracing::info!("Searching EZTV with IMDB ID: {}", imdb);
                             search::eztv::EZTVProvider::new().search_by_imdb(imdb).await
                         } else {
-                            println!("EZTV requires IMDB ID, skipping");
-                            Ok(vec![])
+                            tracing::info!("Searching EZTV by title...");
+                            search::eztv::EZTVProvider::new().search(&query_clone).await
                         }
                     }
-                    _ => {
-                        println!("Unknown tracker: {}", tracker);
-                        Ok(vec![])
-                    }
+                    _ => match (indexer, definition) {
+                        (Some(indexer), _) => {
+                            tracing::info!("Searching {}...", indexer.name);
+                            search::torznab::TorznabProvider::new(indexer.name, indexer.url, indexer.api_key, indexer.categories)
+                                .search(&query_clone)
+                                .await
+                        }
+                        (None, Some(definition)) => {
+                            tracing::info!("Searching {}...", definition.name);
+                            match search::definition::DefinitionProvider::new(definition) {
+                                Ok(provider) => provider.search(&query_clone).await,
+                                Err(e) => Err(e.into()),
+                            }
+                        }
+                        (None, None) => {
+                            tracing::info!("Unknown tracker: {}", tracker);
+                            Ok(vec![])
+                        }
+                    },
                 };
                 
-                match result {
+                let mut degraded = None;
+                let results = match result {
                     Ok(results) => {
-                        println!("{} returned {} results", tracker, results.len());
+                        tracing::info!("{} returned {} results", tracker, results.len());
+                        analytics::record_search(&tracker, Some(results.len()));
                         results
                     }
                     Err(e) => {
-                        println!("{} error: {}", tracker, e);
+                        tracing::info!("{} error: {}", tracker, e);
+                        analytics::record_search(&tracker, None);
+                        if e.is::<search::http::ChallengeError>() {
+                            degraded = Some(e.to_string());
+                        }
                         vec![]
                     }
-                }
+                };
+                (tracker, results, degraded, exact_lookup)
             });
-            handles.push(handle);
         }
         
-        let mut all_results = Vec::new();
-        for handle in handles {
-            if let Ok(results) = handle.await {
-                all_results.extend(results);
+        while let Some(joined) = tasks.join_next().await {
+            let (tracker, results, degraded, exact_lookup) = match joined {
+                Ok(r) => r,
+                Err(e) => {
+                    tracing::info!("search task failed: {}", e);
+                    continue;
+                }
+            };
+            
+            if let Some(reason) = degraded {
+                let _ = app.emit("search-provider-degraded", SearchProviderDegradedPayload {
+                    search_id: search_id.clone(),
+                    provider: tracker.clone(),
+                    reason,
+                });
             }
+            
+            let added = accumulator.extend(results, &query, exact_lookup);
+            tracing::info!("{} added {} new results ({} total after deduplication)", tracker, added, accumulator.results.len());
+            
+            let _ = app.emit("search-results-partial", SearchPartialPayload {
+                search_id: search_id.clone(),
+                provider: tracker,
+                results: accumulator.view(),
+            });
         }
-        all_results
     }
     
-    let mut all_results = search_trackers(trackers, normalized_query.clone(), imdb_id.clone()).await;
+    let mut accumulator = SearchAccumulator {
+        results: Vec::new(),
+        seen_hashes: std::collections::HashSet::new(),
+        is_movie,
+        release_year,
+        block_adult: parental.block_adult,
+        group_releases: group_releases.unwrap_or(settings.group_search_results),
+        min_similarity: min_similarity.unwrap_or(0.0).clamp(0.0, 100.0),
+        season_pattern: None,
+    };
+    
+    let first_query = if is_anime_movie {
+        search::anime_movie_query(&normalized_query)
+    } else {
+        normalized_query.clone()
+    };
+    search_trackers(&app, &search_id, &mut accumulator, trackers.clone(), first_query, imdb_id.clone(), anidb_ids.clone(), &indexers, &definitions).await;
     
-    if is_auto_mode && is_anime && all_results.is_empty() {
-        println!("Anime search returned no results, falling back to regular trackers");
-        let mut fallback_trackers = vec!["limetorrents".to_string(), "thepiratebay".to_string()];
-        if imdb_id.is_some() {
-            fallback_trackers.push("eztv".to_string());
+    if is_auto_mode && is_anime && accumulator.results.is_empty() {
+        tracing::info!("Anime search returned no results, falling back to regular trackers");
+        if imdb_id.is_none() {
+            if let Some(id) = tmdb_id {
+                imdb_id = imdb_resolver.resolve(id, tmdb_media_type, Some(&query), None).await;
+                use_eztv = use_eztv || imdb_id.is_some();
+            }
         }
-        all_results = search_trackers(fallback_trackers, normalized_query.clone(), imdb_id.clone()).await;
+        // EZTV only carries TV, so a movie falls back to YTS and TPB instead
+        let fallback_trackers = if is_anime_movie {
+            vec!["yts".to_string(), "thepiratebay".to_string()]
+        } else {
+            let mut t = vec!["limetorrents".to_string(), "thepiratebay".to_string()];
+            if use_eztv {
+                t.push("eztv".to_string());
+            }
+            t
+        };
+        search_trackers(&app, &search_id, &mut accumulator, fallback_trackers, normalized_query.clone(), imdb_id.clone(), Vec::new(), &indexers, &definitions).await;
     }
     
-    println!("Total results before deduplication: {}", all_results.len());
-    
-    let mut seen_hashes = std::collections::HashSet::new();
-    all_results.retain(|result| {
-        if let Some(hash) = extract_info_hash(&result.magnet_link) {
-            seen_hashes.insert(hash)
-        } else {
-            true
+    // Later anime seasons are often released as "2nd Season", "Part 2" or under the
+    // sequel's title rather than S02; try those in turn until one finds something
+    if let Some(season) = season.filter(|s| *s > 1 && is_anime && !is_movie) {
+        if !accumulator.results.iter().any(|r| r.season == Some(season)) {
+            let sequel_title = match tmdb_id {
+                Some(id) => imdb_resolver.season_name(id, season).await,
+                None => None,
+            };
+            for (label, pattern_query) in search::season_queries(&normalized_query, season, sequel_title.as_deref()) {
+                tracing::info!("No season {} results, trying \"{}\"", season, pattern_query);
+                let before = accumulator.results.len();
+                accumulator.season_pattern = Some((label.clone(), season));
+                let pattern_query = search::normalize_query(&pattern_query, true);
+                search_trackers(&app, &search_id, &mut accumulator, trackers.clone(), pattern_query, imdb_id.clone(), Vec::new(), &indexers, &definitions).await;
+                if accumulator.results.len() > before {
+                    tracing::info!("Season {} found as \"{}\"", season, label);
+                    break;
+                }
+            }
+            accumulator.season_pattern = None;
         }
+    }
+    
+    if settings.dht_verify_top > 0 && !accumulator.results.is_empty() {
+        let torrent_manager = app.state::<Arc<TorrentManager>>();
+        accumulator.verify_peers(&torrent_manager, settings.dht_verify_top as usize).await;
+        let _ = app.emit("search-results-partial", SearchPartialPayload {
+            search_id: search_id.clone(),
+            provider: "dht".to_string(),
+            results: accumulator.view(),
+        });
+    }
+    
+    tracing::info!("Total results after deduplication: {}", accumulator.results.len());
+    let results = accumulator.view();
+    
+    let _ = app.emit("search-results-complete", SearchCompletePayload {
+        search_id,
+        total: results.len(),
     });
     
-    println!("Total results after deduplication: {}", all_results.len());
-    Ok(all_results)
+    // Fetch the file lists of the first few results so opening one doesn't wait on peers
+    if settings.prefetch_metadata {
+        let torrent_manager = app.state::<Arc<TorrentManager>>().inner().clone();
+        let magnets: Vec<String> = results.iter().take(PREFETCH_TOP_RESULTS).map(|r| r.magnet_link.clone()).collect();
+        crash_report::spawn_logged("metadata prefetch", async move {
+            futures::future::join_all(magnets.iter().map(|magnet| torrent_manager.prefetch_listing(magnet))).await;
+        });
+    }
+    
+    Ok(results)
+}
+
+// Definition files for extra public trackers, loaded on every search so new files
+// apply without a restart
+const PROVIDER_DEFINITIONS_DIR: &str = "provider_definitions";
+
+#[derive(Clone, serde::Serialize)]
+struct ProviderDefinitionsPayload {
+    folder: String,
+    definitions: Vec<search::definition::DefinitionInfo>,
+}
+
+#[tauri::command]
+async fn get_provider_definitions(app: tauri::AppHandle) -> Result<ProviderDefinitionsPayload, String> {
+    let folder = app.path().app_data_dir().map_err(|e| e.to_string())?.join(PROVIDER_DEFINITIONS_DIR);
+    std::fs::create_dir_all(&folder).map_err(|e| format!("Failed to create {:?}: {}", folder, e))?;
+    Ok(ProviderDefinitionsPayload {
+        definitions: search::definition::describe_definitions(&folder),
+        folder: folder.to_string_lossy().to_string(),
+    })
 }
 
+// How long each result's DHT peer lookup may take
+const DHT_VERIFY_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(8);
+// Results whose metadata is prefetched when prefetch_metadata is on
+const PREFETCH_TOP_RESULTS: usize = 3;
+
 // Extract info hash from magnet link for deduplication
 fn extract_info_hash(magnet: &str) -> Option<String> {
     magnet
-        .split('&')
+        .split(['?', '&'])
         .find(|part| part.starts_with("xt=urn:btih:"))
         .and_then(|part| part.strip_prefix("xt=urn:btih:"))
         .map(|hash| hash.to_lowercase())
@@ -405,7 +936,7 @@ fn extract_info_hash(magnet: &str) -> Option<String> {
 
 #[tauri::command]
 async fn search_eztv_by_imdb(imdb_id: String) -> Result<Vec<search::SearchResult>, String> {
-    println!("Searching EZTV with IMDb ID: {}", imdb_id);
+    tracing::info!("Searching EZTV with IMDb ID: {}", imdb_id);
     let provider = search::eztv::EZTVProvider::new();
     provider.search_by_imdb(&imdb_id).await.map_err(|e| e.to_string())
 }
@@ -420,11 +951,33 @@ async fn save_torrent_selection(
     file_index: usize,
 ) -> Result<(), String> {
     tracking
-        .save_selection(show_id, season, episode, magnet_link, file_index)
+        .save_selection(show_id, season, episode, magnet_link, file_index)
+        .await;
+    Ok(())
+}
+
+#[tauri::command]
+async fn save_release_preference(
+    tracking: State<'_, TrackingManager>,
+    show_id: u32,
+    provider: String,
+    title: String,
+    quality: Option<String>,
+) -> Result<(), String> {
+    tracking
+        .save_release_preference(show_id, provider, &title, quality)
         .await;
     Ok(())
 }
 
+#[tauri::command]
+async fn get_release_preference(
+    tracking: State<'_, TrackingManager>,
+    show_id: u32,
+) -> Result<Option<tracking::ReleasePreference>, String> {
+    Ok(tracking.get_release_preference(show_id).await)
+}
+
 #[tauri::command]
 async fn save_multiple_torrent_selections(
     tracking: State<'_, TrackingManager>,
@@ -516,6 +1069,11 @@ async fn load_audio_cache(
     track_index: usize,
 ) -> Result<Option<String>, String> {
     use base64::{Engine as _, engine::general_purpose::STANDARD};
+    // extract_audio_track caches its output as .mka under the same info hash
+    if let Some(path) = cache.cached_format(TrackType::Audio, &cache_id, file_index, track_index, "mka") {
+        let bytes = tokio::fs::read(&path).await.map_err(|e| format!("Failed to load track cache: {}", e))?;
+        return Ok(Some(STANDARD.encode(&bytes)));
+    }
     let data = cache.load_track(TrackType::Audio, &cache_id, file_index, track_index).await?;
     Ok(data.map(|bytes| STANDARD.encode(&bytes)))
 }
@@ -542,9 +1100,10 @@ async fn save_font(
     filename: String,
     data: Vec<u8>,
 ) -> Result<String, String> {
-    // Check if font is already installed on system
-    if font_manager::is_font_installed(&filename) {
-        println!("Font {} is already installed on system, skipping save", filename);
+    // Fonts the system already has, by file name or the faces they hold, stay out of the
+    // cache; the player still hands the embedded copy to the subtitle renderer
+    if font_manager::is_font_installed(&filename) || font_manager::is_face_installed(&data) {
+        tracing::info!("Font {} is already installed on system, not caching it", filename);
         return Ok(format!("system:{}", filename));
     }
     
@@ -553,8 +1112,18 @@ async fn save_font(
 }
 
 #[tauri::command]
-fn check_font_installed(filename: String) -> bool {
-    font_manager::is_font_installed(&filename)
+async fn check_font_installed(filename: String) -> bool {
+    // The first check reads every system font, so keep it off the main thread
+    tokio::task::spawn_blocking(move || font_manager::is_font_installed(&filename))
+        .await
+        .unwrap_or(false)
+}
+
+#[tauri::command]
+async fn find_system_fonts(family: String) -> Vec<font_manager::SystemFont> {
+    tokio::task::spawn_blocking(move || font_manager::find_system_fonts(&family))
+        .await
+        .unwrap_or_default()
 }
 
 #[tauri::command]
@@ -627,13 +1196,56 @@ async fn get_track_preference(
     Ok(track_prefs.get_preference(&magnet_link).await)
 }
 
+/// Changes some fields of one settings section, see settings::SETTINGS_SECTIONS
+#[tauri::command]
+async fn patch_settings(
+    settings_manager: State<'_, SettingsManager>,
+    watch_folder: State<'_, WatchFolder>,
+    section: String,
+    values: serde_json::Map<String, serde_json::Value>,
+) -> Result<Settings, String> {
+    let previous_folder = settings_manager.get().await.watch_folder;
+    let settings = settings_manager.patch(&section, values).await?;
+    if settings.watch_folder != previous_folder {
+        watch_folder.watch(settings.watch_folder.clone());
+    }
+    Ok(settings)
+}
+
+#[tauri::command]
+fn get_settings_sections() -> std::collections::HashMap<String, Vec<String>> {
+    settings::SETTINGS_SECTIONS
+        .iter()
+        .map(|(name, fields)| (name.to_string(), fields.iter().map(|f| f.to_string()).collect()))
+        .collect()
+}
+
+/// Replaces a provider's mirror list; an empty list restores the defaults
 #[tauri::command]
-async fn save_settings(
+async fn set_provider_mirrors(
     settings_manager: State<'_, SettingsManager>,
-    settings: Settings,
+    provider: String,
+    mirrors: Vec<String>,
 ) -> Result<(), String> {
-    settings_manager.save(settings).await;
-    Ok(())
+    let Some(defaults) = search::mirrors::default_mirrors().remove(&provider) else {
+        return Err(format!("{} has no mirrors", provider));
+    };
+    let mut cleaned = Vec::new();
+    for mirror in mirrors.iter().map(|m| m.trim().trim_end_matches('/')).filter(|m| !m.is_empty()) {
+        if !reqwest::Url::parse(mirror).map(|u| matches!(u.scheme(), "http" | "https")).unwrap_or(false) {
+            return Err(format!("Invalid mirror URL: {}", mirror));
+        }
+        cleaned.push(mirror.to_string());
+    }
+
+    let mut settings = settings_manager.get().await;
+    settings.provider_mirrors.insert(provider, if cleaned.is_empty() { defaults } else { cleaned });
+    settings_manager.save(settings).await
+}
+
+#[tauri::command]
+async fn get_provider_mirrors() -> Result<std::collections::HashMap<String, Vec<search::mirrors::MirrorStatus>>, String> {
+    Ok(search::mirrors::mirror_statuses())
 }
 
 #[tauri::command]
@@ -643,14 +1255,19 @@ async fn get_settings(
     Ok(settings_manager.get().await)
 }
 
+#[tauri::command]
+async fn get_active_bandwidth_rule(
+    settings_manager: State<'_, SettingsManager>,
+) -> Result<Option<settings::BandwidthRule>, String> {
+    Ok(settings_manager.get_active_bandwidth_rule().await)
+}
+
 #[tauri::command]
 async fn check_external_player(player: String) -> Result<bool, String> {
-    use std::process::Command;
-    
     let command_name = match player.to_lowercase().as_str() {
         "mpv" => "mpv",
         "vlc" => if cfg!(target_os = "windows") { "vlc" } else { "vlc" },
-        _ => return Err(format!("Unsupported player: {}", player)),
+        _ => return Err(i18n::t_args("error.unsupported_player", &[("player", &player)])),
     };
     
     // On Windows, check common VLC installation paths
@@ -669,29 +1286,194 @@ async fn check_external_player(player: String) -> Result<bool, String> {
         }
     }
     
+    let found = on_path(command_name).await;
+    #[cfg(target_os = "linux")]
+    if !found && linux_player_launcher(command_name).await.is_some() {
+        return Ok(true);
+    }
+    Ok(found)
+}
+
+/// Whether `command_name` resolves to a program on PATH
+async fn on_path(command_name: &str) -> bool {
     #[cfg(target_os = "windows")]
-    let check_result = Command::new("where")
-        .arg(command_name)
-        .creation_flags(0x08000000)
-        .output();
-    
+    let mut cmd = {
+        let mut cmd = tokio::process::Command::new("where");
+        cmd.creation_flags(0x08000000);
+        cmd
+    };
     #[cfg(not(target_os = "windows"))]
-    let check_result = Command::new("which")
-        .arg(command_name)
-        .output();
-    
-    match check_result {
-        Ok(output) => Ok(output.status.success()),
-        Err(_) => Ok(false),
+    let mut cmd = tokio::process::Command::new("which");
+
+    cmd.arg(command_name)
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .status()
+        .await
+        .map(|status| status.success())
+        .unwrap_or(false)
+}
+
+/// How to start a player that's installed as a Snap or Flatpak rather than on PATH: the
+/// program to run and the arguments that go before the player's own
+#[cfg(target_os = "linux")]
+async fn linux_player_launcher(command_name: &str) -> Option<(String, Vec<String>)> {
+    let snap = std::path::Path::new("/snap/bin").join(command_name);
+    if snap.exists() {
+        return Some((snap.to_string_lossy().to_string(), Vec::new()));
     }
+
+    let app_id = match command_name {
+        "vlc" => "org.videolan.VLC",
+        "mpv" => "io.mpv.Mpv",
+        _ => return None,
+    };
+    let output = tokio::process::Command::new("flatpak")
+        .args(["list", "--app", "--columns=application"])
+        .output()
+        .await
+        .ok()?;
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .any(|line| line.trim() == app_id)
+        .then(|| ("flatpak".to_string(), vec!["run".to_string(), app_id.to_string()]))
+}
+
+#[derive(Clone, serde::Serialize)]
+struct ExternalPlayerClosedPayload {
+    handle_id: Option<usize>,
+    player: String,
+    exit_code: Option<i32>,
+    // The stream was stopped because of the stop_stream_on_external_exit setting
+    stream_stopped: bool,
+}
+
+// Subtitles for the external player: the track extracted to a file so styling survives,
+// or the embedded track picked by index when extraction didn't work out
+enum ExternalSubtitle {
+    File(String),
+    Embedded(usize),
 }
 
+/// Launches the player on the stream and, once it exits, sends "external-player-closed".
+/// With `handle_id` given and stop_stream_on_external_exit on, the stream is stopped too,
+/// deleting its files if clear_cache_after_watch is set and the player ran long enough
+/// to count as a watch. A player that exits within seconds is left alone. A `subtitle_track` of the
+/// streamed file is handed to the player as an ASS file when the complete track is
+/// already cached, and picked from the embedded tracks otherwise.
 #[tauri::command]
 async fn open_in_external_player(
+    app_handle: tauri::AppHandle,
     player: String,
     stream_url: String,
     title: String,
+    handle_id: Option<usize>,
+    file_index: Option<usize>,
+    subtitle_track: Option<usize>,
 ) -> Result<(), String> {
+    let extraction = match (handle_id, file_index, subtitle_track) {
+        (Some(handle_id), Some(file_index), Some(track_index)) => Some((handle_id, file_index, track_index)),
+        _ => None,
+    };
+    let subtitle = match extraction {
+        Some((handle_id, file_index, track_index)) => {
+            let cached = torrent::cached_subtitle(
+                &app_handle.state::<Arc<TorrentManager>>(),
+                &app_handle.state::<MediaCache>(),
+                handle_id,
+                file_index,
+                track_index,
+                torrent::TrackFormat::Ass,
+            ).await;
+            Some(match cached {
+                Some(path) => ExternalSubtitle::File(path.to_string_lossy().to_string()),
+                None => ExternalSubtitle::Embedded(track_index),
+            })
+        }
+        None => None,
+    };
+    let child = spawn_external_player(&player, &stream_url, &title, subtitle.as_ref()).await?;
+
+    // A partial extraction would cut the track short, so the player keeps the embedded
+    // track and the full one is cached for the next launch
+    if let (Some((handle_id, file_index, track_index)), Some(ExternalSubtitle::Embedded(_))) = (extraction, &subtitle) {
+        let app_handle = app_handle.clone();
+        crash_report::spawn_logged("external player subtitle", async move {
+            let extracted = torrent::extract_subtitle(
+                app_handle.clone(),
+                app_handle.state(),
+                app_handle.state(),
+                handle_id,
+                file_index,
+                track_index,
+                Some(torrent::TrackFormat::Ass),
+            ).await;
+            if let Err(e) = extracted {
+                tracing::error!("couldn't extract subtitle track {} for the external player: {}", track_index, e);
+            }
+        });
+    }
+
+    crash_report::spawn_logged("external player", watch_external_player(app_handle, child, player, handle_id));
+    Ok(())
+}
+
+// A player that exits this quickly handed the stream to an instance already running
+const EXTERNAL_PLAYER_HANDOFF: std::time::Duration = std::time::Duration::from_secs(5);
+// How long a player must have run, exiting cleanly, for the stream to count as watched
+const EXTERNAL_PLAYER_MIN_WATCH: std::time::Duration = std::time::Duration::from_secs(120);
+
+async fn watch_external_player(
+    app_handle: tauri::AppHandle,
+    mut child: std::process::Child,
+    player: String,
+    handle_id: Option<usize>,
+) {
+    use tauri::Emitter;
+    let started = std::time::Instant::now();
+    let exit_code = match tauri::async_runtime::spawn_blocking(move || child.wait()).await {
+        Ok(Ok(status)) => status.code(),
+        Ok(Err(e)) => {
+            tracing::error!("failed to wait for {}: {}", player, e);
+            None
+        }
+        Err(_) => None,
+    };
+    let ran_for = started.elapsed();
+    tracing::info!("{} exited with code {:?} after {:?}", player, exit_code, ran_for);
+    if ran_for < EXTERNAL_PLAYER_HANDOFF {
+        // The stream is still playing in the other instance, which we can't watch
+        tracing::info!("{} exited right away, assuming it handed off to a running instance", player);
+        return;
+    }
+
+    let mut stream_stopped = false;
+    if let Some(handle_id) = handle_id {
+        let settings = app_handle.state::<SettingsManager>().get().await;
+        if settings.stop_stream_on_external_exit {
+            let torrent_manager = app_handle.state::<Arc<TorrentManager>>();
+            let watched = exit_code == Some(0) && ran_for >= EXTERNAL_PLAYER_MIN_WATCH;
+            match torrent_manager.stop_stream(handle_id, settings.clear_cache_after_watch && watched).await {
+                Ok(()) => stream_stopped = true,
+                Err(e) => tracing::error!("failed to stop stream {} after {} closed: {}", handle_id, player, e),
+            }
+        }
+    }
+
+    let _ = app_handle.emit("external-player-closed", ExternalPlayerClosedPayload {
+        handle_id,
+        player,
+        exit_code,
+        stream_stopped,
+    });
+}
+
+async fn spawn_external_player(
+    player: &str,
+    stream_url: &str,
+    title: &str,
+    subtitle: Option<&ExternalSubtitle>,
+) -> Result<std::process::Child, String> {
     use std::process::Command;
     
     let command_name = match player.to_lowercase().as_str() {
@@ -714,9 +1496,21 @@ async fn open_in_external_player(
             #[cfg(not(target_os = "windows"))]
             "vlc".to_string()
         },
-        _ => return Err(format!("Unsupported player: {}", player)),
+        _ => return Err(i18n::t_args("error.unsupported_player", &[("player", &player)])),
     };
     
+    #[cfg(target_os = "linux")]
+    let launcher = if on_path(&command_name).await { None } else { linux_player_launcher(&command_name).await };
+    #[cfg(target_os = "linux")]
+    let mut cmd = match launcher {
+        Some((program, args)) => {
+            let mut cmd = Command::new(program);
+            cmd.args(args);
+            cmd
+        }
+        None => Command::new(&command_name),
+    };
+    #[cfg(not(target_os = "linux"))]
     let mut cmd = Command::new(&command_name);
     
     #[cfg(target_os = "windows")]
@@ -725,34 +1519,244 @@ async fn open_in_external_player(
     // Add player-specific arguments
     match player.to_lowercase().as_str() {
         "mpv" => {
-            cmd.arg(&stream_url)
+            cmd.arg(stream_url)
                 .arg(format!("--title={}", title))
                 .arg("--force-window=immediate");
+            // A single --sub-file is selected by default; --sid counts subtitle tracks from 1
+            match subtitle {
+                Some(ExternalSubtitle::File(path)) => { cmd.arg(format!("--sub-file={}", path)); }
+                Some(ExternalSubtitle::Embedded(index)) => { cmd.arg(format!("--sid={}", index + 1)); }
+                None => {}
+            }
         },
         "vlc" => {
-            cmd.arg(&stream_url)
+            cmd.arg(stream_url)
                 .arg(format!("--meta-title={}", title));
+            match subtitle {
+                Some(ExternalSubtitle::File(path)) => { cmd.arg(format!("--sub-file={}", path)); }
+                Some(ExternalSubtitle::Embedded(index)) => { cmd.arg(format!("--sub-track={}", index)); }
+                None => {}
+            }
         },
-        _ => return Err(format!("Unsupported player: {}", player)),
+        _ => return Err(i18n::t_args("error.unsupported_player", &[("player", &player)])),
     }
     
     // Spawn the process
     cmd.spawn()
-        .map_err(|e| format!("Failed to launch {}: {}", player, e))?;
-    
+        .map_err(|e| i18n::t_args("error.player_launch_failed", &[("player", &player), ("error", &e)]))
+}
+
+#[tauri::command]
+async fn get_library(library: State<'_, LibraryManager>) -> Result<Vec<LibraryEntry>, String> {
+    Ok(library.get_library().await)
+}
+
+#[tauri::command]
+async fn rescan_library(
+    library: State<'_, LibraryManager>,
+    torrent_manager: State<'_, Arc<TorrentManager>>,
+    imdb_resolver: State<'_, ImdbResolver>,
+) -> Result<Vec<LibraryEntry>, String> {
+    let completed = torrent_manager.completed_files().await;
+    Ok(library.rescan(completed, &imdb_resolver).await)
+}
+
+#[tauri::command]
+async fn import_local_file(
+    library: State<'_, LibraryManager>,
+    imdb_resolver: State<'_, ImdbResolver>,
+    path: String,
+    tmdb_id: Option<u32>,
+    season: Option<u32>,
+    episode: Option<u32>,
+) -> Result<LibraryEntry, String> {
+    library.import_file(path, tmdb_id, season, episode, &imdb_resolver).await
+}
+
+#[tauri::command]
+async fn add_library_folder(library: State<'_, LibraryManager>, folder: String) -> Result<(), String> {
+    library.add_folder(folder).await;
+    Ok(())
+}
+
+#[tauri::command]
+async fn remove_library_folder(library: State<'_, LibraryManager>, folder: String) -> Result<(), String> {
+    library.remove_folder(&folder).await;
     Ok(())
 }
 
+#[tauri::command]
+async fn play_local_file(
+    app_handle: tauri::AppHandle,
+    library: State<'_, LibraryManager>,
+    settings_manager: State<'_, SettingsManager>,
+    library_id: String,
+) -> Result<(), String> {
+    let entry = library.get_entry(&library_id).await.ok_or_else(|| i18n::t("error.library_entry_not_found"))?;
+    if !std::path::Path::new(&entry.path).exists() {
+        return Err(i18n::t("error.file_missing"));
+    }
+
+    let player = settings_manager.get().await.external_player;
+    open_in_external_player(app_handle, player, entry.path.clone(), library_entry_title(&entry), None, None, None).await
+}
+
+fn library_entry_title(entry: &LibraryEntry) -> String {
+    match (&entry.title, entry.season, entry.episode) {
+        (Some(title), Some(season), Some(episode)) => format!("{} - S{}E{}", title, season, episode),
+        (Some(title), _, _) => title.clone(),
+        _ => entry.file_name.clone(),
+    }
+}
+
+/// Queues every episode of the season found in the library, in order, as one M3U playlist
+/// for the external player. Returns how many episodes went in.
+#[tauri::command]
+async fn open_season_in_external_player(
+    app_handle: tauri::AppHandle,
+    library: State<'_, LibraryManager>,
+    torrent_manager: State<'_, Arc<TorrentManager>>,
+    settings_manager: State<'_, SettingsManager>,
+    imdb_resolver: State<'_, ImdbResolver>,
+    show_id: u32,
+    season: u32,
+) -> Result<usize, String> {
+    let settings = settings_manager.get().await;
+    parental::check_title(&settings.parental, &imdb_resolver, show_id, "tv").await?;
+
+    let mut episodes: Vec<LibraryEntry> = library.get_library().await
+        .into_iter()
+        .filter(|e| e.tmdb_id == Some(show_id) && e.season == Some(season) && e.episode.is_some())
+        .filter(|e| e.media_type.as_deref().unwrap_or("tv") == "tv")
+        .filter(|e| std::path::Path::new(&e.path).exists())
+        .collect();
+    // The same episode can be on disk twice, e.g. a download and its organized link
+    episodes.sort_by_key(|e| e.episode);
+    episodes.dedup_by_key(|e| e.episode);
+    if episodes.is_empty() {
+        return Err(i18n::t_args("error.no_season_episodes", &[("season", &season)]));
+    }
+
+    let mut playlist = String::from("#EXTM3U\n");
+    for entry in &episodes {
+        let url = torrent_manager.register_local_file(entry.id.clone(), std::path::PathBuf::from(&entry.path)).await;
+        playlist.push_str(&format!("#EXTINF:-1,{}\n{}\n", library_entry_title(entry), url));
+    }
+    let playlist_path = std::env::temp_dir().join(format!("magnolia_{}_s{}.m3u", show_id, season));
+    std::fs::write(&playlist_path, playlist).map_err(|e| format!("Failed to write playlist: {}", e))?;
+
+    let title = match &episodes[0].title {
+        Some(show) => format!("{} - Season {}", show, season),
+        None => format!("Season {}", season),
+    };
+    tracing::info!("opening {} episodes of {} in {}", episodes.len(), title, settings.external_player);
+    let child = spawn_external_player(&settings.external_player, &playlist_path.to_string_lossy(), &title, None).await?;
+    crash_report::spawn_logged(
+        "external player",
+        watch_external_player(app_handle, child, settings.external_player, None),
+    );
+    Ok(episodes.len())
+}
+
+#[tauri::command]
+async fn get_local_stream_url(
+    library: State<'_, LibraryManager>,
+    torrent_manager: State<'_, Arc<TorrentManager>>,
+    settings_manager: State<'_, SettingsManager>,
+    imdb_resolver: State<'_, ImdbResolver>,
+    library_id: String,
+) -> Result<String, String> {
+    let entry = library.get_entry(&library_id).await.ok_or_else(|| i18n::t("error.library_entry_not_found"))?;
+    if let Some(tmdb_id) = entry.tmdb_id {
+        let media_type = entry.media_type.as_deref().unwrap_or("tv");
+        parental::check_title(&settings_manager.get().await.parental, &imdb_resolver, tmdb_id, media_type).await?;
+    }
+    if !std::path::Path::new(&entry.path).exists() {
+        return Err(i18n::t("error.file_missing"));
+    }
+    Ok(torrent_manager.register_local_file(entry.id, std::path::PathBuf::from(entry.path)).await)
+}
+
+fn backup_dir(settings: &Settings) -> Result<std::path::PathBuf, String> {
+    settings.backup_dir.as_deref()
+        .map(str::trim)
+        .filter(|d| !d.is_empty())
+        .map(std::path::PathBuf::from)
+        .ok_or_else(|| i18n::t("error.no_backup_folder"))
+}
+
+#[tauri::command]
+async fn create_backup(
+    backup_manager: State<'_, BackupManager>,
+    settings_manager: State<'_, SettingsManager>,
+) -> Result<String, String> {
+    let settings = settings_manager.get().await;
+    let path = backup_manager.create_backup(&backup_dir(&settings)?, settings.backup_keep)?;
+    Ok(path.to_string_lossy().to_string())
+}
+
+#[tauri::command]
+async fn list_backups(
+    backup_manager: State<'_, BackupManager>,
+    settings_manager: State<'_, SettingsManager>,
+) -> Result<Vec<backup::BackupInfo>, String> {
+    let settings = settings_manager.get().await;
+    Ok(backup_manager.list_backups(&backup_dir(&settings)?))
+}
+
+#[tauri::command]
+async fn restore_backup(
+    app: tauri::AppHandle,
+    backup_manager: State<'_, BackupManager>,
+    path: String,
+) -> Result<(), String> {
+    backup_manager.restore_backup(std::path::Path::new(&path))?;
+    // Every manager loaded its file at startup, so restart to pick up the restored data
+    app.restart();
+}
+
 #[tauri::command]
 async fn get_cache_stats(state: State<'_, MediaCache>) -> Result<Vec<media_cache::CacheGroup>, String> {
     state.get_cache_stats().await
 }
 
+#[tauri::command]
+async fn verify_cache(state: State<'_, MediaCache>) -> Result<media_cache::CacheVerifyReport, String> {
+    Ok(state.verify().await)
+}
+
+#[tauri::command]
+async fn get_cache_entry(
+    state: State<'_, MediaCache>,
+    track_type: TrackType,
+    cache_id: String,
+    file_index: usize,
+    track_index: usize,
+) -> Result<Option<media_cache::CacheEntry>, String> {
+    Ok(state.entry(track_type, &cache_id, file_index, track_index))
+}
+
+#[tauri::command]
+async fn get_session_stats(
+    torrent_manager: State<'_, Arc<TorrentManager>>,
+    media_cache: State<'_, MediaCache>,
+) -> Result<torrent::SessionStats, String> {
+    let mut stats = torrent_manager.get_session_stats().await.map_err(|e| e.to_string())?;
+    stats.audio_cache_bytes = media_cache.get_cache_size(TrackType::Audio);
+    stats.subtitle_cache_bytes = media_cache.get_cache_size(TrackType::Subtitle);
+    Ok(stats)
+}
+
 #[tauri::command]
 async fn get_font_stats(state: State<'_, FontManager>) -> Result<(usize, u64), String> {
     state.get_stats()
 }
 
+#[tauri::command]
+async fn clear_fonts(state: State<'_, FontManager>) -> Result<(), String> {
+    state.clear_fonts()
+}
+
 #[tauri::command]
 async fn clear_cache_item(
     id: String, 
@@ -802,76 +1806,16 @@ async fn clear_cache_item(
             .collect()
     };
     
-    println!("[cache cleanup] found {} cache hashes for TMDB ID {}", hashes_to_delete.len(), tmdb_id);
+    tracing::info!("[cache cleanup] found {} cache hashes for TMDB ID {}", hashes_to_delete.len(), tmdb_id);
     
     for hash in hashes_to_delete {
         state.clear_cache_by_id(&hash).await?;
-        println!("[cache cleanup] cleared cache for hash: {}", &hash[..8.min(hash.len())]);
+        tracing::info!("[cache cleanup] cleared cache for hash: {}", &hash[..8.min(hash.len())]);
     }
     
     Ok(())
 }
 
-#[tauri::command]
-async fn download_update(url: String, _app_handle: tauri::AppHandle) -> Result<String, String> {
-    let temp_dir = std::env::temp_dir();
-    let file_name = url.split('/').last().unwrap_or("magnolia-installer.exe");
-    let dest_path = temp_dir.join(file_name);
-
-    println!("downloading update from: {}", url);
-    println!("saving to: {:?}", dest_path);
-
-    let response = reqwest::get(&url)
-        .await
-        .map_err(|e| format!("failed to download: {}", e))?;
-
-    let bytes = response
-        .bytes()
-        .await
-        .map_err(|e| format!("failed to read response: {}", e))?;
-
-    std::fs::write(&dest_path, bytes)
-        .map_err(|e| format!("failed to write file: {}", e))?;
-
-    println!("download complete: {:?}", dest_path);
-    Ok(dest_path.to_string_lossy().to_string())
-}
-
-#[tauri::command]
-async fn install_update(installer_path: String, _app_handle: tauri::AppHandle) -> Result<(), String> {
-    println!("running installer: {}", installer_path);
-
-    #[cfg(target_os = "windows")]
-    {
-        use std::process::Command;
-        use std::os::windows::process::CommandExt;
-
-        // Run NSIS installer with /S flag for silent install
-        let status = Command::new(&installer_path)
-            .arg("/S")
-            .creation_flags(0x08000000) // CREATE_NO_WINDOW
-            .status()
-            .map_err(|e| format!("failed to run installer: {}", e))?;
-
-        if !status.success() {
-            return Err("installer failed".to_string());
-        }
-
-        // Delete the installer after successful install
-        tokio::time::sleep(tokio::time::Duration::from_secs(2)).await;
-        let _ = std::fs::remove_file(&installer_path);
-        println!("installer removed: {}", installer_path);
-
-        // Exit the app so the new version can start
-        std::process::exit(0);
-    }
-
-    #[cfg(not(target_os = "windows"))]
-    {
-        Err("auto-update only supported on windows".to_string())
-    }
-}
-
 #[tauri::command]
 fn open_external_url(url: String) -> Result<(), String> {
     #[cfg(target_os = "windows")]
@@ -903,16 +1847,22 @@ fn open_external_url(url: String) -> Result<(), String> {
 }
 
 fn main() {
+    crash_report::install_panic_hook();
+    logger::install_tracing();
+
     tauri::Builder::default()
         .plugin(tauri_plugin_shell::init())
         .plugin(tauri_plugin_dialog::init())
         .plugin(tauri_plugin_fs::init())
+        .plugin(tauri_plugin_notification::init())
         .setup(|app| {
             let app_handle = app.handle();
             let app_data_dir = app_handle
                 .path()
                 .app_data_dir()
                 .expect("failed to get app data dir");
+            // From here on a panic, even one failing setup, leaves a crash report for the next launch
+            crash_report::set_crash_dir(app_data_dir.join("logs"));
             
             // Create app data dir if it doesn't exist
             if !app_data_dir.exists() {
@@ -931,12 +1881,37 @@ fn main() {
             let track_preferences_manager = TrackPreferencesManager::new(app_data_dir.clone());
             app.manage(track_preferences_manager);
 
+            analytics::init(app_data_dir.clone());
+
             let settings_manager = SettingsManager::new(app_data_dir.clone());
             app.manage(settings_manager);
 
+            let imdb_resolver = ImdbResolver::new(app_data_dir.clone());
+            app.manage(imdb_resolver);
+
+            let id_mapping_manager = IdMappingManager::new(app_data_dir.clone());
+            app.manage(id_mapping_manager);
+
+            let scrobble_manager = ScrobbleManager::new(app_data_dir.clone());
+            app.manage(scrobble_manager);
+
+            let library_manager = LibraryManager::new(app_data_dir.clone());
+            app.manage(library_manager);
+
+            let backup_manager = BackupManager::new(app_data_dir.clone());
+            app.manage(backup_manager);
+
+            let provider_config_manager = ProviderConfigManager::new(app_data_dir.clone());
+            app.manage(provider_config_manager);
+
             let font_manager = FontManager::new(&app_handle)
                 .expect("failed to create font manager");
             app.manage(font_manager);
+            // Index the system fonts before the player first asks about one
+            std::thread::spawn(font_manager::warm_system_fonts);
+
+            // Make sure ffmpeg and ffprobe are there without holding up the window
+            crash_report::spawn_logged("ffmpeg bootstrap", bootstrap_ffmpeg(app_handle.clone()));
 
             let logger = Logger::new(&app_handle)
                 .expect("failed to create logger");
@@ -947,14 +1922,294 @@ fn main() {
             app.manage(std::sync::Mutex::new(cache_metadata_manager));
 
             let torrent_dir = app_data_dir.join("torrents");
+            let http_port = tauri::async_runtime::block_on(app_handle.state::<SettingsManager>().get()).http_port;
             let torrent_manager = tauri::async_runtime::block_on(async {
-                TorrentManager::new(torrent_dir)
+                TorrentManager::new(torrent_dir, app_data_dir.clone(), http_port, MediaCache::new(app_data_dir.clone()))
                     .await
                     .expect("Failed to initialize torrent manager")
             });
             let torrent_manager_arc = Arc::new(torrent_manager);
             app.manage(torrent_manager_arc.clone());
 
+            let watch_folder = WatchFolder::new(app_handle.clone());
+            watch_folder.watch(tauri::async_runtime::block_on(app_handle.state::<SettingsManager>().get()).watch_folder);
+            app.manage(watch_folder);
+
+            // Watch playing streams for stalls so the player can offer another release
+            let manager_for_watchdog = torrent_manager_arc.clone();
+            let watchdog_handle = app_handle.clone();
+            crash_report::spawn_logged("stall watchdog", async move {
+                use tauri::Emitter;
+                loop {
+                    tokio::time::sleep(tokio::time::Duration::from_secs(5)).await;
+                    analytics::flush();
+                    manager_for_watchdog.update_playback_hold().await;
+                    for idle in manager_for_watchdog.check_idle_streams().await {
+                        let _ = watchdog_handle.emit("stream-idle", idle);
+                    }
+                    for stalled in manager_for_watchdog.check_stalled_streams().await {
+                        let auto_switch = watchdog_handle.state::<SettingsManager>().get().await.auto_switch_stalled_streams;
+                        tracing::info!("stream handle_id={} stalled for {}s", stalled.handle_id, stalled.stalled_seconds);
+                        if !auto_switch {
+                            notifications::notify(
+                                &watchdog_handle,
+                                notifications::NotificationKind::StreamStalled,
+                                &i18n::t("notification.stream_stalled.title"),
+                                &i18n::t("notification.stream_stalled.body"),
+                            ).await;
+                        }
+                        let _ = watchdog_handle.emit("stream-stalled", StreamStalledPayload {
+                            handle_id: stalled.handle_id,
+                            file_index: stalled.file_index,
+                            stalled_seconds: stalled.stalled_seconds,
+                            auto_switch,
+                        });
+                    }
+                }
+            });
+
+            // Progress of the player's metadata reads
+            let mut probe_events = torrent_manager_arc.subscribe_metadata_probes();
+            let probe_handle = app_handle.clone();
+            crash_report::spawn_logged("metadata probe events", async move {
+                use tauri::Emitter;
+                loop {
+                    match probe_events.recv().await {
+                        Ok(progress) => {
+                            let _ = probe_handle.emit("metadata-probe-progress", progress);
+                        }
+                        Err(tokio::sync::broadcast::error::RecvError::Lagged(_)) => continue,
+                        Err(tokio::sync::broadcast::error::RecvError::Closed) => break,
+                    }
+                }
+            });
+
+            // Stream sessions being created, buffering, playing and stopping
+            let mut lifecycle_events = torrent_manager_arc.subscribe_stream_lifecycle();
+            let lifecycle_handle = app_handle.clone();
+            crash_report::spawn_logged("stream lifecycle events", async move {
+                use tauri::Emitter;
+                loop {
+                    match lifecycle_events.recv().await {
+                        Ok(event) => {
+                            let _ = lifecycle_handle.emit("stream-lifecycle", event);
+                        }
+                        Err(tokio::sync::broadcast::error::RecvError::Lagged(_)) => continue,
+                        Err(tokio::sync::broadcast::error::RecvError::Closed) => break,
+                    }
+                }
+            });
+
+            // Let every window know when settings change, whichever one saved them
+            let mut settings_events = app_handle.state::<SettingsManager>().subscribe();
+            let settings_handle = app_handle.clone();
+            crash_report::spawn_logged("settings events", async move {
+                use tauri::Emitter;
+                loop {
+                    match settings_events.recv().await {
+                        Ok(settings) => {
+                            let _ = settings_handle.emit("settings-changed", settings);
+                        }
+                        Err(tokio::sync::broadcast::error::RecvError::Lagged(_)) => continue,
+                        Err(tokio::sync::broadcast::error::RecvError::Closed) => break,
+                    }
+                }
+            });
+
+            // Apply scheduled bandwidth rules; checked every 30s so boundaries are crossed on
+            // time, and right away when the rules are edited
+            let manager_for_bandwidth = torrent_manager_arc.clone();
+            let bandwidth_handle = app_handle.clone();
+            let mut bandwidth_changes = app_handle.state::<SettingsManager>().subscribe();
+            crash_report::spawn_logged("bandwidth schedule", async move {
+                let mut applied: Option<Option<settings::BandwidthRule>> = None;
+                loop {
+                    let rule = bandwidth_handle.state::<SettingsManager>().get_active_bandwidth_rule().await;
+                    if applied.as_ref() != Some(&rule) {
+                        let to_bps = |kbps: Option<u32>| kbps.map(|k| k.saturating_mul(1024));
+                        match &rule {
+                            Some(r) => {
+                                tracing::info!("bandwidth rule {}-{} active", r.start, r.end);
+                                manager_for_bandwidth.set_speed_limits(to_bps(r.download_limit_kbps), to_bps(r.upload_limit_kbps));
+                            }
+                            None => manager_for_bandwidth.set_speed_limits(None, None),
+                        }
+                        applied = Some(rule);
+                    }
+                    tokio::select! {
+                        _ = tokio::time::sleep(tokio::time::Duration::from_secs(30)) => {}
+                        _ = bandwidth_changes.recv() => {}
+                    }
+                }
+            });
+
+            // Pause downloads on battery saver / metered networks when enabled, and resume
+            // only the torrents that were paused this way once conditions clear
+            let manager_for_power = torrent_manager_arc.clone();
+            let power_handle = app_handle.clone();
+            let mut power_changes = app_handle.state::<SettingsManager>().subscribe();
+            crash_report::spawn_logged("power state", async move {
+                use tauri::Emitter;
+                let mut auto_paused: Option<Vec<usize>> = None;
+                loop {
+                    let settings = power_handle.state::<SettingsManager>().get().await;
+                    let reason = if settings.pause_on_battery_saver && power_state::is_battery_saver_active().await {
+                        Some("battery_saver".to_string())
+                    } else if settings.pause_on_metered_network && power_state::is_metered_connection().await {
+                        Some("metered_network".to_string())
+                    } else {
+                        None
+                    };
+
+                    match (&reason, auto_paused.take()) {
+                        (Some(reason), None) => match manager_for_power.pause_all().await {
+                            Ok(paused) => {
+                                tracing::info!("auto-paused {} torrents ({})", paused.len(), reason);
+                                auto_paused = Some(paused);
+                                let _ = power_handle.emit("downloads-auto-paused", DownloadsAutoPausedPayload {
+                                    paused: true,
+                                    reason: Some(reason.clone()),
+                                });
+                            }
+                            Err(e) => tracing::error!("failed to auto-pause torrents: {}", e),
+                        },
+                        (None, Some(paused)) => {
+                            for handle_id in &paused {
+                                if let Err(e) = manager_for_power.resume_torrent(*handle_id).await {
+                                    tracing::error!("failed to resume torrent {}: {}", handle_id, e);
+                                }
+                            }
+                            tracing::info!("resumed {} auto-paused torrents", paused.len());
+                            let _ = power_handle.emit("downloads-auto-paused", DownloadsAutoPausedPayload {
+                                paused: false,
+                                reason: None,
+                            });
+                        }
+                        (_, still_paused) => auto_paused = still_paused,
+                    }
+
+                    // Turning either option off resumes the downloads right away
+                    tokio::select! {
+                        _ = tokio::time::sleep(tokio::time::Duration::from_secs(60)) => {}
+                        _ = power_changes.recv() => {}
+                    }
+                }
+            });
+
+            // Drop cached tracks left corrupt or half-written, shortly after startup and then
+            // every few hours
+            let verify_handle = app_handle.clone();
+            crash_report::spawn_logged("cache verification", async move {
+                tokio::time::sleep(tokio::time::Duration::from_secs(5 * 60)).await;
+                loop {
+                    let report = verify_handle.state::<MediaCache>().verify().await;
+                    tracing::info!("cache integrity scan: checked {}, removed {} ({} bytes)", report.checked, report.removed, report.removed_bytes);
+                    tokio::time::sleep(tokio::time::Duration::from_secs(6 * 60 * 60)).await;
+                }
+            });
+
+            // Fire the download-complete hooks once per torrent. Torrents already finished at
+            // startup are recorded on the first pass without triggering anything.
+            let manager_for_hooks = torrent_manager_arc.clone();
+            let hooks_handle = app_handle.clone();
+            crash_report::spawn_logged("download hooks", async move {
+                let mut seen: Option<std::collections::HashSet<usize>> = None;
+                loop {
+                    let finished = manager_for_hooks.finished_torrents().await;
+                    match seen.as_mut() {
+                        None => seen = Some(finished.iter().map(|t| t.handle_id).collect()),
+                        Some(seen) => {
+                            let settings = hooks_handle.state::<SettingsManager>().get().await;
+                            for torrent in finished {
+                                if !seen.insert(torrent.handle_id) {
+                                    continue;
+                                }
+                                tracing::info!("download finished: {}", torrent.name);
+                                notifications::notify(&hooks_handle, notifications::NotificationKind::DownloadComplete, &i18n::t("notification.download_complete.title"), &torrent.name).await;
+                                let payload = download_hooks::DownloadCompletePayload::from_finished(&torrent);
+                                download_hooks::run(&settings, &payload).await;
+                            }
+                        }
+                    }
+                    tokio::time::sleep(tokio::time::Duration::from_secs(15)).await;
+                }
+            });
+
+            // Back up app data to the chosen folder once the newest backup is older than the
+            // configured interval
+            let backup_handle = app_handle.clone();
+            crash_report::spawn_logged("scheduled backups", async move {
+                loop {
+                    let settings = backup_handle.state::<SettingsManager>().get().await;
+                    if let Ok(dir) = backup_dir(&settings) {
+                        let backup_manager = backup_handle.state::<BackupManager>();
+                        if backup_manager.is_backup_due(&dir, settings.backup_interval_hours.max(1)) {
+                            if let Err(e) = backup_manager.create_backup(&dir, settings.backup_keep) {
+                                tracing::error!("scheduled backup failed: {}", e);
+                            }
+                        }
+                    }
+                    tokio::time::sleep(tokio::time::Duration::from_secs(60 * 60)).await;
+                }
+            });
+
+            // Keep the Prowlarr indexer list in sync, and sync again when its url or key changes
+            let prowlarr_handle = app_handle.clone();
+            let mut prowlarr_changes = app_handle.state::<SettingsManager>().subscribe();
+            crash_report::spawn_logged("prowlarr sync", async move {
+                loop {
+                    let settings = prowlarr_handle.state::<SettingsManager>().get().await;
+                    let synced = (settings.prowlarr_url.clone(), settings.prowlarr_api_key.clone());
+                    if let (Some(url), Some(api_key)) = (settings.prowlarr_url, settings.prowlarr_api_key) {
+                        if let Err(e) = prowlarr_handle.state::<ProviderConfigManager>().sync_prowlarr(&url, &api_key).await {
+                            tracing::error!("prowlarr sync failed: {}", e);
+                        }
+                    }
+
+                    let next_sync = tokio::time::sleep(tokio::time::Duration::from_secs(6 * 60 * 60));
+                    tokio::pin!(next_sync);
+                    loop {
+                        tokio::select! {
+                            _ = &mut next_sync => break,
+                            changed = prowlarr_changes.recv() => match changed {
+                                Ok(settings) if (settings.prowlarr_url.clone(), settings.prowlarr_api_key.clone()) != synced => break,
+                                Err(tokio::sync::broadcast::error::RecvError::Closed) => {
+                                    next_sync.as_mut().await;
+                                    break;
+                                }
+                                _ => {}
+                            },
+                        }
+                    }
+                }
+            });
+
+            // Organize completed episodes when downloads are kept rather than cleared after watching
+            let manager_for_organizer = torrent_manager_arc.clone();
+            let organizer_handle = app_handle.clone();
+            crash_report::spawn_logged("download organizer", async move {
+                loop {
+                    tokio::time::sleep(tokio::time::Duration::from_secs(60)).await;
+                    let settings = organizer_handle.state::<SettingsManager>().get().await;
+                    if !settings.organize_downloads || settings.clear_cache_after_watch {
+                        continue;
+                    }
+
+                    let completed = manager_for_organizer.completed_files().await;
+                    let target_root = settings.organize_dir
+                        .filter(|d| !d.trim().is_empty())
+                        .map(std::path::PathBuf::from);
+                    let library = organizer_handle.state::<LibraryManager>();
+                    let resolver = organizer_handle.state::<ImdbResolver>();
+                    let organized = library
+                        .organize_downloads(completed, &settings.organize_template, target_root, &resolver)
+                        .await;
+                    if !organized.is_empty() {
+                        tracing::info!("organized {} completed downloads", organized.len());
+                    }
+                }
+            });
+
             // Cleanup torrents on app close
             let manager_for_cleanup = torrent_manager_arc.clone();
             let main_window = app.get_webview_window("main").unwrap();
@@ -970,7 +2225,7 @@ fn main() {
                 if let tauri::WindowEvent::CloseRequested { .. } = event {
                     tauri::async_runtime::block_on(async {
                         if let Err(e) = manager_for_cleanup.cleanup_all().await {
-                            eprintln!("Error during cleanup: {}", e);
+                            tracing::error!("Error during cleanup: {}", e);
                         }
                     });
                 }
@@ -980,23 +2235,42 @@ fn main() {
         })
         .invoke_handler(tauri::generate_handler![
             torrent::add_torrent,
+            torrent::preview_torrent_files,
             torrent::get_torrent_info,
             torrent::list_torrents,
             torrent::prepare_stream,
             torrent::get_stream_status,
+            torrent::probe_stream_compatibility,
+            torrent::report_playback_position,
+            torrent::verify_file,
+            torrent::report_playback_paused,
+            torrent::stream_keep_alive,
             torrent::stop_stream,
+            torrent::close_stream,
+            torrent::list_active_streams,
             torrent::wipe_all_torrent_files,
             torrent::pause_torrent,
             torrent::resume_torrent,
+            torrent::update_file_selection,
+            torrent::reannounce,
+            torrent::force_recheck,
+            torrent::pause_all_torrents,
+            torrent::get_stream_access_log,
+            torrent::extend_metadata_probe,
+            torrent::cancel_metadata_probe,
+            torrent::resume_all_torrents,
             torrent::remove_torrent,
             torrent::get_download_dir,
             torrent::extract_subtitle,
             torrent::extract_audio_track,
+            torrent::extract_all_subtitles,
             search_nyaa,
             search_nyaa_filtered,
             search_eztv_by_imdb,
             save_torrent_selection,
             save_multiple_torrent_selections,
+            save_release_preference,
+            get_release_preference,
             get_saved_selection,
             get_all_torrent_selections,
             remove_saved_selection,
@@ -1009,6 +2283,7 @@ fn main() {
             load_transcoded_audio,
             save_font,
             check_font_installed,
+            find_system_fonts,
             list_fonts,
             get_fonts_dir,
             get_http_port,
@@ -1018,24 +2293,74 @@ fn main() {
             clear_watch_history,
             save_track_preference,
             get_track_preference,
-            save_settings,
+            patch_settings,
+            get_settings_sections,
             get_settings,
+            get_active_bandwidth_rule,
             check_external_player,
             open_in_external_player,
+            open_season_in_external_player,
             check_ffmpeg,
+            check_ffprobe,
+            cancel_ffmpeg_install,
+            get_ffmpeg_bootstrap,
+            get_ffmpeg_capabilities,
             install_ffmpeg,
             get_cache_stats,
+            get_cache_entry,
+            verify_cache,
+            get_session_stats,
+            get_library,
+            rescan_library,
+            import_local_file,
+            add_library_folder,
+            remove_library_folder,
+            play_local_file,
+            create_backup,
+            list_backups,
+            restore_backup,
+            get_local_stream_url,
             get_font_stats,
+            clear_fonts,
             clear_cache_item,
             logger::log_message,
+            notifications::send_notification,
+            scrobble::scrobble,
+            scrobble::get_scrobbler_status,
+            scrobble::begin_scrobbler_auth,
+            scrobble::complete_scrobbler_auth,
+            scrobble::disconnect_scrobbler,
+            parental::verify_parental_pin,
+            parental::set_parental_controls,
+            provider_config::get_provider_configs,
+            provider_config::save_provider_config,
+            provider_config::remove_provider_config,
+            provider_config::sync_prowlarr,
+            get_provider_definitions,
+            set_provider_mirrors,
+            get_provider_mirrors,
             cache_metadata::save_cache_metadata,
             cache_metadata::get_cache_metadata,
             cache_metadata::get_all_cache_metadata,
-            download_update,
-            install_update,
+            imdb_resolver::resolve_imdb_id,
+            crash_report::get_pending_crash_report,
+            crash_report::export_crash_report,
+            crash_report::dismiss_crash_report,
+            analytics::get_local_analytics,
+            analytics::clear_local_analytics,
+            updater::check_for_updates,
+            updater::download_update,
+            updater::install_update,
             open_external_url
         ])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while building tauri application")
+        .run(|_app, event| {
+            // Tasks holding running ffmpeg/ffprobe children are never dropped on exit
+            if let tauri::RunEvent::Exit = event {
+                ffmpeg_tools::kill_running();
+                analytics::flush();
+            }
+        });
 }
 
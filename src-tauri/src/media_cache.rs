@@ -3,14 +3,43 @@ use std::path::PathBuf;
 use std::sync::Arc;
 use tokio::sync::RwLock;
 use sha2::{Sha256, Digest};
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::SystemTime;
+use crate::{log_info, log_warn};
 
-#[derive(Clone, Copy, Debug)]
+// Size cap per track type in bytes, set from settings; 0 keeps everything
+static CACHE_LIMIT_BYTES: AtomicU64 = AtomicU64::new(0);
+
+pub fn set_cache_limit(mb: u64) {
+    CACHE_LIMIT_BYTES.store(mb * 1_048_576, Ordering::Relaxed);
+}
+
+// Live transcodes are far bigger than extracted tracks, so they get a cap of their own
+static TRANSCODE_LIMIT_BYTES: AtomicU64 = AtomicU64::new(0);
+
+pub fn set_transcode_cache_limit(mb: u64) {
+    TRANSCODE_LIMIT_BYTES.store(mb * 1_048_576, Ordering::Relaxed);
+}
+
+fn limit_bytes(track_type: TrackType) -> u64 {
+    match track_type {
+        TrackType::Transcode => TRANSCODE_LIMIT_BYTES.load(Ordering::Relaxed),
+        _ => CACHE_LIMIT_BYTES.load(Ordering::Relaxed),
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum TrackType {
     Subtitle,
     Audio,
     Torrent,
+    // Probed container metadata (MkvMetadata as JSON), keyed by info hash and file
+    Metadata,
+    // Output of the live audio transcode, keyed by info hash, file and track
+    Transcode,
 }
 
 impl TrackType {
@@ -19,10 +48,98 @@ impl TrackType {
             TrackType::Subtitle => "subtitles",
             TrackType::Audio => "audio",
             TrackType::Torrent => "torrents",
+            TrackType::Metadata => "metadata",
+            TrackType::Transcode => "transcodes",
+        }
+    }
+
+    fn label(&self) -> &str {
+        match self {
+            TrackType::Subtitle => "Subtitle",
+            TrackType::Audio => "Audio",
+            TrackType::Torrent => "Torrent",
+            TrackType::Metadata => "Metadata",
+            TrackType::Transcode => "Transcode",
         }
     }
 }
 
+/// One cached track on disk
+#[derive(Debug, Clone, Serialize)]
+pub struct CacheEntry {
+    pub size: u64,
+    // Unix milliseconds
+    pub created: Option<u64>,
+    // Bumped on every load, which is what eviction goes by
+    pub last_accessed: Option<u64>,
+}
+
+fn unix_millis(time: std::io::Result<SystemTime>) -> Option<u64> {
+    let since_epoch = time.ok()?.duration_since(SystemTime::UNIX_EPOCH).ok()?;
+    Some(since_epoch.as_millis() as u64)
+}
+
+/// Short hash that keeps cache file names unique even when ids differ only in ways the
+/// name itself doesn't show
+fn hash_key(cache_id: &str, file_index: usize, track_index: usize) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(format!("{}-{}-{}", cache_id, file_index, track_index));
+    let result = hasher.finalize();
+    format!("{:x}", result)[..8].to_string()
+}
+
+/// Outcome of a cache integrity scan
+#[derive(Debug, Default, Serialize)]
+pub struct CacheVerifyReport {
+    pub checked: usize,
+    pub removed: usize,
+    pub removed_bytes: u64,
+    pub removed_files: Vec<String>,
+}
+
+// Enough of a cached file to recognise its format
+const VERIFY_HEADER_BYTES: u64 = 64 * 1024;
+
+/// Whether a cached file looks like something the player can use. Only the start of audio
+/// and subtitle files is checked; metadata is small enough to parse whole.
+fn looks_valid(track_type: TrackType, data: &[u8]) -> bool {
+    if data.is_empty() {
+        return false;
+    }
+    match track_type {
+        // Extracted tracks are Matroska, transcodes are ADTS AAC or MP4
+        TrackType::Audio | TrackType::Transcode => {
+            data.starts_with(&[0x1A, 0x45, 0xDF, 0xA3])
+                || (data.len() >= 2 && data[0] == 0xFF && data[1] & 0xF6 == 0xF0)
+                || data.get(4..8) == Some(b"ftyp".as_slice())
+        }
+        TrackType::Subtitle => {
+            let text = String::from_utf8_lossy(data);
+            let text = text.trim_start_matches('\u{feff}').trim_start();
+            if text.starts_with("[Script Info]") || text.starts_with("WEBVTT") {
+                return true;
+            }
+            // SRT: a cue number, then a timing line
+            let mut lines = text.lines();
+            let numbered = lines.next().is_some_and(|l| !l.trim().is_empty() && l.trim().chars().all(|c| c.is_ascii_digit()));
+            numbered && lines.next().is_some_and(|l| l.contains("-->"))
+        }
+        TrackType::Metadata => serde_json::from_slice::<serde_json::Value>(data).is_ok(),
+        TrackType::Torrent => true,
+    }
+}
+
+// Filename format: {cache_id}_{file_index}_{track_index}_{hash}.cache
+fn cache_file_name(cache_id: &str, file_index: usize, track_index: usize) -> String {
+    format_file_name(cache_id, file_index, track_index, "cache")
+}
+
+// Tracks extracted into a specific format keep its extension so other players can open
+// them; the stem is the same, so stats and clearing by id still find them
+fn format_file_name(cache_id: &str, file_index: usize, track_index: usize, extension: &str) -> String {
+    format!("{}_{}_{}_{}.{}", cache_id, file_index, track_index, hash_key(cache_id, file_index, track_index), extension)
+}
+
 #[derive(Debug, Serialize)]
 pub struct CacheGroup {
     pub id: String,
@@ -57,24 +174,11 @@ impl MediaCache {
     }
 
     fn get_cache_path(&self, track_type: TrackType, cache_id: &str, file_index: usize, track_index: usize) -> PathBuf {
-        let filename = format!("{}_{}_{}_{}.cache", 
-            cache_id, 
-            file_index, 
-            track_index,
-            self.hash_key(cache_id, file_index, track_index)
-        );
-        self.get_cache_dir(track_type).join(filename)
-    }
-
-    fn hash_key(&self, cache_id: &str, file_index: usize, track_index: usize) -> String {
-        let mut hasher = Sha256::new();
-        hasher.update(format!("{}-{}-{}", cache_id, file_index, track_index));
-        let result = hasher.finalize();
-        format!("{:x}", result)[..8].to_string()
+        self.get_cache_dir(track_type).join(cache_file_name(cache_id, file_index, track_index))
     }
 
     // Helper to recursively calculate directory size
-    fn get_dir_size(path: &PathBuf) -> u64 {
+    pub(crate) fn get_dir_size(path: &PathBuf) -> u64 {
         let mut size = 0;
         if let Ok(entries) = fs::read_dir(path) {
             for entry in entries.flatten() {
@@ -90,11 +194,16 @@ impl MediaCache {
         size
     }
 
+    pub fn get_cache_size(&self, track_type: TrackType) -> u64 {
+        Self::get_dir_size(&self.get_cache_dir(track_type))
+    }
+
     pub async fn get_cache_stats(&self) -> Result<Vec<CacheGroup>, String> {
         let mut groups: HashMap<String, CacheGroup> = HashMap::new();
         
-        // Process Audio and Subtitle tracks
-        for track_type in [TrackType::Audio, TrackType::Subtitle] {
+        // Process Audio and Subtitle tracks; probed metadata and transcodes only count
+        // toward the total
+        for track_type in [TrackType::Audio, TrackType::Subtitle, TrackType::Metadata, TrackType::Transcode] {
             let cache_dir = self.get_cache_dir(track_type);
             if let Ok(entries) = fs::read_dir(&cache_dir) {
                 for entry in entries.flatten() {
@@ -210,8 +319,8 @@ impl MediaCache {
             return Ok(());
         }
         
-        // Handle regular cache deletion (audio/subtitle)
-        for track_type in [TrackType::Audio, TrackType::Subtitle] {
+        // Handle regular cache deletion (audio/subtitle/metadata/transcodes)
+        for track_type in [TrackType::Audio, TrackType::Subtitle, TrackType::Metadata, TrackType::Transcode] {
             let cache_dir = self.get_cache_dir(track_type);
             if let Ok(entries) = fs::read_dir(&cache_dir) {
                 for entry in entries.flatten() {
@@ -229,27 +338,165 @@ impl MediaCache {
     pub async fn save_track(&self, track_type: TrackType, cache_id: &str, file_index: usize, track_index: usize, data: Vec<u8>) -> Result<(), String> {
         let path = self.get_cache_path(track_type, cache_id, file_index, track_index);
         fs::write(&path, data).map_err(|e| format!("Failed to save track cache: {}", e))?;
-        println!("[{:?} Cache] Saved to {:?}", match track_type {
-            TrackType::Subtitle => "Subtitle",
-            TrackType::Audio => "Audio",
-            TrackType::Torrent => "Torrent",
-        }, path);
+        log_info!("[{} Cache] Saved to {:?}", track_type.label(), path);
+
+        let limit = limit_bytes(track_type);
+        if limit > 0 {
+            self.evict(track_type, limit)?;
+        }
         Ok(())
     }
 
     pub async fn load_track(&self, track_type: TrackType, cache_id: &str, file_index: usize, track_index: usize) -> Result<Option<Vec<u8>>, String> {
+        if !self.exists(track_type, cache_id, file_index, track_index) {
+            return Ok(None);
+        }
         let path = self.get_cache_path(track_type, cache_id, file_index, track_index);
-        if path.exists() {
-            let data = fs::read(&path).map_err(|e| format!("Failed to load track cache: {}", e))?;
-            println!("[{:?} Cache] Loaded {} bytes from {:?}", match track_type {
-                TrackType::Subtitle => "Subtitle",
-                TrackType::Audio => "Audio",
-                TrackType::Torrent => "Torrent",
-            }, data.len(), path);
-            Ok(Some(data))
-        } else {
-            Ok(None)
+        let data = fs::read(&path).map_err(|e| format!("Failed to load track cache: {}", e))?;
+        // Most filesystems don't keep access times, so the modification time stands in
+        if let Ok(file) = fs::File::options().write(true).open(&path) {
+            let _ = file.set_modified(SystemTime::now());
         }
+        log_info!("[{} Cache] Loaded {} bytes from {:?}", track_type.label(), data.len(), path);
+        Ok(Some(data))
+    }
+
+    /// Where a track extracted as `extension` (e.g. "srt", "mka") is cached
+    pub fn format_path(&self, track_type: TrackType, cache_id: &str, file_index: usize, track_index: usize, extension: &str) -> PathBuf {
+        self.get_cache_dir(track_type).join(format_file_name(cache_id, file_index, track_index, extension))
+    }
+
+    pub async fn save_track_as(&self, track_type: TrackType, cache_id: &str, file_index: usize, track_index: usize, extension: &str, data: Vec<u8>) -> Result<PathBuf, String> {
+        let path = self.format_path(track_type, cache_id, file_index, track_index, extension);
+        fs::write(&path, data).map_err(|e| format!("Failed to save track cache: {}", e))?;
+        log_info!("[{} Cache] Saved to {:?}", track_type.label(), path);
+
+        let limit = limit_bytes(track_type);
+        if limit > 0 {
+            self.evict(track_type, limit)?;
+        }
+        Ok(path)
+    }
+
+    /// Moves a finished file, e.g. a transcode written while it was streamed, into the
+    /// cache as `extension`
+    pub fn store_file_as(&self, track_type: TrackType, cache_id: &str, file_index: usize, track_index: usize, extension: &str, source: &std::path::Path) -> Result<PathBuf, String> {
+        let path = self.format_path(track_type, cache_id, file_index, track_index, extension);
+        // Temp files may sit on another drive, where a rename can't go
+        if fs::rename(source, &path).is_err() {
+            fs::copy(source, &path).map_err(|e| format!("Failed to save track cache: {}", e))?;
+            let _ = fs::remove_file(source);
+        }
+        log_info!("[{} Cache] Stored {:?}", track_type.label(), path);
+
+        let limit = limit_bytes(track_type);
+        if limit > 0 {
+            self.evict(track_type, limit)?;
+        }
+        Ok(path)
+    }
+
+    /// Path of a track cached in `extension`'s format, counted as an access for eviction
+    pub fn cached_format(&self, track_type: TrackType, cache_id: &str, file_index: usize, track_index: usize, extension: &str) -> Option<PathBuf> {
+        let path = self.format_path(track_type, cache_id, file_index, track_index, extension);
+        if !path.is_file() {
+            return None;
+        }
+        if let Ok(file) = fs::File::options().write(true).open(&path) {
+            let _ = file.set_modified(SystemTime::now());
+        }
+        Some(path)
+    }
+
+    pub fn exists(&self, track_type: TrackType, cache_id: &str, file_index: usize, track_index: usize) -> bool {
+        self.get_cache_path(track_type, cache_id, file_index, track_index).is_file()
+    }
+
+    pub fn entry(&self, track_type: TrackType, cache_id: &str, file_index: usize, track_index: usize) -> Option<CacheEntry> {
+        let metadata = fs::metadata(self.get_cache_path(track_type, cache_id, file_index, track_index)).ok()?;
+        if !metadata.is_file() {
+            return None;
+        }
+        Some(CacheEntry {
+            size: metadata.len(),
+            created: unix_millis(metadata.created()),
+            last_accessed: unix_millis(metadata.modified()),
+        })
+    }
+
+    /// Removes the least recently accessed tracks of a type until the rest fit in
+    /// `max_bytes`. Returns how many bytes were freed.
+    pub fn evict(&self, track_type: TrackType, max_bytes: u64) -> Result<u64, String> {
+        let cache_dir = self.get_cache_dir(track_type);
+        let mut entries: Vec<(PathBuf, u64, SystemTime)> = fs::read_dir(&cache_dir)
+            .map_err(|e| format!("Failed to read cache dir: {}", e))?
+            .flatten()
+            .filter_map(|entry| {
+                let metadata = entry.metadata().ok()?;
+                if !metadata.is_file() {
+                    return None;
+                }
+                let accessed = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+                Some((entry.path(), metadata.len(), accessed))
+            })
+            .collect();
+
+        let mut total: u64 = entries.iter().map(|(_, size, _)| size).sum();
+        entries.sort_by_key(|(_, _, accessed)| *accessed);
+
+        let mut freed = 0;
+        for (path, size, _) in entries {
+            if total <= max_bytes {
+                break;
+            }
+            if fs::remove_file(&path).is_ok() {
+                total -= size;
+                freed += size;
+            }
+        }
+        if freed > 0 {
+            log_info!("[{} Cache] Evicted {} bytes", track_type.label(), freed);
+        }
+        Ok(freed)
+    }
+
+    /// Checks every cached audio, subtitle and metadata file and removes the ones that are
+    /// empty or don't look like their format, e.g. left half-written by a crash
+    pub async fn verify(&self) -> CacheVerifyReport {
+        use std::io::Read;
+
+        let mut report = CacheVerifyReport::default();
+        for track_type in [TrackType::Audio, TrackType::Subtitle, TrackType::Metadata, TrackType::Transcode] {
+            let Ok(entries) = fs::read_dir(self.get_cache_dir(track_type)) else { continue };
+            for entry in entries.flatten() {
+                let Ok(metadata) = entry.metadata() else { continue };
+                if !metadata.is_file() {
+                    continue;
+                }
+                let path = entry.path();
+                let mut data = Vec::new();
+                let read = match track_type {
+                    TrackType::Metadata => fs::File::open(&path).and_then(|mut f| f.read_to_end(&mut data)),
+                    _ => fs::File::open(&path).and_then(|f| f.take(VERIFY_HEADER_BYTES).read_to_end(&mut data)),
+                };
+                // Unreadable right now (e.g. still being written on Windows) is not corrupt
+                if read.is_err() {
+                    continue;
+                }
+
+                report.checked += 1;
+                if looks_valid(track_type, &data) {
+                    continue;
+                }
+                if fs::remove_file(&path).is_ok() {
+                    log_warn!("[{} Cache] Removed corrupt entry {:?}", track_type.label(), path);
+                    report.removed += 1;
+                    report.removed_bytes += metadata.len();
+                    report.removed_files.push(entry.file_name().to_string_lossy().to_string());
+                }
+            }
+        }
+        report
     }
 
     pub async fn clear_cache(&self, track_type: TrackType) -> Result<(), String> {
@@ -259,12 +506,150 @@ impl MediaCache {
                 .map_err(|e| format!("Failed to clear cache: {}", e))?;
             fs::create_dir_all(&cache_dir)
                 .map_err(|e| format!("Failed to recreate cache dir: {}", e))?;
-            println!("[{:?} Cache] Cleared all cached tracks", match track_type {
-                TrackType::Subtitle => "Subtitle",
-                TrackType::Audio => "Audio",
-                TrackType::Torrent => "Torrent",
-            });
+            log_info!("[{} Cache] Cleared all cached tracks", track_type.label());
         }
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn touch(cache: &MediaCache, track_type: TrackType, cache_id: &str, age_secs: u64) {
+        let path = cache.get_cache_path(track_type, cache_id, 0, 0);
+        let file = fs::File::options().write(true).open(path).unwrap();
+        file.set_modified(SystemTime::now() - Duration::from_secs(age_secs)).unwrap();
+    }
+
+    #[test]
+    fn hash_key_is_stable_and_short() {
+        let key = hash_key("1399", 2, 3);
+        assert_eq!(key, hash_key("1399", 2, 3));
+        assert_eq!(key.len(), 8);
+        assert!(key.chars().all(|c| c.is_ascii_hexdigit()));
+    }
+
+    #[test]
+    fn hash_key_separates_ids_and_indexes() {
+        let key = hash_key("1399", 2, 3);
+        assert_ne!(key, hash_key("1399", 3, 2));
+        assert_ne!(key, hash_key("1398", 2, 3));
+        assert_ne!(key, hash_key("1399", 2, 4));
+    }
+
+    #[test]
+    fn file_name_starts_with_cache_id() {
+        // get_cache_stats and clear_cache_by_id group files by this prefix
+        let name = cache_file_name("1399", 2, 3);
+        let parts: Vec<&str> = name.trim_end_matches(".cache").split('_').collect();
+        assert_eq!(parts, vec!["1399", "2", "3", hash_key("1399", 2, 3).as_str()]);
+    }
+
+    #[test]
+    fn recognises_cached_formats() {
+        assert!(looks_valid(TrackType::Audio, &[0x1A, 0x45, 0xDF, 0xA3, 0x01]));
+        assert!(looks_valid(TrackType::Audio, &[0xFF, 0xF1, 0x50, 0x80]));
+        assert!(!looks_valid(TrackType::Audio, &[0; 16]));
+        assert!(looks_valid(TrackType::Subtitle, "\u{feff}[Script Info]\nTitle: x".as_bytes()));
+        assert!(looks_valid(TrackType::Subtitle, b"1\n00:00:01,000 --> 00:00:02,000\nHi\n"));
+        assert!(!looks_valid(TrackType::Subtitle, b"\n\n"));
+        assert!(!looks_valid(TrackType::Subtitle, b""));
+        assert!(looks_valid(TrackType::Metadata, br#"{"duration":1.0}"#));
+        assert!(!looks_valid(TrackType::Metadata, br#"{"duration":"#));
+    }
+
+    #[tokio::test]
+    async fn verify_removes_corrupt_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = MediaCache::new(dir.path().to_path_buf());
+        cache.save_track(TrackType::Subtitle, "good", 0, 0, b"WEBVTT\n".to_vec()).await.unwrap();
+        cache.save_track(TrackType::Subtitle, "empty", 0, 0, Vec::new()).await.unwrap();
+        cache.save_track(TrackType::Metadata, "cut", 0, 0, b"{\"tracks\": [".to_vec()).await.unwrap();
+
+        let report = cache.verify().await;
+        assert_eq!(report.checked, 3);
+        assert_eq!(report.removed, 2);
+        assert!(cache.exists(TrackType::Subtitle, "good", 0, 0));
+        assert!(!cache.exists(TrackType::Subtitle, "empty", 0, 0));
+        assert!(!cache.exists(TrackType::Metadata, "cut", 0, 0));
+    }
+
+    #[tokio::test]
+    async fn saved_track_is_found() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = MediaCache::new(dir.path().to_path_buf());
+        assert!(!cache.exists(TrackType::Subtitle, "1399", 0, 1));
+        assert!(cache.load_track(TrackType::Subtitle, "1399", 0, 1).await.unwrap().is_none());
+
+        cache.save_track(TrackType::Subtitle, "1399", 0, 1, b"WEBVTT".to_vec()).await.unwrap();
+        assert!(cache.exists(TrackType::Subtitle, "1399", 0, 1));
+        assert!(!cache.exists(TrackType::Audio, "1399", 0, 1));
+        assert_eq!(cache.entry(TrackType::Subtitle, "1399", 0, 1).unwrap().size, 6);
+        assert_eq!(cache.load_track(TrackType::Subtitle, "1399", 0, 1).await.unwrap().unwrap(), b"WEBVTT");
+    }
+
+    #[tokio::test]
+    async fn format_copies_sit_next_to_the_cached_track() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = MediaCache::new(dir.path().to_path_buf());
+        assert!(cache.cached_format(TrackType::Subtitle, "1399", 0, 1, "srt").is_none());
+
+        let path = cache.save_track_as(TrackType::Subtitle, "1399", 0, 1, "srt", b"1\n".to_vec()).await.unwrap();
+        assert_eq!(cache.cached_format(TrackType::Subtitle, "1399", 0, 1, "srt"), Some(path.clone()));
+        assert!(cache.cached_format(TrackType::Subtitle, "1399", 0, 1, "ass").is_none());
+        assert!(!cache.exists(TrackType::Subtitle, "1399", 0, 1));
+
+        let name = path.file_name().unwrap().to_string_lossy().to_string();
+        assert!(name.starts_with("1399_0_1_") && name.ends_with(".srt"));
+        cache.clear_cache_by_id("1399").await.unwrap();
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn stored_files_move_into_the_cache() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = MediaCache::new(dir.path().join("cache"));
+        let source = dir.path().join("transcode.part");
+        fs::write(&source, [0xFF, 0xF1, 0x50, 0x80]).unwrap();
+
+        let path = cache.store_file_as(TrackType::Transcode, "abc", 0, 1, "stereo-off.aac", &source).unwrap();
+        assert!(!source.exists());
+        assert_eq!(cache.cached_format(TrackType::Transcode, "abc", 0, 1, "stereo-off.aac"), Some(path));
+        assert!(cache.cached_format(TrackType::Transcode, "abc", 0, 1, "surround-off.aac").is_none());
+        assert!(cache.cached_format(TrackType::Audio, "abc", 0, 1, "stereo-off.aac").is_none());
+    }
+
+    #[tokio::test]
+    async fn evicts_least_recently_accessed_first() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = MediaCache::new(dir.path().to_path_buf());
+        for (id, age) in [("old", 300), ("middle", 200), ("new", 100)] {
+            cache.save_track(TrackType::Audio, id, 0, 0, vec![0; 100]).await.unwrap();
+            touch(&cache, TrackType::Audio, id, age);
+        }
+
+        // Reading the oldest track makes it the most recent one
+        cache.load_track(TrackType::Audio, "old", 0, 0).await.unwrap();
+
+        let freed = cache.evict(TrackType::Audio, 200).unwrap();
+        assert_eq!(freed, 100);
+        assert!(cache.exists(TrackType::Audio, "old", 0, 0));
+        assert!(!cache.exists(TrackType::Audio, "middle", 0, 0));
+        assert!(cache.exists(TrackType::Audio, "new", 0, 0));
+    }
+
+    #[tokio::test]
+    async fn eviction_only_touches_its_own_type() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = MediaCache::new(dir.path().to_path_buf());
+        cache.save_track(TrackType::Audio, "1399", 0, 0, vec![0; 100]).await.unwrap();
+        cache.save_track(TrackType::Subtitle, "1399", 0, 0, vec![0; 100]).await.unwrap();
+
+        assert_eq!(cache.evict(TrackType::Audio, 0).unwrap(), 100);
+        assert!(!cache.exists(TrackType::Audio, "1399", 0, 0));
+        assert!(cache.exists(TrackType::Subtitle, "1399", 0, 0));
+        assert_eq!(cache.evict(TrackType::Subtitle, 100).unwrap(), 0);
+    }
+}
@@ -0,0 +1,45 @@
+use crate::settings::SettingsManager;
+use serde::Deserialize;
+use tauri::{AppHandle, Manager};
+use tauri_plugin_notification::NotificationExt;
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NotificationKind {
+    DownloadComplete,
+    TranscodeComplete,
+    StreamStalled,
+    NewEpisode,
+    FfmpegInstalled,
+}
+
+/// Shows an OS notification unless the user turned this kind off
+pub async fn notify(app: &AppHandle, kind: NotificationKind, title: &str, body: &str) {
+    let settings = app.state::<SettingsManager>().get().await.notifications;
+    let enabled = match kind {
+        NotificationKind::DownloadComplete => settings.download_complete,
+        NotificationKind::TranscodeComplete => settings.transcode_complete,
+        NotificationKind::StreamStalled => settings.stream_stalled,
+        NotificationKind::NewEpisode => settings.new_episode,
+        NotificationKind::FfmpegInstalled => settings.ffmpeg_installed,
+    };
+    if !enabled {
+        return;
+    }
+
+    if let Err(e) = app.notification().builder().title(title).body(body).show() {
+        tracing::warn!("failed to show notification: {}", e);
+    }
+}
+
+// New episodes are found by the frontend, which has the user's list and TMDB data
+#[tauri::command]
+pub async fn send_notification(
+    app: AppHandle,
+    kind: NotificationKind,
+    title: String,
+    body: String,
+) -> Result<(), String> {
+    notify(&app, kind, &title, &body).await;
+    Ok(())
+}
@@ -0,0 +1,214 @@
+use crate::imdb_resolver::ImdbResolver;
+use crate::settings::{ParentalControls, SettingsManager};
+use base64::{engine::general_purpose::STANDARD_NO_PAD, Engine as _};
+use rand::RngCore;
+use sha2::Sha256;
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tauri::State;
+
+// Choices for the maximum rating, strictest first
+const CERTIFICATIONS: &[&str] = &["G", "PG", "PG-13", "R", "NC-17"];
+
+// Stored PIN hashes look like "pbkdf2-sha256$rounds$salt$hash"
+const PIN_HASH_SCHEME: &str = "pbkdf2-sha256";
+const PIN_HASH_ROUNDS: u32 = 210_000;
+const PIN_SALT_BYTES: usize = 16;
+
+// Wrong PINs allowed before each further attempt has to wait, and the longest wait
+const FREE_PIN_ATTEMPTS: u32 = 3;
+const PIN_BACKOFF_BASE: Duration = Duration::from_secs(30);
+const PIN_BACKOFF_MAX: Duration = Duration::from_secs(15 * 60);
+
+// Failed PIN attempts in a row, and when the last one was
+static PIN_FAILURES: Mutex<(u32, Option<Instant>)> = Mutex::new((0, None));
+
+// Whether any restriction is on. Set from settings.
+static RESTRICTED: AtomicBool = AtomicBool::new(false);
+// Torrent sessions that passed the checks in prepare_stream. While restricted, the
+// stream server serves only these, so its URLs can't be used to get around them.
+static CLEARED_SESSIONS: Mutex<Option<HashSet<usize>>> = Mutex::new(None);
+
+fn pbkdf2_pin(pin: &str, salt: &[u8], rounds: u32) -> [u8; 32] {
+    let mut hash = [0u8; 32];
+    pbkdf2::pbkdf2_hmac::<Sha256>(pin.trim().as_bytes(), salt, rounds, &mut hash);
+    hash
+}
+
+fn hash_pin(pin: &str) -> String {
+    let mut salt = [0u8; PIN_SALT_BYTES];
+    rand::rngs::OsRng.fill_bytes(&mut salt);
+    let hash = pbkdf2_pin(pin, &salt, PIN_HASH_ROUNDS);
+    format!("{}${}${}${}", PIN_HASH_SCHEME, PIN_HASH_ROUNDS, STANDARD_NO_PAD.encode(salt), STANDARD_NO_PAD.encode(hash))
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+fn pin_matches(stored: &str, pin: &str) -> bool {
+    let parts: Vec<&str> = stored.split('$').collect();
+    match parts.as_slice() {
+        [PIN_HASH_SCHEME, rounds, salt, hash] => {
+            let (Ok(rounds), Ok(salt), Ok(hash)) = (rounds.parse(), STANDARD_NO_PAD.decode(salt), STANDARD_NO_PAD.decode(hash)) else {
+                return false;
+            };
+            constant_time_eq(&pbkdf2_pin(pin, &salt, rounds), &hash)
+        }
+        _ => false,
+    }
+}
+
+// How long the next attempt has to wait after `failures` wrong PINs in a row
+fn pin_backoff(failures: u32) -> Duration {
+    if failures < FREE_PIN_ATTEMPTS {
+        return Duration::ZERO;
+    }
+    let doublings = (failures - FREE_PIN_ATTEMPTS).min(16);
+    PIN_BACKOFF_BASE.saturating_mul(1 << doublings).min(PIN_BACKOFF_MAX)
+}
+
+/// Called from apply_settings
+pub fn set_restrictions(controls: &ParentalControls) {
+    RESTRICTED.store(controls.block_adult || controls.max_certification.is_some(), Ordering::Relaxed);
+}
+
+/// Lets the stream server serve a torrent session that passed the checks
+pub fn clear_session(session_id: usize) {
+    if let Ok(mut sessions) = CLEARED_SESSIONS.lock() {
+        sessions.get_or_insert_with(HashSet::new).insert(session_id);
+    }
+}
+
+/// Whether the stream server may serve a torrent session
+pub fn session_allowed(session_id: usize) -> bool {
+    if !RESTRICTED.load(Ordering::Relaxed) {
+        return true;
+    }
+    CLEARED_SESSIONS.lock()
+        .map(|sessions| sessions.as_ref().is_some_and(|s| s.contains(&session_id)))
+        .unwrap_or(false)
+}
+
+/// Minimum viewer age for a US movie or TV rating, so the two scales can be compared
+fn certification_level(certification: &str) -> Option<u8> {
+    match certification.trim().to_uppercase().as_str() {
+        "G" | "TV-Y" | "TV-G" => Some(0),
+        "TV-Y7" | "TV-Y7-FV" => Some(7),
+        "PG" | "TV-PG" => Some(10),
+        "PG-13" => Some(13),
+        "TV-14" => Some(14),
+        "R" | "TV-MA" => Some(17),
+        "NC-17" => Some(18),
+        _ => None,
+    }
+}
+
+impl ParentalControls {
+    /// Checks the PIN, refusing to while wrong guesses are being backed off from. The
+    /// hashing is slow on purpose, so it runs off the async runtime.
+    pub async fn verify_pin(&self, pin: &str) -> Result<bool, String> {
+        let Some(stored) = self.pin_hash.clone() else {
+            return Ok(true);
+        };
+
+        let (count, last) = *PIN_FAILURES.lock().map_err(|e| e.to_string())?;
+        if let Some(last) = last {
+            let wait = pin_backoff(count).saturating_sub(last.elapsed());
+            if !wait.is_zero() {
+                return Err(crate::i18n::t_args("error.pin_backoff", &[("seconds", &wait.as_secs().max(1))]));
+            }
+        }
+
+        let pin = pin.to_string();
+        let matches = tokio::task::spawn_blocking(move || pin_matches(&stored, &pin))
+            .await
+            .map_err(|e| e.to_string())?;
+
+        let mut failures = PIN_FAILURES.lock().map_err(|e| e.to_string())?;
+        *failures = if matches { (0, None) } else { (failures.0 + 1, Some(Instant::now())) };
+        Ok(matches)
+    }
+}
+
+/// Refuses a torrent whose name marks it as adult while adult content is blocked
+pub fn check_torrent_name(controls: &ParentalControls, name: &str) -> Result<(), String> {
+    if controls.block_adult && crate::search::looks_adult(name) {
+        tracing::info!("parental controls: blocked torrent {}", name);
+        return Err(crate::i18n::t("error.parental_blocked_adult"));
+    }
+    Ok(())
+}
+
+/// Errors when the title's rating is above the allowed maximum. Unrated titles pass,
+/// since a lot of anime never gets a US rating.
+pub async fn check_title(
+    controls: &ParentalControls,
+    resolver: &ImdbResolver,
+    tmdb_id: u32,
+    media_type: &str,
+) -> Result<(), String> {
+    let Some(max_level) = controls.max_certification.as_deref().and_then(certification_level) else {
+        return Ok(());
+    };
+
+    let Some(certification) = resolver.certification(tmdb_id, media_type).await? else {
+        return Ok(());
+    };
+
+    match certification_level(&certification) {
+        Some(level) if level > max_level => {
+            tracing::info!("parental controls: blocked {}:{} rated {}", media_type, tmdb_id, certification);
+            Err(crate::i18n::t_args("error.parental_blocked", &[("rating", &certification)]))
+        }
+        _ => Ok(()),
+    }
+}
+
+#[tauri::command]
+pub async fn verify_parental_pin(
+    settings_manager: State<'_, SettingsManager>,
+    pin: String,
+) -> Result<bool, String> {
+    settings_manager.get().await.parental.verify_pin(&pin).await
+}
+
+/// Updates the restrictions. Needs the current PIN once one is set; `new_pin` replaces
+/// it, and an empty `new_pin` removes it.
+#[tauri::command]
+pub async fn set_parental_controls(
+    settings_manager: State<'_, SettingsManager>,
+    pin: Option<String>,
+    block_adult: bool,
+    max_certification: Option<String>,
+    new_pin: Option<String>,
+) -> Result<(), String> {
+    let mut settings = settings_manager.get().await;
+    if !settings.parental.verify_pin(pin.as_deref().unwrap_or("")).await? {
+        return Err(crate::i18n::t("error.incorrect_pin"));
+    }
+
+    let max_certification = max_certification.filter(|c| !c.is_empty());
+    if let Some(ref certification) = max_certification {
+        if !CERTIFICATIONS.contains(&certification.as_str()) {
+            return Err(format!("Unknown certification: {}", certification));
+        }
+    }
+
+    settings.parental.block_adult = block_adult;
+    settings.parental.max_certification = max_certification;
+    if let Some(new_pin) = new_pin {
+        settings.parental.pin_hash = match new_pin.trim().to_string() {
+            pin if pin.is_empty() => None,
+            pin => Some(
+                tokio::task::spawn_blocking(move || hash_pin(&pin))
+                    .await
+                    .map_err(|e| e.to_string())?,
+            ),
+        };
+    }
+
+    settings_manager.save(settings).await
+}
@@ -0,0 +1,85 @@
+use tokio::process::Command;
+
+// There is no cross-platform API for these, so each OS gets asked through its own tooling.
+// Anything that fails to answer counts as "not active" so downloads are never paused by mistake.
+
+async fn command_output(program: &str, args: &[&str]) -> Option<String> {
+    let mut cmd = Command::new(program);
+    cmd.args(args);
+
+    #[cfg(target_os = "windows")]
+    cmd.creation_flags(0x08000000);
+
+    let output = cmd.output().await.ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    Some(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+#[cfg(target_os = "windows")]
+async fn powershell(script: &str) -> Option<String> {
+    command_output("powershell", &["-NoProfile", "-NonInteractive", "-Command", script]).await
+}
+
+/// Whether the OS is in battery saver / low power mode
+pub async fn is_battery_saver_active() -> bool {
+    #[cfg(target_os = "windows")]
+    {
+        powershell("[Windows.System.Power.PowerManager,Windows.System.Power,ContentType=WindowsRuntime]::EnergySaverStatus")
+            .await
+            .map(|status| status.eq_ignore_ascii_case("On"))
+            .unwrap_or(false)
+    }
+
+    #[cfg(target_os = "macos")]
+    {
+        command_output("pmset", &["-g"])
+            .await
+            .map(|out| out.lines().any(|line| {
+                let mut parts = line.split_whitespace();
+                parts.next() == Some("lowpowermode") && parts.next() == Some("1")
+            }))
+            .unwrap_or(false)
+    }
+
+    #[cfg(target_os = "linux")]
+    {
+        command_output("powerprofilesctl", &["get"])
+            .await
+            .map(|profile| profile == "power-saver")
+            .unwrap_or(false)
+    }
+
+    #[cfg(not(any(target_os = "windows", target_os = "macos", target_os = "linux")))]
+    {
+        false
+    }
+}
+
+/// Whether the active network connection is marked as metered
+pub async fn is_metered_connection() -> bool {
+    #[cfg(target_os = "windows")]
+    {
+        // "Unrestricted" is the only cost type that isn't metered
+        powershell("[Windows.Networking.Connectivity.NetworkInformation,Windows.Networking.Connectivity,ContentType=WindowsRuntime]::GetInternetConnectionProfile().GetConnectionCost().NetworkCostType")
+            .await
+            .map(|cost| !cost.is_empty() && !cost.eq_ignore_ascii_case("Unrestricted") && !cost.eq_ignore_ascii_case("Unknown"))
+            .unwrap_or(false)
+    }
+
+    #[cfg(target_os = "linux")]
+    {
+        // NetworkManager reports "yes", "yes (guessed)", "no" or "unknown" per device
+        command_output("nmcli", &["-t", "-g", "GENERAL.METERED", "dev", "show"])
+            .await
+            .map(|out| out.lines().any(|line| line.trim().starts_with("yes")))
+            .unwrap_or(false)
+    }
+
+    // macOS doesn't expose the Low Data Mode flag to command line tools
+    #[cfg(not(any(target_os = "windows", target_os = "linux")))]
+    {
+        false
+    }
+}
@@ -0,0 +1,317 @@
+use crate::search::http::{self, TrackerCredentials};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tauri::State;
+use tokio::sync::RwLock;
+
+/// A user-configured tracker or Torznab indexer. Cookies, passkeys and API keys live
+/// in the keychain.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProviderConfig {
+    pub id: String,
+    pub name: String,
+    #[serde(flatten)]
+    pub credentials: TrackerCredentials,
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    // Torznab api endpoint; entries without one only supply credentials
+    #[serde(default)]
+    pub torznab_url: Option<String>,
+    #[serde(default)]
+    pub api_key: Option<String>,
+    // "search", "tv-search", "movie-search"
+    #[serde(default)]
+    pub capabilities: Vec<String>,
+    // Newznab category ids, e.g. 2000 movies, 5000 TV, 5070 anime
+    #[serde(default)]
+    pub categories: Vec<u32>,
+    // "prowlarr" for entries managed by the Prowlarr sync
+    #[serde(default)]
+    pub synced_from: Option<String>,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ProwlarrIndexer {
+    id: u32,
+    name: String,
+    #[serde(default)]
+    enable: bool,
+    #[serde(default)]
+    protocol: String,
+    #[serde(default)]
+    capabilities: ProwlarrCapabilities,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+struct ProwlarrCapabilities {
+    #[serde(default)]
+    categories: Vec<ProwlarrCategory>,
+    #[serde(default)]
+    search_params: Vec<String>,
+    #[serde(default)]
+    tv_search_params: Vec<String>,
+    #[serde(default)]
+    movie_search_params: Vec<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ProwlarrCategory {
+    id: u32,
+    #[serde(default)]
+    sub_categories: Vec<ProwlarrCategory>,
+}
+
+fn flatten_categories(categories: &[ProwlarrCategory], out: &mut Vec<u32>) {
+    for category in categories {
+        out.push(category.id);
+        flatten_categories(&category.sub_categories, out);
+    }
+}
+
+impl ProviderConfig {
+    /// Whether this indexer should be queried for the given media type ("tv", "movie", "anime")
+    pub fn supports(&self, media_type: &str) -> bool {
+        if !self.enabled || self.torznab_url.is_none() {
+            return false;
+        }
+        let (capability, range) = match media_type {
+            "movie" => ("movie-search", 2000..3000),
+            _ => ("tv-search", 5000..6000),
+        };
+        let has_capability = self.capabilities.is_empty()
+            || self.capabilities.iter().any(|c| c == capability || c == "search");
+        let has_category = self.categories.is_empty() || self.categories.iter().any(|c| range.contains(c));
+        has_capability && has_category
+    }
+
+    /// The categories to query for a media type, so a general indexer doesn't return music
+    pub fn categories_for(&self, media_type: &str) -> Vec<u32> {
+        let range = match media_type {
+            "movie" => 2000..3000,
+            "anime" => 5070..5080,
+            _ => 5000..6000,
+        };
+        let matching: Vec<u32> = self.categories.iter().copied().filter(|c| range.contains(c)).collect();
+        // Indexers that only list the parent TV category still carry anime under it
+        if matching.is_empty() && media_type == "anime" {
+            return self.categories_for("tv");
+        }
+        matching
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct ProviderConfigData {
+    pub providers: Vec<ProviderConfig>,
+}
+
+fn cookie_key(id: &str) -> String {
+    format!("provider.{}.cookie", id)
+}
+
+fn passkey_key(id: &str) -> String {
+    format!("provider.{}.passkey", id)
+}
+
+fn api_key_key(id: &str) -> String {
+    format!("provider.{}.api_key", id)
+}
+
+pub struct ProviderConfigManager {
+    file_path: PathBuf,
+    data: Arc<RwLock<ProviderConfigData>>,
+}
+
+impl ProviderConfigManager {
+    pub fn new(app_data_dir: PathBuf) -> Self {
+        let file_path = app_data_dir.join("providers.json");
+        let mut data: ProviderConfigData = if file_path.exists() {
+            let content = fs::read_to_string(&file_path).unwrap_or_default();
+            serde_json::from_str(&content).unwrap_or_default()
+        } else {
+            ProviderConfigData::default()
+        };
+
+        // Synced entries never hold secrets of their own
+        for provider in data.providers.iter_mut().filter(|p| p.synced_from.is_none()) {
+            let credentials = &mut provider.credentials;
+            credentials.cookie = credentials.cookie.take().or_else(|| crate::secrets::get(&cookie_key(&provider.id)));
+            credentials.passkey = credentials.passkey.take().or_else(|| crate::secrets::get(&passkey_key(&provider.id)));
+            provider.api_key = provider.api_key.take().or_else(|| crate::secrets::get(&api_key_key(&provider.id)));
+        }
+        apply_credentials(&data);
+
+        Self {
+            file_path,
+            data: Arc::new(RwLock::new(data)),
+        }
+    }
+
+    fn persist(&self, data: &ProviderConfigData) {
+        let mut on_disk = data.clone();
+        for provider in on_disk.providers.iter_mut().filter(|p| p.synced_from.is_none()) {
+            let credentials = &mut provider.credentials;
+            if crate::secrets::store(&cookie_key(&provider.id), credentials.cookie.as_deref()) {
+                credentials.cookie = None;
+            }
+            if crate::secrets::store(&passkey_key(&provider.id), credentials.passkey.as_deref()) {
+                credentials.passkey = None;
+            }
+            if crate::secrets::store(&api_key_key(&provider.id), provider.api_key.as_deref()) {
+                provider.api_key = None;
+            }
+        }
+
+        if let Ok(content) = serde_json::to_string_pretty(&on_disk) {
+            let _ = fs::write(&self.file_path, content);
+        }
+    }
+
+    pub async fn list(&self) -> Vec<ProviderConfig> {
+        self.data.read().await.providers.clone()
+    }
+
+    /// Adds the provider, or replaces the one with the same id
+    pub async fn save(&self, mut provider: ProviderConfig) -> Result<ProviderConfig, String> {
+        provider.credentials.domain = provider.credentials.domain.trim().to_lowercase();
+        if provider.credentials.domain.is_empty() {
+            return Err("A domain is required".to_string());
+        }
+        if provider.id.trim().is_empty() {
+            provider.id = provider.credentials.domain.clone();
+        }
+
+        let mut data = self.data.write().await;
+        match data.providers.iter_mut().find(|p| p.id == provider.id) {
+            Some(existing) => *existing = provider.clone(),
+            None => data.providers.push(provider.clone()),
+        }
+        self.persist(&data);
+        apply_credentials(&data);
+        Ok(provider)
+    }
+
+    pub async fn remove(&self, id: &str) {
+        let mut data = self.data.write().await;
+        data.providers.retain(|p| p.id != id);
+        crate::secrets::store(&cookie_key(id), None);
+        crate::secrets::store(&passkey_key(id), None);
+        crate::secrets::store(&api_key_key(id), None);
+        self.persist(&data);
+        apply_credentials(&data);
+    }
+
+    /// Mirrors the torrent indexers of a Prowlarr instance as Torznab providers. Entries
+    /// from an earlier sync that Prowlarr no longer has are removed; the enabled flag the
+    /// user set here is kept.
+    pub async fn sync_prowlarr(&self, prowlarr_url: &str, api_key: &str) -> Result<usize, String> {
+        let base_url = prowlarr_url.trim().trim_end_matches('/');
+        let client = http::HttpClient::new(std::time::Duration::from_secs(20));
+        let indexers: Vec<ProwlarrIndexer> = client
+            .get_with_headers(&format!("{}/api/v1/indexer", base_url), &[("X-Api-Key", api_key)])
+            .await
+            .and_then(|r| r.error_for_status())
+            .map_err(|e| format!("Failed to reach Prowlarr: {}", e))?
+            .json()
+            .await
+            .map_err(|e| format!("Unexpected Prowlarr response: {}", e))?;
+
+        let domain = reqwest::Url::parse(base_url).ok()
+            .and_then(|u| u.host_str().map(|h| h.to_string()))
+            .unwrap_or_default();
+
+        let mut data = self.data.write().await;
+        let mut synced = Vec::new();
+        for indexer in indexers.into_iter().filter(|i| i.protocol == "torrent") {
+            let id = format!("prowlarr-{}", indexer.id);
+            let existing = data.providers.iter().find(|p| p.id == id);
+
+            let mut capabilities = Vec::new();
+            for (params, name) in [
+                (&indexer.capabilities.search_params, "search"),
+                (&indexer.capabilities.tv_search_params, "tv-search"),
+                (&indexer.capabilities.movie_search_params, "movie-search"),
+            ] {
+                if !params.is_empty() {
+                    capabilities.push(name.to_string());
+                }
+            }
+            let mut categories = Vec::new();
+            flatten_categories(&indexer.capabilities.categories, &mut categories);
+
+            synced.push(ProviderConfig {
+                id: id.clone(),
+                name: indexer.name,
+                credentials: TrackerCredentials {
+                    domain: domain.clone(),
+                    ..Default::default()
+                },
+                enabled: existing.map(|p| p.enabled).unwrap_or(indexer.enable),
+                torznab_url: Some(format!("{}/{}/api", base_url, indexer.id)),
+                // The Prowlarr key from settings is used for these
+                api_key: None,
+                capabilities,
+                categories,
+                synced_from: Some("prowlarr".to_string()),
+            });
+        }
+
+        let count = synced.len();
+        data.providers.retain(|p| p.synced_from.as_deref() != Some("prowlarr"));
+        data.providers.extend(synced);
+        self.persist(&data);
+        apply_credentials(&data);
+        tracing::info!("synced {} indexers from Prowlarr", count);
+        Ok(count)
+    }
+}
+
+// Prowlarr entries only carry the Prowlarr host, which has nothing to log in with
+fn apply_credentials(data: &ProviderConfigData) {
+    http::set_tracker_credentials(
+        data.providers.iter()
+            .filter(|p| p.synced_from.is_none())
+            .map(|p| p.credentials.clone())
+            .collect(),
+    );
+}
+
+#[tauri::command]
+pub async fn get_provider_configs(manager: State<'_, ProviderConfigManager>) -> Result<Vec<ProviderConfig>, String> {
+    Ok(manager.list().await)
+}
+
+#[tauri::command]
+pub async fn save_provider_config(
+    manager: State<'_, ProviderConfigManager>,
+    provider: ProviderConfig,
+) -> Result<ProviderConfig, String> {
+    manager.save(provider).await
+}
+
+#[tauri::command]
+pub async fn remove_provider_config(manager: State<'_, ProviderConfigManager>, id: String) -> Result<(), String> {
+    manager.remove(&id).await;
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn sync_prowlarr(
+    manager: State<'_, ProviderConfigManager>,
+    settings_manager: State<'_, crate::settings::SettingsManager>,
+) -> Result<usize, String> {
+    let settings = settings_manager.get().await;
+    let (Some(url), Some(api_key)) = (settings.prowlarr_url, settings.prowlarr_api_key) else {
+        return Err("Set the Prowlarr URL and API key first".to_string());
+    };
+    manager.sync_prowlarr(&url, &api_key).await
+}
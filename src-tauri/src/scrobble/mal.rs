@@ -0,0 +1,156 @@
+use super::{now_secs, OAuthToken, ScrobbleItem, ScrobbleResult, Scrobbler};
+use async_trait::async_trait;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+use rand::RngCore;
+use serde::Deserialize;
+
+const AUTH_URL: &str = "https://myanimelist.net/v1/oauth2";
+const API_URL: &str = "https://api.myanimelist.net/v2";
+// Same threshold the other trackers use for counting an episode as watched
+const WATCHED_PERCENT: f64 = 80.0;
+// Random bytes in a PKCE verifier; 48 encode to 64 characters, within RFC 7636's 43-128
+const VERIFIER_BYTES: usize = 48;
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    refresh_token: String,
+    expires_in: u64,
+}
+
+impl From<TokenResponse> for OAuthToken {
+    fn from(res: TokenResponse) -> Self {
+        Self {
+            access_token: res.access_token,
+            refresh_token: Some(res.refresh_token),
+            expires_at: Some(now_secs() + res.expires_in),
+        }
+    }
+}
+
+/// Login URL and the PKCE verifier to send back with the code. MAL only supports the
+/// "plain" challenge method, so the challenge is the verifier itself.
+pub fn authorize_url(client_id: &str) -> (String, String) {
+    let mut bytes = [0u8; VERIFIER_BYTES];
+    rand::rngs::OsRng.fill_bytes(&mut bytes);
+    let verifier = URL_SAFE_NO_PAD.encode(bytes);
+
+    let url = format!(
+        "{}/authorize?response_type=code&client_id={}&code_challenge={}&code_challenge_method=plain",
+        AUTH_URL,
+        urlencoding::encode(client_id),
+        verifier
+    );
+    (url, verifier)
+}
+
+// Accepts either the bare code or the full redirect URL copied from the browser
+fn extract_code(input: &str) -> String {
+    let input = input.trim();
+    input
+        .split(['?', '&'])
+        .find_map(|part| part.strip_prefix("code="))
+        .map(|code| urlencoding::decode(code).map(|c| c.into_owned()).unwrap_or_else(|_| code.to_string()))
+        .unwrap_or_else(|| input.to_string())
+}
+
+pub async fn exchange_code(client: &reqwest::Client, client_id: &str, code: &str, verifier: &str) -> ScrobbleResult<OAuthToken> {
+    let code = extract_code(code);
+    let token: TokenResponse = client
+        .post(format!("{}/token", AUTH_URL))
+        .form(&[
+            ("client_id", client_id),
+            ("grant_type", "authorization_code"),
+            ("code", code.as_str()),
+            ("code_verifier", verifier),
+        ])
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    Ok(token.into())
+}
+
+pub async fn refresh(client: &reqwest::Client, client_id: &str, refresh_token: &str) -> ScrobbleResult<OAuthToken> {
+    let token: TokenResponse = client
+        .post(format!("{}/token", AUTH_URL))
+        .form(&[
+            ("client_id", client_id),
+            ("grant_type", "refresh_token"),
+            ("refresh_token", refresh_token),
+        ])
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    Ok(token.into())
+}
+
+/// MAL has no live scrobbling, so this only keeps the list status and watched episode
+/// count up to date. Items without a MAL id (anything that isn't anime) are skipped.
+pub struct MalScrobbler {
+    client: reqwest::Client,
+    access_token: String,
+}
+
+impl MalScrobbler {
+    pub fn new(client: reqwest::Client, access_token: String) -> Self {
+        Self { client, access_token }
+    }
+
+    async fn update_status(&self, mal_id: u32, fields: &[(&str, String)]) -> ScrobbleResult<()> {
+        self.client
+            .patch(format!("{}/anime/{}/my_list_status", API_URL, mal_id))
+            .bearer_auth(&self.access_token)
+            .form(fields)
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Scrobbler for MalScrobbler {
+    fn name(&self) -> &'static str {
+        "mal"
+    }
+
+    async fn start(&self, item: &ScrobbleItem) -> ScrobbleResult<()> {
+        let Some(mal_id) = item.mal_id else { return Ok(()) };
+        self.update_status(mal_id, &[("status", "watching".to_string())]).await
+    }
+
+    async fn pause(&self, _item: &ScrobbleItem) -> ScrobbleResult<()> {
+        Ok(())
+    }
+
+    async fn stop(&self, item: &ScrobbleItem) -> ScrobbleResult<()> {
+        let Some(mal_id) = item.mal_id else { return Ok(()) };
+        if item.progress < WATCHED_PERCENT {
+            return Ok(());
+        }
+
+        match item.episode {
+            Some(episode) => {
+                self.update_status(mal_id, &[
+                    ("status", "watching".to_string()),
+                    ("num_watched_episodes", episode.to_string()),
+                ]).await
+            }
+            // Anime movies are a single "episode"
+            None => {
+                self.update_status(mal_id, &[
+                    ("status", "completed".to_string()),
+                    ("num_watched_episodes", "1".to_string()),
+                ]).await
+            }
+        }
+    }
+
+    async fn set_progress(&self, _item: &ScrobbleItem) -> ScrobbleResult<()> {
+        Ok(())
+    }
+}
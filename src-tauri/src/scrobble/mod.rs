@@ -0,0 +1,341 @@
+pub mod mal;
+pub mod simkl;
+
+use crate::id_mapping::IdMappingManager;
+use crate::settings::{Settings, SettingsManager};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tauri::State;
+use tokio::sync::RwLock;
+
+const MAL_TOKEN_KEY: &str = "scrobble.mal";
+const SIMKL_TOKEN_KEY: &str = "scrobble.simkl";
+
+pub type ScrobbleResult<T> = Result<T, Box<dyn Error + Send + Sync>>;
+
+/// What is being watched, as reported by the player
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScrobbleItem {
+    pub tmdb_id: u32,
+    pub media_type: String, // "tv" or "movie"
+    pub season: Option<u32>,
+    pub episode: Option<u32>,
+    // 0-100
+    pub progress: f64,
+    // Filled in from the anime id mapping for services keyed by MAL ids
+    #[serde(default)]
+    pub mal_id: Option<u32>,
+}
+
+/// A watch-tracking service that follows playback
+#[async_trait]
+pub trait Scrobbler: Send + Sync {
+    fn name(&self) -> &'static str;
+    async fn start(&self, item: &ScrobbleItem) -> ScrobbleResult<()>;
+    async fn pause(&self, item: &ScrobbleItem) -> ScrobbleResult<()>;
+    async fn stop(&self, item: &ScrobbleItem) -> ScrobbleResult<()>;
+    async fn set_progress(&self, item: &ScrobbleItem) -> ScrobbleResult<()>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScrobbleEvent {
+    Start,
+    Pause,
+    Stop,
+    Progress,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OAuthToken {
+    pub access_token: String,
+    #[serde(default)]
+    pub refresh_token: Option<String>,
+    // Unix seconds
+    #[serde(default)]
+    pub expires_at: Option<u64>,
+}
+
+impl OAuthToken {
+    fn is_expired(&self) -> bool {
+        self.expires_at.map(|at| now_secs() + 60 >= at).unwrap_or(false)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct ScrobbleData {
+    #[serde(default)]
+    pub mal: Option<OAuthToken>,
+    #[serde(default)]
+    pub simkl: Option<OAuthToken>,
+    // PKCE verifier for a MAL login that hasn't been completed yet
+    #[serde(default)]
+    pub mal_code_verifier: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ScrobblerStatus {
+    pub provider: String,
+    pub enabled: bool,
+    pub connected: bool,
+}
+
+/// Where to send the user to log in. Simkl uses a device code, MAL a pasted redirect.
+#[derive(Debug, Clone, Serialize)]
+pub struct ScrobblerAuth {
+    pub url: String,
+    pub user_code: Option<String>,
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+/// Holds the login tokens for every scrobbling service and forwards playback events to
+/// the ones that are enabled in settings and connected
+pub struct ScrobbleManager {
+    file_path: PathBuf,
+    data: Arc<RwLock<ScrobbleData>>,
+    client: reqwest::Client,
+}
+
+impl ScrobbleManager {
+    pub fn new(app_data_dir: PathBuf) -> Self {
+        let file_path = app_data_dir.join("scrobble.json");
+        let mut data: ScrobbleData = if file_path.exists() {
+            let content = fs::read_to_string(&file_path).unwrap_or_default();
+            serde_json::from_str(&content).unwrap_or_default()
+        } else {
+            ScrobbleData::default()
+        };
+
+        // Tokens in the file are from before the keychain was used (or it isn't available)
+        let has_plaintext_tokens = data.mal.is_some() || data.simkl.is_some();
+        data.mal = data.mal.or_else(|| crate::secrets::get_json(MAL_TOKEN_KEY));
+        data.simkl = data.simkl.or_else(|| crate::secrets::get_json(SIMKL_TOKEN_KEY));
+
+        let manager = Self {
+            file_path,
+            data: Arc::new(RwLock::new(data.clone())),
+            client: reqwest::Client::builder()
+                .timeout(std::time::Duration::from_secs(15))
+                .build()
+                .unwrap(),
+        };
+        if has_plaintext_tokens {
+            manager.persist(&data);
+        }
+        manager
+    }
+
+    // Tokens go to the keychain; the file only keeps them when that fails
+    fn persist(&self, data: &ScrobbleData) {
+        let mut on_disk = data.clone();
+        if crate::secrets::store_json(MAL_TOKEN_KEY, data.mal.as_ref()) {
+            on_disk.mal = None;
+        }
+        if crate::secrets::store_json(SIMKL_TOKEN_KEY, data.simkl.as_ref()) {
+            on_disk.simkl = None;
+        }
+
+        if let Ok(content) = serde_json::to_string_pretty(&on_disk) {
+            let _ = fs::write(&self.file_path, content);
+        }
+    }
+
+    pub async fn status(&self, settings: &Settings) -> Vec<ScrobblerStatus> {
+        let data = self.data.read().await;
+        vec![
+            ScrobblerStatus {
+                provider: "mal".to_string(),
+                enabled: settings.mal_enabled,
+                connected: data.mal.is_some(),
+            },
+            ScrobblerStatus {
+                provider: "simkl".to_string(),
+                enabled: settings.simkl_enabled,
+                connected: data.simkl.is_some(),
+            },
+        ]
+    }
+
+    pub async fn begin_auth(&self, provider: &str, settings: &Settings) -> Result<ScrobblerAuth, String> {
+        match provider {
+            "mal" => {
+                let client_id = client_id(&settings.mal_client_id)?;
+                let (url, verifier) = mal::authorize_url(client_id);
+                let mut data = self.data.write().await;
+                data.mal_code_verifier = Some(verifier);
+                self.persist(&data);
+                Ok(ScrobblerAuth { url, user_code: None })
+            }
+            "simkl" => {
+                let client_id = client_id(&settings.simkl_client_id)?;
+                simkl::request_pin(&self.client, client_id).await.map_err(|e| e.to_string())
+            }
+            _ => Err(format!("Unknown scrobbler: {}", provider)),
+        }
+    }
+
+    /// Finishes a login. MAL takes the code (or the whole redirect URL); Simkl takes the
+    /// user code and returns false until the user has approved it.
+    pub async fn complete_auth(&self, provider: &str, code: String, settings: &Settings) -> Result<bool, String> {
+        let token = match provider {
+            "mal" => {
+                let client_id = client_id(&settings.mal_client_id)?;
+                let verifier = self.data.read().await.mal_code_verifier.clone()
+                    .ok_or("Start the MyAnimeList login first")?;
+                Some(mal::exchange_code(&self.client, client_id, &code, &verifier).await.map_err(|e| e.to_string())?)
+            }
+            "simkl" => {
+                let client_id = client_id(&settings.simkl_client_id)?;
+                simkl::poll_pin(&self.client, client_id, &code).await.map_err(|e| e.to_string())?
+            }
+            _ => return Err(format!("Unknown scrobbler: {}", provider)),
+        };
+
+        let Some(token) = token else { return Ok(false) };
+        let mut data = self.data.write().await;
+        match provider {
+            "mal" => {
+                data.mal = Some(token);
+                data.mal_code_verifier = None;
+            }
+            _ => data.simkl = Some(token),
+        }
+        self.persist(&data);
+        tracing::info!("connected {} scrobbler", provider);
+        Ok(true)
+    }
+
+    pub async fn disconnect(&self, provider: &str) {
+        let mut data = self.data.write().await;
+        match provider {
+            "mal" => data.mal = None,
+            "simkl" => data.simkl = None,
+            _ => return,
+        }
+        self.persist(&data);
+    }
+
+    // MAL tokens only last a month, so refresh them before use
+    async fn mal_access_token(&self, client_id: &str) -> Option<String> {
+        let token = self.data.read().await.mal.clone()?;
+        if !token.is_expired() {
+            return Some(token.access_token);
+        }
+
+        let refresh_token = token.refresh_token.as_deref()?;
+        match mal::refresh(&self.client, client_id, refresh_token).await {
+            Ok(refreshed) => {
+                let access_token = refreshed.access_token.clone();
+                let mut data = self.data.write().await;
+                data.mal = Some(refreshed);
+                self.persist(&data);
+                Some(access_token)
+            }
+            Err(e) => {
+                tracing::warn!("failed to refresh MyAnimeList token: {}", e);
+                None
+            }
+        }
+    }
+
+    async fn scrobblers(&self, settings: &Settings) -> Vec<Box<dyn Scrobbler>> {
+        let mut scrobblers: Vec<Box<dyn Scrobbler>> = Vec::new();
+
+        if settings.simkl_enabled {
+            if let (Some(client_id), Some(token)) = (settings.simkl_client_id.clone(), self.data.read().await.simkl.clone()) {
+                scrobblers.push(Box::new(simkl::SimklScrobbler::new(self.client.clone(), client_id, token.access_token)));
+            }
+        }
+
+        if settings.mal_enabled {
+            if let Some(client_id) = settings.mal_client_id.clone() {
+                if let Some(access_token) = self.mal_access_token(&client_id).await {
+                    scrobblers.push(Box::new(mal::MalScrobbler::new(self.client.clone(), access_token)));
+                }
+            }
+        }
+
+        scrobblers
+    }
+
+    pub async fn dispatch(&self, event: ScrobbleEvent, item: &ScrobbleItem, settings: &Settings) {
+        for scrobbler in self.scrobblers(settings).await {
+            let result = match event {
+                ScrobbleEvent::Start => scrobbler.start(item).await,
+                ScrobbleEvent::Pause => scrobbler.pause(item).await,
+                ScrobbleEvent::Stop => scrobbler.stop(item).await,
+                ScrobbleEvent::Progress => scrobbler.set_progress(item).await,
+            };
+            if let Err(e) = result {
+                tracing::warn!("{} scrobble {:?} failed: {}", scrobbler.name(), event, e);
+            }
+        }
+    }
+}
+
+fn client_id(value: &Option<String>) -> Result<&str, String> {
+    value.as_deref()
+        .map(str::trim)
+        .filter(|id| !id.is_empty())
+        .ok_or_else(|| "Set a client ID for this service in settings first".to_string())
+}
+
+#[tauri::command]
+pub async fn scrobble(
+    manager: State<'_, ScrobbleManager>,
+    settings_manager: State<'_, SettingsManager>,
+    id_mapping: State<'_, IdMappingManager>,
+    event: ScrobbleEvent,
+    mut item: ScrobbleItem,
+) -> Result<(), String> {
+    let settings = settings_manager.get().await;
+    if settings.mal_enabled && item.mal_id.is_none() {
+        // AniDB splits seasons into separate entries, which the mapping lists in order
+        let ids = id_mapping.lookup_tmdb(item.tmdb_id).await;
+        let index = item.season.map(|s| s.saturating_sub(1) as usize).unwrap_or(0);
+        item.mal_id = ids.get(index).or(ids.first()).and_then(|ids| ids.mal_id);
+    }
+    manager.dispatch(event, &item, &settings).await;
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn get_scrobbler_status(
+    manager: State<'_, ScrobbleManager>,
+    settings_manager: State<'_, SettingsManager>,
+) -> Result<Vec<ScrobblerStatus>, String> {
+    Ok(manager.status(&settings_manager.get().await).await)
+}
+
+#[tauri::command]
+pub async fn begin_scrobbler_auth(
+    manager: State<'_, ScrobbleManager>,
+    settings_manager: State<'_, SettingsManager>,
+    provider: String,
+) -> Result<ScrobblerAuth, String> {
+    manager.begin_auth(&provider, &settings_manager.get().await).await
+}
+
+#[tauri::command]
+pub async fn complete_scrobbler_auth(
+    manager: State<'_, ScrobbleManager>,
+    settings_manager: State<'_, SettingsManager>,
+    provider: String,
+    code: String,
+) -> Result<bool, String> {
+    manager.complete_auth(&provider, code, &settings_manager.get().await).await
+}
+
+#[tauri::command]
+pub async fn disconnect_scrobbler(manager: State<'_, ScrobbleManager>, provider: String) -> Result<(), String> {
+    manager.disconnect(&provider).await;
+    Ok(())
+}
@@ -0,0 +1,119 @@
+use super::{OAuthToken, ScrobbleItem, ScrobbleResult, Scrobbler, ScrobblerAuth};
+use async_trait::async_trait;
+use serde::Deserialize;
+use serde_json::json;
+
+const API_URL: &str = "https://api.simkl.com";
+
+#[derive(Deserialize)]
+struct PinResponse {
+    user_code: String,
+    verification_url: String,
+}
+
+#[derive(Deserialize)]
+struct PinStatus {
+    result: String,
+    #[serde(default)]
+    access_token: Option<String>,
+}
+
+/// Starts Simkl's device login; the user enters `user_code` at the returned URL
+pub async fn request_pin(client: &reqwest::Client, client_id: &str) -> ScrobbleResult<ScrobblerAuth> {
+    let pin: PinResponse = client
+        .get(format!("{}/oauth/pin", API_URL))
+        .query(&[("client_id", client_id)])
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+
+    Ok(ScrobblerAuth {
+        url: pin.verification_url,
+        user_code: Some(pin.user_code),
+    })
+}
+
+/// None while the user hasn't approved the code yet
+pub async fn poll_pin(client: &reqwest::Client, client_id: &str, user_code: &str) -> ScrobbleResult<Option<OAuthToken>> {
+    let status: PinStatus = client
+        .get(format!("{}/oauth/pin/{}", API_URL, user_code))
+        .query(&[("client_id", client_id)])
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+
+    Ok(match (status.result.as_str(), status.access_token) {
+        ("OK", Some(access_token)) => Some(OAuthToken {
+            access_token,
+            refresh_token: None,
+            // Simkl tokens don't expire
+            expires_at: None,
+        }),
+        _ => None,
+    })
+}
+
+pub struct SimklScrobbler {
+    client: reqwest::Client,
+    client_id: String,
+    access_token: String,
+}
+
+impl SimklScrobbler {
+    pub fn new(client: reqwest::Client, client_id: String, access_token: String) -> Self {
+        Self { client, client_id, access_token }
+    }
+
+    async fn send(&self, action: &str, item: &ScrobbleItem) -> ScrobbleResult<()> {
+        let body = match (item.media_type.as_str(), item.season, item.episode) {
+            ("tv", Some(season), Some(episode)) => json!({
+                "progress": item.progress,
+                "show": { "ids": { "tmdb": item.tmdb_id } },
+                "episode": { "season": season, "number": episode },
+            }),
+            _ => json!({
+                "progress": item.progress,
+                "movie": { "ids": { "tmdb": item.tmdb_id } },
+            }),
+        };
+
+        self.client
+            .post(format!("{}/scrobble/{}", API_URL, action))
+            .bearer_auth(&self.access_token)
+            .header("simkl-api-key", &self.client_id)
+            .json(&body)
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Scrobbler for SimklScrobbler {
+    fn name(&self) -> &'static str {
+        "simkl"
+    }
+
+    async fn start(&self, item: &ScrobbleItem) -> ScrobbleResult<()> {
+        self.send("start", item).await
+    }
+
+    async fn pause(&self, item: &ScrobbleItem) -> ScrobbleResult<()> {
+        self.send("pause", item).await
+    }
+
+    // Simkl marks the item watched when stopped past 80%
+    async fn stop(&self, item: &ScrobbleItem) -> ScrobbleResult<()> {
+        self.send("stop", item).await
+    }
+
+    // There's no separate progress call; starting again updates the position
+    async fn set_progress(&self, item: &ScrobbleItem) -> ScrobbleResult<()> {
+        self.send("start", item).await
+    }
+}
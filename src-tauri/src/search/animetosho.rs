@@ -0,0 +1,140 @@
+use super::{SearchProvider, SearchResult, parse_audio_codec};
+use super::http::HttpClient;
+use async_trait::async_trait;
+use serde::Deserialize;
+use std::error::Error;
+use regex::Regex;
+use crate::log_info;
+
+#[derive(Debug, Deserialize)]
+struct AnimeToshoEntry {
+    title: String,
+    #[serde(default)]
+    magnet_uri: Option<String>,
+    #[serde(default)]
+    total_size: u64,
+    #[serde(default)]
+    seeders: Option<u32>,
+    #[serde(default)]
+    leechers: Option<u32>,
+    #[serde(default)]
+    num_files: Option<u32>,
+}
+
+pub struct AnimeToshoProvider {
+    client: HttpClient,
+    season_regex: Regex,
+    episode_regex: Regex,
+    quality_regex: Regex,
+    encode_regex: Regex,
+    batch_regex: Regex,
+}
+
+impl AnimeToshoProvider {
+    pub fn new() -> Self {
+        Self {
+            client: HttpClient::new(std::time::Duration::from_secs(20)),
+            season_regex: Regex::new(r"(?i)S(\d{1,2})|Season\s*(\d{1,2})").unwrap(),
+            episode_regex: Regex::new(r"(?i)S\d{1,2}E(\d+)|E(\d+)|Episode\s*(\d+)|\s-\s*(\d+)\s*(?:v\d)?").unwrap(),
+            quality_regex: Regex::new(r"(?i)(\d{3,4}p|4K|8K|2160p|1440p|1080p|720p|480p)").unwrap(),
+            encode_regex: Regex::new(r"(?i)(x264|x265|H\.?264|H\.?265|HEVC|AVC|VP9|AV1)").unwrap(),
+            batch_regex: Regex::new(r"(?i)(batch|complete|\d+\s*-\s*\d+|S\d+E\d+-E?\d+)").unwrap(),
+        }
+    }
+
+    fn parse_metadata(&self, title: &str, num_files: Option<u32>) -> (Option<u32>, Option<u32>, Option<String>, Option<String>, bool) {
+        let season = self.season_regex.captures(title)
+            .and_then(|c| c.get(1).or_else(|| c.get(2)))
+            .and_then(|m| m.as_str().parse().ok());
+
+        let episode = self.episode_regex.captures(title)
+            .and_then(|c| c.get(1).or_else(|| c.get(2)).or_else(|| c.get(3)).or_else(|| c.get(4)))
+            .and_then(|m| m.as_str().parse().ok());
+
+        let quality = self.quality_regex.captures(title)
+            .and_then(|c| c.get(1))
+            .map(|m| m.as_str().to_uppercase());
+
+        let encode = self.encode_regex.captures(title)
+            .and_then(|c| c.get(1))
+            .map(|m| m.as_str().to_uppercase());
+
+        // The feed reports the file count, which beats guessing from the title
+        let is_batch = match num_files {
+            Some(n) => n > 1,
+            None => self.batch_regex.is_match(title),
+        };
+
+        (season, episode, quality, encode, is_batch)
+    }
+
+    fn format_size(bytes: u64) -> String {
+        if bytes >= 1_073_741_824 {
+            format!("{:.2} GiB", bytes as f64 / 1_073_741_824.0)
+        } else if bytes >= 1_048_576 {
+            format!("{:.2} MiB", bytes as f64 / 1_048_576.0)
+        } else {
+            format!("{:.2} KiB", bytes as f64 / 1024.0)
+        }
+    }
+
+    /// Exact lookup by AniDB anime id, avoids fuzzy matching across romaji/English titles
+    pub async fn search_by_anidb(&self, anidb_id: u32) -> Result<Vec<SearchResult>, Box<dyn Error + Send + Sync>> {
+        let url = format!("https://feed.animetosho.org/json?aid={}", anidb_id);
+        self.fetch(&url).await
+    }
+
+    async fn fetch(&self, url: &str) -> Result<Vec<SearchResult>, Box<dyn Error + Send + Sync>> {
+        log_info!("AnimeTosho: Fetching {}", url);
+
+        let response = self.client.get(url).await?;
+        if !response.status().is_success() {
+            log_info!("AnimeTosho: Status {}", response.status());
+            return Ok(vec![]);
+        }
+
+        let entries: Vec<AnimeToshoEntry> = response.json().await?;
+
+        let mut results = Vec::new();
+        for entry in entries {
+            let Some(magnet_link) = entry.magnet_uri else { continue };
+
+            let (season, episode, quality, encode, is_batch) = self.parse_metadata(&entry.title, entry.num_files);
+            let audio_codec = parse_audio_codec(&entry.title);
+
+            results.push(SearchResult {
+                size: Self::format_size(entry.total_size),
+                seeds: entry.seeders.unwrap_or(0),
+                peers: entry.leechers.unwrap_or(0),
+                magnet_link,
+                provider: "AnimeTosho".to_string(),
+                season,
+                episode,
+                quality,
+                encode,
+                is_batch,
+                audio_codec,
+                year: None,
+                is_collection: false,
+                is_adult: false,
+                sources: Vec::new(),
+                similarity: None,
+                release_group: None,
+                verified_peers: None,
+                season_pattern: None,
+                title: entry.title,
+            });
+        }
+
+        log_info!("AnimeTosho: Found {} results", results.len());
+        Ok(results)
+    }
+}
+
+#[async_trait]
+impl SearchProvider for AnimeToshoProvider {
+    async fn search(&self, query: &str) -> Result<Vec<SearchResult>, Box<dyn Error + Send + Sync>> {
+        let url = format!("https://feed.animetosho.org/json?q={}", urlencoding::encode(query));
+        self.fetch(&url).await
+    }
+}
@@ -0,0 +1,350 @@
+use super::{QueryEncoding, SearchProvider, SearchResult, encode_query, looks_adult, parse_audio_codec, parse_season_episode};
+use super::http::{ChallengeError, HttpClient};
+use async_trait::async_trait;
+use regex::Regex;
+use scraper::{ElementRef, Html, Selector};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+use crate::{log_info, log_warn};
+
+/// A public tracker described in a JSON or YAML file instead of code. The search page
+/// is fetched from the first mirror that answers and every row matching `rows` becomes
+/// a result, with each field read from an element inside the row.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProviderDefinition {
+    pub id: String,
+    pub name: String,
+    // Base urls tried in order, e.g. ["https://example.org"]
+    pub mirrors: Vec<String>,
+    // Appended to the mirror; {query} is url-encoded, {query_plus} uses + for spaces
+    pub search_path: String,
+    // "anime", "tv", "movie"; empty means all
+    #[serde(default)]
+    pub media_types: Vec<String>,
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+    pub rows: String,
+    // Header rows the row selector also matches
+    #[serde(default)]
+    pub skip_rows: usize,
+    pub fields: DefinitionFields,
+    // Sites that only list adult content
+    #[serde(default)]
+    pub adult: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DefinitionFields {
+    pub title: FieldSelector,
+    // At least one of magnet and download is needed; a download is a .torrent link
+    #[serde(default)]
+    pub magnet: Option<FieldSelector>,
+    #[serde(default)]
+    pub download: Option<FieldSelector>,
+    #[serde(default)]
+    pub size: Option<FieldSelector>,
+    #[serde(default)]
+    pub seeds: Option<FieldSelector>,
+    #[serde(default)]
+    pub peers: Option<FieldSelector>,
+}
+
+/// Where a field's value comes from: the text (or `attribute`) of the first element
+/// matching `selector` within the row, optionally narrowed by the first capture group
+/// of `regex`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FieldSelector {
+    // None reads the row itself
+    #[serde(default)]
+    pub selector: Option<String>,
+    #[serde(default)]
+    pub attribute: Option<String>,
+    #[serde(default)]
+    pub regex: Option<String>,
+}
+
+/// A definition file as shown in settings, with the reason it was skipped if invalid
+#[derive(Debug, Clone, Serialize)]
+pub struct DefinitionInfo {
+    pub file: String,
+    pub id: Option<String>,
+    pub name: Option<String>,
+    pub media_types: Vec<String>,
+    pub error: Option<String>,
+}
+
+struct CompiledField {
+    selector: Option<Selector>,
+    attribute: Option<String>,
+    regex: Option<Regex>,
+}
+
+impl CompiledField {
+    fn compile(field: &FieldSelector) -> Result<Self, String> {
+        let selector = match field.selector.as_deref() {
+            Some(s) => Some(Selector::parse(s).map_err(|e| format!("invalid selector \"{}\": {:?}", s, e))?),
+            None => None,
+        };
+        let regex = match field.regex.as_deref() {
+            Some(r) => Some(Regex::new(r).map_err(|e| format!("invalid regex \"{}\": {}", r, e))?),
+            None => None,
+        };
+        Ok(Self {
+            selector,
+            attribute: field.attribute.clone(),
+            regex,
+        })
+    }
+
+    fn extract(&self, row: &ElementRef) -> Option<String> {
+        let element = match self.selector {
+            Some(ref selector) => row.select(selector).next()?,
+            None => *row,
+        };
+        let raw = match self.attribute {
+            Some(ref attribute) => element.value().attr(attribute)?.to_string(),
+            None => element.text().collect::<Vec<_>>().join(" "),
+        };
+        let raw = raw.split_whitespace().collect::<Vec<_>>().join(" ");
+
+        let value = match self.regex {
+            Some(ref regex) => {
+                let caps = regex.captures(&raw)?;
+                caps.get(1).or_else(|| caps.get(0))?.as_str().trim().to_string()
+            }
+            None => raw,
+        };
+        Some(value).filter(|v| !v.is_empty())
+    }
+}
+
+impl ProviderDefinition {
+    /// Whether this provider should be queried for the given media type
+    pub fn supports(&self, media_type: &str) -> bool {
+        self.media_types.is_empty() || self.media_types.iter().any(|t| t == media_type)
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if self.id.trim().is_empty() {
+            return Err("id is missing".to_string());
+        }
+        if self.mirrors.is_empty() {
+            return Err("no mirrors listed".to_string());
+        }
+        if !self.search_path.contains("{query}") && !self.search_path.contains("{query_plus}") {
+            return Err("search_path has no {query} placeholder".to_string());
+        }
+        if self.fields.magnet.is_none() && self.fields.download.is_none() {
+            return Err("needs a magnet or download field".to_string());
+        }
+        DefinitionProvider::new(self.clone()).map(|_| ())
+    }
+}
+
+fn parse_definition(path: &Path) -> Result<ProviderDefinition, String> {
+    let content = fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let definition: ProviderDefinition = match path.extension().and_then(|e| e.to_str()) {
+        Some("yaml") | Some("yml") => serde_yaml::from_str(&content).map_err(|e| e.to_string())?,
+        _ => serde_json::from_str(&content).map_err(|e| e.to_string())?,
+    };
+    definition.validate()?;
+    Ok(definition)
+}
+
+fn definition_files(dir: &Path) -> Vec<std::path::PathBuf> {
+    let mut files: Vec<_> = fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .flatten()
+                .map(|e| e.path())
+                .filter(|p| matches!(p.extension().and_then(|e| e.to_str()), Some("json" | "yaml" | "yml")))
+                .collect()
+        })
+        .unwrap_or_default();
+    files.sort();
+    files
+}
+
+/// Every valid definition in `dir`. Invalid files are logged and skipped; the first
+/// file wins when two share an id.
+pub fn load_definitions(dir: &Path) -> Vec<ProviderDefinition> {
+    let mut definitions: Vec<ProviderDefinition> = Vec::new();
+    for path in definition_files(dir) {
+        match parse_definition(&path) {
+            Ok(definition) if definitions.iter().any(|d| d.id == definition.id) => {
+                log_warn!("skipping {:?}: duplicate provider id {}", path, definition.id);
+            }
+            Ok(definition) => definitions.push(definition),
+            Err(e) => log_warn!("skipping provider definition {:?}: {}", path, e),
+        }
+    }
+    definitions
+}
+
+/// Every definition file in `dir`, including the invalid ones
+pub fn describe_definitions(dir: &Path) -> Vec<DefinitionInfo> {
+    definition_files(dir)
+        .into_iter()
+        .map(|path| {
+            let file = path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+            match parse_definition(&path) {
+                Ok(definition) => DefinitionInfo {
+                    file,
+                    id: Some(definition.id),
+                    name: Some(definition.name),
+                    media_types: definition.media_types,
+                    error: None,
+                },
+                Err(e) => DefinitionInfo {
+                    file,
+                    id: None,
+                    name: None,
+                    media_types: Vec::new(),
+                    error: Some(e),
+                },
+            }
+        })
+        .collect()
+}
+
+pub struct DefinitionProvider {
+    client: HttpClient,
+    definition: ProviderDefinition,
+    rows: Selector,
+    title: CompiledField,
+    magnet: Option<CompiledField>,
+    download: Option<CompiledField>,
+    size: Option<CompiledField>,
+    seeds: Option<CompiledField>,
+    peers: Option<CompiledField>,
+    quality_regex: Regex,
+    encode_regex: Regex,
+    batch_regex: Regex,
+}
+
+impl DefinitionProvider {
+    pub fn new(definition: ProviderDefinition) -> Result<Self, String> {
+        let rows = Selector::parse(&definition.rows)
+            .map_err(|e| format!("invalid row selector \"{}\": {:?}", definition.rows, e))?;
+        let compile = |field: &Option<FieldSelector>| field.as_ref().map(CompiledField::compile).transpose();
+        let fields = &definition.fields;
+
+        Ok(Self {
+            client: HttpClient::new(std::time::Duration::from_secs(20)),
+            rows,
+            title: CompiledField::compile(&fields.title)?,
+            magnet: compile(&fields.magnet)?,
+            download: compile(&fields.download)?,
+            size: compile(&fields.size)?,
+            seeds: compile(&fields.seeds)?,
+            peers: compile(&fields.peers)?,
+            quality_regex: Regex::new(r"(?i)(\d{3,4}p|4K|8K|2160p|1440p|1080p|720p|480p)").unwrap(),
+            encode_regex: Regex::new(r"(?i)(x264|x265|H\.?264|H\.?265|HEVC|AVC|VP9|AV1)").unwrap(),
+            batch_regex: Regex::new(r"(?i)(batch|complete|\d+-\d+|S\d+E\d+-E?\d+)").unwrap(),
+            definition,
+        })
+    }
+
+    async fn fetch_with_mirrors(&self, query: &str) -> Result<(String, String), Box<dyn Error + Send + Sync>> {
+        let path = self.definition.search_path
+            .replace("{query_plus}", &encode_query(query, QueryEncoding::Plus))
+            .replace("{query}", &encode_query(query, QueryEncoding::Percent));
+        let headers: Vec<(&str, &str)> = self.definition.headers.iter()
+            .map(|(k, v)| (k.as_str(), v.as_str()))
+            .collect();
+
+        let mut challenged_host = None;
+        for mirror in &self.definition.mirrors {
+            let url = format!("{}{}", mirror.trim_end_matches('/'), path);
+            match self.client.get_html(&url, &headers).await {
+                Ok(html) => return Ok((html, mirror.clone())),
+                Err(e) => {
+                    log_warn!("{}: {} failed: {}", self.definition.name, mirror, e);
+                    if let Some(challenge) = e.downcast_ref::<ChallengeError>() {
+                        challenged_host = Some(challenge.host.clone());
+                    }
+                }
+            }
+        }
+
+        if let Some(host) = challenged_host {
+            return Err(Box::new(ChallengeError { host }));
+        }
+        Err(format!("All {} mirrors failed", self.definition.name).into())
+    }
+
+    fn parse_count(field: &Option<CompiledField>, row: &ElementRef) -> u32 {
+        field.as_ref()
+            .and_then(|f| f.extract(row))
+            .map(|v| v.chars().filter(|c| c.is_ascii_digit()).collect::<String>())
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(0)
+    }
+}
+
+// Relative links on the page are resolved against the mirror that served it
+fn absolute_url(base: &str, link: &str) -> String {
+    reqwest::Url::parse(base)
+        .and_then(|base| base.join(link))
+        .map(|url| url.to_string())
+        .unwrap_or_else(|_| link.to_string())
+}
+
+#[async_trait]
+impl SearchProvider for DefinitionProvider {
+    async fn search(&self, query: &str) -> Result<Vec<SearchResult>, Box<dyn Error + Send + Sync>> {
+        let name = &self.definition.name;
+        let (html, base_url) = self.fetch_with_mirrors(query).await?;
+
+        let document = Html::parse_document(&html);
+        let mut results = Vec::new();
+        for row in document.select(&self.rows).skip(self.definition.skip_rows) {
+            let Some(title) = self.title.extract(&row) else { continue };
+
+            let magnet_link = match self.magnet.as_ref().and_then(|f| f.extract(&row)) {
+                Some(magnet) if magnet.starts_with("magnet:") => magnet,
+                _ => match self.download.as_ref().and_then(|f| f.extract(&row)) {
+                    Some(link) => absolute_url(&base_url, &link),
+                    None => continue,
+                },
+            };
+
+            let (season, episode) = parse_season_episode(&title);
+            let quality = self.quality_regex.captures(&title).and_then(|c| c.get(1)).map(|m| m.as_str().to_uppercase());
+            let encode = self.encode_regex.captures(&title).and_then(|c| c.get(1)).map(|m| m.as_str().to_uppercase());
+            let is_batch = self.batch_regex.is_match(&title) || (season.is_some() && episode.is_none());
+            let audio_codec = parse_audio_codec(&title);
+            let is_adult = self.definition.adult || looks_adult(&title);
+
+            results.push(SearchResult {
+                size: self.size.as_ref().and_then(|f| f.extract(&row)).unwrap_or_else(|| "Unknown".to_string()),
+                seeds: Self::parse_count(&self.seeds, &row),
+                peers: Self::parse_count(&self.peers, &row),
+                magnet_link,
+                provider: name.clone(),
+                season,
+                episode,
+                quality,
+                encode,
+                is_batch,
+                audio_codec,
+                year: None,
+                is_collection: false,
+                is_adult,
+                sources: Vec::new(),
+                similarity: None,
+                release_group: None,
+                verified_peers: None,
+                season_pattern: None,
+                title,
+            });
+        }
+
+        results.sort_by(|a, b| b.seeds.cmp(&a.seeds));
+        log_info!("{}: {} results", name, results.len());
+        Ok(results)
+    }
+}
@@ -1,9 +1,11 @@
-use crate::search::{SearchProvider, SearchResult, parse_audio_codec};
+use crate::search::{QueryEncoding, SearchProvider, SearchResult, encode_query, parse_audio_codec};
+use crate::search::http::HttpClient;
 use async_trait::async_trait;
-use reqwest::Client;
+use scraper::{Html, Selector};
 use serde::Deserialize;
 use std::error::Error;
 use regex::Regex;
+use crate::{log_info, log_warn};
 
 #[derive(Debug, Deserialize)]
 struct EZTVResponse {
@@ -30,7 +32,7 @@ struct EZTVTorrent {
 }
 
 pub struct EZTVProvider {
-    client: Client,
+    client: HttpClient,
     season_regex: Regex,
     episode_regex: Regex,
     quality_regex: Regex,
@@ -40,11 +42,7 @@ pub struct EZTVProvider {
 impl EZTVProvider {
     pub fn new() -> Self {
         EZTVProvider {
-            client: Client::builder()
-                .user_agent("Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36")
-                .timeout(std::time::Duration::from_secs(30))
-                .build()
-                .unwrap(),
+            client: HttpClient::new(std::time::Duration::from_secs(30)),
             season_regex: Regex::new(r"(?i)S(\d+)").unwrap(),
             episode_regex: Regex::new(r"(?i)E(\d+)").unwrap(),
             quality_regex: Regex::new(r"(?i)(\d{3,4}p|4K|2160p|1080p|720p|480p)").unwrap(),
@@ -100,25 +98,25 @@ impl EZTVProvider {
         let clean_id = imdb_id.trim_start_matches("tt");
         
         let url = format!("https://eztvx.to/api/get-torrents?imdb_id={}&limit=100", clean_id);
-        println!("EZTV: Fetching {}", url);
+        log_info!("EZTV: Fetching {}", url);
         
-        let response = self.client.get(&url).send().await?;
+        let response = self.client.get(&url).await?;
         let status = response.status();
         
         if !status.is_success() {
-            println!("EZTV: API returned status {}", status);
+            log_warn!("EZTV: API returned status {}", status);
             return Ok(vec![]);
         }
         
         let data: EZTVResponse = match response.json().await {
             Ok(d) => d,
             Err(e) => {
-                println!("EZTV: Failed to parse JSON: {}", e);
+                log_warn!("EZTV: Failed to parse JSON: {}", e);
                 return Ok(vec![]);
             }
         };
         
-        println!("EZTV: Found {} torrents", data.torrents_count);
+        log_info!("EZTV: Found {} torrents", data.torrents_count);
         
         let mut results = Vec::new();
         for torrent in data.torrents.iter() {
@@ -139,6 +137,14 @@ impl EZTVProvider {
                 encode,
                 is_batch,
                 audio_codec,
+                year: None,
+                is_collection: false,
+                is_adult: false,
+                sources: Vec::new(),
+                similarity: None,
+                release_group: None,
+                verified_peers: None,
+                season_pattern: None,
             });
         }
         
@@ -152,10 +158,75 @@ impl EZTVProvider {
 #[async_trait]
 impl SearchProvider for EZTVProvider {
     async fn search(&self, query: &str) -> Result<Vec<SearchResult>, Box<dyn Error + Send + Sync>> {
-        // EZTV API doesn't support text search, only IMDB ID lookup
-        // This method is called when no IMDB ID is available
-        // We'll just return empty and log a message
-        println!("EZTV: Text search not supported. Query was: '{}'. Use search_by_imdb with IMDB ID instead.", query);
-        Ok(vec![])
+        // The API only supports IMDB lookups, so scrape the search listing instead
+        let slug = encode_query(query, QueryEncoding::Slug);
+        let url = format!("https://eztvx.to/search/{}", slug);
+        log_info!("EZTV: Fetching {}", url);
+
+        let html = match self.client.get_html(&url, &[]).await {
+            Ok(html) => html,
+            Err(e) => {
+                log_warn!("EZTV: Search request failed: {}", e);
+                return Ok(vec![]);
+            }
+        };
+
+        let document = Html::parse_document(&html);
+        let row_selector = Selector::parse("tr.forum_header_border").unwrap();
+        let cell_selector = Selector::parse("td").unwrap();
+        let title_selector = Selector::parse("a.epinfo").unwrap();
+        let magnet_selector = Selector::parse("a.magnet, a[href^='magnet:']").unwrap();
+
+        let mut results = Vec::new();
+        for row in document.select(&row_selector) {
+            let Some(title) = row.select(&title_selector).next()
+                .map(|a| a.text().collect::<String>().trim().to_string())
+                .filter(|t| !t.is_empty()) else { continue };
+
+            let Some(magnet_link) = row.select(&magnet_selector).next()
+                .and_then(|a| a.value().attr("href"))
+                .map(|h| h.to_string()) else { continue };
+
+            // Columns: show, title, links, size, released, seeds
+            let cells: Vec<String> = row.select(&cell_selector)
+                .map(|c| c.text().collect::<String>().trim().to_string())
+                .collect();
+            let size = cells.get(3).cloned().filter(|s| !s.is_empty()).unwrap_or_else(|| "Unknown".to_string());
+            let seeds = cells.get(5)
+                .and_then(|s| s.replace(',', "").parse().ok())
+                .unwrap_or(0);
+
+            let (season, episode, quality, encode, is_batch) = self.parse_metadata(&title, "", "");
+            let audio_codec = parse_audio_codec(&title);
+
+            results.push(SearchResult {
+                title,
+                size,
+                seeds,
+                peers: 0,
+                magnet_link,
+                provider: "EZTV".to_string(),
+                season,
+                episode,
+                quality,
+                encode,
+                is_batch,
+                audio_codec,
+                year: None,
+                is_collection: false,
+                is_adult: false,
+                sources: Vec::new(),
+                similarity: None,
+                release_group: None,
+                verified_peers: None,
+                season_pattern: None,
+            });
+        }
+
+        log_info!("EZTV: Found {} results for '{}'", results.len(), query);
+
+        results.sort_by(|a, b| b.seeds.cmp(&a.seeds));
+
+        Ok(results)
     }
 }
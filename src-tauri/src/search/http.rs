@@ -0,0 +1,383 @@
+use reqwest::{Client, Response, StatusCode};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{OnceLock, RwLock};
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+use crate::{log_info, log_warn};
+
+// Rotated per request so a single blocked fingerprint doesn't take a provider down
+const USER_AGENTS: &[&str] = &[
+    "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/131.0.0.0 Safari/537.36",
+    "Mozilla/5.0 (Windows NT 10.0; Win64; x64; rv:133.0) Gecko/20100101 Firefox/133.0",
+    "Mozilla/5.0 (Macintosh; Intel Mac OS X 10_15_7) AppleWebKit/605.1.15 (KHTML, like Gecko) Version/18.1 Safari/605.1.15",
+    "Mozilla/5.0 (X11; Linux x86_64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/130.0.0.0 Safari/537.36",
+    "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/131.0.0.0 Safari/537.36 Edg/131.0.0.0",
+];
+
+static NEXT_USER_AGENT: AtomicUsize = AtomicUsize::new(0);
+
+// Providers are created per search, so the per-host schedule has to be global
+static HOST_SCHEDULE: OnceLock<Mutex<HashMap<String, Instant>>> = OnceLock::new();
+
+// FlareSolverr endpoint from settings, e.g. "http://localhost:8191"
+static FLARESOLVERR_URL: RwLock<Option<String>> = RwLock::new(None);
+
+pub fn set_flaresolverr_url(url: Option<String>) {
+    let url = url
+        .map(|u| u.trim().trim_end_matches('/').to_string())
+        .filter(|u| !u.is_empty());
+    if let Ok(mut current) = FLARESOLVERR_URL.write() {
+        *current = url;
+    }
+}
+
+fn flaresolverr_url() -> Option<String> {
+    FLARESOLVERR_URL.read().ok().and_then(|u| u.clone())
+}
+
+/// Login details for a (semi-)private tracker, matched to requests by domain
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct TrackerCredentials {
+    // e.g. "tracker.example.org"; subdomains match too
+    pub domain: String,
+    #[serde(default)]
+    pub cookie: Option<String>,
+    #[serde(default)]
+    pub passkey: Option<String>,
+    // Announce URL with a {passkey} placeholder, added to torrents from this tracker
+    #[serde(default)]
+    pub announce_url: Option<String>,
+}
+
+impl TrackerCredentials {
+    fn matches_host(&self, host: &str) -> bool {
+        let domain = self.domain.trim().trim_start_matches("www.").to_lowercase();
+        let host = host.to_lowercase();
+        !domain.is_empty() && (host == domain || host.ends_with(&format!(".{}", domain)))
+    }
+
+    pub fn announce(&self) -> Option<String> {
+        let passkey = self.passkey.as_deref().filter(|p| !p.is_empty())?;
+        let template = self.announce_url.as_deref().filter(|u| !u.is_empty())?;
+        Some(template.replace("{passkey}", passkey))
+    }
+}
+
+// Set from the provider configs, like the FlareSolverr URL
+static TRACKER_CREDENTIALS: RwLock<Vec<TrackerCredentials>> = RwLock::new(Vec::new());
+
+pub fn set_tracker_credentials(credentials: Vec<TrackerCredentials>) {
+    if let Ok(mut current) = TRACKER_CREDENTIALS.write() {
+        *current = credentials;
+    }
+}
+
+fn host_of(url: &str) -> Option<String> {
+    reqwest::Url::parse(url).ok().and_then(|u| u.host_str().map(|h| h.to_string()))
+}
+
+pub fn credentials_for(url: &str) -> Option<TrackerCredentials> {
+    let host = host_of(url)?;
+    TRACKER_CREDENTIALS.read().ok()?
+        .iter()
+        .find(|c| c.matches_host(&host))
+        .cloned()
+}
+
+/// Passkey announce URLs for a magnet (matched by its trackers) or a .torrent URL
+/// (matched by its host)
+pub fn passkey_announce_urls(magnet_or_url: &str) -> Vec<String> {
+    let urls: Vec<String> = match magnet_or_url.strip_prefix("magnet:?") {
+        Some(params) => params
+            .split('&')
+            .filter_map(|part| part.strip_prefix("tr="))
+            .filter_map(|tr| urlencoding::decode(tr).ok().map(|t| t.into_owned()))
+            .collect(),
+        None => vec![magnet_or_url.to_string()],
+    };
+
+    let mut announces: Vec<String> = urls.iter()
+        .filter_map(|url| credentials_for(url)?.announce())
+        .collect();
+    announces.dedup();
+    announces
+}
+
+/// Returned when a page is behind a Cloudflare challenge and no FlareSolverr
+/// instance is configured (or it failed to solve it)
+#[derive(Debug)]
+pub struct ChallengeError {
+    pub host: String,
+}
+
+impl fmt::Display for ChallengeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} is behind a Cloudflare challenge", self.host)
+    }
+}
+
+impl Error for ChallengeError {}
+
+#[derive(Deserialize)]
+struct FlareSolverrResponse {
+    status: String,
+    #[serde(default)]
+    message: String,
+    solution: Option<FlareSolverrSolution>,
+}
+
+#[derive(Deserialize)]
+struct FlareSolverrSolution {
+    status: u16,
+    response: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    pub max_retries: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+    // Minimum spacing between two requests to the same host
+    pub min_host_interval: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(8),
+            min_host_interval: Duration::from_millis(250),
+        }
+    }
+}
+
+impl RetryPolicy {
+    fn backoff(&self, attempt: u32) -> Duration {
+        let factor = 2u32.saturating_pow(attempt);
+        self.base_delay.saturating_mul(factor).min(self.max_delay)
+    }
+}
+
+/// Shared HTTP client for the search providers: retries with exponential backoff,
+/// spaces out requests per host and rotates user agents.
+#[derive(Clone)]
+pub struct HttpClient {
+    client: Client,
+    policy: RetryPolicy,
+}
+
+impl HttpClient {
+    pub fn new(timeout: Duration) -> Self {
+        Self {
+            client: Client::builder()
+                .timeout(timeout)
+                .build()
+                .unwrap(),
+            policy: RetryPolicy::default(),
+        }
+    }
+
+    /// Same as `new` but accepts invalid certificates, for mirrors with broken TLS setups
+    pub fn new_insecure(timeout: Duration) -> Self {
+        Self {
+            client: Client::builder()
+                .timeout(timeout)
+                .danger_accept_invalid_certs(true)
+                .build()
+                .unwrap(),
+            policy: RetryPolicy::default(),
+        }
+    }
+
+    pub async fn get(&self, url: &str) -> Result<Response, reqwest::Error> {
+        self.get_with_headers(url, &[]).await
+    }
+
+    /// GET with retries. Connection errors, timeouts, 429 and 5xx responses are retried;
+    /// once retries are exhausted the last response (or error) is returned as-is.
+    /// Cookies and the passkey of a configured private tracker are added for its domain.
+    pub async fn get_with_headers(&self, url: &str, headers: &[(&str, &str)]) -> Result<Response, reqwest::Error> {
+        let mut attempt = 0;
+        loop {
+            wait_for_host_slot(url, self.policy.min_host_interval).await;
+
+            let credentials = credentials_for(url);
+            // Substituted here so the passkey never shows up in the logged URL
+            let request_url = match credentials.as_ref().and_then(|c| c.passkey.as_deref()) {
+                Some(passkey) => url.replace("{passkey}", passkey),
+                None => url.to_string(),
+            };
+
+            let mut request = self.client.get(&request_url)
+                .header(reqwest::header::USER_AGENT, next_user_agent());
+            if let Some(cookie) = credentials.as_ref().and_then(|c| c.cookie.as_deref()) {
+                request = request.header(reqwest::header::COOKIE, cookie);
+            }
+            for (name, value) in headers {
+                request = request.header(*name, *value);
+            }
+
+            let retry_after = match request.send().await {
+                Ok(response) => {
+                    let status = response.status();
+                    // Challenges won't clear by retrying, leave them to get_html
+                    if !is_retryable_status(status) || is_cf_mitigated(&response) || attempt >= self.policy.max_retries {
+                        return Ok(response);
+                    }
+                    log_warn!("http: {} returned {}, retrying ({}/{})", url, status, attempt + 1, self.policy.max_retries);
+                    retry_after_header(&response)
+                }
+                Err(e) => {
+                    // Request errors (a bad URL or header) fail the same way every time
+                    if !(e.is_connect() || e.is_timeout()) || attempt >= self.policy.max_retries {
+                        return Err(e);
+                    }
+                    log_warn!("http: {} failed: {}, retrying ({}/{})", url, e, attempt + 1, self.policy.max_retries);
+                    None
+                }
+            };
+
+            let delay = retry_after
+                .map(|d| d.min(self.policy.max_delay))
+                .unwrap_or_else(|| self.policy.backoff(attempt));
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+        }
+    }
+}
+
+impl HttpClient {
+    /// Fetches a page body, routing it through FlareSolverr when the site answers
+    /// with a Cloudflare challenge. Fails with `ChallengeError` if it can't get past it.
+    pub async fn get_html(&self, url: &str, headers: &[(&str, &str)]) -> Result<String, Box<dyn Error + Send + Sync>> {
+        let response = self.get_with_headers(url, headers).await?;
+        let status = response.status();
+        let mitigated = is_cf_mitigated(&response);
+        let body = response.text().await?;
+
+        if !mitigated && !is_challenge_page(status, &body) {
+            if !status.is_success() {
+                return Err(format!("{} returned status {}", url, status).into());
+            }
+            return Ok(body);
+        }
+
+        let host = reqwest::Url::parse(url).ok()
+            .and_then(|u| u.host_str().map(|h| h.to_string()))
+            .unwrap_or_else(|| url.to_string());
+
+        let Some(solver) = flaresolverr_url() else {
+            log_info!("http: {} is behind a Cloudflare challenge and FlareSolverr is not configured", host);
+            return Err(Box::new(ChallengeError { host }));
+        };
+
+        log_info!("http: solving Cloudflare challenge for {} via FlareSolverr", host);
+        match self.solve_with_flaresolverr(&solver, url).await {
+            Ok(html) => Ok(html),
+            Err(e) => {
+                log_warn!("http: FlareSolverr failed for {}: {}", host, e);
+                Err(Box::new(ChallengeError { host }))
+            }
+        }
+    }
+
+    async fn solve_with_flaresolverr(&self, solver: &str, url: &str) -> Result<String, Box<dyn Error + Send + Sync>> {
+        // FlareSolverr drives a real browser, so give it far longer than a normal request
+        let response = self.client.post(format!("{}/v1", solver))
+            .timeout(Duration::from_secs(70))
+            .json(&serde_json::json!({
+                "cmd": "request.get",
+                "url": url,
+                "maxTimeout": 60000,
+            }))
+            .send()
+            .await?
+            .json::<FlareSolverrResponse>()
+            .await?;
+
+        if response.status != "ok" {
+            return Err(format!("FlareSolverr returned {}: {}", response.status, response.message).into());
+        }
+
+        let solution = response.solution.ok_or("FlareSolverr response had no solution")?;
+        let solved_status = StatusCode::from_u16(solution.status).unwrap_or(StatusCode::OK);
+        if solution.status >= 400 || is_challenge_page(solved_status, &solution.response) {
+            return Err(format!("challenge still present after solving (status {})", solution.status).into());
+        }
+        Ok(solution.response)
+    }
+}
+
+// Titles of Cloudflare's interstitial pages. Ordinary pages served through Cloudflare
+// also load /cdn-cgi/challenge-platform scripts, so the body alone can't be trusted.
+const CHALLENGE_TITLES: &[&str] = &["just a moment", "attention required", "checking your browser"];
+
+fn is_challenge_page(status: StatusCode, body: &str) -> bool {
+    if page_title(body).is_some_and(|title| CHALLENGE_TITLES.iter().any(|t| title.starts_with(t))) {
+        return true;
+    }
+    // The challenge itself comes back as a 403 or 503 carrying its own markers
+    matches!(status, StatusCode::FORBIDDEN | StatusCode::SERVICE_UNAVAILABLE)
+        && (body.contains("cf-browser-verification") || body.contains("cf_chl_opt"))
+}
+
+fn page_title(body: &str) -> Option<String> {
+    let lower = body.to_lowercase();
+    let start = lower.find("<title")?;
+    let open_end = start + lower[start..].find('>')? + 1;
+    let close = open_end + lower[open_end..].find("</title>")?;
+    Some(lower[open_end..close].trim().to_string())
+}
+
+fn is_cf_mitigated(response: &Response) -> bool {
+    response.headers()
+        .get("cf-mitigated")
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.eq_ignore_ascii_case("challenge"))
+        .unwrap_or(false)
+}
+
+fn is_retryable_status(status: StatusCode) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+}
+
+fn retry_after_header(response: &Response) -> Option<Duration> {
+    response.headers()
+        .get(reqwest::header::RETRY_AFTER)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.trim().parse::<u64>().ok())
+        .map(Duration::from_secs)
+}
+
+fn next_user_agent() -> &'static str {
+    let index = NEXT_USER_AGENT.fetch_add(1, Ordering::Relaxed);
+    USER_AGENTS[index % USER_AGENTS.len()]
+}
+
+async fn wait_for_host_slot(url: &str, min_interval: Duration) {
+    let host = match reqwest::Url::parse(url).ok().and_then(|u| u.host_str().map(|h| h.to_string())) {
+        Some(h) => h,
+        None => return,
+    };
+
+    // Reserve the next slot for this host while holding the lock, then sleep outside it
+    let wait = {
+        let mut schedule = HOST_SCHEDULE.get_or_init(|| Mutex::new(HashMap::new())).lock().await;
+        let now = Instant::now();
+        let slot = match schedule.get(&host) {
+            Some(next) if *next > now => *next,
+            _ => now,
+        };
+        schedule.insert(host, slot + min_interval);
+        slot.saturating_duration_since(now)
+    };
+
+    if !wait.is_zero() {
+        tokio::time::sleep(wait).await;
+    }
+}
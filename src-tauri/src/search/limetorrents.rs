@@ -1,11 +1,13 @@
-use super::{SearchProvider, SearchResult, parse_audio_codec};
+use super::{QueryEncoding, SearchProvider, SearchResult, encode_query, parse_audio_codec};
+use super::http::{ChallengeError, HttpClient};
+use super::mirrors;
 use async_trait::async_trait;
-use reqwest::Client;
 use std::error::Error;
 use regex::Regex;
+use crate::{log_info, log_warn};
 
 pub struct LimeTorrentsProvider {
-    client: Client,
+    client: HttpClient,
     season_regex: Regex,
     episode_regex: Regex,
     quality_regex: Regex,
@@ -17,11 +19,7 @@ pub struct LimeTorrentsProvider {
 impl LimeTorrentsProvider {
     pub fn new() -> Self {
         Self {
-            client: Client::builder()
-                .user_agent("Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/131.0.0.0 Safari/537.36")
-                .timeout(std::time::Duration::from_secs(15))
-                .build()
-                .unwrap(),
+            client: HttpClient::new(std::time::Duration::from_secs(15)),
             season_regex: Regex::new(r"(?i)S(\d{1,2})|Season\s*(\d{1,2})").unwrap(),
             episode_regex: Regex::new(r"(?i)S\d{1,2}E(\d+)|E(\d+)|Episode\s*(\d+)|\s-\s*(\d+)\s*(?:v\d)?").unwrap(),
             quality_regex: Regex::new(r"(?i)(\d{3,4}p|4K|8K|2160p|1440p|1080p|720p|480p)").unwrap(),
@@ -88,33 +86,38 @@ impl SearchProvider for LimeTorrentsProvider {
     async fn search(&self, query: &str) -> Result<Vec<SearchResult>, Box<dyn Error + Send + Sync>> {
         let mut results = Vec::new();
         
-        let encoded_query = query.replace(" ", "%20").replace(":", "%3A");
-        let url = format!("https://www.limetorrents.fun/searchrss/{}/", encoded_query);
-        
-        println!("LimeTorrents: Fetching {}", url);
-        
-        let response = match self.client.get(&url).send().await {
-            Ok(r) => r,
-            Err(e) => {
-                println!("LimeTorrents: Request failed: {}", e);
-                return Ok(results);
+        let encoded_query = encode_query(query, QueryEncoding::Percent);
+        let mut xml = None;
+        let mut challenged_host = None;
+        for base_url in mirrors::mirrors_for("limetorrents") {
+            let url = format!("{}/searchrss/{}/", base_url, encoded_query);
+            log_info!("LimeTorrents: Fetching {}", url);
+            
+            match self.client.get_html(&url, &[]).await {
+                Ok(t) => {
+                    mirrors::record(&base_url, true);
+                    xml = Some(t);
+                    break;
+                }
+                Err(e) => {
+                    mirrors::record(&base_url, false);
+                    log_warn!("LimeTorrents: Request failed: {}", e);
+                    if let Some(challenge) = e.downcast_ref::<ChallengeError>() {
+                        challenged_host = Some(challenge.host.clone());
+                    }
+                }
             }
-        };
-        
-        if !response.status().is_success() {
-            println!("LimeTorrents: Status {}", response.status());
-            return Ok(results);
         }
         
-        let xml = match response.text().await {
-            Ok(t) => t,
-            Err(e) => {
-                println!("LimeTorrents: Failed to read body: {}", e);
-                return Ok(results);
+        let Some(xml) = xml else {
+            // Surface challenges so the search can flag the provider as degraded
+            if let Some(host) = challenged_host {
+                return Err(Box::new(ChallengeError { host }));
             }
+            return Ok(results);
         };
         
-        println!("LimeTorrents: Got RSS feed, length: {}", xml.len());
+        log_info!("LimeTorrents: Got RSS feed, length: {}", xml.len());
         
         // Parse XML manually since we don't need a full XML parser
         // Each item looks like:
@@ -196,10 +199,18 @@ impl SearchProvider for LimeTorrentsProvider {
                 encode,
                 is_batch,
                 audio_codec,
+                year: None,
+                is_collection: false,
+                is_adult: false,
+                sources: Vec::new(),
+                similarity: None,
+                release_group: None,
+                verified_peers: None,
+                season_pattern: None,
             });
         }
         
-        println!("LimeTorrents: Found {} results after category filter", results.len());
+        log_info!("LimeTorrents: Found {} results after category filter", results.len());
         
         // Sort by seeds descending
         results.sort_by(|a, b| b.seeds.cmp(&a.seeds));
@@ -222,7 +233,7 @@ impl SearchProvider for LimeTorrentsProvider {
             }
         }
         
-        println!("LimeTorrents: Returning {} results with magnet links", final_results.len());
+        log_info!("LimeTorrents: Returning {} results with magnet links", final_results.len());
         
         Ok(final_results)
     }
@@ -230,7 +241,7 @@ impl SearchProvider for LimeTorrentsProvider {
 
 impl LimeTorrentsProvider {
     async fn fetch_magnet_link(&self, page_url: &str) -> Result<String, Box<dyn Error + Send + Sync>> {
-        let response = self.client.get(page_url).send().await?;
+        let response = self.client.get(page_url).await?;
         
         if !response.status().is_success() {
             return Err("Failed to fetch detail page".into());
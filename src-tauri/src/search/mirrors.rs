@@ -0,0 +1,114 @@
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::sync::RwLock;
+
+// Recent outcomes kept per mirror; older ones stop counting so a mirror that came
+// back is tried first again soon
+const OUTCOME_HISTORY: usize = 20;
+
+/// Mirror base urls for the providers whose domains rotate, best known first
+pub fn default_mirrors() -> HashMap<String, Vec<String>> {
+    let mirrors: &[(&str, &[&str])] = &[
+        ("nyaa", &["https://nyaa.si", "https://nyaa.land", "https://nyaa.iss.ink"]),
+        ("1337x", &[
+            "https://1337x.unblockit.ch",
+            "https://1337x.unblockninja.com",
+            "https://1337xto.to",
+            "https://www.1337xx.to",
+            "https://1337x.to",
+            "https://1337x.st",
+            "https://x1337x.ws",
+            "https://1337x.is",
+        ]),
+        ("limetorrents", &["https://www.limetorrents.fun", "https://www.limetorrents.lol", "https://www.limetorrents.info"]),
+    ];
+
+    mirrors
+        .iter()
+        .map(|(provider, urls)| (provider.to_string(), urls.iter().map(|u| u.to_string()).collect()))
+        .collect()
+}
+
+// Set from settings, like the FlareSolverr URL
+static CONFIGURED: RwLock<Option<HashMap<String, Vec<String>>>> = RwLock::new(None);
+
+// true for a successful request, newest last
+static OUTCOMES: RwLock<Option<HashMap<String, VecDeque<bool>>>> = RwLock::new(None);
+
+pub fn set_configured_mirrors(mirrors: HashMap<String, Vec<String>>) {
+    if let Ok(mut current) = CONFIGURED.write() {
+        *current = Some(mirrors);
+    }
+}
+
+fn normalize(mirror: &str) -> String {
+    mirror.trim().trim_end_matches('/').to_string()
+}
+
+/// Records whether a request to `mirror` worked
+pub fn record(mirror: &str, success: bool) {
+    if let Ok(mut outcomes) = OUTCOMES.write() {
+        let history = outcomes.get_or_insert_with(HashMap::new).entry(normalize(mirror)).or_default();
+        history.push_back(success);
+        if history.len() > OUTCOME_HISTORY {
+            history.pop_front();
+        }
+    }
+}
+
+/// Share of recent requests to `mirror` that worked, or None when it hasn't been tried
+pub fn success_rate(mirror: &str) -> Option<f64> {
+    let outcomes = OUTCOMES.read().ok()?;
+    let history = outcomes.as_ref()?.get(&normalize(mirror))?;
+    if history.is_empty() {
+        return None;
+    }
+    Some(history.iter().filter(|ok| **ok).count() as f64 / history.len() as f64)
+}
+
+/// The provider's mirrors, from settings or the defaults, ordered by recent success
+/// rate. Untried mirrors count as working so they keep their configured position
+/// until they fail.
+pub fn mirrors_for(provider: &str) -> Vec<String> {
+    let configured = CONFIGURED.read().ok()
+        .and_then(|c| c.as_ref().and_then(|m| m.get(provider).cloned()))
+        .filter(|m| !m.is_empty());
+    let mut mirrors: Vec<String> = configured
+        .or_else(|| default_mirrors().remove(provider))
+        .unwrap_or_default()
+        .iter()
+        .map(|m| normalize(m))
+        .filter(|m| !m.is_empty())
+        .collect();
+
+    // Stable sort, so equally reliable mirrors stay in the configured order
+    mirrors.sort_by(|a, b| {
+        let rate_a = success_rate(a).unwrap_or(1.0);
+        let rate_b = success_rate(b).unwrap_or(1.0);
+        rate_b.partial_cmp(&rate_a).unwrap_or(std::cmp::Ordering::Equal)
+    });
+    mirrors
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MirrorStatus {
+    pub url: String,
+    pub success_rate: Option<f64>,
+}
+
+/// Every provider's mirrors in the order they'll be tried, for settings
+pub fn mirror_statuses() -> HashMap<String, Vec<MirrorStatus>> {
+    default_mirrors()
+        .into_keys()
+        .map(|provider| {
+            let statuses = mirrors_for(&provider)
+                .into_iter()
+                .map(|url| MirrorStatus {
+                    success_rate: success_rate(&url),
+                    url,
+                })
+                .collect();
+            (provider, statuses)
+        })
+        .collect()
+}
@@ -2,10 +2,19 @@ pub mod nyaa;
 pub mod limetorrents;
 pub mod piratebay;
 pub mod eztv;
+pub mod animetosho;
+pub mod http;
+pub mod x1337;
+pub mod torznab;
+pub mod definition;
+pub mod mirrors;
+pub mod yts;
 
 use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::error::Error;
+use std::sync::LazyLock;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SearchResult {
@@ -21,6 +30,38 @@ pub struct SearchResult {
     pub encode: Option<String>,
     pub is_batch: bool,
     pub audio_codec: Option<String>,
+    // Movie searches only: release year and whether the torrent bundles several movies
+    #[serde(default)]
+    pub year: Option<u32>,
+    #[serde(default)]
+    pub is_collection: bool,
+    // Listed under an adult category by the tracker, or tagged as such in the title
+    #[serde(default)]
+    pub is_adult: bool,
+    // Set when grouping by release: every listing merged into this one, best first
+    #[serde(default)]
+    pub sources: Vec<ReleaseSource>,
+    // 0-100 fuzzy match between the searched title and this release's title
+    #[serde(default)]
+    pub similarity: Option<f64>,
+    // Group tag from the title ("[SubsPlease] ..." or "...-NTb"), filled in with the similarity
+    #[serde(default)]
+    pub release_group: Option<String>,
+    // Distinct peers found on the DHT for this info hash, when the check ran
+    #[serde(default)]
+    pub verified_peers: Option<u32>,
+    // Alternate season naming that found this release ("2nd Season", "Part 2", ...)
+    #[serde(default)]
+    pub season_pattern: Option<String>,
+}
+
+/// One provider's listing of a release that was merged with others
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReleaseSource {
+    pub provider: String,
+    pub seeds: u32,
+    pub peers: u32,
+    pub magnet_link: String,
 }
 
 #[async_trait]
@@ -28,6 +69,14 @@ pub trait SearchProvider: Send + Sync {
     async fn search(&self, query: &str) -> Result<Vec<SearchResult>, Box<dyn Error + Send + Sync>>;
 }
 
+/// Catches adult releases from trackers that don't expose a category
+pub fn looks_adult(title: &str) -> bool {
+    let title_upper = title.to_uppercase();
+    title_upper
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .any(|word| matches!(word, "XXX" | "PORN" | "HENTAI" | "JAV"))
+}
+
 pub fn parse_audio_codec(title: &str) -> Option<String> {
     let title_upper = title.to_uppercase();
     
@@ -71,3 +120,438 @@ pub fn is_web_compatible(codec: Option<&str>) -> bool {
         _ => false,
     }
 }
+
+static SEASON_EPISODE_SEASON_REGEX: LazyLock<regex::Regex> =
+    LazyLock::new(|| regex::Regex::new(r"(?i)S(\d{1,2})|Season\s*(\d{1,2})").unwrap());
+static SEASON_EPISODE_EPISODE_REGEX: LazyLock<regex::Regex> =
+    LazyLock::new(|| regex::Regex::new(r"(?i)S\d{1,2}E(\d+)|E(\d+)|Episode\s*(\d+)|\s-\s*(\d+)\s*(?:v\d)?").unwrap());
+
+// Parse season/episode numbers from a release or file name using the same patterns as the providers
+pub fn parse_season_episode(name: &str) -> (Option<u32>, Option<u32>) {
+    let season = SEASON_EPISODE_SEASON_REGEX.captures(name)
+        .and_then(|c| c.get(1).or_else(|| c.get(2)))
+        .and_then(|m| m.as_str().parse().ok());
+
+    let episode = SEASON_EPISODE_EPISODE_REGEX.captures(name)
+        .and_then(|c| c.get(1).or_else(|| c.get(2)).or_else(|| c.get(3)).or_else(|| c.get(4)))
+        .and_then(|m| m.as_str().parse().ok());
+
+    (season, episode)
+}
+
+static RELEASE_GROUP_BRACKET_REGEX: LazyLock<regex::Regex> =
+    LazyLock::new(|| regex::Regex::new(r"^\s*\[([^\]]+)\]").unwrap());
+static RELEASE_GROUP_SUFFIX_REGEX: LazyLock<regex::Regex> =
+    LazyLock::new(|| regex::Regex::new(r"-([A-Za-z0-9]+)(?:\s*\[[^\]]*\])*(?:\.(?:mkv|mp4|avi))?\s*$").unwrap());
+
+/// Release group from a torrent title: a leading "[Group]" tag (anime style) or a
+/// trailing "-GROUP" suffix (scene style)
+pub fn parse_release_group(title: &str) -> Option<String> {
+    RELEASE_GROUP_BRACKET_REGEX.captures(title)
+        .or_else(|| RELEASE_GROUP_SUFFIX_REGEX.captures(title))
+        .and_then(|c| c.get(1))
+        .map(|m| m.as_str().trim().to_string())
+        .filter(|g| !g.is_empty())
+}
+
+static RELEASE_TITLE_EXTENSION_REGEX: LazyLock<regex::Regex> =
+    LazyLock::new(|| regex::Regex::new(r"(?i)\.(mkv|mp4|avi|mov|webm|m4v)$").unwrap());
+static RELEASE_TITLE_GROUP_REGEX: LazyLock<regex::Regex> =
+    LazyLock::new(|| regex::Regex::new(r"^\s*\[[^\]]*\]\s*").unwrap());
+static RELEASE_TITLE_YEAR_REGEX: LazyLock<regex::Regex> =
+    LazyLock::new(|| regex::Regex::new(r"[\(\[]?\b((?:19|20)\d{2})\b[\)\]]?").unwrap());
+// Everything from the first episode/quality/source marker on is release noise
+static RELEASE_TITLE_NOISE_REGEX: LazyLock<regex::Regex> =
+    LazyLock::new(|| regex::Regex::new(r"(?i)\b(S\d{1,2}E\d+|S\d{1,2}\b|Season\s*\d+|\d{1,2}x\d{2,3}|E\d{2,4}\b|\d{3,4}p|4K|WEB|WEB-?DL|WEBRip|BluRay|BDRip|HDTV|DVDRip|REMUX|x26[45]|H\.?26[45]|HEVC)|\s-\s*\d{1,4}\b|[\[\(]").unwrap());
+
+/// Show or movie title and year from a release or file name, e.g.
+/// "The.Show.2019.S01E02.1080p.WEB.mkv" -> ("The Show", Some(2019))
+pub fn parse_release_title(name: &str) -> (String, Option<u32>) {
+    let stem = RELEASE_TITLE_EXTENSION_REGEX.replace(name, "");
+    let stem = RELEASE_TITLE_GROUP_REGEX.replace(&stem, "");
+    let spaced = stem.replace(['.', '_'], " ");
+
+    let year_match = RELEASE_TITLE_YEAR_REGEX.captures(&spaced)
+        .and_then(|c| c.get(1).map(|m| (c.get(0).unwrap().start(), m.as_str().parse::<u32>().ok())));
+    let noise_start = RELEASE_TITLE_NOISE_REGEX.find(&spaced).map(|m| m.start());
+
+    // The title ends at whichever comes first; a year at the very start is part of the title
+    let mut end = spaced.len();
+    if let Some((start, _)) = year_match.filter(|(start, _)| *start > 0) {
+        end = end.min(start);
+    }
+    if let Some(start) = noise_start.filter(|start| *start > 0) {
+        end = end.min(start);
+    }
+
+    let title = spaced[..end]
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .trim_end_matches(['-', ' '])
+        .to_string();
+    let year = year_match.filter(|(start, _)| *start > 0).and_then(|(_, year)| year);
+
+    (title, year)
+}
+
+static MOVIE_YEAR_REGEX: LazyLock<regex::Regex> =
+    LazyLock::new(|| regex::Regex::new(r"(?:^|[^\d])((?:19|20)\d{2})(?:[^\dp]|$)").unwrap());
+static MOVIE_RANGE_REGEX: LazyLock<regex::Regex> =
+    LazyLock::new(|| regex::Regex::new(r"(?:19|20)\d{2}\s*-\s*(?:19|20)\d{2}").unwrap());
+static MOVIE_COLLECTION_REGEX: LazyLock<regex::Regex> =
+    LazyLock::new(|| regex::Regex::new(r"(?i)\b(trilogy|quadrilogy|duology|pentalogy|hexalogy|saga|collection|anthology|box\s*set|movie\s*pack|\d+\s*(?:movies|films))\b").unwrap());
+
+/// Movie-specific parsing: the season/episode heuristics misread trilogy packs and
+/// year ranges as batches, so movies get their own year and collection detection
+pub fn apply_movie_metadata(result: &mut SearchResult) {
+    let years: Vec<u32> = MOVIE_YEAR_REGEX.captures_iter(&result.title)
+        .filter_map(|c| c.get(1))
+        .filter_map(|m| m.as_str().parse().ok())
+        .collect();
+
+    result.is_collection = MOVIE_COLLECTION_REGEX.is_match(&result.title) || MOVIE_RANGE_REGEX.is_match(&result.title);
+    // A collection spans several years, so a single year would be misleading
+    result.year = if result.is_collection { None } else { years.first().copied() };
+    result.is_batch = result.is_collection;
+    result.season = None;
+    result.episode = None;
+}
+
+// Public trackers put on magnets built from a bare info hash, to find peers sooner than
+// the DHT alone would
+pub const DEFAULT_TRACKERS: &[&str] = &[
+    "udp://tracker.opentrackr.org:1337/announce",
+    "udp://open.demonii.com:1337/announce",
+    "udp://open.stealth.si:80/announce",
+    "udp://tracker.torrent.eu.org:451/announce",
+    "udp://exodus.desync.com:6969/announce",
+];
+
+/// Magnet for a bare info hash (40 hex or 32 base32 characters), None for anything else.
+/// Base32 hashes are written out as hex, which librqbit and the dedup both expect.
+pub fn info_hash_magnet(hash: &str, name: Option<&str>) -> Option<String> {
+    let hash = hash.trim();
+    let hex = match hash.len() {
+        40 if hash.chars().all(|c| c.is_ascii_hexdigit()) => hash.to_lowercase(),
+        32 => base32_to_hex(hash)?,
+        _ => return None,
+    };
+    let mut magnet = format!("magnet:?xt=urn:btih:{}", hex);
+    if let Some(name) = name.filter(|n| !n.is_empty()) {
+        magnet.push_str(&format!("&dn={}", urlencoding::encode(name)));
+    }
+    for tracker in DEFAULT_TRACKERS {
+        magnet.push_str(&format!("&tr={}", urlencoding::encode(tracker)));
+    }
+    Some(magnet)
+}
+
+// RFC 4648 base32, as found in older magnets
+fn base32_to_hex(s: &str) -> Option<String> {
+    let mut bits: u64 = 0;
+    let mut pending = 0;
+    let mut hex = String::with_capacity(40);
+    for c in s.chars() {
+        let value = match c.to_ascii_uppercase() {
+            c @ 'A'..='Z' => c as u64 - 'A' as u64,
+            c @ '2'..='7' => c as u64 - '2' as u64 + 26,
+            _ => return None,
+        };
+        bits = (bits << 5) | value;
+        pending += 5;
+        if pending >= 8 {
+            pending -= 8;
+            hex.push_str(&format!("{:02x}", (bits >> pending) & 0xff));
+        }
+    }
+    Some(hex)
+}
+
+// "1st", "2nd", "3rd", "4th", ..., "11th", "12th", "13th", "21st"
+fn ordinal(n: u32) -> String {
+    let suffix = match (n % 10, n % 100) {
+        (_, 11..=13) => "th",
+        (1, _) => "st",
+        (2, _) => "nd",
+        (3, _) => "rd",
+        _ => "th",
+    };
+    format!("{}{}", n, suffix)
+}
+
+/// Other ways trackers name season `season` of an anime, for when searching the title
+/// finds nothing labelled with it: split cours and sequels are often released as
+/// "2nd Season", "Part 2" or under the sequel's own title instead of S02. Each entry is
+/// the pattern's label and the query to run, in the order worth trying.
+pub fn season_queries(title: &str, season: u32, sequel_title: Option<&str>) -> Vec<(String, String)> {
+    let mut queries = Vec::new();
+    if let Some(sequel) = sequel_title.map(str::trim).filter(|s| !s.is_empty()) {
+        // TMDB season names are often just the arc ("Entertainment District Arc")
+        let query = if fold_title(sequel).contains(&fold_title(title)) {
+            sequel.to_string()
+        } else {
+            format!("{} {}", title, sequel)
+        };
+        queries.push((sequel.to_string(), query));
+    }
+    let ordinal_season = format!("{} Season", ordinal(season));
+    queries.push((ordinal_season.clone(), format!("{} {}", title, ordinal_season)));
+    queries.push((format!("Season {}", season), format!("{} Season {}", title, season)));
+    queries.push((format!("Part {}", season), format!("{} Part {}", title, season)));
+    queries
+}
+
+static ANIME_MOVIE_NOISE_REGEX: LazyLock<regex::Regex> =
+    LazyLock::new(|| regex::Regex::new(r"(?i)\b(?:the\s+movie|movie|gekij(?:ou|ō|o)[\s-]?ban|film)\b|[\(\[]?\b(?:19|20)\d{2}\b[\)\]]?").unwrap());
+
+/// Nyaa query for an anime movie. Fansub releases rarely repeat "The Movie" or the
+/// year the way TMDB titles do, and Nyaa needs every word to match, so those words are
+/// dropped: "Demon Slayer The Movie Mugen Train (2020)" -> "Demon Slayer Mugen Train"
+pub fn anime_movie_query(query: &str) -> String {
+    let stripped = ANIME_MOVIE_NOISE_REGEX
+        .replace_all(query, " ")
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ");
+    // A title made of nothing but those words is left alone
+    if stripped.is_empty() {
+        query.trim().to_string()
+    } else {
+        stripped
+    }
+}
+
+// Base letter of a lowercase accented Latin letter, or the character itself
+fn strip_diacritic(c: char) -> char {
+    match c {
+        'à' | 'á' | 'â' | 'ã' | 'ä' | 'å' | 'ā' => 'a',
+        'ç' => 'c',
+        'è' | 'é' | 'ê' | 'ë' | 'ē' => 'e',
+        'ì' | 'í' | 'î' | 'ï' | 'ī' => 'i',
+        'ñ' => 'n',
+        'ò' | 'ó' | 'ô' | 'õ' | 'ö' | 'ø' | 'ō' => 'o',
+        'ù' | 'ú' | 'û' | 'ü' | 'ū' => 'u',
+        'ý' | 'ÿ' => 'y',
+        c => c,
+    }
+}
+
+/// Lowercases, strips diacritics and punctuation and collapses romaji long vowels, so
+/// "Shōgun", "Shougun" and "Shogun" compare equal
+pub fn fold_title(title: &str) -> String {
+    let folded: String = title
+        .to_lowercase()
+        .chars()
+        .map(|c| match strip_diacritic(c) {
+            c if c.is_alphanumeric() => c,
+            _ => ' ',
+        })
+        .collect();
+
+    folded
+        .split_whitespace()
+        .map(|word| word.replace("ou", "o").replace("oo", "o").replace("uu", "u"))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Turns a title into something every tracker's search can match: full-width forms
+/// become ASCII, accents are dropped, "&" becomes "and", apostrophes are removed
+/// ("Don't" -> "Dont", as scene releases spell it) and all other punctuation, Japanese
+/// included, becomes a space. CJK text is kept. With `romaji`, macron long vowels are
+/// spelled out the way fansub groups write them ("Shōgun" -> "Shougun") instead of
+/// just losing the macron.
+pub fn normalize_query(query: &str, romaji: bool) -> String {
+    let mut normalized = String::with_capacity(query.len());
+    let chars: Vec<char> = query.chars().collect();
+    for (i, &c) in chars.iter().enumerate() {
+        // Full-width ASCII (！, Ａ, ＆) and the ideographic space
+        let c = match c {
+            '\u{FF01}'..='\u{FF5E}' => char::from_u32(c as u32 - 0xFEE0).unwrap_or(c),
+            '\u{3000}' => ' ',
+            c => c,
+        };
+        let lower = c.to_lowercase().next().unwrap_or(c);
+        match lower {
+            '&' => normalized.push_str(" and "),
+            '\'' | '’' | '‘' | '`' | 'ʼ' => {}
+            // Keep decimal points in titles like "Evangelion 3.0"
+            '.' if i > 0 && chars[i - 1].is_ascii_digit() && chars.get(i + 1).is_some_and(|n| n.is_ascii_digit()) => {
+                normalized.push('.')
+            }
+            'ō' | 'ū' if romaji => {
+                let base = strip_diacritic(lower);
+                normalized.push(if c.is_uppercase() { base.to_ascii_uppercase() } else { base });
+                normalized.push(if lower == 'ō' { 'u' } else { base });
+            }
+            _ => {
+                let base = strip_diacritic(lower);
+                if base != lower {
+                    normalized.push(if c.is_uppercase() { base.to_ascii_uppercase() } else { base });
+                } else if c.is_alphanumeric() {
+                    normalized.push(c);
+                } else {
+                    normalized.push(' ');
+                }
+            }
+        }
+    }
+    normalized.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// How a provider puts the normalized query into its search URL
+#[derive(Debug, Clone, Copy)]
+pub enum QueryEncoding {
+    /// Percent-encoded, spaces as %20
+    Percent,
+    /// Words percent-encoded and joined with "+"
+    Plus,
+    /// Lowercase words joined with "-", for sites that search by path ("/search/one-piece")
+    Slug,
+}
+
+pub fn encode_query(query: &str, encoding: QueryEncoding) -> String {
+    match encoding {
+        QueryEncoding::Percent => urlencoding::encode(query).into_owned(),
+        QueryEncoding::Plus => query
+            .split_whitespace()
+            .map(|word| urlencoding::encode(word).into_owned())
+            .collect::<Vec<_>>()
+            .join("+"),
+        QueryEncoding::Slug => query
+            .split_whitespace()
+            .map(|word| urlencoding::encode(&word.to_lowercase()).into_owned())
+            .collect::<Vec<_>>()
+            .join("-"),
+    }
+}
+
+// Indel similarity of two strings, 0-100
+fn ratio(a: &str, b: &str) -> f64 {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    if a.is_empty() && b.is_empty() {
+        return 100.0;
+    }
+
+    // Longest common subsequence, one row at a time
+    let mut previous = vec![0usize; b.len() + 1];
+    for ca in &a {
+        let mut current = vec![0usize; b.len() + 1];
+        for (j, cb) in b.iter().enumerate() {
+            current[j + 1] = if ca == cb { previous[j] + 1 } else { previous[j + 1].max(current[j]) };
+        }
+        previous = current;
+    }
+
+    200.0 * previous[b.len()] as f64 / (a.len() + b.len()) as f64
+}
+
+/// Token set ratio of two already folded titles: word order and words only one side has
+/// don't count against a match, so "vinland saga" fully matches "vinland saga season 2"
+/// but only half matches "saga of tanya the evil"
+pub fn token_set_ratio(a: &str, b: &str) -> f64 {
+    let tokens_a: std::collections::BTreeSet<&str> = a.split_whitespace().collect();
+    let tokens_b: std::collections::BTreeSet<&str> = b.split_whitespace().collect();
+    if tokens_a.is_empty() || tokens_b.is_empty() {
+        return 0.0;
+    }
+
+    let join = |tokens: Vec<&str>| tokens.join(" ");
+    let common = join(tokens_a.intersection(&tokens_b).copied().collect());
+    let only_a = join(tokens_a.difference(&tokens_b).copied().collect());
+    let only_b = join(tokens_b.difference(&tokens_a).copied().collect());
+    let combined_a = format!("{} {}", common, only_a).trim().to_string();
+    let combined_b = format!("{} {}", common, only_b).trim().to_string();
+
+    let mut best = ratio(&combined_a, &combined_b);
+    if !common.is_empty() {
+        best = best.max(ratio(&common, &combined_a)).max(ratio(&common, &combined_b));
+    }
+    best
+}
+
+/// How well a release title matches the searched title, 0-100. Episode, quality and group
+/// tags are stripped from both sides first.
+pub fn title_similarity(query: &str, title: &str) -> f64 {
+    let (query_title, _) = parse_release_title(query);
+    let (release_title, _) = parse_release_title(title);
+    token_set_ratio(&fold_title(&query_title), &fold_title(&release_title))
+}
+
+// Same release across providers: title without extension, CRC tag and punctuation, plus
+// quality and group so a re-encode doesn't merge with the original
+static RELEASE_EXTENSION_REGEX: LazyLock<regex::Regex> =
+    LazyLock::new(|| regex::Regex::new(r"(?i)\.(mkv|mp4|avi)$").unwrap());
+static RELEASE_CRC_REGEX: LazyLock<regex::Regex> =
+    LazyLock::new(|| regex::Regex::new(r"(?i)\[[0-9a-f]{8}\]").unwrap());
+
+fn release_key(result: &SearchResult) -> String {
+    let title = RELEASE_EXTENSION_REGEX.replace(&result.title, "");
+    let title = RELEASE_CRC_REGEX.replace_all(&title, "");
+    let normalized = title
+        .to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    format!(
+        "{}|{}|{}",
+        normalized,
+        result.quality.as_deref().unwrap_or("").to_lowercase(),
+        parse_release_group(&result.title).unwrap_or_default().to_lowercase()
+    )
+}
+
+/// Merges results that are the same release listed by different providers (or uploaded
+/// twice) into one entry with the combined seeds and peers. The entry keeps the fields
+/// and magnet of its best-seeded listing; the others are in `sources`.
+pub fn group_releases(results: &[SearchResult]) -> Vec<SearchResult> {
+    // Groups stay in the order their first member appeared
+    let mut groups: Vec<Vec<&SearchResult>> = Vec::new();
+    let mut index: HashMap<String, usize> = HashMap::new();
+    for result in results {
+        let position = *index.entry(release_key(result)).or_insert_with(|| {
+            groups.push(Vec::new());
+            groups.len() - 1
+        });
+        groups[position].push(result);
+    }
+
+    groups
+        .into_iter()
+        .map(|mut members| {
+            if members.len() == 1 {
+                return members[0].clone();
+            }
+            members.sort_by(|a, b| b.seeds.cmp(&a.seeds));
+
+            let mut grouped = members[0].clone();
+            grouped.seeds = members.iter().map(|m| m.seeds).sum();
+            grouped.peers = members.iter().map(|m| m.peers).sum();
+            grouped.verified_peers = members.iter().filter_map(|m| m.verified_peers).reduce(|a, b| a + b);
+            grouped.sources = members
+                .iter()
+                .map(|m| ReleaseSource {
+                    provider: m.provider.clone(),
+                    seeds: m.seeds,
+                    peers: m.peers,
+                    magnet_link: m.magnet_link.clone(),
+                })
+                .collect();
+            grouped
+        })
+        .collect()
+}
+
+/// Ranks movie results against the release year the frontend passed: exact year
+/// matches first, then collections, then everything else
+pub fn movie_year_score(result: &SearchResult, release_year: Option<u32>) -> u32 {
+    match release_year {
+        Some(year) if result.year == Some(year) => 2,
+        Some(year) if result.is_collection && result.title.contains(&year.to_string()) => 1,
+        _ => 0,
+    }
+}
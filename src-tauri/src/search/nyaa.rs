@@ -1,13 +1,22 @@
-use super::{SearchProvider, SearchResult, parse_audio_codec};
+use super::{QueryEncoding, SearchProvider, SearchResult, encode_query, parse_audio_codec};
+use super::http::HttpClient;
+use super::mirrors;
 use async_trait::async_trait;
-use reqwest::Client;
 use scraper::{Html, Selector};
 use std::error::Error;
 use regex::Regex;
 use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::Semaphore;
+use crate::{log_info, log_warn};
+
+// How many .torrent files we download in parallel, and for how many rows
+const METADATA_FETCH_CONCURRENCY: usize = 4;
+const METADATA_FETCH_LIMIT: usize = 12;
 
 pub struct NyaaProvider {
-    client: Client,
+    client: HttpClient,
     season_regex: Regex,
     episode_regex: Regex,
     quality_regex: Regex,
@@ -18,7 +27,7 @@ pub struct NyaaProvider {
 impl NyaaProvider {
     pub fn new() -> Self {
         Self {
-            client: Client::new(),
+            client: HttpClient::new(std::time::Duration::from_secs(20)),
             // Updated to capture season in multiple formats including "Season X"
             season_regex: Regex::new(r"(?i)S(\d{1,2})|Season\s*(\d{1,2})").unwrap(),
             // Updated to handle 3+ digit episodes
@@ -29,22 +38,28 @@ impl NyaaProvider {
         }
     }
 
-    fn parse_metadata(&self, title: &str, magnet: &str) -> (Option<u32>, Option<u32>, Option<String>, Option<String>, bool) {
+    fn parse_metadata(&self, title: &str, torrent_data: Option<&[u8]>) -> (Option<u32>, Option<u32>, Option<String>, Option<String>, bool, Option<String>) {
         let mut season = None;
         let mut episode = None;
         let mut quality = None;
         let mut encode = None;
         let mut is_batch = false;
-
-        // Try to extract info hash and fetch torrent metadata first
-        if let Some(info_hash) = self.extract_info_hash(magnet) {
-            if let Ok(metadata) = self.fetch_torrent_metadata(&info_hash) {
-                if let Some((s, e, q, enc, batch)) = self.parse_torrent_metadata(&metadata) {
-                    season = s;
-                    episode = e;
-                    quality = q;
-                    encode = enc;
-                    is_batch = batch;
+        let mut audio_codec = parse_audio_codec(title);
+        // When we have the real file list, batch detection comes from it instead of the title
+        let mut has_file_list = false;
+
+        // Use the .torrent metadata first if it was fetched
+        if let Some(data) = torrent_data {
+            if let Some((s, e, q, enc, batch, audio)) = self.parse_torrent_metadata(data) {
+                season = s;
+                episode = e;
+                quality = q;
+                encode = enc;
+                is_batch = batch;
+                has_file_list = true;
+                // Title wins, file names fill in releases that don't advertise the codec
+                if audio_codec.is_none() {
+                    audio_codec = audio;
                 }
             }
         }
@@ -80,46 +95,91 @@ impl NyaaProvider {
             }
         }
 
-        // Check if it's a batch release from title if not already detected
-        if !is_batch {
-            is_batch = self.batch_regex.is_match(title);
-        }
+        if !has_file_list {
+            // Check if it's a batch release from title if not already detected
+            if !is_batch {
+                is_batch = self.batch_regex.is_match(title);
+            }
 
-        // Mark as batch if "Season X" format appears in title (even with episode numbers)
-        // This catches torrents like "Season 1" which are always full season packs
-        if season.is_some() && title.to_lowercase().contains("season") {
-            is_batch = true;
-        }
+            // Mark as batch if "Season X" format appears in title (even with episode numbers)
+            // This catches torrents like "Season 1" which are always full season packs
+            if season.is_some() && title.to_lowercase().contains("season") {
+                is_batch = true;
+            }
 
-        // Also mark as batch if has season but no episode
-        if season.is_some() && episode.is_none() {
-            is_batch = true;
+            // Also mark as batch if has season but no episode
+            if season.is_some() && episode.is_none() {
+                is_batch = true;
+            }
         }
 
-        (season, episode, quality, encode, is_batch)
+        (season, episode, quality, encode, is_batch, audio_codec)
     }
 
-    fn extract_info_hash(&self, magnet: &str) -> Option<String> {
-        if let Some(start) = magnet.find("urn:btih:") {
-            let hash_start = start + 9;
-            let hash_part = &magnet[hash_start..];
-            if let Some(end) = hash_part.find('&') {
-                Some(hash_part[..end].to_string())
-            } else {
-                Some(hash_part.to_string())
+    /// One page of search results from `mirror`, or from the first configured mirror
+    /// that answers. Returns the body and the mirror used.
+    async fn fetch_search_page(&self, query: &str, page: u32, mirror: Option<&str>) -> Result<(String, String), Box<dyn Error + Send + Sync>> {
+        let candidates = match mirror {
+            Some(mirror) => vec![mirror.to_string()],
+            None => mirrors::mirrors_for("nyaa"),
+        };
+
+        let encoded_query = encode_query(query, QueryEncoding::Percent);
+        let mut last_error = None;
+        for base_url in candidates {
+            let url = format!("{}/?f=0&c=1_0&q={}&s=seeders&o=desc&p={}", base_url, encoded_query, page);
+            match self.client.get(&url).await.and_then(|r| r.error_for_status()) {
+                Ok(response) => {
+                    mirrors::record(&base_url, true);
+                    return Ok((response.text().await?, base_url));
+                }
+                Err(e) => {
+                    mirrors::record(&base_url, false);
+                    log_warn!("Nyaa: {} failed: {}", base_url, e);
+                    last_error = Some(e);
+                }
             }
-        } else {
-            None
+        }
+
+        match last_error {
+            Some(e) => Err(e.into()),
+            None => Err("No Nyaa mirrors configured".into()),
         }
     }
 
-    fn fetch_torrent_metadata(&self, _info_hash: &str) -> Result<Vec<u8>, Box<dyn Error>> {
-        // For now, return empty as we'd need to actually fetch .torrent file
-        // This would require accessing torrent trackers or DHT
-        Err("Metadata fetching not implemented".into())
+    /// Download .torrent files for the given Nyaa ids (from `/download/{id}.torrent`),
+    /// at most METADATA_FETCH_CONCURRENCY at a time. Failures are skipped.
+    async fn fetch_torrent_metadata(&self, base_url: &str, ids: Vec<String>) -> HashMap<String, Vec<u8>> {
+        let semaphore = Arc::new(Semaphore::new(METADATA_FETCH_CONCURRENCY));
+        let mut tasks = tokio::task::JoinSet::new();
+
+        for id in ids {
+            let client = self.client.clone();
+            let semaphore = semaphore.clone();
+            let base_url = base_url.to_string();
+            tasks.spawn(async move {
+                let _permit = semaphore.acquire_owned().await.ok()?;
+                let url = format!("{}/download/{}.torrent", base_url, id);
+                let response = client.get(&url).await.ok()?;
+                if !response.status().is_success() {
+                    log_warn!("Nyaa: {} returned status {}", url, response.status());
+                    return None;
+                }
+                let bytes = response.bytes().await.ok()?;
+                Some((id, bytes.to_vec()))
+            });
+        }
+
+        let mut metadata = HashMap::new();
+        while let Some(joined) = tasks.join_next().await {
+            if let Ok(Some((id, bytes))) = joined {
+                metadata.insert(id, bytes);
+            }
+        }
+        metadata
     }
 
-    fn parse_torrent_metadata(&self, data: &[u8]) -> Option<(Option<u32>, Option<u32>, Option<String>, Option<String>, bool)> {
+    fn parse_torrent_metadata(&self, data: &[u8]) -> Option<(Option<u32>, Option<u32>, Option<String>, Option<String>, bool, Option<String>)> {
         #[derive(Debug, Deserialize)]
         #[allow(dead_code)]
         struct FileEntry {
@@ -158,16 +218,17 @@ impl NyaaProvider {
                         .collect()
                 } else {
                     // Single file torrent
-                    if name.to_lowercase().ends_with(".mkv") || 
-                       name.to_lowercase().ends_with(".mp4") {
+                    let lower = name.to_lowercase();
+                    if lower.ends_with(".mkv") || lower.ends_with(".mp4") ||
+                       lower.ends_with(".avi") || lower.ends_with(".m4v") {
                         vec![name.to_string()]
                     } else {
                         vec![]
                     }
                 };
 
-                // Batch detection based on video file count
-                let mut is_batch = video_files.len() > 1;
+                // Batch detection based on the real video file count
+                let is_batch = video_files.len() > 1;
 
                 // Parse metadata from torrent name first
                 let season = self.season_regex.captures(name)
@@ -186,8 +247,8 @@ impl NyaaProvider {
                     .and_then(|c| c.get(1))
                     .map(|m| m.as_str().to_uppercase());
 
-                // If no episode found in name, scan video filenames
-                if episode.is_none() && !video_files.is_empty() {
+                // If no episode found in name, take it from the file when there is exactly one
+                if episode.is_none() && video_files.len() == 1 {
                     for vf in &video_files {
                         if let Some(caps) = self.episode_regex.captures(vf) {
                             episode = caps.get(1)
@@ -202,17 +263,11 @@ impl NyaaProvider {
                     }
                 }
 
-                // Additional batch indicators
-                if !is_batch {
-                    is_batch = self.batch_regex.is_match(name);
-                }
+                // Audio codec from the torrent name, then from any video file name
+                let audio_codec = parse_audio_codec(name)
+                    .or_else(|| video_files.iter().find_map(|vf| parse_audio_codec(vf)));
 
-                // Mark as batch if season without specific episode
-                if season.is_some() && episode.is_none() {
-                    is_batch = true;
-                }
-
-                return Some((season, episode, quality, encode, is_batch));
+                return Some((season, episode, quality, encode, is_batch, audio_codec));
             }
         }
         None
@@ -228,13 +283,17 @@ impl SearchProvider for NyaaProvider {
         let size_selector = Selector::parse("td:nth-child(4)").unwrap();
         let seeds_selector = Selector::parse("td:nth-child(6)").unwrap();
         let peers_selector = Selector::parse("td:nth-child(7)").unwrap();
+        let download_selector = Selector::parse("td:nth-child(3) a[href$='.torrent']").unwrap();
 
-        let mut results = Vec::new();
+        // (title, magnet, size, seeds, peers, nyaa id)
+        let mut rows = Vec::new();
 
-        // Fetch first 3 pages for more results (75 total)
+        // Fetch first 3 pages for more results (75 total), all from the mirror that
+        // answered the first
+        let mut base_url: Option<String> = None;
         for page in 1..=3 {
-            let url = format!("https://nyaa.si/?f=0&c=1_0&q={}&s=seeders&o=desc&p={}", query, page);
-            let response = self.client.get(&url).send().await?.text().await?;
+            let (response, mirror) = self.fetch_search_page(query, page, base_url.as_deref()).await?;
+            base_url = Some(mirror);
             let document = Html::parse_document(&response);
 
             for row in document.select(&row_selector) {
@@ -266,12 +325,43 @@ impl SearchProvider for NyaaProvider {
                 None => 0,
             };
 
-            let (season, episode, quality, encode, is_batch) = self.parse_metadata(&title, &magnet_link);
-            let audio_codec = parse_audio_codec(&title);
+            // Download links look like /download/1234567.torrent
+            let nyaa_id = row.select(&download_selector).next()
+                .and_then(|el| el.value().attr("href"))
+                .and_then(|href| href.rsplit('/').next())
+                .and_then(|file| file.strip_suffix(".torrent"))
+                .map(|id| id.to_string());
+
+            rows.push((title, magnet_link, size, seeds, peers, nyaa_id));
+            }
+        }
+
+        // A title naming a single episode needs no file list. Only titles that could be
+        // batches get one, and rows are sorted by seeders, so the most relevant of those
+        let ids: Vec<String> = rows.iter()
+            .filter(|row| {
+                let (_, episode, _, _, is_batch, _) = self.parse_metadata(&row.0, None);
+                episode.is_none() || is_batch
+            })
+            .filter_map(|row| row.5.clone())
+            .take(METADATA_FETCH_LIMIT)
+            .collect();
+        let torrent_files = match base_url {
+            Some(ref base_url) => self.fetch_torrent_metadata(base_url, ids).await,
+            None => HashMap::new(),
+        };
+        log_info!("Nyaa: fetched {} .torrent files for metadata", torrent_files.len());
+
+        let mut results = Vec::new();
+        for (title, magnet_link, size, seeds, peers, nyaa_id) in rows {
+            let torrent_data = nyaa_id.as_ref()
+                .and_then(|id| torrent_files.get(id))
+                .map(|data| data.as_slice());
+            let (season, episode, quality, encode, is_batch, audio_codec) = self.parse_metadata(&title, torrent_data);
 
             // Debug logging
             if season.is_some() || episode.is_some() {
-                println!("Parsed: {} -> S:{:?} E:{:?} Batch:{}", 
+                log_info!("Parsed: {} -> S:{:?} E:{:?} Batch:{}", 
                     title, season, episode, is_batch);
             }
 
@@ -288,8 +378,15 @@ impl SearchProvider for NyaaProvider {
                 encode,
                 is_batch,
                 audio_codec,
+                year: None,
+                is_collection: false,
+                is_adult: false,
+                sources: Vec::new(),
+                similarity: None,
+                release_group: None,
+                verified_peers: None,
+                season_pattern: None,
             });
-            }
         }
 
         Ok(results)
@@ -1,11 +1,12 @@
-use super::{SearchProvider, SearchResult, parse_audio_codec};
+use super::{SearchProvider, SearchResult, looks_adult, parse_audio_codec};
+use super::http::HttpClient;
 use async_trait::async_trait;
-use reqwest::Client;
 use std::error::Error;
 use regex::Regex;
+use crate::{log_error, log_info};
 
 pub struct PirateBayProvider {
-    client: Client,
+    client: HttpClient,
     season_regex: Regex,
     episode_regex: Regex,
     quality_regex: Regex,
@@ -16,11 +17,7 @@ pub struct PirateBayProvider {
 impl PirateBayProvider {
     pub fn new() -> Self {
         Self {
-            client: Client::builder()
-                .user_agent("Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/120.0.0.0 Safari/537.36")
-                .timeout(std::time::Duration::from_secs(15))
-                .build()
-                .unwrap(),
+            client: HttpClient::new(std::time::Duration::from_secs(15)),
             season_regex: Regex::new(r"(?i)S(\d{1,2})|Season\s*(\d{1,2})").unwrap(),
             episode_regex: Regex::new(r"(?i)S\d{1,2}E(\d+)|E(\d+)|Episode\s*(\d+)|\s-\s*(\d+)\s*(?:v\d)?").unwrap(),
             quality_regex: Regex::new(r"(?i)(\d{3,4}p|4K|8K|2160p|1440p|1080p|720p|480p)").unwrap(),
@@ -64,18 +61,18 @@ impl PirateBayProvider {
         // Use apibay.org API - cat=200 is video category
         let api_url = format!("https://apibay.org/q.php?q={}&cat=200", encoded_query);
         
-        println!("TPB: Trying API at {}", api_url);
+        log_info!("TPB: Trying API at {}", api_url);
         
         // Normalize target IMDB (strip "tt" prefix if present)
         let normalized_target_imdb = target_imdb.map(|id| {
             id.trim_start_matches("tt").to_string()
         });
         
-        match self.client.get(&api_url).send().await {
+        match self.client.get(&api_url).await {
             Ok(response) => {
-                println!("TPB API: Got response, status: {}", response.status());
+                log_info!("TPB API: Got response, status: {}", response.status());
                 if let Ok(text) = response.text().await {
-                    println!("TPB API: Got response, length: {}", text.len());
+                    log_info!("TPB API: Got response, length: {}", text.len());
                     // Parse JSON array of torrents
                     if let Ok(torrents) = serde_json::from_str::<Vec<serde_json::Value>>(&text) {
                         for torrent in torrents {
@@ -113,12 +110,10 @@ impl PirateBayProvider {
                                 _ => false,
                             };
                             
-                            // Build magnet link
-                            let magnet_link = format!(
-                                "magnet:?xt=urn:btih:{}&dn={}",
-                                info_hash,
-                                urlencoding::encode(&name)
-                            );
+                            // apibay only gives the hash, so the magnet gets public trackers
+                            let Some(magnet_link) = super::info_hash_magnet(info_hash, Some(&name)) else {
+                                continue;
+                            };
                             
                             let size_bytes: u64 = torrent.get("size")
                                 .and_then(|v| v.as_str())
@@ -145,6 +140,14 @@ impl PirateBayProvider {
                                 .and_then(|s| s.parse().ok())
                                 .unwrap_or(0);
                             
+                            // apibay doesn't always honour cat=200, and 500-599 are the porn categories
+                            let is_adult = torrent.get("category")
+                                .and_then(|v| v.as_str())
+                                .and_then(|s| s.parse::<u32>().ok())
+                                .map(|cat| (500..600).contains(&cat))
+                                .unwrap_or(false)
+                                || looks_adult(&name);
+
                             let (season, episode, quality, encode, is_batch) = self.parse_metadata(&name);
                             let audio_codec = parse_audio_codec(&name);
 
@@ -161,13 +164,21 @@ impl PirateBayProvider {
                                 encode,
                                 is_batch,
                                 audio_codec,
+                                year: None,
+                                is_collection: false,
+                                is_adult,
+                                sources: Vec::new(),
+                                similarity: None,
+                                release_group: None,
+                                verified_peers: None,
+                                season_pattern: None,
                             }));
                         }
                     }
                 }
             }
             Err(e) => {
-                println!("TPB API: Error fetching: {}", e);
+                log_error!("TPB API: Error fetching: {}", e);
             }
         }
 
@@ -185,7 +196,7 @@ impl PirateBayProvider {
         // Extract just the SearchResults
         let final_results: Vec<SearchResult> = results.into_iter().map(|(_, r)| r).collect();
         
-        println!("TPB: Returning {} results", final_results.len());
+        log_info!("TPB: Returning {} results", final_results.len());
         Ok(final_results)
     }
 }
@@ -0,0 +1,159 @@
+use super::{SearchProvider, SearchResult, looks_adult, parse_audio_codec, parse_season_episode};
+use super::http::HttpClient;
+use async_trait::async_trait;
+use regex::Regex;
+use std::error::Error;
+use crate::log_info;
+
+/// Any Torznab endpoint, e.g. a Prowlarr or Jackett indexer
+pub struct TorznabProvider {
+    client: HttpClient,
+    name: String,
+    // The indexer's api endpoint, e.g. "http://localhost:9696/1/api"
+    url: String,
+    api_key: Option<String>,
+    categories: Vec<u32>,
+    quality_regex: Regex,
+    encode_regex: Regex,
+    batch_regex: Regex,
+}
+
+impl TorznabProvider {
+    pub fn new(name: String, url: String, api_key: Option<String>, categories: Vec<u32>) -> Self {
+        Self {
+            client: HttpClient::new(std::time::Duration::from_secs(30)),
+            name,
+            url,
+            api_key,
+            categories,
+            quality_regex: Regex::new(r"(?i)(\d{3,4}p|4K|8K|2160p|1440p|1080p|720p|480p)").unwrap(),
+            encode_regex: Regex::new(r"(?i)(x264|x265|H\.?264|H\.?265|HEVC|AVC|VP9|AV1)").unwrap(),
+            batch_regex: Regex::new(r"(?i)(batch|complete|\d+-\d+|S\d+E\d+-E?\d+)").unwrap(),
+        }
+    }
+
+    fn format_size(bytes: u64) -> String {
+        if bytes >= 1_073_741_824 {
+            format!("{:.2} GiB", bytes as f64 / 1_073_741_824.0)
+        } else if bytes >= 1_048_576 {
+            format!("{:.2} MiB", bytes as f64 / 1_048_576.0)
+        } else {
+            format!("{:.2} KiB", bytes as f64 / 1024.0)
+        }
+    }
+}
+
+fn unescape_xml(value: &str) -> String {
+    value
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+#[async_trait]
+impl SearchProvider for TorznabProvider {
+    async fn search(&self, query: &str) -> Result<Vec<SearchResult>, Box<dyn Error + Send + Sync>> {
+        let mut url = format!("{}?t=search&q={}", self.url.trim_end_matches('/'), urlencoding::encode(query));
+        if let Some(ref api_key) = self.api_key {
+            url.push_str(&format!("&apikey={}", urlencoding::encode(api_key)));
+        }
+        if !self.categories.is_empty() {
+            let categories: Vec<String> = self.categories.iter().map(|c| c.to_string()).collect();
+            url.push_str(&format!("&cat={}", categories.join(",")));
+        }
+
+        log_info!("Torznab ({}): searching", self.name);
+        let xml = self.client.get(&url).await?.error_for_status()?.text().await?;
+
+        // Items carry most fields as <torznab:attr name="..." value="..."/>
+        let item_regex = Regex::new(r"(?s)<item>(.*?)</item>").unwrap();
+        let title_regex = Regex::new(r"<title>(?:<!\[CDATA\[)?(.*?)(?:\]\]>)?</title>").unwrap();
+        let link_regex = Regex::new(r"<link>(?:<!\[CDATA\[)?(.*?)(?:\]\]>)?</link>").unwrap();
+        let size_regex = Regex::new(r"<size>(\d+)</size>").unwrap();
+        let attr_regex = Regex::new(r#"<torznab:attr\s+name="([^"]+)"\s+value="([^"]*)"\s*/>"#).unwrap();
+
+        let mut results = Vec::new();
+        for item_cap in item_regex.captures_iter(&xml) {
+            let item_xml = &item_cap[1];
+
+            let Some(title) = title_regex.captures(item_xml)
+                .and_then(|c| c.get(1))
+                .map(|m| unescape_xml(m.as_str().trim()))
+                .filter(|t| !t.is_empty())
+            else {
+                continue;
+            };
+
+            let mut attrs = std::collections::HashMap::new();
+            let mut categories = Vec::new();
+            for attr in attr_regex.captures_iter(item_xml) {
+                let value = unescape_xml(&attr[2]);
+                if &attr[1] == "category" {
+                    if let Ok(category) = value.parse::<u32>() {
+                        categories.push(category);
+                    }
+                } else {
+                    attrs.insert(attr[1].to_string(), value);
+                }
+            }
+
+            let info_hash = attrs.get("infohash").filter(|h| !h.is_empty());
+            let magnet_link = match (attrs.get("magneturl"), info_hash) {
+                (Some(magnet), _) if magnet.starts_with("magnet:") => magnet.clone(),
+                (_, Some(hash)) => format!("magnet:?xt=urn:btih:{}&dn={}", hash, urlencoding::encode(&title)),
+                // Only a .torrent download link, which add_torrent can fetch
+                _ => match link_regex.captures(item_xml).and_then(|c| c.get(1)) {
+                    Some(link) => unescape_xml(link.as_str().trim()),
+                    None => continue,
+                },
+            };
+
+            let size_bytes = attrs.get("size")
+                .and_then(|s| s.parse::<u64>().ok())
+                .or_else(|| size_regex.captures(item_xml).and_then(|c| c[1].parse().ok()))
+                .unwrap_or(0);
+            let seeds: u32 = attrs.get("seeders").and_then(|s| s.parse().ok()).unwrap_or(0);
+            // Torznab "peers" counts seeders too
+            let peers: u32 = attrs.get("leechers").and_then(|s| s.parse().ok())
+                .or_else(|| attrs.get("peers").and_then(|s| s.parse::<u32>().ok()).map(|p| p.saturating_sub(seeds)))
+                .unwrap_or(0);
+
+            let (season, episode) = parse_season_episode(&title);
+            let quality = self.quality_regex.captures(&title).and_then(|c| c.get(1)).map(|m| m.as_str().to_uppercase());
+            let encode = self.encode_regex.captures(&title).and_then(|c| c.get(1)).map(|m| m.as_str().to_uppercase());
+            let is_batch = self.batch_regex.is_match(&title) || (season.is_some() && episode.is_none());
+            let audio_codec = parse_audio_codec(&title);
+            // Newznab category 6000 is XXX
+            let is_adult = categories.iter().any(|c| (6000..7000).contains(c)) || looks_adult(&title);
+
+            results.push(SearchResult {
+                title,
+                size: Self::format_size(size_bytes),
+                seeds,
+                peers,
+                magnet_link,
+                provider: self.name.clone(),
+                season,
+                episode,
+                quality,
+                encode,
+                is_batch,
+                audio_codec,
+                year: None,
+                is_collection: false,
+                is_adult,
+                sources: Vec::new(),
+                similarity: None,
+                release_group: None,
+                verified_peers: None,
+                season_pattern: None,
+            });
+        }
+
+        results.sort_by(|a, b| b.seeds.cmp(&a.seeds));
+        log_info!("Torznab ({}): {} results", self.name, results.len());
+        Ok(results)
+    }
+}
@@ -1,12 +1,14 @@
-use super::{SearchProvider, SearchResult, parse_audio_codec};
+use super::{QueryEncoding, SearchProvider, SearchResult, encode_query, looks_adult, parse_audio_codec};
+use super::http::{ChallengeError, HttpClient};
+use super::mirrors;
 use async_trait::async_trait;
-use reqwest::Client;
 use scraper::{Html, Selector};
 use std::error::Error;
 use regex::Regex;
+use crate::{log_info, log_warn};
 
 pub struct X1337Provider {
-    client: Client,
+    client: HttpClient,
     season_regex: Regex,
     episode_regex: Regex,
     quality_regex: Regex,
@@ -17,12 +19,7 @@ pub struct X1337Provider {
 impl X1337Provider {
     pub fn new() -> Self {
         Self {
-            client: Client::builder()
-                .user_agent("Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/131.0.0.0 Safari/537.36")
-                .timeout(std::time::Duration::from_secs(15))
-                .danger_accept_invalid_certs(true)
-                .build()
-                .unwrap(),
+            client: HttpClient::new_insecure(std::time::Duration::from_secs(15)),
             season_regex: Regex::new(r"(?i)S(\d{1,2})|Season\s*(\d{1,2})").unwrap(),
             episode_regex: Regex::new(r"(?i)S\d{1,2}E(\d+)|E(\d+)|Episode\s*(\d+)|\s-\s*(\d+)\s*(?:v\d)?").unwrap(),
             quality_regex: Regex::new(r"(?i)(\d{3,4}p|4K|8K|2160p|1440p|1080p|720p|480p)").unwrap(),
@@ -57,57 +54,44 @@ impl X1337Provider {
         (season, episode, quality, encode, is_batch)
     }
     
-    // Try multiple mirror domains
+    // Try the configured mirrors, most reliable first
     async fn fetch_with_mirrors(&self, query: &str) -> Result<(String, String), Box<dyn Error + Send + Sync>> {
-        let mirrors = [
-            "https://1337x.unblockit.ch",
-            "https://1337x.unblockninja.com",
-            "https://1337xto.to",
-            "https://www.1337xx.to",
-            "https://1337x.to",
-            "https://1337x.st",
-            "https://x1337x.ws",
-            "https://1337x.is",
-        ];
+        let mirror_urls = mirrors::mirrors_for("1337x");
         
-        let encoded_query = query.replace(" ", "+");
+        let encoded_query = encode_query(query, QueryEncoding::Plus);
+        let mut challenged_host = None;
         
-        for base_url in mirrors {
+        for base_url in &mirror_urls {
             let url = format!("{}/search/{}/1/", base_url, encoded_query);
-            println!("1337x: Trying {}", url);
+            log_info!("1337x: Trying {}", url);
             
-            match self.client.get(&url)
-                .header("Accept", "text/html,application/xhtml+xml,application/xml;q=0.9,*/*;q=0.8")
-                .header("Accept-Language", "en-US,en;q=0.5")
-                .header("Connection", "keep-alive")
-                .header("Upgrade-Insecure-Requests", "1")
-                .send()
-                .await 
+            match self.client.get_html(&url, &[
+                ("Accept", "text/html,application/xhtml+xml,application/xml;q=0.9,*/*;q=0.8"),
+                ("Accept-Language", "en-US,en;q=0.5"),
+                ("Connection", "keep-alive"),
+                ("Upgrade-Insecure-Requests", "1"),
+            ]).await
             {
-                Ok(response) => {
-                    let status = response.status();
-                    if status.is_success() {
-                        if let Ok(html) = response.text().await {
-                            // Check if we got a Cloudflare challenge page
-                            if html.contains("Checking your browser") || 
-                               html.contains("cf-browser-verification") ||
-                               html.contains("Just a moment") {
-                                println!("1337x: Cloudflare challenge at {}", base_url);
-                                continue;
-                            }
-                            return Ok((html, base_url.to_string()));
-                        }
-                    } else {
-                        println!("1337x: {} returned status {}", base_url, status);
-                    }
+                Ok(html) => {
+                    mirrors::record(base_url, true);
+                    return Ok((html, base_url.to_string()));
                 }
                 Err(e) => {
-                    println!("1337x: {} failed: {}", base_url, e);
+                    mirrors::record(base_url, false);
+                    log_warn!("1337x: {} failed: {}", base_url, e);
+                    if let Some(challenge) = e.downcast_ref::<ChallengeError>() {
+                        challenged_host = Some(challenge.host.clone());
+                    }
                 }
             }
         }
         
-        Err("All 1337x mirrors failed or blocked by Cloudflare".into())
+        // Report the challenge so the caller can mark 1337x as degraded
+        if let Some(host) = challenged_host {
+            return Err(Box::new(ChallengeError { host }));
+        }
+        
+        Err("All 1337x mirrors failed".into())
     }
 }
 
@@ -118,13 +102,14 @@ impl SearchProvider for X1337Provider {
 
         let (html, base_url) = match self.fetch_with_mirrors(query).await {
             Ok(result) => result,
+            Err(e) if e.is::<ChallengeError>() => return Err(e),
             Err(e) => {
-                println!("1337x: {}", e);
+                log_warn!("1337x: {}", e);
                 return Ok(results);
             }
         };
         
-        println!("1337x: Got response from {}, length: {}", base_url, html.len());
+        log_info!("1337x: Got response from {}, length: {}", base_url, html.len());
         
         // Collect data first, then fetch detail pages
         let pending_results = {
@@ -136,22 +121,24 @@ impl SearchProvider for X1337Provider {
             let seeds_selector = Selector::parse("td.coll-2").unwrap();
             let peers_selector = Selector::parse("td.coll-3").unwrap();
             let size_selector = Selector::parse("td.coll-4").unwrap();
+            // The first link in the name cell is the category icon, e.g. <i class="flaticon-xxx">
+            let category_selector = Selector::parse("td.coll-1 a.icon i").unwrap();
 
             let mut pending = Vec::new();
             let row_count = document.select(&row_selector).count();
-            println!("1337x: Found {} rows", row_count);
+            log_info!("1337x: Found {} rows", row_count);
             
             // Debug: print the first few rows to see structure
             if row_count == 0 {
                 // Try alternative selector
                 let alt_row_selector = Selector::parse("tbody tr").unwrap();
                 let alt_count = document.select(&alt_row_selector).count();
-                println!("1337x: Alternative selector found {} rows", alt_count);
+                log_info!("1337x: Alternative selector found {} rows", alt_count);
                 
                 // Check if we have any tables at all
                 let all_tables = Selector::parse("table").unwrap();
                 let table_count = document.select(&all_tables).count();
-                println!("1337x: Found {} tables total", table_count);
+                log_info!("1337x: Found {} tables total", table_count);
             }
 
             for row in document.select(&row_selector) {
@@ -191,7 +178,13 @@ impl SearchProvider for X1337Provider {
                     None => "Unknown".to_string(),
                 };
 
-                pending.push((name, link_path, seeds, peers, size));
+                let is_adult = row.select(&category_selector).next()
+                    .and_then(|el| el.value().attr("class"))
+                    .map(|class| class.contains("flaticon-xxx"))
+                    .unwrap_or(false)
+                    || looks_adult(&name);
+
+                pending.push((name, link_path, seeds, peers, size, is_adult));
             }
             
             pending
@@ -202,37 +195,43 @@ impl SearchProvider for X1337Provider {
         sorted_pending.sort_by(|a, b| b.2.cmp(&a.2));
         sorted_pending.truncate(10);
         
-        println!("1337x: Fetching detail pages for top {} results", sorted_pending.len());
+        log_info!("1337x: Fetching detail pages for top {} results", sorted_pending.len());
 
         // Now fetch detail pages without holding document references
-        for (i, (name, link_path, seeds, peers, size)) in sorted_pending.into_iter().enumerate() {
-            println!("1337x: Fetching detail page {}/{}", i + 1, 10);
+        for (i, (name, link_path, seeds, peers, size, is_adult)) in sorted_pending.into_iter().enumerate() {
+            log_info!("1337x: Fetching detail page {}/{}", i + 1, 10);
             let detail_url = format!("{}{}", base_url, link_path);
-            if let Ok(detail_response) = self.client.get(&detail_url).send().await {
-                if let Ok(detail_html) = detail_response.text().await {
-                    let detail_doc = Html::parse_document(&detail_html);
-                    let magnet_selector = Selector::parse("a[href^='magnet:']").unwrap();
-                    
-                    if let Some(magnet_el) = detail_doc.select(&magnet_selector).next() {
-                        if let Some(magnet_link) = magnet_el.value().attr("href") {
-                            let (season, episode, quality, encode, is_batch) = self.parse_metadata(&name);
-                            let audio_codec = parse_audio_codec(&name);
-
-                            results.push(SearchResult {
-                                title: name,
-                                size,
-                                seeds,
-                                peers,
-                                magnet_link: magnet_link.to_string(),
-                                provider: "1337x".to_string(),
-                                season,
-                                episode,
-                                quality,
-                                encode,
-                                is_batch,
-                                audio_codec,
-                            });
-                        }
+            if let Ok(detail_html) = self.client.get_html(&detail_url, &[]).await {
+                let detail_doc = Html::parse_document(&detail_html);
+                let magnet_selector = Selector::parse("a[href^='magnet:']").unwrap();
+                
+                if let Some(magnet_el) = detail_doc.select(&magnet_selector).next() {
+                    if let Some(magnet_link) = magnet_el.value().attr("href") {
+                        let (season, episode, quality, encode, is_batch) = self.parse_metadata(&name);
+                        let audio_codec = parse_audio_codec(&name);
+
+                        results.push(SearchResult {
+                            title: name,
+                            size,
+                            seeds,
+                            peers,
+                            magnet_link: magnet_link.to_string(),
+                            provider: "1337x".to_string(),
+                            season,
+                            episode,
+                            quality,
+                            encode,
+                            is_batch,
+                            audio_codec,
+                            year: None,
+                            is_collection: false,
+                            is_adult,
+                            sources: Vec::new(),
+                            similarity: None,
+                            release_group: None,
+                            verified_peers: None,
+                            season_pattern: None,
+                        });
                     }
                 }
             }
@@ -0,0 +1,159 @@
+use super::{SearchProvider, SearchResult, parse_audio_codec};
+use super::http::HttpClient;
+use async_trait::async_trait;
+use serde::Deserialize;
+use std::error::Error;
+use crate::{log_info, log_warn};
+
+// YTS magnets carry no trackers of their own, so the public ones it recommends are added
+const YTS_TRACKERS: &[&str] = &[
+    "udp://open.demonii.com:1337/announce",
+    "udp://tracker.openbittorrent.com:80",
+    "udp://tracker.coppersurfer.tk:6969",
+    "udp://tracker.opentrackr.org:1337/announce",
+    "udp://p4p.arenabg.com:1337",
+];
+
+#[derive(Debug, Deserialize)]
+struct YTSResponse {
+    #[serde(default)]
+    data: Option<YTSData>,
+}
+
+#[derive(Debug, Deserialize)]
+struct YTSData {
+    #[serde(default)]
+    movies: Vec<YTSMovie>,
+}
+
+#[derive(Debug, Deserialize)]
+struct YTSMovie {
+    title_long: String,
+    #[serde(default)]
+    year: Option<u32>,
+    #[serde(default)]
+    torrents: Vec<YTSTorrent>,
+}
+
+#[derive(Debug, Deserialize)]
+struct YTSTorrent {
+    hash: String,
+    #[serde(default)]
+    quality: String,
+    #[serde(default, rename = "type")]
+    source: String,
+    #[serde(default)]
+    video_codec: Option<String>,
+    #[serde(default)]
+    size: String,
+    #[serde(default)]
+    seeds: u32,
+    #[serde(default)]
+    peers: u32,
+}
+
+/// YTS movie API. Movies only, one listing per quality of each movie.
+pub struct YTSProvider {
+    client: HttpClient,
+}
+
+impl YTSProvider {
+    pub fn new() -> Self {
+        YTSProvider {
+            client: HttpClient::new(std::time::Duration::from_secs(20)),
+        }
+    }
+
+    /// Exact lookup; the API's query_term matches IMDB ids as well as titles
+    pub async fn search_by_imdb(&self, imdb_id: &str) -> Result<Vec<SearchResult>, Box<dyn Error + Send + Sync>> {
+        let imdb_id = format!("tt{}", imdb_id.trim_start_matches("tt"));
+        self.list_movies(&imdb_id).await
+    }
+
+    async fn list_movies(&self, query_term: &str) -> Result<Vec<SearchResult>, Box<dyn Error + Send + Sync>> {
+        let url = format!(
+            "https://yts.mx/api/v2/list_movies.json?query_term={}&limit=50",
+            urlencoding::encode(query_term)
+        );
+        log_info!("YTS: Fetching {}", url);
+
+        let response = self.client.get(&url).await?;
+        let status = response.status();
+        if !status.is_success() {
+            log_warn!("YTS: API returned status {}", status);
+            return Ok(vec![]);
+        }
+
+        let data: YTSResponse = match response.json().await {
+            Ok(d) => d,
+            Err(e) => {
+                log_warn!("YTS: Failed to parse JSON: {}", e);
+                return Ok(vec![]);
+            }
+        };
+
+        let mut results = Vec::new();
+        for movie in data.data.map(|d| d.movies).unwrap_or_default() {
+            for torrent in movie.torrents {
+                // "Movie Title (2019) [1080p] [BluRay] [x265] [YTS]", close to how the
+                // releases are named elsewhere so dedup and title matching behave
+                let mut title = format!("{} [{}] [{}]", movie.title_long, torrent.quality, capitalize(&torrent.source));
+                if let Some(codec) = torrent.video_codec.as_deref().filter(|c| !c.is_empty()) {
+                    title.push_str(&format!(" [{}]", codec));
+                }
+                title.push_str(" [YTS]");
+
+                let mut magnet_link = format!("magnet:?xt=urn:btih:{}&dn={}", torrent.hash, urlencoding::encode(&title));
+                for tracker in YTS_TRACKERS {
+                    magnet_link.push_str(&format!("&tr={}", urlencoding::encode(tracker)));
+                }
+
+                let audio_codec = parse_audio_codec(&title);
+                results.push(SearchResult {
+                    size: if torrent.size.is_empty() { "Unknown".to_string() } else { torrent.size },
+                    seeds: torrent.seeds,
+                    peers: torrent.peers,
+                    magnet_link,
+                    provider: "YTS".to_string(),
+                    season: None,
+                    episode: None,
+                    quality: Some(torrent.quality.to_uppercase()).filter(|q| !q.is_empty()),
+                    encode: torrent.video_codec.map(|c| c.to_uppercase()).filter(|c| !c.is_empty()),
+                    is_batch: false,
+                    audio_codec,
+                    year: movie.year,
+                    is_collection: false,
+                    is_adult: false,
+                    sources: Vec::new(),
+                    similarity: None,
+                    release_group: None,
+                    verified_peers: None,
+                    season_pattern: None,
+                    title,
+                });
+            }
+        }
+
+        log_info!("YTS: Found {} results for '{}'", results.len(), query_term);
+
+        results.sort_by(|a, b| b.seeds.cmp(&a.seeds));
+
+        Ok(results)
+    }
+}
+
+// "bluray" -> "Bluray", "web" -> "Web"
+fn capitalize(word: &str) -> String {
+    let mut chars = word.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+#[async_trait]
+impl SearchProvider for YTSProvider {
+    async fn search(&self, query: &str) -> Result<Vec<SearchResult>, Box<dyn Error + Send + Sync>> {
+        self.list_movies(query).await
+    }
+}
@@ -0,0 +1,61 @@
+use keyring::Entry;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+// Every secret is stored as its own keychain entry under this service name
+const SERVICE: &str = "magnolia";
+
+fn entry(key: &str) -> Option<Entry> {
+    match Entry::new(SERVICE, key) {
+        Ok(entry) => Some(entry),
+        Err(e) => {
+            tracing::warn!("keychain unavailable for {}: {}", key, e);
+            None
+        }
+    }
+}
+
+pub fn get(key: &str) -> Option<String> {
+    match entry(key)?.get_password() {
+        Ok(value) => Some(value),
+        Err(keyring::Error::NoEntry) => None,
+        Err(e) => {
+            tracing::warn!("failed to read {} from keychain: {}", key, e);
+            None
+        }
+    }
+}
+
+/// Saves `value` to the keychain, or removes the entry when it's None or empty.
+/// Returns false when the keychain couldn't be used, so the caller can keep the value
+/// in its own file rather than lose it.
+pub fn store(key: &str, value: Option<&str>) -> bool {
+    let Some(entry) = entry(key) else { return false };
+    let result = match value.filter(|v| !v.is_empty()) {
+        Some(value) => entry.set_password(value),
+        None => match entry.delete_credential() {
+            Err(keyring::Error::NoEntry) => Ok(()),
+            other => other,
+        },
+    };
+
+    match result {
+        Ok(()) => true,
+        Err(e) => {
+            tracing::error!("failed to write {} to keychain: {}", key, e);
+            false
+        }
+    }
+}
+
+pub fn get_json<T: DeserializeOwned>(key: &str) -> Option<T> {
+    serde_json::from_str(&get(key)?).ok()
+}
+
+pub fn store_json<T: Serialize>(key: &str, value: Option<&T>) -> bool {
+    match value.map(serde_json::to_string) {
+        Some(Ok(json)) => store(key, Some(&json)),
+        Some(Err(_)) => false,
+        None => store(key, None),
+    }
+}
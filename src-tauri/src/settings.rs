@@ -1,12 +1,26 @@
+use chrono::NaiveTime;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
-use tokio::sync::RwLock;
+use tokio::sync::{broadcast, RwLock};
+
+// Bumped whenever a field is renamed or changes shape, together with a step in MIGRATIONS
+pub const SETTINGS_VERSION: u32 = 1;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Settings {
+    // Schema of the file; files from before versioning have none and count as 0
+    #[serde(default)]
+    pub version: u32,
     pub external_player: String,
+    // Language of errors and notifications coming from the backend, one of i18n::LANGUAGES
+    #[serde(default = "default_language")]
+    pub language: String,
+    // Stop the stream once the external player it was opened in closes
+    #[serde(default)]
+    pub stop_stream_on_external_exit: bool,
     pub remember_preferences: bool,
     pub show_skip_prompts: bool,
     #[serde(default)]
@@ -15,28 +29,624 @@ pub struct Settings {
     pub clear_cache_after_watch: bool,
     #[serde(default = "default_true")]
     pub check_for_updates: bool,
+    #[serde(default)]
+    pub update_channel: UpdateChannel,
+    // Keep local stats on provider results, buffering times, transcodes and stream
+    // errors (analytics.rs). Off unless the user turns it on.
+    #[serde(default)]
+    pub local_analytics: bool,
+    // Used to get past Cloudflare challenges on scraped providers, e.g. "http://localhost:8191"
+    #[serde(default)]
+    pub flaresolverr_url: Option<String>,
+    // Switch to another release of the same episode when a stream stalls instead of asking
+    #[serde(default)]
+    pub auto_switch_stalled_streams: bool,
+    // Pause downloads while the OS is saving battery or on a metered connection
+    #[serde(default)]
+    pub pause_on_battery_saver: bool,
+    #[serde(default)]
+    pub pause_on_metered_network: bool,
+    #[serde(default)]
+    pub bandwidth_rules: Vec<BandwidthRule>,
+    // .torrent files dropped here are added automatically
+    #[serde(default)]
+    pub watch_folder: Option<String>,
+    #[serde(default)]
+    pub watch_folder_autostart: bool,
+    // Link completed episodes into a tidy folder layout when downloads are kept
+    #[serde(default)]
+    pub organize_downloads: bool,
+    // Relative path without extension; {show}, {season} and {episode} are filled in
+    #[serde(default = "default_organize_template")]
+    pub organize_template: String,
+    // Defaults to the library folder in app data
+    #[serde(default)]
+    pub organize_dir: Option<String>,
+    // Run when a download finishes; both get a JSON payload describing the file
+    #[serde(default)]
+    pub download_complete_command: Option<String>,
+    #[serde(default)]
+    pub download_complete_webhook: Option<String>,
+    #[serde(default)]
+    pub notifications: NotificationSettings,
+    // Watch tracking services; each needs an API client ID registered by the user
+    #[serde(default)]
+    pub mal_enabled: bool,
+    #[serde(default)]
+    pub mal_client_id: Option<String>,
+    #[serde(default)]
+    pub simkl_enabled: bool,
+    #[serde(default)]
+    pub simkl_client_id: Option<String>,
+    // Automatic backups of app data, e.g. into a Dropbox or Drive folder
+    #[serde(default)]
+    pub backup_dir: Option<String>,
+    #[serde(default = "default_backup_interval_hours")]
+    pub backup_interval_hours: u32,
+    #[serde(default = "default_backup_keep")]
+    pub backup_keep: usize,
+    // Merge the same release listed by several providers into one search result
+    #[serde(default)]
+    pub group_search_results: bool,
+    // How many of the best-seeded results get their peers counted on the DHT; 0 is off
+    #[serde(default)]
+    pub dht_verify_top: u32,
+    // Fetch the file lists of the top few results in the background once a search is done
+    #[serde(default)]
+    pub prefetch_metadata: bool,
+    // Port of the local media server, applied on restart; 0 reuses the last one or picks any
+    #[serde(default)]
+    pub http_port: u16,
+    #[serde(default)]
+    pub audio_normalization: AudioNormalization,
+    #[serde(default)]
+    pub audio_downmix: AudioDownmix,
+    // Seconds between keyframes in live video transcodes, i.e. how finely they can be sought
+    #[serde(default = "default_transcode_keyframe_secs")]
+    pub transcode_keyframe_secs: u32,
+    // Live transcodes running at once before more are turned away; 0 picks from the CPU count
+    #[serde(default)]
+    pub max_transcode_jobs: usize,
+    // ISO 639 code; forced subtitles turn on when the audio is in another language
+    #[serde(default = "default_subtitle_language")]
+    pub preferred_subtitle_language: String,
+    // Frame previews in the chapter picker, cut from already downloaded parts
+    #[serde(default = "default_true")]
+    pub chapter_thumbnails: bool,
+    // How much of a video the player reads to find its tracks, and how long it waits
+    #[serde(default = "default_metadata_probe_mb")]
+    pub metadata_probe_mb: u32,
+    #[serde(default = "default_metadata_probe_timeout_secs")]
+    pub metadata_probe_timeout_secs: u32,
+    // Streams that can play at once, e.g. picture-in-picture or a second window
+    #[serde(default = "default_max_concurrent_streams")]
+    pub max_concurrent_streams: usize,
+    // Minutes playback stays paused before downloads drop to a trickle; 0 never holds
+    #[serde(default)]
+    pub pause_hold_minutes: u32,
+    // Minutes a stream may go without hearing from its player before the torrent is
+    // paused, e.g. after the window crashed; it's stopped after twice as long. 0 is never.
+    #[serde(default = "default_stream_idle_timeout_mins")]
+    pub stream_idle_timeout_mins: u32,
+    // Size cap for each kind of cached track (subtitles, audio, metadata); 0 is unlimited
+    #[serde(default)]
+    pub media_cache_limit_mb: u64,
+    // Size cap of the fonts extracted from releases; 0 is unlimited
+    #[serde(default = "default_font_cache_limit_mb")]
+    pub font_cache_limit_mb: u64,
+    // Size cap of the finished live audio transcodes kept for re-watching; 0 is unlimited
+    #[serde(default = "default_transcode_cache_limit_mb")]
+    pub transcode_cache_limit_mb: u64,
+    // Indexers are mirrored from Prowlarr as Torznab providers
+    #[serde(default)]
+    pub prowlarr_url: Option<String>,
+    #[serde(default)]
+    pub prowlarr_api_key: Option<String>,
+    // Base urls per provider ("nyaa", "1337x", "limetorrents"), changed through
+    // set_provider_mirrors
+    #[serde(default = "crate::search::mirrors::default_mirrors")]
+    pub provider_mirrors: HashMap<String, Vec<String>>,
+    // Only changed through set_parental_controls, which checks the PIN
+    #[serde(default)]
+    pub parental: ParentalControls,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct ParentalControls {
+    #[serde(default)]
+    pub block_adult: bool,
+    // Highest US rating allowed ("G", "PG", "PG-13", "R"), None for no limit
+    #[serde(default)]
+    pub max_certification: Option<String>,
+    // Salted PBKDF2 hash of the PIN, never the PIN itself
+    #[serde(default)]
+    pub pin_hash: Option<String>,
+}
+
+// Which events raise an OS notification
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NotificationSettings {
+    #[serde(default = "default_true")]
+    pub download_complete: bool,
+    #[serde(default = "default_true")]
+    pub transcode_complete: bool,
+    #[serde(default = "default_true")]
+    pub stream_stalled: bool,
+    #[serde(default = "default_true")]
+    pub new_episode: bool,
+    #[serde(default = "default_true")]
+    pub ffmpeg_installed: bool,
+}
+
+impl Default for NotificationSettings {
+    fn default() -> Self {
+        Self {
+            download_complete: true,
+            transcode_complete: true,
+            stream_stalled: true,
+            new_episode: true,
+            ffmpeg_installed: true,
+        }
+    }
+}
+
+// Loudness processing applied when audio is transcoded to AAC
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AudioNormalization {
+    #[default]
+    Off,
+    // EBU R128 loudness normalization
+    Loudnorm,
+    // Dynamic range compression that lifts quiet dialogue
+    Dynaudnorm,
+}
+
+// Channel layout of transcoded AAC audio
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AudioDownmix {
+    #[default]
+    Stereo,
+    // Keeps 5.1 for receivers, e.g. when casting
+    Surround,
+    // Stereo with the center channel raised over music and effects
+    DialogueBoost,
+}
+
+// Which GitHub releases the updater offers
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UpdateChannel {
+    #[default]
+    Stable,
+    // Pre-releases too
+    Beta,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BandwidthRule {
+    pub start: String, // "HH:MM" local time
+    pub end: String,   // "HH:MM", earlier than start means the rule runs past midnight
+    // KiB/s, None means unlimited
+    pub download_limit_kbps: Option<u32>,
+    pub upload_limit_kbps: Option<u32>,
+}
+
+impl BandwidthRule {
+    fn contains(&self, time: NaiveTime) -> bool {
+        let (Ok(start), Ok(end)) = (
+            NaiveTime::parse_from_str(&self.start, "%H:%M"),
+            NaiveTime::parse_from_str(&self.end, "%H:%M"),
+        ) else {
+            return false;
+        };
+
+        if start <= end {
+            time >= start && time < end
+        } else {
+            time >= start || time < end
+        }
+    }
+}
+
+/// The first rule whose window covers `time`; with no match downloads run unlimited
+pub fn active_bandwidth_rule(rules: &[BandwidthRule], time: NaiveTime) -> Option<BandwidthRule> {
+    rules.iter().find(|rule| rule.contains(time)).cloned()
 }
 
 fn default_true() -> bool {
     true
 }
 
+fn default_backup_interval_hours() -> u32 {
+    24
+}
+
+fn default_backup_keep() -> usize {
+    10
+}
+
+fn default_organize_template() -> String {
+    "{show}/Season {season}/{show} - S{season}E{episode}".to_string()
+}
+
+fn default_subtitle_language() -> String {
+    "en".to_string()
+}
+
+fn default_metadata_probe_mb() -> u32 {
+    100
+}
+
+fn default_metadata_probe_timeout_secs() -> u32 {
+    30
+}
+
+fn default_max_concurrent_streams() -> usize {
+    2
+}
+
+fn default_language() -> String {
+    "en".to_string()
+}
+
+fn default_stream_idle_timeout_mins() -> u32 {
+    10
+}
+
+fn default_transcode_keyframe_secs() -> u32 {
+    2
+}
+
+fn default_font_cache_limit_mb() -> u64 {
+    500
+}
+
+fn default_transcode_cache_limit_mb() -> u64 {
+    2048
+}
+
 impl Default for Settings {
     fn default() -> Self {
         Self {
+            version: SETTINGS_VERSION,
             external_player: "vlc".to_string(),
+            language: default_language(),
+            stop_stream_on_external_exit: false,
             remember_preferences: true,
             show_skip_prompts: true,
             hide_recommendations: false,
             clear_cache_after_watch: false,
             check_for_updates: true,
+            update_channel: UpdateChannel::Stable,
+            local_analytics: false,
+            flaresolverr_url: None,
+            auto_switch_stalled_streams: false,
+            pause_on_battery_saver: false,
+            pause_on_metered_network: false,
+            bandwidth_rules: Vec::new(),
+            watch_folder: None,
+            watch_folder_autostart: false,
+            organize_downloads: false,
+            organize_template: default_organize_template(),
+            organize_dir: None,
+            download_complete_command: None,
+            download_complete_webhook: None,
+            notifications: NotificationSettings::default(),
+            mal_enabled: false,
+            mal_client_id: None,
+            simkl_enabled: false,
+            simkl_client_id: None,
+            backup_dir: None,
+            backup_interval_hours: default_backup_interval_hours(),
+            backup_keep: default_backup_keep(),
+            group_search_results: false,
+            dht_verify_top: 0,
+            prefetch_metadata: false,
+            http_port: 0,
+            audio_normalization: AudioNormalization::Off,
+            audio_downmix: AudioDownmix::Stereo,
+            preferred_subtitle_language: default_subtitle_language(),
+            chapter_thumbnails: true,
+            metadata_probe_mb: default_metadata_probe_mb(),
+            metadata_probe_timeout_secs: default_metadata_probe_timeout_secs(),
+            max_concurrent_streams: default_max_concurrent_streams(),
+            pause_hold_minutes: 0,
+            stream_idle_timeout_mins: default_stream_idle_timeout_mins(),
+            transcode_keyframe_secs: default_transcode_keyframe_secs(),
+            max_transcode_jobs: 0,
+            media_cache_limit_mb: 0,
+            font_cache_limit_mb: default_font_cache_limit_mb(),
+            transcode_cache_limit_mb: default_transcode_cache_limit_mb(),
+            prowlarr_url: None,
+            prowlarr_api_key: None,
+            provider_mirrors: crate::search::mirrors::default_mirrors(),
+            parental: ParentalControls::default(),
+        }
+    }
+}
+
+impl Settings {
+    /// Rejects values the rest of the app can't work with, naming the field
+    pub fn validate(&self) -> Result<(), String> {
+        match self.problems().into_iter().next() {
+            Some((_, message)) => Err(message),
+            None => Ok(()),
+        }
+    }
+
+    /// Puts fields that fail validation back to their defaults, so a file written before a
+    /// check existed doesn't block every later save. Returns the fields that were reset.
+    pub fn repair(&mut self) -> Vec<&'static str> {
+        let problems = self.problems();
+        if problems.is_empty() {
+            return Vec::new();
+        }
+        let (Ok(serde_json::Value::Object(mut object)), Ok(serde_json::Value::Object(defaults))) =
+            (serde_json::to_value(&*self), serde_json::to_value(Settings::default()))
+        else {
+            return Vec::new();
+        };
+        let mut reset = Vec::new();
+        for (field, message) in problems {
+            tracing::warn!("{}, using the default", message);
+            if let Some(default) = defaults.get(field) {
+                object.insert(field.to_string(), default.clone());
+                reset.push(field);
+            }
+        }
+        match serde_json::from_value(serde_json::Value::Object(object)) {
+            Ok(repaired) => {
+                *self = repaired;
+                reset
+            }
+            Err(_) => Vec::new(),
+        }
+    }
+
+    // Each field that fails validation, with why
+    fn problems(&self) -> Vec<(&'static str, String)> {
+        let mut problems = Vec::new();
+        if !crate::i18n::LANGUAGES.contains(&self.language.as_str()) {
+            problems.push(("language", format!("language must be one of {:?}, got \"{}\"", crate::i18n::LANGUAGES, self.language)));
+        }
+        if !matches!(self.external_player.as_str(), "vlc" | "mpv") {
+            problems.push(("external_player", format!("external_player must be \"vlc\" or \"mpv\", got \"{}\"", self.external_player)));
+        }
+        if self.http_port != 0 && self.http_port < 1024 {
+            problems.push(("http_port", format!("http_port must be 0 or at least 1024, got {}", self.http_port)));
+        }
+        if !(10..=1000).contains(&self.metadata_probe_mb) {
+            problems.push(("metadata_probe_mb", format!("metadata_probe_mb must be between 10 and 1000, got {}", self.metadata_probe_mb)));
+        }
+        if !(5..=600).contains(&self.metadata_probe_timeout_secs) {
+            problems.push(("metadata_probe_timeout_secs", format!("metadata_probe_timeout_secs must be between 5 and 600, got {}", self.metadata_probe_timeout_secs)));
+        }
+        if !(1..=4).contains(&self.max_concurrent_streams) {
+            problems.push(("max_concurrent_streams", format!("max_concurrent_streams must be between 1 and 4, got {}", self.max_concurrent_streams)));
+        }
+        if self.pause_hold_minutes > 240 {
+            problems.push(("pause_hold_minutes", format!("pause_hold_minutes must be at most 240, got {}", self.pause_hold_minutes)));
+        }
+        if self.stream_idle_timeout_mins == 1 || self.stream_idle_timeout_mins > 120 {
+            problems.push(("stream_idle_timeout_mins", format!("stream_idle_timeout_mins must be 0 or between 2 and 120, got {}", self.stream_idle_timeout_mins)));
+        }
+        if !(1..=10).contains(&self.transcode_keyframe_secs) {
+            problems.push(("transcode_keyframe_secs", format!("transcode_keyframe_secs must be between 1 and 10, got {}", self.transcode_keyframe_secs)));
+        }
+        if self.max_transcode_jobs > 16 {
+            problems.push(("max_transcode_jobs", format!("max_transcode_jobs must be at most 16, got {}", self.max_transcode_jobs)));
+        }
+        if self.dht_verify_top > 50 {
+            problems.push(("dht_verify_top", format!("dht_verify_top must be at most 50, got {}", self.dht_verify_top)));
+        }
+        if self.backup_interval_hours == 0 {
+            problems.push(("backup_interval_hours", "backup_interval_hours must be at least 1".to_string()));
+        }
+        if self.backup_keep == 0 {
+            problems.push(("backup_keep", "backup_keep must be at least 1".to_string()));
+        }
+        let language = &self.preferred_subtitle_language;
+        if !(2..=3).contains(&language.len()) || !language.chars().all(|c| c.is_ascii_lowercase()) {
+            problems.push(("preferred_subtitle_language", format!("preferred_subtitle_language must be a 2 or 3 letter ISO 639 code, got \"{}\"", language)));
+        }
+        if self.organize_template.trim().is_empty() {
+            problems.push(("organize_template", "organize_template can't be empty".to_string()));
+        }
+        for rule in &self.bandwidth_rules {
+            for time in [&rule.start, &rule.end] {
+                if NaiveTime::parse_from_str(time, "%H:%M").is_err() {
+                    problems.push(("bandwidth_rules", format!("bandwidth rule time must be HH:MM, got \"{}\"", time)));
+                }
+            }
+        }
+        problems
+    }
+}
+
+type Migration = fn(&mut serde_json::Map<String, serde_json::Value>);
+
+// MIGRATIONS[n] upgrades a version n file to version n + 1
+const MIGRATIONS: &[Migration] = &[migrate_v0_to_v1];
+
+// Before versioning, hand-edited files could hold null for fields that aren't optional,
+// which failed the whole parse. Dropping them lets those fields take their defaults.
+fn migrate_v0_to_v1(settings: &mut serde_json::Map<String, serde_json::Value>) {
+    let Ok(serde_json::Value::Object(defaults)) = serde_json::to_value(Settings::default()) else { return };
+    settings.retain(|key, value| !(value.is_null() && defaults.get(key).is_some_and(|d| !d.is_null())));
+}
+
+/// Parses settings.json, upgrading files written by older versions. A field that still
+/// doesn't fit falls back to its default on its own instead of resetting everything.
+/// Returns whether the file should be rewritten.
+fn parse_settings(content: &str) -> Result<(Settings, bool), String> {
+    let value: serde_json::Value = serde_json::from_str(content).map_err(|e| e.to_string())?;
+    let serde_json::Value::Object(mut object) = value else {
+        return Err("not a JSON object".to_string());
+    };
+
+    let version = object.get("version").and_then(|v| v.as_u64()).unwrap_or(0) as u32;
+    if version > SETTINGS_VERSION {
+        tracing::warn!("settings were written by a newer version ({}), unknown fields are ignored", version);
+    }
+    for (from, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+        migration(&mut object);
+        tracing::info!("migrated settings from version {} to {}", from, from + 1);
+    }
+    object.insert("version".to_string(), SETTINGS_VERSION.into());
+
+    if let Ok(settings) = serde_json::from_value::<Settings>(serde_json::Value::Object(object.clone())) {
+        return Ok((settings, version < SETTINGS_VERSION));
+    }
+
+    let serde_json::Value::Object(mut merged) = serde_json::to_value(Settings::default()).map_err(|e| e.to_string())? else {
+        return Err("default settings are not a JSON object".to_string());
+    };
+    for (key, field) in object {
+        let mut candidate = merged.clone();
+        candidate.insert(key.clone(), field.clone());
+        if serde_json::from_value::<Settings>(serde_json::Value::Object(candidate)).is_ok() {
+            merged.insert(key, field);
+        } else {
+            tracing::warn!("invalid value for setting {}, using the default", key);
         }
     }
+    let settings = serde_json::from_value(serde_json::Value::Object(merged)).map_err(|e| e.to_string())?;
+    Ok((settings, true))
 }
 
+/// Settings grouped the way the settings panel shows them. patch_settings changes one
+/// section at a time, so two windows editing different toggles don't overwrite each other.
+/// Parental controls and provider mirrors have their own commands and aren't listed.
+pub const SETTINGS_SECTIONS: &[(&str, &[&str])] = &[
+    ("general", &[
+        "language",
+        "external_player",
+        "stop_stream_on_external_exit",
+        "remember_preferences",
+        "show_skip_prompts",
+        "hide_recommendations",
+        "check_for_updates",
+        "update_channel",
+        "local_analytics",
+    ]),
+    ("network", &[
+        "http_port",
+        "flaresolverr_url",
+        "bandwidth_rules",
+        "pause_on_battery_saver",
+        "pause_on_metered_network",
+    ]),
+    ("search", &[
+        "group_search_results",
+        "dht_verify_top",
+        "prefetch_metadata",
+        "prowlarr_url",
+        "prowlarr_api_key",
+    ]),
+    ("playback", &[
+        "auto_switch_stalled_streams",
+        "preferred_subtitle_language",
+        "chapter_thumbnails",
+        "metadata_probe_mb",
+        "metadata_probe_timeout_secs",
+        "max_concurrent_streams",
+        "pause_hold_minutes",
+        "stream_idle_timeout_mins",
+    ]),
+    ("transcoding", &[
+        "audio_normalization",
+        "audio_downmix",
+        "transcode_keyframe_secs",
+        "max_transcode_jobs",
+    ]),
+    ("cache", &[
+        "clear_cache_after_watch",
+        "media_cache_limit_mb",
+        "font_cache_limit_mb",
+        "transcode_cache_limit_mb",
+    ]),
+    ("downloads", &[
+        "watch_folder",
+        "watch_folder_autostart",
+        "organize_downloads",
+        "organize_template",
+        "organize_dir",
+        "download_complete_command",
+        "download_complete_webhook",
+        "notifications",
+    ]),
+    ("scrobblers", &[
+        "mal_enabled",
+        "mal_client_id",
+        "simkl_enabled",
+        "simkl_client_id",
+    ]),
+    ("backup", &[
+        "backup_dir",
+        "backup_interval_hours",
+        "backup_keep",
+    ]),
+];
+
 pub struct SettingsManager {
     file_path: PathBuf,
     data: Arc<RwLock<Settings>>,
+    // Every saved change, for subsystems that apply settings without a restart
+    changes: broadcast::Sender<Settings>,
+}
+
+// Settings that can hold credentials (webhook URLs often embed a token). They're kept
+// in the OS keychain and left out of settings.json.
+fn secret_fields(settings: &mut Settings) -> [(&'static str, &mut Option<String>); 4] {
+    [
+        ("settings.mal_client_id", &mut settings.mal_client_id),
+        ("settings.simkl_client_id", &mut settings.simkl_client_id),
+        ("settings.download_complete_webhook", &mut settings.download_complete_webhook),
+        ("settings.prowlarr_api_key", &mut settings.prowlarr_api_key),
+    ]
+}
+
+fn write_settings(file_path: &Path, settings: &Settings) {
+    let mut on_disk = settings.clone();
+    for (key, field) in secret_fields(&mut on_disk) {
+        // Without a keychain the value stays in the file instead of being lost
+        if crate::secrets::store(key, field.as_deref()) {
+            *field = None;
+        }
+    }
+
+    match serde_json::to_string_pretty(&on_disk) {
+        Ok(content) => {
+            match fs::write(file_path, content) {
+                Ok(_) => tracing::info!("settings saved to {:?}", file_path),
+                Err(e) => tracing::error!("failed to write settings file: {}", e),
+            }
+        }
+        Err(e) => tracing::error!("failed to serialize settings: {}", e),
+    }
+}
+
+// Hands the settings that other modules read from statics over to them
+fn apply_settings(settings: &Settings) {
+    crate::i18n::set_language(&settings.language);
+    crate::analytics::set_enabled(settings.local_analytics);
+    crate::search::http::set_flaresolverr_url(settings.flaresolverr_url.clone());
+    crate::search::mirrors::set_configured_mirrors(settings.provider_mirrors.clone());
+    crate::torrent::set_audio_normalization(settings.audio_normalization);
+    crate::torrent::set_audio_downmix(settings.audio_downmix);
+    crate::torrent::set_metadata_probe_budget(settings.metadata_probe_mb, settings.metadata_probe_timeout_secs);
+    crate::torrent::set_max_concurrent_streams(settings.max_concurrent_streams);
+    crate::torrent::set_pause_hold_minutes(settings.pause_hold_minutes);
+    crate::torrent::set_stream_idle_timeout(settings.stream_idle_timeout_mins);
+    crate::torrent::set_transcode_keyframe_interval(settings.transcode_keyframe_secs);
+    crate::torrent::set_max_transcode_jobs(settings.max_transcode_jobs);
+    crate::torrent::set_chapter_thumbnails(settings.chapter_thumbnails);
+    crate::parental::set_restrictions(&settings.parental);
+    crate::media_cache::set_cache_limit(settings.media_cache_limit_mb);
+    crate::font_manager::set_font_limit(settings.font_cache_limit_mb);
+    crate::media_cache::set_transcode_cache_limit(settings.transcode_cache_limit_mb);
 }
 
 impl SettingsManager {
@@ -48,53 +658,106 @@ impl SettingsManager {
             let _ = fs::create_dir_all(parent);
         }
         
-        let data = if file_path.exists() {
+        let mut rewrite = false;
+        let mut data = if file_path.exists() {
             match fs::read_to_string(&file_path) {
                 Ok(content) => {
-                    match serde_json::from_str(&content) {
-                        Ok(settings) => {
-                            println!("loaded settings from {:?}", file_path);
+                    match parse_settings(&content) {
+                        Ok((settings, upgraded)) => {
+                            tracing::info!("loaded settings from {:?}", file_path);
+                            rewrite = upgraded;
                             settings
                         }
                         Err(e) => {
-                            eprintln!("failed to parse settings file: {}, using defaults", e);
+                            tracing::error!("failed to parse settings file: {}, using defaults", e);
                             Settings::default()
                         }
                     }
                 }
                 Err(e) => {
-                    eprintln!("failed to read settings file: {}, using defaults", e);
+                    tracing::error!("failed to read settings file: {}, using defaults", e);
                     Settings::default()
                 }
             }
         } else {
-            println!("no settings file found, using defaults");
+            tracing::info!("no settings file found, using defaults");
             Settings::default()
         };
 
+        if !data.repair().is_empty() {
+            rewrite = true;
+        }
+
+        let mut has_plaintext_secrets = false;
+        for (key, field) in secret_fields(&mut data) {
+            match field {
+                Some(_) => has_plaintext_secrets = true,
+                None => *field = crate::secrets::get(key),
+            }
+        }
+        // Settings from before the keychain was used, or an older schema; rewrite them
+        if has_plaintext_secrets || rewrite {
+            write_settings(&file_path, &data);
+        }
+
+        apply_settings(&data);
+
+        let (changes, _) = broadcast::channel(16);
         Self {
             file_path,
             data: Arc::new(RwLock::new(data)),
+            changes,
         }
     }
 
-    pub async fn save(&self, settings: Settings) {
+    pub async fn save(&self, mut settings: Settings) -> Result<(), String> {
+        settings.validate()?;
+        settings.version = SETTINGS_VERSION;
         let mut data = self.data.write().await;
         *data = settings.clone();
+        apply_settings(&settings);
+        write_settings(&self.file_path, &settings);
+        let _ = self.changes.send(settings);
+        Ok(())
+    }
 
-        match serde_json::to_string_pretty(&settings) {
-            Ok(content) => {
-                match fs::write(&self.file_path, content) {
-                    Ok(_) => println!("settings saved to {:?}", self.file_path),
-                    Err(e) => eprintln!("failed to write settings file: {}", e),
-                }
-            }
-            Err(e) => eprintln!("failed to serialize settings: {}", e),
+    /// Changes only the given fields of one section. The read and write happen under one
+    /// lock, so a concurrent patch of another section isn't lost.
+    pub async fn patch(&self, section: &str, values: serde_json::Map<String, serde_json::Value>) -> Result<Settings, String> {
+        let Some((_, fields)) = SETTINGS_SECTIONS.iter().find(|(name, _)| *name == section) else {
+            return Err(format!("Unknown settings section: {}", section));
+        };
+        if let Some(key) = values.keys().find(|key| !fields.contains(&key.as_str())) {
+            return Err(format!("{} is not in the {} settings", key, section));
         }
+
+        let mut data = self.data.write().await;
+        let serde_json::Value::Object(mut object) = serde_json::to_value(&*data).map_err(|e| e.to_string())? else {
+            return Err("settings are not a JSON object".to_string());
+        };
+        object.extend(values);
+        let settings: Settings = serde_json::from_value(serde_json::Value::Object(object))
+            .map_err(|e| format!("Invalid {} settings: {}", section, e))?;
+        settings.validate()?;
+
+        *data = settings.clone();
+        apply_settings(&settings);
+        write_settings(&self.file_path, &settings);
+        let _ = self.changes.send(settings.clone());
+        Ok(settings)
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<Settings> {
+        self.changes.subscribe()
     }
 
     pub async fn get(&self) -> Settings {
         let data = self.data.read().await;
         data.clone()
     }
+
+    pub async fn get_active_bandwidth_rule(&self) -> Option<BandwidthRule> {
+        let data = self.data.read().await;
+        active_bandwidth_rule(&data.bandwidth_rules, chrono::Local::now().time())
+    }
 }
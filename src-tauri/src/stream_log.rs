@@ -0,0 +1,126 @@
+use axum::{
+    body::Body,
+    extract::{ConnectInfo, Request, State},
+    http::header,
+    middleware::Next,
+    response::Response,
+};
+use futures::StreamExt;
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+// Requests kept in the log; older ones are dropped
+const ACCESS_LOG_CAPACITY: usize = 500;
+
+/// One request to the local media server, recorded once its body has been sent or the
+/// client hung up
+#[derive(Debug, Clone, Serialize)]
+pub struct StreamAccess {
+    pub started_at: String,
+    pub method: String,
+    pub path: String,
+    pub range: Option<String>,
+    pub status: u16,
+    pub bytes: u64,
+    pub duration_ms: u64,
+    pub bytes_per_second: u64,
+    pub client: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+/// Ring buffer of recent media server requests, for debugging players that keep
+/// re-requesting the same ranges
+#[derive(Clone, Default)]
+pub struct StreamAccessLog {
+    entries: Arc<Mutex<VecDeque<StreamAccess>>>,
+}
+
+impl StreamAccessLog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn push(&self, entry: StreamAccess) {
+        if let Ok(mut entries) = self.entries.lock() {
+            if entries.len() >= ACCESS_LOG_CAPACITY {
+                entries.pop_front();
+            }
+            entries.push_back(entry);
+        }
+    }
+
+    /// Newest first
+    pub fn entries(&self) -> Vec<StreamAccess> {
+        self.entries.lock().map(|e| e.iter().rev().cloned().collect()).unwrap_or_default()
+    }
+}
+
+// Travels with the response body and writes the entry when the body is dropped, so the
+// byte count and duration cover the whole transfer
+struct PendingAccess {
+    log: StreamAccessLog,
+    entry: StreamAccess,
+    started: Instant,
+    bytes: AtomicU64,
+}
+
+impl Drop for PendingAccess {
+    fn drop(&mut self) {
+        let elapsed = self.started.elapsed();
+        let bytes = self.bytes.load(Ordering::Relaxed);
+        let mut entry = self.entry.clone();
+        entry.bytes = bytes;
+        entry.duration_ms = elapsed.as_millis() as u64;
+        entry.bytes_per_second = match elapsed.as_secs_f64() {
+            secs if secs > 0.0 => (bytes as f64 / secs) as u64,
+            _ => 0,
+        };
+        self.log.push(entry);
+    }
+}
+
+/// axum middleware recording every request to the media server
+pub async fn record_access(State(log): State<StreamAccessLog>, request: Request, next: Next) -> Response {
+    let header_value = |name: header::HeaderName| {
+        request.headers().get(name).and_then(|v| v.to_str().ok()).map(|v| v.to_string())
+    };
+    let entry = StreamAccess {
+        started_at: chrono::Local::now().to_rfc3339(),
+        method: request.method().to_string(),
+        path: request.uri().path().to_string(),
+        range: header_value(header::RANGE),
+        status: 0,
+        bytes: 0,
+        duration_ms: 0,
+        bytes_per_second: 0,
+        client: request.extensions().get::<ConnectInfo<SocketAddr>>().map(|c| c.0.to_string()),
+        user_agent: header_value(header::USER_AGENT),
+    };
+    let started = Instant::now();
+
+    let response = next.run(request).await;
+    let (parts, body) = response.into_parts();
+
+    let pending = PendingAccess {
+        log,
+        entry: StreamAccess {
+            status: parts.status.as_u16(),
+            ..entry
+        },
+        started,
+        bytes: AtomicU64::new(0),
+    };
+    // The closure owns `pending`, so it's dropped (and logged) along with the body
+    let counted = body.into_data_stream().map(move |chunk| {
+        if let Ok(ref data) = chunk {
+            pending.bytes.fetch_add(data.len() as u64, Ordering::Relaxed);
+        }
+        chunk
+    });
+
+    Response::from_parts(parts, Body::from_stream(counted))
+}
@@ -0,0 +1,387 @@
+use crate::torrent::{MetadataProbe, MkvMetadata, TranscodeState};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::sync::{broadcast, RwLock};
+
+/// librqbit's session id of the torrent plus the index of the file in it
+pub type StreamKey = (usize, usize);
+
+/// Where a stream is in its life. Sessions only move forward, so a playing stream that
+/// runs dry stays playing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StreamPhase {
+    // The stream server or the player asked about the file for the first time
+    Created,
+    // Waiting for the startup buffer
+    Buffering,
+    // The player is reporting its position
+    Playing,
+    Stopped,
+}
+
+/// How far a stream whose player stopped sending keep-alives has been wound down
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IdleStage {
+    // The idle timeout is about to run out
+    Warned,
+    // The torrent is paused until the player pings again
+    Paused,
+    // Past twice the timeout; the stream is stopped and its files released
+    Stopped,
+}
+
+/// Payload of the `stream-lifecycle` event
+#[derive(Debug, Clone, Serialize)]
+pub struct StreamLifecycleEvent {
+    pub session_id: usize,
+    pub file_index: usize,
+    pub handle_id: Option<usize>,
+    pub phase: StreamPhase,
+    pub url: Option<String>,
+}
+
+/// What one open stream is holding on to, for the per-stream resource view
+#[derive(Debug, Clone, Serialize)]
+pub struct StreamUsage {
+    pub session_id: usize,
+    pub file_index: usize,
+    pub handle_id: usize,
+    pub phase: StreamPhase,
+    pub url: Option<String>,
+    // Bytes the readahead window keeps prioritized ahead of the playhead
+    pub readahead_bytes: u64,
+    // Metadata read and transcode output on disk
+    pub temp_file_bytes: u64,
+    pub transcoding: bool,
+}
+
+// Readahead following the playhead
+pub(crate) struct PlaybackWindow {
+    pub start: u64,
+    pub end: u64,
+    pub task: tokio::task::JoinHandle<()>,
+}
+
+// When a playing stream stopped receiving data
+pub(crate) struct StallState {
+    pub since: std::time::Instant,
+    pub reannounced: bool,
+    pub reported: bool,
+}
+
+/// Everything the app holds for one streamed file: its metadata, transcode job, reads in
+/// progress and temp files. Stopping the session releases all of it.
+pub struct StreamSession {
+    // The frontend's torrent handle; unknown while only the stream server has seen the file
+    pub handle_id: Option<usize>,
+    pub phase: StreamPhase,
+    pub url: Option<String>,
+    pub metadata: Option<MkvMetadata>,
+    pub transcode: Option<TranscodeState>,
+    pub probe: Option<MetadataProbe>,
+    pub(crate) playback_window: Option<PlaybackWindow>,
+    pub(crate) stall: Option<StallState>,
+    // When the player reported playback paused, None while it plays
+    pub(crate) paused_since: Option<std::time::Instant>,
+    // Last keep-alive from the player; one that stops pinging has probably crashed
+    pub(crate) last_ping: std::time::Instant,
+    pub(crate) idle_stage: Option<IdleStage>,
+    // When the stream was first requested, to time how long it took to start playing
+    pub(crate) created_at: std::time::Instant,
+    // Folder the embedded attachments were extracted to, once they have been; the lock
+    // makes concurrent font requests wait for one extraction
+    pub(crate) attachments: Arc<tokio::sync::Mutex<Option<PathBuf>>>,
+    // Deleted when the session stops; folders with everything in them
+    pub temp_files: Vec<PathBuf>,
+}
+
+impl StreamSession {
+    fn new() -> Self {
+        Self {
+            handle_id: None,
+            phase: StreamPhase::Created,
+            url: None,
+            metadata: None,
+            transcode: None,
+            probe: None,
+            playback_window: None,
+            stall: None,
+            paused_since: None,
+            last_ping: std::time::Instant::now(),
+            idle_stage: None,
+            created_at: std::time::Instant::now(),
+            attachments: Arc::new(tokio::sync::Mutex::new(None)),
+            temp_files: Vec::new(),
+        }
+    }
+
+    /// Moves to `phase` unless the session is already past it
+    pub fn advance(&mut self, phase: StreamPhase) {
+        let rank = |p: StreamPhase| match p {
+            StreamPhase::Created => 0,
+            StreamPhase::Buffering => 1,
+            StreamPhase::Playing => 2,
+            StreamPhase::Stopped => 3,
+        };
+        if rank(phase) > rank(self.phase) {
+            self.phase = phase;
+        }
+    }
+
+    /// Whether the player is waiting on the torrent, so no incoming data means a stall.
+    /// A paused player, one wound down for not sending keep-alives (its torrent may have
+    /// been paused on purpose) or a readahead window that's already downloaded needs nothing.
+    pub(crate) fn waiting_for_data(&self) -> bool {
+        self.paused_since.is_none()
+            && self.idle_stage.is_none()
+            && self.playback_window.as_ref().is_some_and(|w| !w.task.is_finished())
+    }
+
+    pub fn add_temp_file(&mut self, path: PathBuf) {
+        if !self.temp_files.contains(&path) {
+            self.temp_files.push(path);
+        }
+    }
+
+    // Stops the readahead, ends any metadata read and returns the files to delete
+    fn release(&mut self) -> Vec<PathBuf> {
+        if let Some(window) = self.playback_window.take() {
+            window.task.abort();
+        }
+        self.stall = None;
+        if let Some(probe) = self.probe.as_mut() {
+            probe.cancelled = true;
+        }
+        let mut files = std::mem::take(&mut self.temp_files);
+        if let Some(output) = self.transcode.take().and_then(|t| t.output_path) {
+            files.push(output);
+        }
+        files
+    }
+}
+
+/// The stream sessions of every torrent, shared by the torrent manager and the stream
+/// server. Phase changes go out on a broadcast channel, forwarded to the frontend as
+/// `stream-lifecycle` events.
+#[derive(Clone)]
+pub struct StreamSessions {
+    sessions: Arc<RwLock<HashMap<StreamKey, StreamSession>>>,
+    events: broadcast::Sender<StreamLifecycleEvent>,
+}
+
+impl StreamSessions {
+    pub fn new() -> Self {
+        let (events, _) = broadcast::channel(64);
+        Self {
+            sessions: Arc::new(RwLock::new(HashMap::new())),
+            events,
+        }
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<StreamLifecycleEvent> {
+        self.events.subscribe()
+    }
+
+    fn announce(&self, (session_id, file_index): StreamKey, session: &StreamSession) {
+        match session.phase {
+            StreamPhase::Created => crate::analytics::record_stream_started(),
+            StreamPhase::Playing => crate::analytics::record_buffering(session.created_at.elapsed()),
+            _ => {}
+        }
+        let _ = self.events.send(StreamLifecycleEvent {
+            session_id,
+            file_index,
+            handle_id: session.handle_id,
+            phase: session.phase,
+            url: session.url.clone(),
+        });
+    }
+
+    /// Runs `f` on the session, creating it first if the file has none yet
+    pub async fn update<R>(&self, key: StreamKey, f: impl FnOnce(&mut StreamSession) -> R) -> R {
+        let mut sessions = self.sessions.write().await;
+        let created = !sessions.contains_key(&key);
+        let session = sessions.entry(key).or_insert_with(StreamSession::new);
+        if created {
+            self.announce(key, session);
+        }
+        let phase = session.phase;
+        let result = f(session);
+        if session.phase != phase {
+            self.announce(key, session);
+        }
+        result
+    }
+
+    /// Runs `f` on the session if there is one
+    pub async fn update_existing<R>(&self, key: StreamKey, f: impl FnOnce(&mut StreamSession) -> R) -> Option<R> {
+        let mut sessions = self.sessions.write().await;
+        let session = sessions.get_mut(&key)?;
+        let phase = session.phase;
+        let result = f(session);
+        if session.phase != phase {
+            self.announce(key, session);
+        }
+        Some(result)
+    }
+
+    pub async fn read<R>(&self, key: StreamKey, f: impl FnOnce(&StreamSession) -> R) -> Option<R> {
+        self.sessions.read().await.get(&key).map(f)
+    }
+
+    pub async fn metadata(&self, key: StreamKey) -> Option<MkvMetadata> {
+        self.read(key, |s| s.metadata.clone()).await.flatten()
+    }
+
+    /// Probed duration in seconds, when it's known and non-zero
+    pub async fn duration(&self, key: StreamKey) -> Option<f64> {
+        self.read(key, |s| s.metadata.as_ref().and_then(|m| m.duration)).await
+            .flatten()
+            .filter(|d| *d > 0.0)
+    }
+
+    /// Streams being played that are waiting for data, with their handle, for the stall
+    /// check. The others have their stall timer reset.
+    pub async fn stall_candidates(&self) -> Vec<(StreamKey, usize)> {
+        let mut sessions = self.sessions.write().await;
+        let mut candidates = Vec::new();
+        for (key, s) in sessions.iter_mut() {
+            let Some(handle_id) = s.handle_id else { continue };
+            if s.waiting_for_data() {
+                candidates.push((*key, handle_id));
+            } else {
+                s.stall = None;
+            }
+        }
+        candidates
+    }
+
+    /// Whether at least one stream is being played and all of them have been paused for
+    /// `duration` or longer
+    pub async fn all_paused_for(&self, duration: std::time::Duration) -> bool {
+        let sessions = self.sessions.read().await;
+        let mut playing = sessions.values().filter(|s| s.playback_window.is_some()).peekable();
+        playing.peek().is_some() && playing.all(|s| s.paused_since.is_some_and(|since| since.elapsed() >= duration))
+    }
+
+    /// Streams a player has opened, with the handle that opened them. Sessions the stream
+    /// server created on its own don't count.
+    pub async fn open_streams(&self) -> Vec<(StreamKey, usize)> {
+        self.sessions.read().await
+            .iter()
+            .filter_map(|(key, s)| s.handle_id.map(|handle_id| (*key, handle_id)))
+            .collect()
+    }
+
+    pub async fn usage(&self) -> Vec<StreamUsage> {
+        let sessions = self.sessions.read().await;
+        let mut usage: Vec<StreamUsage> = sessions
+            .iter()
+            .filter_map(|(&(session_id, file_index), s)| {
+                let handle_id = s.handle_id?;
+                let mut files: Vec<&PathBuf> = s.temp_files.iter().collect();
+                files.extend(s.transcode.as_ref().and_then(|t| t.output_path.as_ref()));
+                Some(StreamUsage {
+                    session_id,
+                    file_index,
+                    handle_id,
+                    phase: s.phase,
+                    url: s.url.clone(),
+                    readahead_bytes: s.playback_window.as_ref().map(|w| w.end - w.start).unwrap_or(0),
+                    temp_file_bytes: files.iter().filter_map(|p| std::fs::metadata(p).ok()).map(|m| m.len()).sum(),
+                    transcoding: s.transcode.as_ref().is_some_and(|t| !t.completed && t.error.is_none()),
+                })
+            })
+            .collect();
+        usage.sort_by_key(|u| (u.handle_id, u.file_index));
+        usage
+    }
+
+    /// Stops every stream of the torrent: aborts readahead, cuts metadata reads short and
+    /// deletes temp files and transcoded output
+    pub async fn stop_torrent(&self, session_id: usize) {
+        self.stop_matching(|(id, _), _| id == session_id).await;
+    }
+
+    /// Stops the streams the handle opened, leaving other players on the same torrent be.
+    /// Returns the keys that were stopped.
+    pub async fn stop_handle(&self, handle_id: usize) -> Vec<StreamKey> {
+        self.stop_matching(|_, s| s.handle_id == Some(handle_id)).await
+    }
+
+    pub async fn stop_matching(&self, matches: impl Fn(StreamKey, &StreamSession) -> bool) -> Vec<StreamKey> {
+        let stopped: Vec<(StreamKey, StreamSession)> = {
+            let mut sessions = self.sessions.write().await;
+            let keys: Vec<StreamKey> = sessions.iter().filter(|(key, s)| matches(**key, s)).map(|(key, _)| *key).collect();
+            keys.into_iter().filter_map(|key| sessions.remove(&key).map(|s| (key, s))).collect()
+        };
+
+        let keys = stopped.iter().map(|(key, _)| *key).collect();
+        for (key, mut session) in stopped {
+            for path in session.release() {
+                let removed = if path.is_dir() {
+                    tokio::fs::remove_dir_all(&path).await
+                } else {
+                    tokio::fs::remove_file(&path).await
+                };
+                if let Err(e) = removed {
+                    if e.kind() != std::io::ErrorKind::NotFound {
+                        tracing::warn!("Failed to remove stream temp file {:?}: {}", path, e);
+                    }
+                }
+            }
+            session.phase = StreamPhase::Stopped;
+            self.announce(key, &session);
+        }
+        keys
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn playing_stream(sessions: &StreamSessions, key: StreamKey) {
+        let task = tokio::spawn(std::future::pending::<()>());
+        sessions.update(key, |s| {
+            s.handle_id = Some(1);
+            s.playback_window = Some(PlaybackWindow { start: 0, end: 1024, task });
+            s.advance(StreamPhase::Playing);
+        }).await;
+    }
+
+    async fn stall_check_candidates(sessions: &StreamSessions) -> Vec<StreamKey> {
+        sessions.stall_candidates().await.into_iter().map(|(key, _)| key).collect()
+    }
+
+    #[tokio::test]
+    async fn playing_stream_is_checked_for_stalls() {
+        let sessions = StreamSessions::new();
+        playing_stream(&sessions, (1, 0)).await;
+        assert_eq!(stall_check_candidates(&sessions).await, vec![(1, 0)]);
+    }
+
+    #[tokio::test]
+    async fn idle_paused_stream_stays_paused_across_a_watchdog_tick() {
+        let sessions = StreamSessions::new();
+        playing_stream(&sessions, (1, 0)).await;
+        sessions.update_existing((1, 0), |s| s.idle_stage = Some(IdleStage::Paused)).await;
+
+        // The stall check is what restarts (unpauses) torrents, so it must skip this one
+        assert!(stall_check_candidates(&sessions).await.is_empty());
+        let stage = sessions.read((1, 0), |s| s.idle_stage).await.flatten();
+        assert_eq!(stage, Some(IdleStage::Paused));
+    }
+
+    #[tokio::test]
+    async fn paused_player_is_not_a_stall() {
+        let sessions = StreamSessions::new();
+        playing_stream(&sessions, (1, 0)).await;
+        sessions.update_existing((1, 0), |s| s.paused_since = Some(std::time::Instant::now())).await;
+        assert!(stall_check_candidates(&sessions).await.is_empty());
+    }
+}
@@ -16,9 +16,14 @@ use axum::{
     body::Body,
 };
 use tower_http::cors::CorsLayer;
+use crate::web_seed::{self, WebSeeds};
+use crate::stream_log::{self, StreamAccess, StreamAccessLog};
+use crate::settings::{AudioDownmix, AudioNormalization};
+use crate::media_cache::{MediaCache, TrackType};
 use tokio::io::{AsyncReadExt, AsyncSeekExt};
 // use tokio::sync::Mutex;
-use ffmpeg_sidecar::paths::ffmpeg_path;
+use crate::ffmpeg_tools;
+use crate::stream_session::{IdleStage, PlaybackWindow, StallState, StreamLifecycleEvent, StreamPhase, StreamSessions, StreamUsage};
 
 const UNSUPPORTED_AUDIO_CODECS: &[&str] = &[
     "truehd", "mlp", "pcm", "dsd",
@@ -27,12 +32,290 @@ const UNSUPPORTED_AUDIO_CODECS: &[&str] = &[
     "cook", "ra", "sipr", "wma", "wmav1", "wmav2", "wmapro",
 ];
 
+// Set from settings; read whenever ffmpeg is started to encode AAC
+static AUDIO_NORMALIZATION: std::sync::RwLock<AudioNormalization> = std::sync::RwLock::new(AudioNormalization::Off);
+static AUDIO_DOWNMIX: std::sync::RwLock<AudioDownmix> = std::sync::RwLock::new(AudioDownmix::Stereo);
+
+// How much of a file the metadata request reads, and how long it waits for the swarm
+// to deliver it before giving up; set from settings
+static METADATA_PROBE_BUDGET: std::sync::RwLock<(u64, u64)> = std::sync::RwLock::new((100 * 1024 * 1024, 30));
+
+pub fn set_metadata_probe_budget(megabytes: u32, timeout_secs: u32) {
+    if let Ok(mut current) = METADATA_PROBE_BUDGET.write() {
+        *current = (megabytes.max(10) as u64 * 1024 * 1024, timeout_secs.max(5) as u64);
+    }
+}
+
+fn metadata_probe_budget() -> (u64, u64) {
+    METADATA_PROBE_BUDGET.read().map(|b| *b).unwrap_or((100 * 1024 * 1024, 30))
+}
+
+// How many torrent handles may have a stream open at once; set from settings
+static MAX_CONCURRENT_STREAMS: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(2);
+
+pub fn set_max_concurrent_streams(max: usize) {
+    MAX_CONCURRENT_STREAMS.store(max.max(1), std::sync::atomic::Ordering::Relaxed);
+}
+
+// How long every playing stream has to stay paused before downloads are held at a
+// trickle; 0 turns holding off. Set from settings.
+static PAUSE_HOLD_SECS: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(0);
+
+pub fn set_pause_hold_minutes(minutes: u32) {
+    PAUSE_HOLD_SECS.store(minutes as u64 * 60, std::sync::atomic::Ordering::Relaxed);
+}
+
+// How long a stream may go without a keep-alive from its player before its torrent is
+// paused; it's stopped after twice as long. 0 keeps streams forever. Set from settings.
+static STREAM_IDLE_TIMEOUT_SECS: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(10 * 60);
+
+pub fn set_stream_idle_timeout(minutes: u32) {
+    STREAM_IDLE_TIMEOUT_SECS.store(minutes as u64 * 60, std::sync::atomic::Ordering::Relaxed);
+}
+
+// Warning ahead of the idle timeout
+const IDLE_WARNING_SECS: u64 = 60;
+
+// Whether the chapter picker may have frames cut for it. Set from settings.
+static CHAPTER_THUMBNAILS: std::sync::atomic::AtomicBool = std::sync::atomic::AtomicBool::new(true);
+
+pub fn set_chapter_thumbnails(enabled: bool) {
+    CHAPTER_THUMBNAILS.store(enabled, std::sync::atomic::Ordering::Relaxed);
+}
+
+// Download and upload speed while playback is held, in bytes/sec
+const HOLD_TRICKLE_BPS: u32 = 64 * 1024;
+
+// Seconds between forced keyframes in live video transcodes. Each fragment of the MP4
+// output starts at a keyframe, so this is also how far apart the points a seek can land
+// on are; x264 would otherwise place one every 250 frames, about 10s.
+static TRANSCODE_KEYFRAME_SECS: std::sync::atomic::AtomicU32 = std::sync::atomic::AtomicU32::new(2);
+
+pub fn set_transcode_keyframe_interval(secs: u32) {
+    TRANSCODE_KEYFRAME_SECS.store(secs.max(1), std::sync::atomic::Ordering::Relaxed);
+}
+
+// Live ffmpeg transcodes allowed at once, from settings; 0 sizes it from the CPU count
+static MAX_TRANSCODE_JOBS: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+// The slots and the size they were made with. A new limit gets a fresh semaphore; jobs
+// holding permits from the old one keep them until they finish.
+static TRANSCODE_SLOTS: std::sync::Mutex<Option<(usize, Arc<tokio::sync::Semaphore>)>> = std::sync::Mutex::new(None);
+// How long a request waits for a transcode slot before it's turned away
+const TRANSCODE_QUEUE_WAIT: std::time::Duration = std::time::Duration::from_secs(5);
+const TRANSCODE_RETRY_SECS: u64 = 3;
+
+pub fn set_max_transcode_jobs(max: usize) {
+    MAX_TRANSCODE_JOBS.store(max, std::sync::atomic::Ordering::Relaxed);
+}
+
+fn max_transcode_jobs() -> usize {
+    match MAX_TRANSCODE_JOBS.load(std::sync::atomic::Ordering::Relaxed) {
+        // An encode already spreads over a few cores
+        0 => std::thread::available_parallelism().map(|n| (n.get() / 2).max(1)).unwrap_or(2),
+        max => max,
+    }
+}
+
+fn transcode_slots() -> (usize, Arc<tokio::sync::Semaphore>) {
+    let max = max_transcode_jobs();
+    let mut slots = TRANSCODE_SLOTS.lock().unwrap_or_else(|e| e.into_inner());
+    match slots.as_ref() {
+        Some((size, semaphore)) if *size == max => (max, semaphore.clone()),
+        _ => {
+            let semaphore = Arc::new(tokio::sync::Semaphore::new(max));
+            *slots = Some((max, semaphore.clone()));
+            (max, semaphore)
+        }
+    }
+}
+
+/// A running transcode's slot, given back when dropped
+struct TranscodeJob {
+    _permit: tokio::sync::OwnedSemaphorePermit,
+}
+
+/// Takes a transcode slot, waiting up to `TRANSCODE_QUEUE_WAIT` for one to free up, so a
+/// burst of seeks can't start more ffmpeg encoders than the machine handles. `kind` is
+/// what the job does ("audio", "tonemap", "remux"), for the local analytics.
+async fn start_transcode_job(kind: &str) -> Option<TranscodeJob> {
+    let (max, slots) = transcode_slots();
+    match tokio::time::timeout(TRANSCODE_QUEUE_WAIT, slots.acquire_owned()).await {
+        Ok(Ok(permit)) => {
+            crate::analytics::record_transcode(kind);
+            Some(TranscodeJob { _permit: permit })
+        }
+        _ => {
+            tracing::warn!("All {} transcode slots busy, turning a request away", max);
+            None
+        }
+    }
+}
+
+fn transcode_busy_response() -> Response {
+    StreamError::unavailable("transcode_busy", "Too many transcodes running", Some(TRANSCODE_RETRY_SECS)).into_response()
+}
+
+// How much of the end of ffmpeg's stderr goes into an error response
+const STDERR_EXCERPT_BYTES: usize = 2048;
+// How long a child may take to produce its first bytes, which covers waiting on pieces
+const FIRST_OUTPUT_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(60);
+
+/// JSON body of the stream server's 5xx answers. The code goes out in the
+/// `X-Magnolia-Error` header too, so a player can decide between retrying, waiting and
+/// showing the message without reading the body.
+#[derive(Debug, Serialize)]
+struct StreamError {
+    #[serde(skip)]
+    status: StatusCode,
+    code: &'static str,
+    retryable: bool,
+    message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    retry_after_secs: Option<u64>,
+    // The end of ffmpeg's stderr when ffmpeg is what failed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ffmpeg_stderr: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    details: Option<serde_json::Value>,
+}
+
+impl StreamError {
+    /// Broken on this end; asking again won't help
+    fn internal(code: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            status: StatusCode::INTERNAL_SERVER_ERROR,
+            code,
+            retryable: false,
+            message: message.into(),
+            retry_after_secs: None,
+            ffmpeg_stderr: None,
+            details: None,
+        }
+    }
+
+    /// Not possible yet, e.g. the data is still downloading or every transcode slot is taken
+    fn unavailable(code: &'static str, message: impl Into<String>, retry_after_secs: Option<u64>) -> Self {
+        Self {
+            status: StatusCode::SERVICE_UNAVAILABLE,
+            retryable: true,
+            retry_after_secs,
+            ..Self::internal(code, message)
+        }
+    }
+
+    /// ffmpeg ran and failed; `stderr` is whatever it printed
+    fn ffmpeg(message: impl Into<String>, stderr: &str) -> Self {
+        Self {
+            status: StatusCode::BAD_GATEWAY,
+            ffmpeg_stderr: Some(stderr_excerpt(stderr)).filter(|s| !s.is_empty()),
+            ..Self::internal("ffmpeg_failed", message)
+        }
+    }
+
+    fn with_details(mut self, details: serde_json::Value) -> Self {
+        self.details = Some(details);
+        self
+    }
+}
+
+impl IntoResponse for StreamError {
+    fn into_response(self) -> Response {
+        crate::analytics::record_failure(self.code);
+        let mut response = (self.status, [("X-Magnolia-Error", self.code)], axum::Json(&self)).into_response();
+        if let Some(secs) = self.retry_after_secs {
+            response.headers_mut().insert(header::RETRY_AFTER, secs.into());
+        }
+        response
+    }
+}
+
+fn stderr_excerpt(stderr: &str) -> String {
+    let stderr = stderr.trim();
+    let mut start = stderr.len().saturating_sub(STDERR_EXCERPT_BYTES);
+    while !stderr.is_char_boundary(start) {
+        start += 1;
+    }
+    stderr[start..].to_string()
+}
+
+pub fn set_audio_normalization(normalization: AudioNormalization) {
+    if let Ok(mut current) = AUDIO_NORMALIZATION.write() {
+        *current = normalization;
+    }
+}
+
+pub fn set_audio_downmix(downmix: AudioDownmix) {
+    if let Ok(mut current) = AUDIO_DOWNMIX.write() {
+        *current = downmix;
+    }
+}
+
+/// ffmpeg arguments for encoding the selected audio to AAC, with the channel layout and
+/// loudness filter from settings
+fn aac_encode_args() -> Vec<String> {
+    let normalization = AUDIO_NORMALIZATION.read().map(|n| *n).unwrap_or_default();
+    let downmix = AUDIO_DOWNMIX.read().map(|d| *d).unwrap_or_default();
+
+    // Sources are first brought to 5.1 so the pan always has a center channel to work
+    // with; stereo and mono sources only gain silent channels. Surround keeps up to 5.1
+    // and leaves smaller layouts alone.
+    let (layout_filter, bitrate) = match downmix {
+        AudioDownmix::Stereo => (
+            "aformat=channel_layouts=5.1,pan=stereo|FL<FL+0.707*FC+0.707*BL|FR<FR+0.707*FC+0.707*BR",
+            "192k",
+        ),
+        AudioDownmix::DialogueBoost => (
+            "aformat=channel_layouts=5.1,pan=stereo|FL<1.4*FC+0.6*FL+0.4*BL|FR<1.4*FC+0.6*FR+0.4*BR",
+            "192k",
+        ),
+        AudioDownmix::Surround => ("aformat=channel_layouts=5.1|stereo|mono", "384k"),
+    };
+    let normalization_filter = match normalization {
+        AudioNormalization::Off => None,
+        AudioNormalization::Loudnorm => Some("loudnorm=I=-16:TP=-1.5:LRA=11"),
+        AudioNormalization::Dynaudnorm => Some("dynaudnorm=f=150:g=15"),
+    };
+    let filters: Vec<&str> = std::iter::once(layout_filter).chain(normalization_filter).collect();
+    let filter = filters.join(",");
+
+    ["-c:a", "aac", "-b:a", bitrate, "-af", filter.as_str()]
+        .iter()
+        .map(|a| a.to_string())
+        .collect()
+}
+
 #[derive(Clone, Serialize, Deserialize)]
 pub struct TorrentFile {
     pub index: usize,
     pub name: String,
     pub size: u64,
     pub path: String,
+    // This file's own progress, so a single episode of a batch reads sensibly
+    #[serde(default)]
+    pub downloaded_bytes: u64,
+    #[serde(default)]
+    pub progress: f64,
+    // Whether the session is downloading this file
+    #[serde(default)]
+    pub selected: bool,
+}
+
+#[derive(Clone, Serialize)]
+pub struct TorrentFilePreview {
+    pub index: usize,
+    pub name: String,
+    pub path: String,
+    pub size: u64,
+    pub is_video: bool,
+    pub season: Option<u32>,
+    pub episode: Option<u32>,
+}
+
+#[derive(Clone, Serialize)]
+pub struct TorrentPreview {
+    pub name: String,
+    pub total_size: u64,
+    pub files: Vec<TorrentFilePreview>,
 }
 
 #[derive(Clone, Serialize, Deserialize)]
@@ -53,6 +336,15 @@ pub struct SubtitleTrack {
     pub language: Option<String>,
     pub codec: Option<String>,
     pub name: Option<String>,
+    // Only covers foreign-language dialogue and signs
+    #[serde(default)]
+    pub is_forced: bool,
+    // Where the stream server serves the track as text, and the MIME type it answers with;
+    // filled in per response since the port and session change between runs
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mime_type: Option<String>,
 }
 
 #[derive(Clone, Serialize, Deserialize)]
@@ -63,12 +355,26 @@ pub struct Chapter {
     pub end_time: f64,
 }
 
+// A file embedded in the container, e.g. a font for ASS subtitles or cover art
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Attachment {
+    pub index: usize,
+    // Absolute ffprobe stream index, used to extract it
+    pub stream_index: usize,
+    pub filename: Option<String>,
+    pub mimetype: Option<String>,
+    #[serde(default)]
+    pub is_cover: bool,
+}
+
 #[derive(Clone, Serialize, Deserialize)]
 pub struct MkvMetadata {
     pub audio_tracks: Vec<AudioTrack>,
     pub subtitle_tracks: Vec<SubtitleTrack>,
     pub chapters: Vec<Chapter>,
     #[serde(default)]
+    pub attachments: Vec<Attachment>,
+    #[serde(default)]
     pub needs_audio_transcoding: bool,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub transcoded_audio_url: Option<String>,
@@ -83,11 +389,42 @@ pub struct TorrentInfo {
     pub size: u64,
     pub files: Vec<TorrentFile>,
     pub progress: f64,
-    pub download_speed: u64,
-    pub upload_speed: u64,
+    pub download_speed: u64, // bytes/sec, smoothed
+    pub upload_speed: u64,   // bytes/sec, smoothed
     pub peers: usize,
     pub is_paused: bool,
     pub state: String, // "checking", "downloading", "paused", "live"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub eta_seconds: Option<u64>,
+    // Time until the largest video file has its startup buffer
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub streamable_eta_seconds: Option<u64>,
+    // BEP 19 web seeds and how much of the stream they delivered
+    #[serde(default)]
+    pub web_seeds: usize,
+    #[serde(default)]
+    pub web_seed_bytes: u64,
+}
+
+#[derive(Clone, Serialize)]
+pub struct FinishedTorrent {
+    pub handle_id: usize,
+    pub name: String,
+    pub main_file: PathBuf,
+}
+
+#[derive(Clone, Serialize)]
+pub struct SessionStats {
+    pub active_torrents: usize,
+    pub paused_torrents: usize,
+    pub download_speed: u64, // bytes/sec, smoothed
+    pub upload_speed: u64,   // bytes/sec, smoothed
+    pub downloaded_bytes: u64,
+    pub uploaded_bytes: u64,
+    pub download_dir_bytes: u64,
+    pub cached_torrents: usize,
+    pub audio_cache_bytes: u64,
+    pub subtitle_cache_bytes: u64,
 }
 
 #[derive(Clone, Serialize)]
@@ -101,22 +438,88 @@ pub struct StreamInfo {
 #[derive(Clone, Serialize)]
 pub struct StreamStatus {
     pub status: String, // "initializing", "ready", "transcoding", "error"
+    // Of the streamed file, not the whole torrent
     pub progress_bytes: u64,
     pub total_bytes: u64,
     pub peers: usize,
-    pub download_speed: u64,
+    pub download_speed: u64, // bytes/sec, smoothed
     pub stream_info: Option<StreamInfo>,
     pub state: String, // "checking", "downloading", "transcoding"
     #[serde(skip_serializing_if = "Option::is_none")]
     pub transcode_progress: Option<f32>, // 0.0 - 100.0
+    pub buffer_target_bytes: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub estimated_seconds_until_ready: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub eta_seconds: Option<u64>,
+    pub verification: String, // "incomplete", "verifying", "verified", "corrupt"
+}
+
+#[derive(Clone, Serialize)]
+pub struct VideoStreamInfo {
+    pub codec: String,
+    pub profile: Option<String>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub bit_depth: Option<u32>,
+    pub pix_fmt: Option<String>,
+}
+
+#[derive(Clone, Serialize)]
+pub struct StreamCompatibility {
+    pub container: String,
+    pub video: Option<VideoStreamInfo>,
+    pub audio_codecs: Vec<String>,
+    pub hdr: Option<String>, // "HDR10", "HDR10+", "HLG", "Dolby Vision"
+    pub video_compatible: bool,
+    pub audio_compatible: bool,
+    pub recommended_mode: String, // "direct", "audio_transcode", "full_transcode", "tonemap"
+    // SDR tone-mapped transcode of this stream, offered when HDR is detected
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tonemapped_url: Option<String>,
+}
+
+#[derive(Clone, Serialize)]
+pub struct FileAvailability {
+    pub file_size: u64,
+    pub piece_length: u64,
+    pub downloaded_bytes: u64,
+    // Merged [start, end) byte ranges within the file that are on disk
+    pub ranges: Vec<(u64, u64)>,
 }
 
+#[derive(Clone, Serialize)]
+pub struct FileVerification {
+    pub file_index: usize,
+    pub total_pieces: usize,
+    pub verified_pieces: usize,
+    // Pieces shared with a neighbouring file that isn't on disk can't be hashed
+    pub skipped_pieces: usize,
+    pub bad_pieces: Vec<usize>,
+    pub ok: bool,
+}
+
+type ChapterThumbnails = Arc<RwLock<HashMap<(usize, usize, usize), Vec<u8>>>>;
+
 #[derive(Clone)]
 pub struct AppState {
     pub session: Arc<Session>,
-    pub transcode_states: Arc<RwLock<HashMap<(usize, usize), TranscodeState>>>,
-    pub metadata_cache: Arc<RwLock<HashMap<(usize, usize), MkvMetadata>>>,
+    // Metadata, transcodes and metadata reads of each streamed file
+    pub streams: StreamSessions,
     pub download_dir: PathBuf,
+    pub http_addr: SocketAddr,
+    // Key: library_id -> file on disk served under /local/
+    pub local_files: Arc<RwLock<HashMap<String, PathBuf>>>,
+    // Key: session_id -> BEP 19 web seeds of the torrent
+    pub web_seeds: Arc<RwLock<HashMap<usize, Arc<WebSeeds>>>>,
+    // Key: (session_id, file_id, chapter_index) -> JPEG of the chapter's first frames
+    pub chapter_thumbnails: ChapterThumbnails,
+    // Key: (session_id, file_id) -> loudness of the first minutes of each audio track
+    pub audio_previews: Arc<RwLock<HashMap<(usize, usize), AudioPreview>>>,
+    // Metadata persisted by info hash, so re-watching skips the probe
+    pub media_cache: Arc<MediaCache>,
+    // Forwarded to the frontend as `metadata-probe-progress` events
+    pub probe_events: tokio::sync::broadcast::Sender<MetadataProbeProgress>,
 }
 
 struct TorrentEntry {
@@ -165,18 +568,210 @@ pub struct TranscodeState {
     pub error: Option<String>,
 }
 
+// A running metadata read, which the player can extend or cut short
+#[derive(Clone)]
+pub struct MetadataProbe {
+    pub deadline: tokio::time::Instant,
+    pub cancelled: bool,
+}
+
+/// Payload of the `metadata-probe-progress` event
+#[derive(Clone, Serialize)]
+pub struct MetadataProbeProgress {
+    pub session_id: usize,
+    pub file_id: usize,
+    pub bytes_read: u64,
+    pub target_bytes: u64,
+    pub peers: usize,
+    pub seconds_left: u64,
+    // "reading", "waiting" (out of time without enough data), "done", "failed" or "cancelled"
+    pub state: String,
+}
+
+/// File list of a torrent fetched without adding it, so opening a search result a second
+/// time (or after it was prefetched) doesn't wait on peers for its metadata again
+#[derive(Clone)]
+struct TorrentListing {
+    name: String,
+    files: Vec<TorrentFile>,
+    fetched_at: std::time::Instant,
+}
+
+// Listings kept at once; the oldest goes first
+const LISTING_CACHE_LIMIT: usize = 50;
+// How long a background prefetch may wait for metadata before giving up
+const PREFETCH_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(60);
+
 pub struct TorrentManager {
     session: Arc<Session>,
     download_dir: PathBuf,
     torrents: Arc<RwLock<HashMap<usize, TorrentEntry>>>,
     next_id: Arc<RwLock<usize>>,
     http_addr: SocketAddr,
-    // Key: (handle_id, file_index) -> TranscodeState
-    transcode_states: Arc<RwLock<HashMap<(usize, usize), TranscodeState>>>,
-    // Cache metadata by (session_id, file_index)
-    metadata_cache: Arc<RwLock<HashMap<(usize, usize), MkvMetadata>>>,
+    // Key: (session_id, file_index) -> everything held for a streamed file
+    streams: StreamSessions,
     // Torrent cache: keep up to 10 torrents paused with data cleared
     torrent_cache: Arc<RwLock<Vec<CachedTorrent>>>,
+    // Key: session_id -> BEP 19 web seeds, shared with the stream server
+    web_seeds: Arc<RwLock<HashMap<usize, Arc<WebSeeds>>>>,
+    // Chapter picker frames, shared with the stream server and dropped with the stream
+    chapter_thumbnails: ChapterThumbnails,
+    // Recent requests to the stream server
+    access_log: StreamAccessLog,
+    // Key: handle_id -> moving average of transfer speeds
+    speed_averages: Arc<RwLock<HashMap<usize, SpeedAverage>>>,
+    // Key: (session_id, file_index) -> hash check of a completed file, None while running
+    file_verifications: Arc<RwLock<HashMap<(usize, usize), Option<FileVerification>>>>,
+    // Key: session_id -> files the user chose to download besides the ones being streamed
+    selected_files: Arc<RwLock<HashMap<usize, std::collections::BTreeSet<usize>>>>,
+    // Key: info hash -> file list fetched with list_only, prefetched or from an earlier look
+    listings: Arc<RwLock<HashMap<String, TorrentListing>>>,
+    // Key: info hash -> held while its list_only fetch runs, so callers share one fetch
+    listing_fetches: Arc<RwLock<HashMap<String, Arc<tokio::sync::Mutex<()>>>>>,
+    // Key: library_id -> local file the HTTP server may stream
+    local_files: Arc<RwLock<HashMap<String, PathBuf>>>,
+    probe_events: tokio::sync::broadcast::Sender<MetadataProbeProgress>,
+    // Limits from the bandwidth schedule, applied as is unless playback is held
+    speed_limits: std::sync::Mutex<(Option<u32>, Option<u32>)>,
+    playback_hold: std::sync::atomic::AtomicBool,
+}
+
+// Bitrate assumed when the duration hasn't been probed yet (~10 Mbps)
+const FALLBACK_BYTES_PER_SECOND: u64 = 1_250_000;
+const READAHEAD_SECONDS: u64 = 60;
+const MIN_READAHEAD_BYTES: u64 = 32 * 1024 * 1024;
+// Startup buffer: at least this much media before playback starts
+const MIN_STARTUP_BUFFER_BYTES: u64 = 2 * 1024 * 1024;
+const STARTUP_BUFFER_SECONDS: u64 = 10;
+// When downloading slower than the bitrate, buffer enough to play this long before catching up
+const SUSTAIN_PLAYBACK_SECONDS: u64 = 60;
+
+// Lowercase hex info hash of a magnet; None for .torrent files and base32 hashes
+fn magnet_info_hash(magnet_url: &str) -> Option<String> {
+    magnet_url.split(['?', '&'])
+        .find_map(|part| part.strip_prefix("xt=urn:btih:"))
+        .filter(|hash| hash.len() == 40)
+        .map(|hash| hash.to_lowercase())
+}
+
+/// Bare info hashes, as some search APIs return, become magnets with public trackers;
+/// librqbit then fetches the metadata from peers found through those and the DHT
+fn expand_info_hash(magnet_or_url: String) -> String {
+    crate::search::info_hash_magnet(&magnet_or_url, None).unwrap_or(magnet_or_url)
+}
+
+/// Where librqbit should load a torrent from, plus passkey announce URLs for private
+/// trackers. .torrent links behind a tracker login are downloaded here with its cookie,
+/// since librqbit's own fetch can't authenticate.
+async fn torrent_source(magnet_or_url: &str) -> Result<(AddTorrent<'static>, Option<Vec<String>>, Vec<String>)> {
+    let announces = crate::search::http::passkey_announce_urls(magnet_or_url);
+    let trackers = if announces.is_empty() { None } else { Some(announces) };
+
+    // .torrent files are read here rather than by librqbit so their url-list is kept
+    let (add_torrent, web_seeds) = if magnet_or_url.starts_with("magnet:") {
+        (AddTorrent::from_url(magnet_or_url.to_string()), web_seed::magnet_web_seeds(magnet_or_url))
+    } else {
+        let bytes = if magnet_or_url.starts_with("http") {
+            let client = crate::search::http::HttpClient::new(std::time::Duration::from_secs(30));
+            let response = client.get(magnet_or_url).await?.error_for_status()?;
+            response.bytes().await?.to_vec()
+        } else {
+            tokio::fs::read(magnet_or_url).await
+                .with_context(|| format!("Failed to read {}", magnet_or_url))?
+        };
+        let web_seeds = web_seed::torrent_web_seeds(&bytes);
+        (AddTorrent::from_bytes(bytes), web_seeds)
+    };
+
+    Ok((add_torrent, trackers, web_seeds))
+}
+
+/// Bytes to download before declaring a stream ready. Covers a few seconds of media,
+/// plus the shortfall over the next minute when the swarm can't keep up with the bitrate.
+fn startup_buffer_bytes(bytes_per_second: u64, download_bytes_per_second: u64, file_size: u64) -> u64 {
+    let mut buffer = std::cmp::max(MIN_STARTUP_BUFFER_BYTES, bytes_per_second * STARTUP_BUFFER_SECONDS);
+    // No measured speed yet (no peers) isn't a reason to demand a huge buffer
+    if download_bytes_per_second > 0 && download_bytes_per_second < bytes_per_second {
+        buffer += (bytes_per_second - download_bytes_per_second) * SUSTAIN_PLAYBACK_SECONDS;
+    }
+    std::cmp::min(buffer, file_size)
+}
+
+// Time constant of the speed moving average; librqbit's instantaneous speed jumps around a lot
+const SPEED_SMOOTHING_SECS: f64 = 5.0;
+
+struct SpeedAverage {
+    download: f64,
+    upload: f64,
+    updated_at: std::time::Instant,
+}
+
+fn mib_to_bytes(mbps: f64) -> f64 {
+    mbps * 1024.0 * 1024.0
+}
+
+fn eta_seconds(remaining_bytes: u64, bytes_per_second: u64) -> Option<u64> {
+    if remaining_bytes == 0 {
+        Some(0)
+    } else if bytes_per_second > 0 {
+        Some(remaining_bytes.div_ceil(bytes_per_second))
+    } else {
+        None
+    }
+}
+
+// 0-100, or 0 for an empty file
+fn file_percent(downloaded_bytes: u64, size: u64) -> f64 {
+    if size > 0 {
+        (downloaded_bytes.min(size) as f64 / size as f64 * 100.0).min(100.0)
+    } else {
+        0.0
+    }
+}
+
+// Stall watchdog: restart peer discovery first, then let the player offer another release
+const STALL_REANNOUNCE_SECS: u64 = 15;
+const STALL_REPORT_SECS: u64 = 45;
+
+/// An open stream and its share of the app's resources. Peers are counted for the whole
+/// torrent, which streams of the same season pack share.
+#[derive(Clone, Serialize)]
+pub struct ActiveStream {
+    #[serde(flatten)]
+    pub usage: StreamUsage,
+    pub file_size: u64,
+    pub downloaded_bytes: u64,
+    pub peers: usize,
+}
+
+#[derive(Clone, Serialize)]
+pub struct StalledStream {
+    pub handle_id: usize,
+    pub file_index: usize,
+    pub stalled_seconds: u64,
+}
+
+#[derive(Clone, Serialize)]
+pub struct IdleStream {
+    pub handle_id: usize,
+    pub file_index: usize,
+    pub idle_seconds: u64,
+    pub stage: IdleStage,
+}
+
+/// Points every text subtitle track at the subtitles route: styled ASS/SSA as a sidecar
+/// ASS file, everything else converted to WebVTT, which players take as a text track.
+/// Bitmap tracks get no URL since they can't be served as text.
+fn add_subtitle_urls(metadata: &mut MkvMetadata, base_url: &str) {
+    for track in metadata.subtitle_tracks.iter_mut() {
+        let codec = track.codec.as_deref().unwrap_or("");
+        if BITMAP_SUBTITLE_CODECS.contains(&codec) {
+            continue;
+        }
+        let format = if matches!(codec, "ass" | "ssa") { TrackFormat::Ass } else { TrackFormat::Vtt };
+        track.url = Some(format!("{}/{}?format={}", base_url, track.index, format.extension()));
+        track.mime_type = Some(format.mime_type().to_string());
+    }
 }
 
 async fn get_file_metadata(
@@ -184,6 +779,11 @@ async fn get_file_metadata(
     axum::extract::State(state): axum::extract::State<AppState>,
 ) -> impl IntoResponse {
     tracing::info!("Metadata request: session_id={}, file_id={}", session_id, file_id);
+    let subtitles_url = format!("http://{}/torrents/{}/subtitles/{}", state.http_addr, session_id, file_id);
+    let respond = |mut metadata: MkvMetadata| {
+        add_subtitle_urls(&mut metadata, &subtitles_url);
+        axum::Json(metadata).into_response()
+    };
     
     let handle = match state.session.get(TorrentIdOrHash::Id(session_id)) {
         Some(h) => {
@@ -201,6 +801,24 @@ async fn get_file_metadata(
         return (StatusCode::NOT_FOUND, "File not found").into_response();
     }
     
+    if let Some(metadata) = state.streams.metadata((session_id, file_id)).await {
+        tracing::info!("Using cached metadata for session_id={}, file_id={}", session_id, file_id);
+        return respond(metadata);
+    }
+    
+    // Probed on an earlier watch of the same torrent
+    let info_hash = handle.info_hash().as_string();
+    if let Ok(Some(bytes)) = state.media_cache.load_track(TrackType::Metadata, &info_hash, file_id, 0).await {
+        match serde_json::from_slice::<MkvMetadata>(&bytes) {
+            Ok(metadata) => {
+                tracing::info!("Using persisted metadata for {} file {}", info_hash, file_id);
+                state.streams.update((session_id, file_id), |s| s.metadata = Some(metadata.clone())).await;
+                return respond(metadata);
+            }
+            Err(e) => tracing::warn!("Ignoring unreadable persisted metadata: {}", e),
+        }
+    }
+    
     tracing::info!("Creating stream for file_id={}", file_id);
     
     // Check file size first
@@ -210,15 +828,16 @@ async fn get_file_metadata(
         Ok(Some(size)) => size,
         _ => {
             tracing::error!("Could not get file size");
-            return (StatusCode::INTERNAL_SERVER_ERROR, "Could not get file size").into_response();
+            return StreamError::internal("file_size_unavailable", "Could not get file size").into_response();
         }
     };
     
     tracing::info!("File size: {} bytes", file_size);
     
-    // For metadata extraction, we need enough data downloaded
-    // Check if we have at least 100MB or the full file if smaller
-    let min_required = std::cmp::min(file_size, 100 * 1024 * 1024);
+    let (probe_bytes, probe_secs) = metadata_probe_budget();
+    let max_size = std::cmp::min(file_size, probe_bytes) as usize;
+    // ffprobe needs at least this much to find the tracks
+    let min_required = std::cmp::min(max_size, METADATA_PROBE_MIN_BYTES);
     
     let mut stream = match handle.stream(file_id) {
         Ok(s) => {
@@ -227,7 +846,7 @@ async fn get_file_metadata(
         },
         Err(e) => {
             tracing::error!("Failed to create stream for file_id {}: {}", file_id, e);
-            return (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to stream: {}", e)).into_response();
+            return StreamError::internal("stream_open_failed", format!("Failed to stream: {}", e)).into_response();
         }
     };
 
@@ -242,68 +861,130 @@ async fn get_file_metadata(
         },
         Err(e) => {
             tracing::error!("Failed to create temp file: {}", e);
-            return (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to create temp file: {}", e)).into_response();
+            return StreamError::internal("temp_file_failed", format!("Failed to create temp file: {}", e)).into_response();
         }
     };
 
-    // Read up to 100MB for metadata extraction (needs more data for complete MKV headers)
-    tracing::info!("Starting to read stream data (need at least {} bytes)...", min_required);
+    let probe_key = (session_id, file_id);
+    state.streams.update(probe_key, |s| {
+        s.probe = Some(MetadataProbe {
+            deadline: tokio::time::Instant::now() + std::time::Duration::from_secs(probe_secs),
+            cancelled: false,
+        });
+        s.add_temp_file(temp_file_path.clone());
+    }).await;
+    let report = |bytes_read: usize, deadline: tokio::time::Instant, probe_state: &str| {
+        let peers = handle.stats().live.as_ref().map(|l| l.snapshot.peer_stats.live).unwrap_or(0);
+        let _ = state.probe_events.send(MetadataProbeProgress {
+            session_id,
+            file_id,
+            bytes_read: bytes_read as u64,
+            target_bytes: max_size as u64,
+            peers,
+            seconds_left: deadline.saturating_duration_since(tokio::time::Instant::now()).as_secs(),
+            state: probe_state.to_string(),
+        });
+    };
+
+    tracing::info!("Starting to read stream data (up to {} bytes within {}s)...", max_size, probe_secs);
     let mut total_read = 0usize;
     let chunk_size = 1024 * 1024; // 1MB chunks
-    let max_size = std::cmp::min(file_size as usize, 100 * 1024 * 1024); // Up to 100MB
     let mut buffer = vec![0u8; chunk_size];
-    
-    let mut consecutive_empty_reads = 0;
-    let max_empty_reads = 150; // Allow up to 150 empty reads (30 seconds total with delays) for slower connections
+    let mut last_report = tokio::time::Instant::now();
+    let mut waiting_since: Option<tokio::time::Instant> = None;
     
     while total_read < max_size {
-        let bytes_read = match stream.read(&mut buffer).await {
-            Ok(0) => {
-                consecutive_empty_reads += 1;
-                if consecutive_empty_reads >= max_empty_reads {
-                    tracing::warn!("Too many empty reads, stopping at {} bytes", total_read);
-                    break;
-                }
-                tracing::debug!("No data available yet, waiting... (attempt {}/{})", consecutive_empty_reads, max_empty_reads);
+        let Some(probe) = state.streams.read(probe_key, |s| s.probe.clone()).await.flatten() else { break };
+        let now = tokio::time::Instant::now();
+        if probe.cancelled {
+            tracing::info!("Metadata read cancelled at {} bytes", total_read);
+            break;
+        }
+        if now >= probe.deadline {
+            if total_read >= min_required {
+                tracing::warn!("Metadata read out of time, continuing with {} bytes", total_read);
+                break;
+            }
+            // Not enough to probe yet; hold on for a while in case the player extends the read
+            let since = *waiting_since.get_or_insert(now);
+            if now.duration_since(since) >= METADATA_PROBE_DECISION_WAIT {
+                break;
+            }
+            if now.duration_since(last_report) >= METADATA_PROBE_REPORT_INTERVAL {
+                report(total_read, probe.deadline, "waiting");
+                last_report = now;
+            }
+            tokio::time::sleep(tokio::time::Duration::from_millis(200)).await;
+            continue;
+        }
+        waiting_since = None;
+        if now.duration_since(last_report) >= METADATA_PROBE_REPORT_INTERVAL {
+            report(total_read, probe.deadline, "reading");
+            last_report = now;
+        }
+
+        // Bounded so the deadline and cancellation are noticed while the swarm is slow
+        let bytes_read = match tokio::time::timeout(METADATA_PROBE_REPORT_INTERVAL, stream.read(&mut buffer)).await {
+            Err(_) => continue,
+            Ok(Ok(0)) => {
+                tracing::debug!("No data available yet, waiting...");
                 tokio::time::sleep(tokio::time::Duration::from_millis(200)).await;
                 continue;
             },
-            Ok(n) => {
-                consecutive_empty_reads = 0; // Reset counter on successful read
-                tracing::debug!("Read {} bytes (total: {})", n, total_read + n);
-                n
-            },
-            Err(e) => {
+            Ok(Ok(n)) => n,
+            Ok(Err(e)) => {
                 tracing::error!("Failed to read stream at byte {}: {}", total_read, e);
+                state.streams.update_existing(probe_key, |s| s.probe = None).await;
+                report(total_read, probe.deadline, "failed");
                 let _ = tokio::fs::remove_file(&temp_file_path).await;
-                return (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to read stream: {}", e)).into_response();
+                return StreamError::unavailable("stream_read_failed", format!("Failed to read stream: {}", e), None).into_response();
             }
         };
         
         if let Err(e) = tokio::io::AsyncWriteExt::write_all(&mut temp_file, &buffer[..bytes_read]).await {
             tracing::error!("Failed to write temp file at byte {}: {}", total_read, e);
+            state.streams.update_existing(probe_key, |s| s.probe = None).await;
+            report(total_read, probe.deadline, "failed");
             let _ = tokio::fs::remove_file(&temp_file_path).await;
-            return (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to write temp file: {}", e)).into_response();
+            return StreamError::internal("temp_file_failed", format!("Failed to write temp file: {}", e)).into_response();
         }
         
         total_read += bytes_read;
     }
     
+    let probe = state.streams.update_existing(probe_key, |s| s.probe.take()).await.flatten();
+    let deadline = probe.as_ref().map(|p| p.deadline).unwrap_or_else(tokio::time::Instant::now);
+    let cancelled = probe.map(|p| p.cancelled).unwrap_or(false);
+    
     tracing::info!("Finished reading {} bytes ({}% of target), syncing file...", 
-        total_read, (total_read * 100) / max_size);
+        total_read, (total_read * 100) / max_size.max(1));
     
     // Check if we have enough data
-    if total_read < 10 * 1024 * 1024 {
-        tracing::error!("Not enough data read for metadata extraction: {} bytes (need at least 10MB)", total_read);
+    if total_read < min_required {
+        tracing::error!("Not enough data read for metadata extraction: {} bytes (need at least {})", total_read, min_required);
+        report(total_read, deadline, if cancelled { "cancelled" } else { "failed" });
         let _ = tokio::fs::remove_file(&temp_file_path).await;
-        return (StatusCode::SERVICE_UNAVAILABLE, "Not enough data available yet, please wait for torrent to buffer more data").into_response();
+        let message = if cancelled {
+            "Metadata read cancelled before enough data arrived"
+        } else {
+            "Not enough data available yet, please wait for torrent to buffer more data"
+        };
+        let code = if cancelled { "metadata_cancelled" } else { "not_buffered" };
+        return StreamError { retryable: !cancelled, ..StreamError::unavailable(code, message, None) }
+            .with_details(serde_json::json!({
+                "cancelled": cancelled,
+                "bytes_read": total_read,
+                "required_bytes": min_required,
+            }))
+            .into_response();
     }
+    report(total_read, deadline, "done");
     
     // Flush and sync the file before reading with ffprobe
     if let Err(e) = temp_file.sync_all().await {
         tracing::error!("Failed to sync temp file: {}", e);
         let _ = tokio::fs::remove_file(&temp_file_path).await;
-        return (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to sync temp file: {}", e)).into_response();
+        return StreamError::internal("temp_file_failed", format!("Failed to sync temp file: {}", e)).into_response();
     }
     drop(temp_file); // Close the file handle
     
@@ -318,1649 +999,4959 @@ async fn get_file_metadata(
         Err(e) => {
             tracing::error!("Failed to extract metadata: {}", e);
             let _ = tokio::fs::remove_file(&temp_file_path).await;
-            return (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to extract metadata: {}", e)).into_response();
+            return StreamError::internal("metadata_failed", format!("Failed to extract metadata: {}", e)).into_response();
         }
     };
 
     tracing::info!("Cleaning up temp file...");
     let _ = tokio::fs::remove_file(&temp_file_path).await;
     
-    // Cache the metadata for later use by get_stream_status
-    {
-        let mut cache = state.metadata_cache.write().await;
-        cache.insert((session_id, file_id), metadata.clone());
-        tracing::info!("Cached metadata for session_id={}, file_id={}", session_id, file_id);
+    // Keep the metadata with the stream for later use by get_stream_status
+    state.streams.update((session_id, file_id), |s| s.metadata = Some(metadata.clone())).await;
+    tracing::info!("Cached metadata for session_id={}, file_id={}", session_id, file_id);
+    
+    // Keep it across sessions too, unless the read was cut short and tracks may be missing
+    if total_read >= max_size {
+        match serde_json::to_vec(&metadata) {
+            Ok(json) => {
+                if let Err(e) = state.media_cache.save_track(TrackType::Metadata, &info_hash, file_id, 0, json).await {
+                    tracing::warn!("Failed to persist metadata: {}", e);
+                }
+            }
+            Err(e) => tracing::warn!("Failed to serialize metadata: {}", e),
+        }
     }
     
     tracing::info!("Returning metadata response");
-    axum::Json(metadata).into_response()
+    respond(metadata)
 }
 
-async fn get_subtitle_track(
-    Path((session_id, file_id, track_index)): Path<(usize, usize, usize)>,
+// Smallest read ffprobe is given; files shorter than this are read whole
+const METADATA_PROBE_MIN_BYTES: usize = 10 * 1024 * 1024;
+const METADATA_PROBE_REPORT_INTERVAL: std::time::Duration = std::time::Duration::from_millis(500);
+// How long an out-of-time read waits for the player to extend it before failing
+const METADATA_PROBE_DECISION_WAIT: std::time::Duration = std::time::Duration::from_secs(30);
+
+// Downloaded byte ranges of a file, for the player's buffered bar and to avoid seeking into holes
+async fn get_file_availability(
+    Path((session_id, file_id)): Path<(usize, usize)>,
     axum::extract::State(state): axum::extract::State<AppState>,
 ) -> impl IntoResponse {
-    use tokio::process::Command;
-    
-    tracing::info!("Subtitle request: session={}, file={}, track={}", session_id, file_id, track_index);
-    
     let handle = match state.session.get(TorrentIdOrHash::Id(session_id)) {
         Some(h) => h,
         None => return (StatusCode::NOT_FOUND, "Torrent not found").into_response(),
     };
 
-    let mut stream = match handle.stream(file_id) {
-        Ok(s) => s,
-        Err(e) => {
-            tracing::error!("Failed to create stream: {}", e);
-            return (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to stream: {}", e)).into_response();
-        }
+    let layout = handle.with_metadata(|meta| {
+        meta.file_infos.get(file_id).map(|f| (
+            f.offset_in_torrent,
+            f.len,
+            meta.lengths.default_piece_length() as u64,
+        ))
+    });
+    let (file_offset, file_size, piece_length) = match layout {
+        Ok(Some(l)) => l,
+        _ => return (StatusCode::NOT_FOUND, "File not found").into_response(),
     };
 
-    // Read enough data for subtitle extraction
-    let temp_dir = std::env::temp_dir();
-    let temp_file_path = temp_dir.join(format!("magnolia_sub_{}_{}.mkv", session_id, file_id));
-    
-    let mut temp_file = match tokio::fs::File::create(&temp_file_path).await {
-        Ok(f) => f,
-        Err(e) => {
-            tracing::error!("Failed to create temp file: {}", e);
-            return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to create temp file").into_response();
-        }
+    if file_size == 0 || piece_length == 0 {
+        return axum::Json(FileAvailability { file_size, piece_length, downloaded_bytes: 0, ranges: Vec::new() }).into_response();
+    }
+
+    let first_piece = file_offset / piece_length;
+    let last_piece = (file_offset + file_size - 1) / piece_length;
+
+    let have_pieces: Vec<u64> = match handle.with_chunk_tracker(|chunks| {
+        chunks.get_have_pieces()
+            .iter_ones()
+            .map(|p| p as u64)
+            .filter(|p| *p >= first_piece && *p <= last_piece)
+            .collect()
+    }) {
+        Ok(pieces) => pieces,
+        // No chunk tracker yet while the torrent is still initializing
+        Err(_) => Vec::new(),
     };
 
-    // Read up to 500MB to ensure we get all subtitle data
-    let mut total_read = 0usize;
-    let chunk_size = 1024 * 1024;
-    let max_size = 500 * 1024 * 1024;
-    let mut buffer = vec![0u8; chunk_size];
-    
-    while total_read < max_size {
-        match stream.read(&mut buffer).await {
-            Ok(0) => break,
-            Ok(n) => {
-                if tokio::io::AsyncWriteExt::write_all(&mut temp_file, &buffer[..n]).await.is_err() {
-                    let _ = tokio::fs::remove_file(&temp_file_path).await;
-                    return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to write temp file").into_response();
-                }
-                total_read += n;
+    let mut ranges: Vec<(u64, u64)> = Vec::new();
+    let mut downloaded_bytes = 0;
+    for piece in have_pieces {
+        let start = std::cmp::max(piece * piece_length, file_offset) - file_offset;
+        let end = std::cmp::min((piece + 1) * piece_length, file_offset + file_size) - file_offset;
+        downloaded_bytes += end - start;
+        match ranges.last_mut() {
+            Some(last) if last.1 == start => last.1 = end,
+            _ => ranges.push((start, end)),
+        }
+    }
+
+    axum::Json(FileAvailability {
+        file_size,
+        piece_length,
+        downloaded_bytes,
+        ranges,
+    }).into_response()
+}
+
+// Hashes the pieces covering one file. Pieces the session doesn't have yet are skipped, as
+// are pieces whose data in a neighbouring file can't be read.
+fn hash_check_file(
+    files: &[(PathBuf, u64, u64)],
+    file_index: usize,
+    piece_length: u64,
+    piece_hashes: &[u8],
+    have_pieces: &std::collections::HashSet<u64>,
+) -> Result<FileVerification> {
+    use sha1::{Digest, Sha1};
+    use std::collections::hash_map::Entry;
+    use std::io::{Read, Seek, SeekFrom};
+
+    let (_, file_offset, file_len) = files.get(file_index).cloned().context("File index out of range")?;
+    let total_length: u64 = files.iter().map(|(_, _, len)| len).sum();
+
+    let mut verification = FileVerification {
+        file_index,
+        total_pieces: 0,
+        verified_pieces: 0,
+        skipped_pieces: 0,
+        bad_pieces: Vec::new(),
+        ok: true,
+    };
+    if file_len == 0 || piece_length == 0 {
+        return Ok(verification);
+    }
+
+    let first_piece = file_offset / piece_length;
+    let last_piece = (file_offset + file_len - 1) / piece_length;
+    let mut open_files: HashMap<usize, std::fs::File> = HashMap::new();
+    let mut buffer: Vec<u8> = Vec::with_capacity(piece_length as usize);
+
+    'pieces: for piece in first_piece..=last_piece {
+        verification.total_pieces += 1;
+        if !have_pieces.contains(&piece) {
+            verification.skipped_pieces += 1;
+            continue;
+        }
+
+        let piece_start = piece * piece_length;
+        let piece_end = std::cmp::min(piece_start + piece_length, total_length);
+        buffer.clear();
+
+        for (index, (path, offset, len)) in files.iter().enumerate() {
+            let start = std::cmp::max(piece_start, *offset);
+            let end = std::cmp::min(piece_end, offset + len);
+            if start >= end {
+                continue;
             }
-            Err(_) => break,
+
+            let file = match open_files.entry(index) {
+                Entry::Occupied(e) => e.into_mut(),
+                Entry::Vacant(e) => match std::fs::File::open(path) {
+                    Ok(f) => e.insert(f),
+                    Err(_) => {
+                        verification.skipped_pieces += 1;
+                        continue 'pieces;
+                    }
+                },
+            };
+
+            let filled = buffer.len();
+            buffer.resize(filled + (end - start) as usize, 0);
+            let read = file.seek(SeekFrom::Start(start - offset))
+                .and_then(|_| file.read_exact(&mut buffer[filled..]));
+            if read.is_err() {
+                verification.skipped_pieces += 1;
+                continue 'pieces;
+            }
+        }
+
+        let hash_start = piece as usize * 20;
+        let expected = piece_hashes.get(hash_start..hash_start + 20);
+        if expected == Some(Sha1::digest(&buffer).as_slice()) {
+            verification.verified_pieces += 1;
+        } else {
+            verification.bad_pieces.push(piece as usize);
         }
     }
-    
-    temp_file.sync_all().await.ok();
-    drop(temp_file);
 
-    // Extract subtitle using ffmpeg
-    let mut cmd = Command::new("ffmpeg");
-    cmd.args(&[
-            "-i", temp_file_path.to_str().unwrap(),
-            "-map", &format!("0:s:{}", track_index),
-            "-f", "ass",
-            "-"
-        ]);
+    verification.ok = verification.bad_pieces.is_empty();
+    Ok(verification)
+}
 
-    #[cfg(target_os = "windows")]
-    cmd.creation_flags(0x08000000);
+/// Formats a subtitle or audio track can be extracted in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TrackFormat {
+    Srt,
+    Ass,
+    Vtt,
+    // Audio remuxed into Matroska as is
+    Mka,
+    // Audio encoded with the normalization and downmix from settings
+    Aac,
+}
 
-    let output = match cmd.output().await {
-            Ok(out) => out,
-            Err(e) => {
-                tracing::error!("Failed to run ffmpeg: {}", e);
-                let _ = tokio::fs::remove_file(&temp_file_path).await;
-                return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to extract subtitle").into_response();
+impl TrackFormat {
+    fn extension(self) -> &'static str {
+        match self {
+            TrackFormat::Srt => "srt",
+            TrackFormat::Ass => "ass",
+            TrackFormat::Vtt => "vtt",
+            TrackFormat::Mka => "mka",
+            TrackFormat::Aac => "aac",
+        }
+    }
+
+    fn mime_type(self) -> &'static str {
+        match self {
+            TrackFormat::Srt => "application/x-subrip",
+            TrackFormat::Ass => "text/x-ssa",
+            TrackFormat::Vtt => "text/vtt",
+            TrackFormat::Mka => "audio/x-matroska",
+            TrackFormat::Aac => "audio/aac",
+        }
+    }
+
+    fn is_subtitle(self) -> bool {
+        matches!(self, TrackFormat::Srt | TrackFormat::Ass | TrackFormat::Vtt)
+    }
+
+    fn track_type(self) -> TrackType {
+        if self.is_subtitle() { TrackType::Subtitle } else { TrackType::Audio }
+    }
+
+    // ffmpeg output options once the track is mapped
+    fn output_args(self) -> Vec<String> {
+        let args: &[&str] = match self {
+            TrackFormat::Srt => &["-f", "srt"],
+            TrackFormat::Ass => &["-f", "ass"],
+            TrackFormat::Vtt => &["-f", "webvtt"],
+            TrackFormat::Mka => &["-c:a", "copy", "-f", "matroska"],
+            TrackFormat::Aac => {
+                let mut args = aac_encode_args();
+                args.extend(["-f".to_string(), "adts".to_string()]);
+                return args;
             }
         };
+        args.iter().map(|a| a.to_string()).collect()
+    }
+}
 
-    let _ = tokio::fs::remove_file(&temp_file_path).await;
+/// Response body streaming an ffmpeg child's output. The child lives as long as the body,
+/// so when the client disconnects and axum drops it, ffmpeg (spawned with kill_on_drop) is
+/// killed instead of running to the end, e.g. on every seek. Its transcode slot, if it
+/// holds one, is freed along with it.
+///
+/// Waits for the first bytes before answering, so a child that fails without output,
+/// e.g. on a track that isn't there, becomes an error carrying its stderr rather than an
+/// empty 200. A child that produces nothing within `FIRST_OUTPUT_TIMEOUT` is killed so it
+/// can't hold its transcode slot forever.
+async fn child_output_body(
+    mut child: ffmpeg_tools::ToolChild,
+    stdout: tokio::process::ChildStdout,
+    job: Option<TranscodeJob>,
+) -> Result<Body, StreamError> {
+    use futures::StreamExt;
+    let stderr = child.stderr.take().map(|stderr| tokio::spawn(stderr_tail(stderr)));
+    let mut output = tokio_util::io::ReaderStream::new(stdout);
+    let Ok(first) = tokio::time::timeout(FIRST_OUTPUT_TIMEOUT, output.next()).await else {
+        let _ = child.start_kill();
+        let _ = child.wait().await;
+        let stderr = match stderr {
+            Some(task) => task.await.unwrap_or_default(),
+            None => String::new(),
+        };
+        tracing::error!("ffmpeg produced no output in {}s, killed it", FIRST_OUTPUT_TIMEOUT.as_secs());
+        return Err(StreamError::ffmpeg("ffmpeg produced no output in time", &stderr));
+    };
+    match first {
+        Some(Ok(first)) => {
+            let stream = futures::stream::once(async move { Ok(first) }).chain(output).map(move |chunk| {
+                let _held = (&child, &job);
+                chunk
+            });
+            Ok(Body::from_stream(stream))
+        }
+        first => {
+            if first.is_some() {
+                let _ = child.start_kill();
+            }
+            let succeeded = child.wait().await.is_ok_and(|status| status.success());
+            if first.is_none() && succeeded {
+                // Nothing to output, e.g. an empty subtitle track
+                return Ok(Body::empty());
+            }
+            let stderr = match stderr {
+                Some(task) => task.await.unwrap_or_default(),
+                None => String::new(),
+            };
+            tracing::error!("ffmpeg failed before producing output: {}", stderr);
+            let message = match first {
+                Some(Err(e)) => format!("Failed to read ffmpeg output: {}", e),
+                _ => "ffmpeg exited without producing output".to_string(),
+            };
+            Err(StreamError::ffmpeg(message, &stderr))
+        }
+    }
+}
+
+// Reads a child's stderr to the end, keeping about the last STDERR_EXCERPT_BYTES. Running
+// all along also keeps ffmpeg from blocking on a full pipe.
+async fn stderr_tail(mut stderr: tokio::process::ChildStderr) -> String {
+    let mut tail = Vec::new();
+    let mut buffer = [0u8; 4096];
+    while let Ok(n) = stderr.read(&mut buffer).await {
+        if n == 0 {
+            break;
+        }
+        tail.extend_from_slice(&buffer[..n]);
+        if tail.len() > STDERR_EXCERPT_BYTES * 2 {
+            tail.drain(..tail.len() - STDERR_EXCERPT_BYTES);
+        }
+    }
+    String::from_utf8_lossy(&tail).to_string()
+}
 
+/// Pulls one subtitle or audio track out of `input` with ffmpeg. The extraction commands
+/// and the subtitle routes all go through here. ffmpeg is killed if the caller stops
+/// waiting, e.g. when a subtitle request's client goes away.
+async fn extract_track(input: &std::path::Path, track_index: usize, format: TrackFormat) -> Result<Vec<u8>, String> {
+    let stream_type = if format.is_subtitle() { "s" } else { "a" };
+    let mut cmd = ffmpeg_tools::ffmpeg_command();
+    cmd.arg("-i")
+        .arg(input)
+        .args(["-map", &format!("0:{}:{}", stream_type, track_index)])
+        .args(format.output_args())
+        .arg("-");
+
+    let output = ffmpeg_tools::output(&mut cmd)
+        .await
+        .map_err(|e| format!("Failed to run ffmpeg: {}", e))?;
     if !output.status.success() {
         let stderr = String::from_utf8_lossy(&output.stderr);
-        tracing::error!("ffmpeg subtitle extraction failed: {}", stderr);
-        return (StatusCode::INTERNAL_SERVER_ERROR, "Subtitle extraction failed").into_response();
+        tracing::error!("ffmpeg track extraction failed: {}", stderr);
+        return Err(format!("Track extraction failed: {}", stderr));
     }
+    tracing::info!("Extracted track {} as {:?}, {} bytes", track_index, format, output.stdout.len());
+    Ok(output.stdout)
+}
 
-    Response::builder()
+/// Extracts several tracks in one ffmpeg pass, one output per track, so the file is only
+/// read once. Returns the tracks' data in the order given.
+async fn extract_tracks(input: &std::path::Path, tracks: &[(usize, TrackFormat)]) -> Result<Vec<Vec<u8>>, String> {
+    let id = EXTRACTION_COUNTER.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+    let outputs: Vec<PathBuf> = tracks
+        .iter()
+        .map(|(track_index, format)| std::env::temp_dir().join(format!("magnolia_batch_{}_{}.{}", id, track_index, format.extension())))
+        .collect();
+
+    let mut cmd = ffmpeg_tools::ffmpeg_command();
+    cmd.args(["-y", "-i"]).arg(input);
+    for ((track_index, format), output) in tracks.iter().zip(&outputs) {
+        let stream_type = if format.is_subtitle() { "s" } else { "a" };
+        cmd.args(["-map", &format!("0:{}:{}", stream_type, track_index)])
+            .args(format.output_args())
+            .arg(output);
+    }
+
+    let output = ffmpeg_tools::output(&mut cmd)
+        .await
+        .map_err(|e| format!("Failed to run ffmpeg: {}", e));
+    let mut data = Vec::with_capacity(outputs.len());
+    for path in &outputs {
+        data.push(tokio::fs::read(path).await.unwrap_or_default());
+        let _ = tokio::fs::remove_file(path).await;
+    }
+
+    let output = output?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        tracing::error!("ffmpeg batch extraction failed: {}", stderr);
+        return Err(format!("Track extraction failed: {}", stderr));
+    }
+    tracing::info!("Extracted {} tracks in one pass", tracks.len());
+    Ok(data)
+}
+
+// Tells concurrent extractions of the same file apart
+static EXTRACTION_COUNTER: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(0);
+
+/// The file ffmpeg extracts tracks from: the download itself once it's complete, otherwise
+/// a temp copy of up to `limit` bytes read from the torrent (all of it when None)
+struct ExtractionSource {
+    path: PathBuf,
+    temp: bool,
+    // Whether the whole file is there, rather than its start
+    complete: bool,
+}
+
+impl ExtractionSource {
+    async fn open(
+        handle: &librqbit::ManagedTorrentHandle,
+        download_dir: &std::path::Path,
+        session_id: usize,
+        file_index: usize,
+        limit: Option<u64>,
+    ) -> Result<Self, String> {
+        let (relative_path, file_size) = handle.with_metadata(|meta| {
+            meta.file_infos.get(file_index).map(|fi| (fi.relative_filename.clone(), fi.len))
+        }).map_err(|e| e.to_string())?
+            .ok_or("File index out of range")?;
+
+        let path = download_dir.join(&relative_path);
+        if tokio::fs::metadata(&path).await.map(|m| m.len() == file_size).unwrap_or(false) {
+            return Ok(Self { path, temp: false, complete: true });
+        }
+
+        let mut reader = handle.stream(file_index)
+            .map_err(|e| format!("Failed to create stream: {}", e))?;
+        let id = EXTRACTION_COUNTER.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        let temp_path = std::env::temp_dir().join(format!("magnolia_extract_{}_{}_{}.mkv", session_id, file_index, id));
+        let mut temp_file = tokio::fs::File::create(&temp_path).await
+            .map_err(|e| format!("Failed to create temp file: {}", e))?;
+        let source = Self { path: temp_path, temp: true, complete: false };
+
+        let limit = limit.unwrap_or(file_size);
+        tracing::info!("Reading {} of {} bytes from the torrent for extraction", limit.min(file_size), file_size);
+        let mut buffer = vec![0u8; 1024 * 1024];
+        let mut total_read = 0u64;
+        while total_read < limit {
+            match reader.read(&mut buffer).await {
+                Ok(0) => break,
+                Ok(n) => {
+                    if let Err(e) = tokio::io::AsyncWriteExt::write_all(&mut temp_file, &buffer[..n]).await {
+                        drop(temp_file);
+                        source.cleanup().await;
+                        return Err(format!("Failed to write temp file: {}", e));
+                    }
+                    total_read += n as u64;
+                }
+                // A partial read still has whatever subtitles it got; a full one must be whole
+                Err(e) if limit < file_size => {
+                    tracing::warn!("Error reading stream for extraction: {}, have {} bytes", e, total_read);
+                    break;
+                }
+                Err(e) => {
+                    drop(temp_file);
+                    source.cleanup().await;
+                    return Err(format!("Failed to read stream: {}", e));
+                }
+            }
+        }
+        let _ = temp_file.sync_all().await;
+
+        Ok(Self { complete: total_read >= file_size, ..source })
+    }
+
+    async fn cleanup(self) {
+        if self.temp {
+            let _ = tokio::fs::remove_file(&self.path).await;
+        }
+    }
+}
+
+// Retry hint for subtitles cut short by missing pieces
+const SUBTITLE_RETRY_SECS: u64 = 30;
+// Dialogue ending before this share of the video, read from an incomplete file, is
+// taken as cut short rather than a track that just ends early
+const SUBTITLE_COVERAGE_RATIO: f64 = 0.8;
+
+/// How usable an extracted ASS track is
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SubtitleStatus {
+    Complete,
+    // Has dialogue, but stops well before the end of a file that wasn't fully read
+    Partial,
+    // Valid, but no dialogue lines
+    Empty,
+    // Missing the sections or styles every ASS file has, e.g. truncated output
+    Invalid,
+}
+
+struct AssCheck {
+    status: SubtitleStatus,
+    dialogue_lines: usize,
+    // End of the last dialogue line in seconds
+    covered_until: Option<f64>,
+}
+
+// "0:01:02.50" -> 62.5
+fn parse_ass_time(time: &str) -> Option<f64> {
+    let mut parts = time.trim().split(':');
+    let hours: f64 = parts.next()?.parse().ok()?;
+    let minutes: f64 = parts.next()?.parse().ok()?;
+    let seconds: f64 = parts.next()?.parse().ok()?;
+    Some(hours * 3600.0 + minutes * 60.0 + seconds)
+}
+
+/// Checks ffmpeg's ASS output: [Script Info], styles and [Events] must be there, dialogue
+/// must not be empty, and when the file was only partly read the last line is compared
+/// with the video's duration
+fn check_ass(data: &[u8], source_complete: bool, duration: Option<f64>) -> AssCheck {
+    let text = String::from_utf8_lossy(data);
+    let text = text.trim_start_matches('\u{feff}');
+    let has_script_info = text.contains("[Script Info]");
+    let has_events = text.contains("[Events]");
+    // SSA names the section [V4 Styles], ASS [V4+ Styles]
+    let styles = text.lines().filter(|l| l.starts_with("Style:")).count();
+
+    let mut dialogue_lines = 0;
+    let mut covered_until: Option<f64> = None;
+    for line in text.lines() {
+        let Some(fields) = line.strip_prefix("Dialogue:") else { continue };
+        dialogue_lines += 1;
+        // Layer, Start, End, ...
+        if let Some(end) = fields.split(',').nth(2).and_then(parse_ass_time) {
+            covered_until = Some(covered_until.map_or(end, |c| c.max(end)));
+        }
+    }
+
+    let status = if !has_script_info || !has_events || styles == 0 {
+        SubtitleStatus::Invalid
+    } else if dialogue_lines == 0 {
+        SubtitleStatus::Empty
+    } else {
+        match (source_complete, duration, covered_until) {
+            (false, Some(duration), Some(until)) if until < duration * SUBTITLE_COVERAGE_RATIO => SubtitleStatus::Partial,
+            _ => SubtitleStatus::Complete,
+        }
+    };
+    AssCheck { status, dialogue_lines, covered_until }
+}
+
+/// Serves checked ASS output. Partial tracks are still sent, flagged with a
+/// `Retry-After`; when nothing usable came out of a file that isn't complete yet, 503
+/// tells the player to try again later rather than show nothing. `downloaded_until` goes
+/// out as `X-Subtitle-Downloaded-Until`, where a windowed request's next `?since=` should
+/// start.
+fn ass_response(data: Vec<u8>, check: &AssCheck, source_complete: bool, downloaded_until: Option<f64>) -> Response {
+    tracing::info!("Subtitle check: {:?}, {} dialogue lines, covered until {:?}s", check.status, check.dialogue_lines, check.covered_until);
+    let retry = match check.status {
+        SubtitleStatus::Complete => false,
+        SubtitleStatus::Partial => true,
+        SubtitleStatus::Empty | SubtitleStatus::Invalid if !source_complete => {
+            return StreamError::unavailable("subtitle_not_downloaded", "Subtitle data isn't downloaded yet", Some(SUBTITLE_RETRY_SECS))
+                .into_response();
+        }
+        // The whole file was read, so an empty track really is empty
+        SubtitleStatus::Empty => false,
+        SubtitleStatus::Invalid => {
+            return StreamError::internal("invalid_subtitle", "Extracted subtitle is not valid ASS").into_response();
+        }
+    };
+
+    let mut response = Response::builder()
         .status(StatusCode::OK)
-        .header(header::CONTENT_TYPE, "text/x-ssa")
-        .body(Body::from(output.stdout))
-        .unwrap()
+        .header(header::CONTENT_TYPE, "text/x-ssa");
+    if retry {
+        response = response.header(header::RETRY_AFTER, SUBTITLE_RETRY_SECS.to_string());
+    }
+    if let Some(until) = downloaded_until {
+        response = response.header("X-Subtitle-Downloaded-Until", format!("{:.2}", until));
+    }
+    response.body(Body::from(data)).unwrap().into_response()
 }
 
-async fn stream_file(
-    Path((session_id, file_id)): Path<(usize, usize)>,
-    headers: HeaderMap,
+// How much of a file a request for the whole track reads, enough for the subtitle data
+// of a feature-length file
+const SUBTITLE_FULL_READ_BYTES: u64 = 500 * 1024 * 1024;
+
+// Share of the bitrate estimate of how far the downloaded start of a file plays that
+// is trusted, since bitrate varies over a file. Lines past it come with the next fetch.
+const SUBTITLE_DOWNLOADED_MARGIN: f64 = 0.9;
+
+/// `?since=` and `?until=` in seconds. Without either the whole track is extracted, as
+/// before; with one, only the downloaded start of the file is read and only dialogue
+/// starting in that window is returned, so a player can fetch the lines of what's
+/// downloaded so far and append later ones as the download goes on. `?format=srt|vtt` converts the track instead of serving ASS;
+/// the window only applies to ASS.
+#[derive(Debug, Deserialize)]
+struct SubtitleWindow {
+    since: Option<f64>,
+    until: Option<f64>,
+    format: Option<TrackFormat>,
+}
+
+/// Serves a track converted to SRT or WebVTT with its MIME type. ffmpeg writes these
+/// line by line, so unlike ASS there's no structure to check; a read cut short by the
+/// download is flagged the same way.
+fn text_subtitle_response(data: Vec<u8>, format: TrackFormat, source_complete: bool) -> Response {
+    let mut response = Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, format!("{}; charset=utf-8", format.mime_type()));
+    if !source_complete {
+        response = response.header(header::RETRY_AFTER, SUBTITLE_RETRY_SECS.to_string());
+    }
+    response.body(Body::from(data)).unwrap().into_response()
+}
+
+// Keeps the header and styles, and the dialogue lines starting in `since..until`
+fn filter_ass_dialogue(data: &[u8], since: f64, until: f64) -> Vec<u8> {
+    let text = String::from_utf8_lossy(data);
+    let mut filtered = String::with_capacity(text.len());
+    for line in text.lines() {
+        let keep = match line.strip_prefix("Dialogue:") {
+            // Layer, Start, End, ...
+            Some(fields) => fields.split(',').nth(1)
+                .and_then(parse_ass_time)
+                .is_some_and(|start| start >= since && start < until),
+            None => true,
+        };
+        if keep {
+            filtered.push_str(line);
+            filtered.push('\n');
+        }
+    }
+    filtered.into_bytes()
+}
+
+async fn get_subtitle_track(
+    Path((session_id, file_id, track_index)): Path<(usize, usize, usize)>,
+    axum::extract::Query(window): axum::extract::Query<SubtitleWindow>,
     axum::extract::State(state): axum::extract::State<AppState>,
 ) -> impl IntoResponse {
-    use std::io::SeekFrom;
-    use tokio_util::io::ReaderStream;
+    tracing::info!("Subtitle request: session={}, file={}, track={}, window={:?}", session_id, file_id, track_index, window);
+    
+    let format = window.format.unwrap_or(TrackFormat::Ass);
+    if !format.is_subtitle() {
+        return (StatusCode::BAD_REQUEST, "Not a subtitle format").into_response();
+    }
 
     let handle = match state.session.get(TorrentIdOrHash::Id(session_id)) {
         Some(h) => h,
         None => return (StatusCode::NOT_FOUND, "Torrent not found").into_response(),
     };
 
-    let file_size = match handle.with_metadata(|meta| {
-        meta.file_infos.get(file_id).map(|f| f.len)
-    }) {
-        Ok(Some(size)) => size,
-        _ => return (StatusCode::NOT_FOUND, "File not found").into_response(),
-    };
-
-    let range = headers.get(header::RANGE).and_then(|v| v.to_str().ok());
-    
-    let (start, end, status_code) = if let Some(range_str) = range {
-        if let Some(range_values) = range_str.strip_prefix("bytes=") {
-            let parts: Vec<&str> = range_values.split('-').collect();
-            let start = parts[0].parse::<u64>().unwrap_or(0);
-            let end = if parts.len() > 1 && !parts[1].is_empty() {
-                parts[1].parse::<u64>().unwrap_or(file_size - 1).min(file_size - 1)
-            } else {
-                file_size - 1
-            };
-            (start, end, StatusCode::PARTIAL_CONTENT)
-        } else {
-            (0, file_size - 1, StatusCode::OK)
-        }
+    // A windowed request only reads the downloaded start of the file, so it never waits on
+    // the swarm, and re-fetches from X-Subtitle-Downloaded-Until as more comes in
+    let windowed = window.since.is_some() || window.until.is_some();
+    let (prefix, file_size) = if windowed {
+        downloaded_prefix(&handle, file_id)
     } else {
-        (0, file_size - 1, StatusCode::OK)
+        (SUBTITLE_FULL_READ_BYTES, 0)
     };
-
-    let mut stream = match handle.stream(file_id) {
-        Ok(s) => s,
+    let source = match ExtractionSource::open(&handle, &state.download_dir, session_id, file_id, Some(prefix)).await {
+        Ok(source) => source,
         Err(e) => {
-            tracing::error!("Failed to create stream for file_id {}: {}", file_id, e);
-            return (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to stream: {}", e)).into_response();
+            tracing::error!("Failed to read file for subtitles: {}", e);
+            return StreamError::internal("source_unavailable", e).into_response();
         }
     };
+    let source_complete = source.complete;
+    let subtitle = if source_complete || prefix > 0 {
+        extract_track(&source.path, track_index, format).await
+    } else {
+        // Nothing downloaded yet; an empty track gets the retry response below
+        Ok(Vec::new())
+    };
+    source.cleanup().await;
 
-    if start > 0 {
-        if let Err(e) = stream.seek(SeekFrom::Start(start)).await {
-            tracing::error!("Failed to seek stream to {}: {}", start, e);
-            return (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to seek: {}", e)).into_response();
+    match subtitle {
+        Ok(data) if format != TrackFormat::Ass => {
+            if data.is_empty() && !source_complete {
+                return StreamError::unavailable("subtitle_not_downloaded", "Subtitle data isn't downloaded yet", Some(SUBTITLE_RETRY_SECS))
+                    .into_response();
+            }
+            text_subtitle_response(data, format, source_complete)
+        }
+        Ok(data) => {
+            let duration = state.streams.duration((session_id, file_id)).await;
+            let downloaded_until = match (source_complete, duration) {
+                (false, Some(duration)) if windowed && file_size > 0 => {
+                    Some(duration * (prefix as f64 / file_size as f64) * SUBTITLE_DOWNLOADED_MARGIN)
+                }
+                _ => None,
+            };
+            let until = match (window.until, downloaded_until) {
+                (Some(a), Some(b)) => Some(a.min(b)),
+                (a, b) => a.or(b),
+            };
+            let data = if windowed {
+                filter_ass_dialogue(&data, window.since.unwrap_or(0.0), until.unwrap_or(f64::INFINITY))
+            } else {
+                data
+            };
+            // A window the player asked for ends where it said, not at the end of the video
+            let expected_end = match (window.until, duration) {
+                (Some(until), Some(duration)) => Some(until.min(duration)),
+                (until, duration) => until.or(duration),
+            };
+            let check = check_ass(&data, source_complete, expected_end);
+            ass_response(data, &check, source_complete, downloaded_until)
         }
+        Err(e) => StreamError::ffmpeg("Subtitle extraction failed", &e).into_response(),
     }
+}
 
-    let content_length = end - start + 1;
-    let limited_stream = stream.take(content_length);
-    
-    let reader_stream = ReaderStream::new(limited_stream);
-    let body = Body::from_stream(reader_stream);
+// Bytes around a chapter's estimated offset that must be downloaded before a thumbnail
+// is cut there, so thumbnails never make the swarm fetch pieces out of order
+const CHAPTER_THUMB_WINDOW_BYTES: u64 = 4 * 1024 * 1024;
+// The container header is needed too for ffmpeg to open the file
+const CHAPTER_THUMB_HEADER_BYTES: u64 = 2 * 1024 * 1024;
+const CHAPTER_THUMB_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(20);
 
-    let mut response = Response::builder()
-        .status(status_code)
-        .header(header::CONTENT_TYPE, "video/x-matroska")
-        .header(header::CONTENT_LENGTH, content_length.to_string())
-        .header(header::ACCEPT_RANGES, "bytes");
-    
-    if status_code == StatusCode::PARTIAL_CONTENT {
-        let content_range = format!("bytes {}-{}/{}", start, end, file_size);
-        response = response.header(header::CONTENT_RANGE, content_range);
+// Whether every piece covering `start..end` of the file has been downloaded
+fn file_range_downloaded(handle: &librqbit::ManagedTorrent, file_id: usize, start: u64, end: u64) -> bool {
+    let layout = handle.with_metadata(|meta| {
+        meta.file_infos.get(file_id).map(|f| (f.offset_in_torrent, f.len, meta.lengths.default_piece_length() as u64))
+    });
+    let Ok(Some((file_offset, file_size, piece_length))) = layout else {
+        return false;
+    };
+    let end = end.min(file_size);
+    if piece_length == 0 || start >= end {
+        return false;
     }
 
-    response.body(body).unwrap().into_response()
+    let first_piece = (file_offset + start) / piece_length;
+    let last_piece = (file_offset + end - 1) / piece_length;
+    handle
+        .with_chunk_tracker(|chunks| {
+            let have = chunks.get_have_pieces();
+            (first_piece..=last_piece).all(|p| have.get(p as usize).map(|b| *b).unwrap_or(false))
+        })
+        .unwrap_or(false)
 }
 
-impl TorrentManager {
-    pub async fn new(download_dir: PathBuf) -> Result<Self> {
-        println!("initializing TorrentManager with download_dir: {:?}", download_dir);
-        
-        if let Err(e) = std::fs::create_dir_all(&download_dir) {
-            eprintln!("failed to create download directory: {}", e);
-            return Err(e.into());
-        }
+// Copies downloaded `ranges` of a file, one after the other, into `destination`
+async fn write_file_ranges(
+    handle: &librqbit::ManagedTorrentHandle,
+    file_id: usize,
+    ranges: &[(u64, u64)],
+    destination: &std::path::Path,
+) -> Result<(), String> {
+    use tokio::io::AsyncWriteExt;
+    let mut stream = handle.clone().stream(file_id).map_err(|e| format!("Failed to stream: {}", e))?;
+    let mut file = tokio::fs::File::create(destination).await
+        .map_err(|e| format!("Failed to create temp file: {}", e))?;
+    for &(start, end) in ranges {
+        stream.seek(std::io::SeekFrom::Start(start)).await.map_err(|e| e.to_string())?;
+        let mut range = (&mut stream).take(end.saturating_sub(start));
+        tokio::io::copy(&mut range, &mut file).await
+            .map_err(|e| format!("Failed to write temp file: {}", e))?;
+    }
+    file.flush().await.map_err(|e| e.to_string())
+}
 
-        // Create session with default options
-        println!("creating librqbit session...");
-        let session = match Session::new(download_dir.clone()).await {
-            Ok(s) => {
-                println!("librqbit session created successfully");
-                s
-            }
-            Err(e) => {
-                eprintln!("failed to create librqbit session: {}", e);
-                return Err(anyhow::anyhow!("Failed to create librqbit session: {}", e));
+// Bytes from the start of the file that are downloaded without a gap, and the file's size
+fn downloaded_prefix(handle: &librqbit::ManagedTorrent, file_id: usize) -> (u64, u64) {
+    let layout = handle.with_metadata(|meta| {
+        meta.file_infos.get(file_id).map(|f| (f.offset_in_torrent, f.len, meta.lengths.default_piece_length() as u64))
+    });
+    let Ok(Some((file_offset, file_size, piece_length))) = layout else {
+        return (0, 0);
+    };
+    if piece_length == 0 || file_size == 0 {
+        return (0, file_size);
+    }
+
+    let first_piece = file_offset / piece_length;
+    let last_piece = (file_offset + file_size - 1) / piece_length;
+    let prefix = handle
+        .with_chunk_tracker(|chunks| {
+            let have = chunks.get_have_pieces();
+            let missing = (first_piece..=last_piece).find(|p| !have.get(*p as usize).map(|b| *b).unwrap_or(false));
+            match missing {
+                Some(piece) => (piece * piece_length).saturating_sub(file_offset),
+                None => file_size,
             }
-        };
+        })
+        .unwrap_or(0);
+    (prefix.min(file_size), file_size)
+}
 
-        let torrents = Arc::new(RwLock::new(HashMap::new()));
-        let next_id = Arc::new(RwLock::new(0));
+// Small JPEG at a chapter's start for the chapter picker. Only cut from data that's
+// already downloaded, read straight from those pieces so ffmpeg never asks the stream
+// for more; otherwise 404 so the picker shows the title alone. Also 404 with the
+// chapter_thumbnails setting off.
+async fn get_chapter_thumbnail(
+    Path((session_id, file_id, chapter_index)): Path<(usize, usize, usize)>,
+    axum::extract::State(state): axum::extract::State<AppState>,
+) -> impl IntoResponse {
+    if !CHAPTER_THUMBNAILS.load(std::sync::atomic::Ordering::Relaxed) {
+        return (StatusCode::NOT_FOUND, "Chapter thumbnails are turned off").into_response();
+    }
 
-        // Note: We don't load existing torrents from session since we store URLs separately
-        // and only add them to session when streaming starts
-        tracing::info!("TorrentManager initialized");
+    let jpeg = |data: Vec<u8>| {
+        Response::builder()
+            .status(StatusCode::OK)
+            .header(header::CONTENT_TYPE, "image/jpeg")
+            .header(header::CACHE_CONTROL, "max-age=3600")
+            .body(Body::from(data))
+            .unwrap()
+            .into_response()
+    };
 
-        println!("binding HTTP server to localhost...");
-        let listener = match tokio::net::TcpListener::bind("127.0.0.1:0").await {
-            Ok(l) => {
-                println!("HTTP server listener created successfully");
-                l
-            }
-            Err(e) => {
-                eprintln!("failed to bind HTTP server: {}", e);
-                return Err(e.into());
-            }
-        };
-        let http_addr = listener.local_addr()?;
-        println!("HTTP server will run on: {}", http_addr);
-        
-        let transcode_states: Arc<RwLock<HashMap<(usize, usize), TranscodeState>>> = 
-            Arc::new(RwLock::new(HashMap::new()));
-        let metadata_cache: Arc<RwLock<HashMap<(usize, usize), MkvMetadata>>> =
-            Arc::new(RwLock::new(HashMap::new()));
+    if let Some(data) = state.chapter_thumbnails.read().await.get(&(session_id, file_id, chapter_index)) {
+        return jpeg(data.clone());
+    }
 
-        let state = AppState {
-            session: session.clone(),
-            transcode_states: transcode_states.clone(),
-            metadata_cache: metadata_cache.clone(),
-            download_dir: download_dir.clone(),
-        };
+    let (chapter, duration) = match state.streams.metadata((session_id, file_id)).await {
+        Some(m) => (m.chapters.get(chapter_index).cloned(), m.duration),
+        None => (None, None),
+    };
+    let (Some(chapter), Some(duration)) = (chapter, duration.filter(|d| *d > 0.0)) else {
+        return (StatusCode::NOT_FOUND, "Chapter not found").into_response();
+    };
 
-        let app = Router::new()
-            .route("/torrents/{session_id}/stream/{file_id}", get(stream_file))
-            .route("/torrents/{session_id}/metadata/{file_id}", get(get_file_metadata))
-            .route("/torrents/{session_id}/subtitles/{file_id}/{track_index}", get(get_subtitle_track))
-            .route("/torrents/{session_id}/srt-stream/{file_id}/{track_index}", get(stream_srt_subtitles))
-            .route("/torrents/{session_id}/transcoded-audio-stream/{file_id}/{track_index}", get(stream_transcoded_audio))
-            .route("/torrents/{session_id}/transcoded-audio-stream/{file_id}", get(stream_transcoded_audio_default))
-            .route("/torrents/{session_id}/transcoded-audio/{file_id}", get(serve_transcoded_audio))
-            .route("/fonts/{filename}", get(serve_font))
-            .layer(CorsLayer::permissive())
-            .with_state(state);
+    let handle = match state.session.get(TorrentIdOrHash::Id(session_id)) {
+        Some(h) => h,
+        None => return (StatusCode::NOT_FOUND, "Torrent not found").into_response(),
+    };
+    let Ok(Some(file_size)) = handle.with_metadata(|meta| meta.file_infos.get(file_id).map(|f| f.len)) else {
+        return (StatusCode::NOT_FOUND, "File not found").into_response();
+    };
 
-        tokio::spawn(async move {
-            axum::serve(listener, app).await.ok();
-        });
+    // A little past the chapter mark, where fades from black are usually over
+    let seek_time = chapter.start_time + ((chapter.end_time - chapter.start_time) / 2.0).clamp(0.0, 2.0);
+    let estimated_offset = ((seek_time / duration).clamp(0.0, 1.0) * file_size as f64) as u64;
+    let window_start = estimated_offset.saturating_sub(CHAPTER_THUMB_WINDOW_BYTES / 2);
+    if !file_range_downloaded(&handle, file_id, 0, CHAPTER_THUMB_HEADER_BYTES)
+        || !file_range_downloaded(&handle, file_id, window_start, window_start + CHAPTER_THUMB_WINDOW_BYTES)
+    {
+        return (StatusCode::NOT_FOUND, "Chapter not downloaded yet").into_response();
+    }
 
-        let manager = Self {
-            session,
-            download_dir,
-            torrents,
-            next_id,
-            http_addr,
-            transcode_states,
-            metadata_cache,
-            torrent_cache: Arc::new(RwLock::new(Vec::new())),
-        };
-        
-        // Load cached torrents from disk
-        if let Err(e) = manager.load_cache_from_disk().await {
-            tracing::warn!("Failed to load torrent cache from disk: {}", e);
+    // The header followed by the window; the demuxer resyncs on the first cluster in it
+    let source_path = std::env::temp_dir().join(format!("magnolia_chapter_{}_{}_{}.mkv", session_id, file_id, chapter_index));
+    let window_end = (window_start + CHAPTER_THUMB_WINDOW_BYTES).min(file_size);
+    let ranges = [(0, CHAPTER_THUMB_HEADER_BYTES.min(file_size)), (window_start, window_end)];
+    if let Err(e) = write_file_ranges(&handle, file_id, &ranges, &source_path).await {
+        let _ = tokio::fs::remove_file(&source_path).await;
+        return StreamError::internal("temp_file_failed", e).into_response();
+    }
+
+    let mut cmd = ffmpeg_tools::ffmpeg_command();
+    cmd.args(["-v", "error", "-skip_frame", "nokey", "-i"])
+        .arg(&source_path)
+        .args([
+            "-frames:v", "1",
+            "-vf", "scale=320:-2",
+            "-q:v", "5",
+            "-f", "image2",
+            "-c:v", "mjpeg",
+            "pipe:1",
+        ]);
+
+    let result = tokio::time::timeout(CHAPTER_THUMB_TIMEOUT, ffmpeg_tools::output(&mut cmd)).await;
+    let _ = tokio::fs::remove_file(&source_path).await;
+    let output = match result {
+        Ok(Ok(output)) if output.status.success() && !output.stdout.is_empty() => output.stdout,
+        Ok(Ok(output)) => {
+            return StreamError::ffmpeg("Thumbnail extraction failed", &String::from_utf8_lossy(&output.stderr)).into_response();
         }
-        
-        Ok(manager)
+        Ok(Err(e)) => {
+            tracing::error!("Failed to run ffmpeg: {}", e);
+            return StreamError::internal("ffmpeg_spawn_failed", "Failed to extract thumbnail").into_response();
+        }
+        // Usually the container index lives in a part that isn't downloaded
+        Err(_) => return StreamError::unavailable("thumbnail_timeout", "Thumbnail extraction timed out", None).into_response(),
+    };
+
+    state.chapter_thumbnails.write().await.insert((session_id, file_id, chapter_index), output.clone());
+    jpeg(output)
+}
+
+// How much of each audio track the loudness preview covers
+const AUDIO_PREVIEW_SECS: f64 = 180.0;
+// One loudness value per this many seconds
+const AUDIO_PREVIEW_INTERVAL_SECS: f64 = 0.5;
+// Decoded at a low rate, loudness doesn't need the highs
+const AUDIO_PREVIEW_SAMPLE_RATE: u32 = 8000;
+// Anything quieter reads as silence
+const AUDIO_PREVIEW_FLOOR_DB: f32 = -60.0;
+// Read cap when there's no duration to size the read from
+const AUDIO_PREVIEW_MAX_READ_BYTES: u64 = 300 * 1024 * 1024;
+
+/// Loudness envelope of one audio track
+#[derive(Debug, Clone, Serialize)]
+pub struct AudioTrackPreview {
+    pub index: usize,
+    pub language: Option<String>,
+    pub name: Option<String>,
+    pub codec: Option<String>,
+    // RMS level in dBFS for each interval from the start of the file
+    pub levels: Vec<f32>,
+    pub average_db: Option<f32>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AudioPreview {
+    pub interval_secs: f64,
+    // Less than the preview length while the start of the file is still downloading
+    pub covered_secs: f64,
+    pub tracks: Vec<AudioTrackPreview>,
+}
+
+fn mean_square_db(mean_square: f64) -> f32 {
+    if mean_square <= 0.0 {
+        return AUDIO_PREVIEW_FLOOR_DB;
     }
+    // 20 * log10(rms) == 10 * log10(mean square)
+    let db = ((10.0 * mean_square.log10()) as f32).max(AUDIO_PREVIEW_FLOOR_DB);
+    (db * 10.0).round() / 10.0
+}
 
-    pub async fn add_torrent(&self, magnet_or_url: String) -> Result<usize> {
-        tracing::info!("Adding torrent with list_only to fetch metadata: {}", magnet_or_url);
-        
-        let add_torrent = if magnet_or_url.starts_with("magnet:") {
-            AddTorrent::from_url(&magnet_or_url)
-        } else if magnet_or_url.starts_with("http") {
-            AddTorrent::from_url(&magnet_or_url)
-        } else {
-            AddTorrent::from_local_filename(&magnet_or_url)?
-        };
-        
-        let opts = AddTorrentOptions {
-            list_only: true,
-            ..Default::default()
-        };
-        
-        let response = self.session.add_torrent(add_torrent, Some(opts)).await?;
-        
-        // Extract session_id if it was added (shouldn't happen with list_only, but handle it)
-        let session_id = match response {
-            AddTorrentResponse::Added(id, _) | AddTorrentResponse::AlreadyManaged(id, _) => {
-                tracing::info!("Torrent was added to session with id: {}", id);
-                Some(id)
-            }
-            AddTorrentResponse::ListOnly(_) => {
-                tracing::info!("Got list-only response (metadata fetched)");
-                None
+// Mono f32le samples -> (level per interval, average level)
+fn loudness_envelope(pcm: &[u8]) -> (Vec<f32>, Option<f32>) {
+    let samples: Vec<f64> = pcm
+        .chunks_exact(4)
+        .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64)
+        .collect();
+    if samples.is_empty() {
+        return (Vec::new(), None);
+    }
+    let per_interval = (AUDIO_PREVIEW_SAMPLE_RATE as f64 * AUDIO_PREVIEW_INTERVAL_SECS) as usize;
+    let levels = samples
+        .chunks(per_interval)
+        .map(|window| mean_square_db(window.iter().map(|s| s * s).sum::<f64>() / window.len() as f64))
+        .collect();
+    let average = mean_square_db(samples.iter().map(|s| s * s).sum::<f64>() / samples.len() as f64);
+    (levels, Some(average))
+}
+
+// Loudness over the first minutes of every audio track, so the player can tell e.g. a
+// commentary track from the main mix before picking one to transcode. Decoded from the
+// downloaded start of the file only; `covered_secs` says how far that got.
+async fn get_audio_preview(
+    Path((session_id, file_id)): Path<(usize, usize)>,
+    axum::extract::State(state): axum::extract::State<AppState>,
+) -> impl IntoResponse {
+    if let Some(preview) = state.audio_previews.read().await.get(&(session_id, file_id)) {
+        return axum::Json(preview.clone()).into_response();
+    }
+
+    let Some(metadata) = state.streams.metadata((session_id, file_id)).await else {
+        return (StatusCode::NOT_FOUND, "Metadata not loaded").into_response();
+    };
+    if metadata.audio_tracks.is_empty() {
+        return (StatusCode::NOT_FOUND, "No audio tracks").into_response();
+    }
+    let duration = metadata.duration.filter(|d| *d > 0.0);
+
+    let handle = match state.session.get(TorrentIdOrHash::Id(session_id)) {
+        Some(h) => h,
+        None => return (StatusCode::NOT_FOUND, "Torrent not found").into_response(),
+    };
+    let (prefix, file_size) = downloaded_prefix(&handle, file_id);
+    let wanted = match duration {
+        Some(duration) => ((AUDIO_PREVIEW_SECS / duration).min(1.0) * file_size as f64) as u64,
+        None => AUDIO_PREVIEW_MAX_READ_BYTES,
+    };
+    if prefix == 0 {
+        return StreamError::unavailable("not_downloaded", "Audio isn't downloaded yet", Some(SUBTITLE_RETRY_SECS)).into_response();
+    }
+    let source = match ExtractionSource::open(&handle, &state.download_dir, session_id, file_id, Some(prefix.min(wanted))).await {
+        Ok(source) => source,
+        Err(e) => {
+            tracing::error!("Failed to read file for audio preview: {}", e);
+            return StreamError::internal("source_unavailable", e).into_response();
+        }
+    };
+
+    let id = EXTRACTION_COUNTER.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+    let outputs: Vec<PathBuf> = metadata.audio_tracks
+        .iter()
+        .map(|track| std::env::temp_dir().join(format!("magnolia_loudness_{}_{}.pcm", id, track.index)))
+        .collect();
+    let mut cmd = ffmpeg_tools::ffmpeg_command();
+    cmd.args(["-y", "-v", "error", "-i"]).arg(&source.path);
+    for (track, output) in metadata.audio_tracks.iter().zip(&outputs) {
+        cmd.args(["-map", &format!("0:a:{}", track.index)])
+            .args(["-t", &AUDIO_PREVIEW_SECS.to_string()])
+            .args(["-ac", "1", "-ar", &AUDIO_PREVIEW_SAMPLE_RATE.to_string(), "-f", "f32le"])
+            .arg(output);
+    }
+
+    let output = ffmpeg_tools::output(&mut cmd).await;
+    let source_complete = source.complete;
+    source.cleanup().await;
+    let mut pcm = Vec::with_capacity(outputs.len());
+    for path in &outputs {
+        pcm.push(tokio::fs::read(path).await.unwrap_or_default());
+        let _ = tokio::fs::remove_file(path).await;
+    }
+    match output {
+        Ok(output) if output.status.success() => {}
+        Ok(output) => {
+            // A cut-off read usually still decodes up to the cut
+            tracing::warn!("ffmpeg audio preview: {}", String::from_utf8_lossy(&output.stderr));
+        }
+        Err(e) => {
+            tracing::error!("Failed to run ffmpeg: {}", e);
+            return StreamError::internal("ffmpeg_spawn_failed", "Failed to decode audio").into_response();
+        }
+    }
+
+    let tracks: Vec<AudioTrackPreview> = metadata.audio_tracks
+        .iter()
+        .zip(&pcm)
+        .map(|(track, pcm)| {
+            let (levels, average_db) = loudness_envelope(pcm);
+            AudioTrackPreview {
+                index: track.index,
+                language: track.language.clone(),
+                name: track.name.clone(),
+                codec: track.codec.clone(),
+                levels,
+                average_db,
             }
-        };
-        
-        let mut id_lock = self.next_id.write().await;
-        let our_id = *id_lock;
-        *id_lock += 1;
-        drop(id_lock);
-        
-        let mut torrents = self.torrents.write().await;
-        torrents.insert(our_id, TorrentEntry {
-            magnet_url: magnet_or_url,
-            session_id,
-        });
-        
-        tracing::info!("Stored torrent with our_id: {}", our_id);
-        Ok(our_id)
+        })
+        .collect();
+    if tracks.iter().all(|t| t.levels.is_empty()) {
+        return StreamError::internal("audio_decode_failed", "Audio preview decoding failed").into_response();
     }
 
-    pub async fn get_torrent_info(&self, handle_id: usize) -> Result<TorrentInfo> {
-        let torrents = self.torrents.read().await;
-        let entry = torrents
-            .get(&handle_id)
-            .context("Torrent handle not found")?;
-        
-        // If not yet added to session, fetch metadata via list_only
-        if entry.session_id.is_none() {
-            let magnet_url = entry.magnet_url.clone();
-            drop(torrents);
-            
-            let add_torrent = if magnet_url.starts_with("magnet:") {
-                AddTorrent::from_url(&magnet_url)
-            } else if magnet_url.starts_with("http") {
-                AddTorrent::from_url(&magnet_url)
-            } else {
-                AddTorrent::from_local_filename(&magnet_url)?
-            };
-            
-            let opts = AddTorrentOptions {
-                list_only: true,
-                ..Default::default()
-            };
-            
-            let response = self.session.add_torrent(add_torrent, Some(opts)).await?;
-            
-            match response {
-                AddTorrentResponse::ListOnly(list_info) => {
-                    let files: Vec<TorrentFile> = list_info.info
-                        .iter_file_details()?
-                        .enumerate()
-                        .filter_map(|(index, detail)| {
-                            let filename_str = detail.filename.to_string().ok()?;
-                            let lower = filename_str.to_lowercase();
-                            if lower.ends_with(".mkv") || lower.ends_with(".mp4") || lower.ends_with(".avi") || lower.ends_with(".mov") {
-                                let pathbuf = detail.filename.to_pathbuf().ok()?;
-                                let name = pathbuf
-                                    .file_name()
-                                    .and_then(|n| n.to_str())
-                                    .unwrap_or("unknown")
-                                    .to_string();
-                                
-                                Some(TorrentFile {
-                                    index,
-                                    name,
-                                    size: detail.len,
-                                    path: filename_str,
-                                })
-                            } else {
-                                None
-                            }
-                        })
-                        .collect();
-                    
-                    let name = match &list_info.info.name {
-                        Some(n) => n.to_string(),
-                        None => "Unknown".to_string(),
-                    };
-                    
-                    return Ok(TorrentInfo {
-                        handle_id,
-                        name,
-                        size: files.iter().map(|f| f.size).sum(),
-                        files,
-                        progress: 0.0,
-                        download_speed: 0,
-                        upload_speed: 0,
-                        peers: 0,
-                        is_paused: true,
-                        state: "paused".to_string(),
-                    });
-                }
-                _ => {
-                    return Err(anyhow::anyhow!("Expected list_only response"));
+    let covered_secs = tracks.iter().map(|t| t.levels.len()).max().unwrap_or(0) as f64 * AUDIO_PREVIEW_INTERVAL_SECS;
+    let preview = AudioPreview {
+        interval_secs: AUDIO_PREVIEW_INTERVAL_SECS,
+        covered_secs: covered_secs.min(AUDIO_PREVIEW_SECS),
+        tracks,
+    };
+    tracing::info!("Audio preview of {} tracks, {:.0}s covered", preview.tracks.len(), preview.covered_secs);
+    // Only a full preview is kept; a partial one is redone once more is downloaded
+    if source_complete || preview.covered_secs >= AUDIO_PREVIEW_SECS {
+        state.audio_previews.write().await.insert((session_id, file_id), preview.clone());
+    }
+    axum::Json(preview).into_response()
+}
+
+// Attachments sit in the container header, so the start of the file is enough
+const ATTACHMENT_READ_BYTES: usize = 100 * 1024 * 1024;
+
+// Writes every attachment of the file into a directory of its own in one ffmpeg run, each
+// named by its attachment index, so fonts requested together don't each read the header
+// again. Cover images are written out as single frames.
+async fn extract_attachments(
+    handle: &librqbit::ManagedTorrentHandle,
+    session_id: usize,
+    file_id: usize,
+    attachments: &[Attachment],
+) -> Result<PathBuf, StreamError> {
+    let mut stream = handle.clone().stream(file_id)
+        .map_err(|e| StreamError::internal("stream_open_failed", format!("Failed to stream: {}", e)))?;
+
+    let dir = std::env::temp_dir().join(format!("magnolia_attachments_{}_{}", session_id, file_id));
+    tokio::fs::create_dir_all(&dir).await
+        .map_err(|e| StreamError::internal("temp_file_failed", format!("Failed to create attachment folder: {}", e)))?;
+    let source_path = dir.join("source.mkv");
+    let mut source = tokio::fs::File::create(&source_path).await
+        .map_err(|e| StreamError::internal("temp_file_failed", format!("Failed to create temp file: {}", e)))?;
+
+    let mut total_read = 0usize;
+    let mut buffer = vec![0u8; 1024 * 1024];
+    while total_read < ATTACHMENT_READ_BYTES {
+        match stream.read(&mut buffer).await {
+            Ok(0) => break,
+            Ok(n) => {
+                if tokio::io::AsyncWriteExt::write_all(&mut source, &buffer[..n]).await.is_err() {
+                    let _ = tokio::fs::remove_file(&source_path).await;
+                    return Err(StreamError::internal("temp_file_failed", "Failed to write temp file"));
                 }
+                total_read += n;
             }
+            Err(_) => break,
         }
-        
-        let session_id = entry.session_id.unwrap();
+    }
+    source.sync_all().await.ok();
+    drop(source);
 
-        let handle = self
-            .session
-            .get(TorrentIdOrHash::Id(session_id))
-            .context("Session torrent not found")?;
+    // -dump_attachment writes the files while opening the input; without a cover to
+    // write ffmpeg then complains there's no output, so success is judged by the files
+    let mut cmd = ffmpeg_tools::ffmpeg_command();
+    cmd.arg("-y");
+    for attachment in attachments.iter().filter(|a| !a.is_cover) {
+        cmd.arg(format!("-dump_attachment:{}", attachment.stream_index))
+            .arg(dir.join(attachment.index.to_string()));
+    }
+    cmd.arg("-i").arg(&source_path);
+    for attachment in attachments.iter().filter(|a| a.is_cover) {
+        cmd.arg("-map").arg(format!("0:{}", attachment.stream_index))
+            .args(["-c", "copy", "-frames:v", "1", "-f", "image2"])
+            .arg(dir.join(attachment.index.to_string()));
+    }
 
-        // Get torrent metadata - filter to video files (.mkv, .mp4, .avi, .mov)
-        let files: Vec<TorrentFile> = handle
-            .with_metadata(|meta| {
-                meta.file_infos
-                    .iter()
-                    .enumerate()
-                    .filter_map(|(index, file_info)| {
-                        let filename = file_info
-                            .relative_filename
-                            .to_string_lossy()
-                            .to_string();
-                        let lower = filename.to_lowercase();
-                        
-                        if lower.ends_with(".mkv") || lower.ends_with(".mp4") || lower.ends_with(".avi") || lower.ends_with(".mov") {
-                            Some(TorrentFile {
-                                index,
-                                name: file_info
-                                    .relative_filename
-                                    .file_name()
-                                    .and_then(|n| n.to_str())
-                                    .unwrap_or("unknown")
-                                    .to_string(),
-                                size: file_info.len,
-                                path: filename,
-                            })
-                        } else {
-                            None
-                        }
-                    })
-                    .collect()
-            })?;
+    let result = ffmpeg_tools::output(&mut cmd).await;
+    let _ = tokio::fs::remove_file(&source_path).await;
+    if let Err(e) = result {
+        tracing::error!("Failed to run ffmpeg: {}", e);
+        return Err(StreamError::internal("ffmpeg_spawn_failed", "Failed to extract attachments"));
+    }
+    tracing::info!("Extracted {} attachments of session={} file={} to {:?}", attachments.len(), session_id, file_id, dir);
+    Ok(dir)
+}
 
-        let torrent_name = handle.name().unwrap_or_else(|| "Unknown".to_string());
-        let stats = handle.stats();
-        let is_paused = handle.is_paused();
-        
-        // Determine state: when stats.live is None, torrent is checking/hashing
-        let state = if is_paused {
-            "paused".to_string()
-        } else if stats.live.is_none() {
-            "checking".to_string()
-        } else {
-            "live".to_string()
-        };
+// Body of one embedded attachment, e.g. a font for the subtitle renderer. Needs the
+// metadata request to have run so the attachment list is known. The first request
+// extracts all of them while later ones wait on the stream's lock and read the result.
+async fn get_attachment(
+    Path((session_id, file_id, attachment_index)): Path<(usize, usize, usize)>,
+    axum::extract::State(state): axum::extract::State<AppState>,
+) -> impl IntoResponse {
 
-        Ok(TorrentInfo {
-            handle_id,
-            name: torrent_name,
-            size: files.iter().map(|f| f.size).sum(),
-            files,
-            progress: if stats.total_bytes > 0 {
-                stats.progress_bytes as f64 / stats.total_bytes as f64 * 100.0
-            } else {
-                0.0
-            },
-            download_speed: stats
-                .live
-                .as_ref()
-                .map(|l| l.download_speed.mbps as u64)
-                .unwrap_or(0),
-            upload_speed: stats
-                .live
-                .as_ref()
-                .map(|l| l.upload_speed.mbps as u64)
-                .unwrap_or(0),
-            peers: stats.live.as_ref().map(|l| l.snapshot.peer_stats.live).unwrap_or(0),
-            is_paused,
-            state,
-        })
-    }
+    tracing::info!("Attachment request: session={}, file={}, attachment={}", session_id, file_id, attachment_index);
 
-    pub async fn list_torrents(&self) -> Result<Vec<TorrentInfo>> {
-        let torrents = self.torrents.read().await;
-        let mut result = Vec::new();
+    let stream_key = (session_id, file_id);
+    let Some(metadata) = state.streams.metadata(stream_key).await else {
+        return (StatusCode::NOT_FOUND, "Attachment not found").into_response();
+    };
+    let Some(attachment) = metadata.attachments.get(attachment_index).cloned() else {
+        return (StatusCode::NOT_FOUND, "Attachment not found").into_response();
+    };
+    let Some(extracted) = state.streams.read(stream_key, |s| s.attachments.clone()).await else {
+        return (StatusCode::NOT_FOUND, "Attachment not found").into_response();
+    };
 
-        for (our_id, _) in torrents.iter() {
-            if let Ok(info) = self.get_torrent_info(*our_id).await {
-                result.push(info);
+    let dir = {
+        let mut extracted = extracted.lock().await;
+        match extracted.as_ref() {
+            Some(dir) => dir.clone(),
+            None => {
+                let handle = match state.session.get(TorrentIdOrHash::Id(session_id)) {
+                    Some(h) => h,
+                    None => return (StatusCode::NOT_FOUND, "Torrent not found").into_response(),
+                };
+                let dir = match extract_attachments(&handle, session_id, file_id, &metadata.attachments).await {
+                    Ok(dir) => dir,
+                    Err(e) => return e.into_response(),
+                };
+                // Deleted with the stream's other temp files
+                state.streams.update_existing(stream_key, |s| s.add_temp_file(dir.clone())).await;
+                *extracted = Some(dir.clone());
+                dir
             }
         }
+    };
 
-        Ok(result)
+    let data = match tokio::fs::read(dir.join(attachment.index.to_string())).await {
+        Ok(d) if !d.is_empty() => d,
+        _ => {
+            tracing::error!("ffmpeg did not extract attachment {}", attachment_index);
+            return StreamError::internal("attachment_missing", "Attachment extraction failed").into_response();
+        }
+    };
+
+    let content_type = attachment.mimetype
+        .filter(|m| !m.is_empty() && m.bytes().all(|b| b.is_ascii_graphic()))
+        .unwrap_or_else(|| "application/octet-stream".to_string());
+    Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, content_type)
+        .body(Body::from(data))
+        .unwrap()
+        .into_response()
+}
+
+/// Parses a `Range` header into an inclusive byte range (RFC 7233): `bytes=start-end`,
+/// open-ended `bytes=start-` and suffix `bytes=-length`. Ranges that can't be satisfied
+/// get a 416; multi-range requests are rejected the same way since we never send
+/// multipart bodies. Malformed headers and other range units are ignored and the whole
+/// file is served.
+fn parse_byte_range(headers: &HeaderMap, file_size: u64) -> Result<(u64, u64, StatusCode), Response> {
+    let unsatisfiable = || {
+        Response::builder()
+            .status(StatusCode::RANGE_NOT_SATISFIABLE)
+            .header(header::CONTENT_RANGE, format!("bytes */{}", file_size))
+            .header(header::ACCEPT_RANGES, "bytes")
+            .body(Body::empty())
+            .unwrap()
+    };
+
+    let full = || Ok((0, file_size.saturating_sub(1), StatusCode::OK));
+
+    let Some(range) = headers.get(header::RANGE) else {
+        return full();
+    };
+    let Some(spec) = range.to_str().ok().and_then(|r| r.trim().strip_prefix("bytes=")) else {
+        return full();
+    };
+    if spec.contains(',') {
+        tracing::warn!("Rejecting multi-range request: {}", spec);
+        return Err(unsatisfiable());
     }
 
-    pub async fn prepare_stream(&self, handle_id: usize, file_index: usize) -> Result<()> {
-        let torrents = self.torrents.read().await;
-        let entry = torrents
-            .get(&handle_id)
-            .context("Torrent handle not found")?;
-        
-        // Check if this torrent is in the cache
-        let mut cache = self.torrent_cache.write().await;
-        let cached_session_id = cache.iter()
-            .find(|ct| ct.handle_id == handle_id)
-            .map(|ct| ct.session_id);
-        
-        if let Some(session_id) = cached_session_id {
-            tracing::info!("Found cached torrent for handle_id {}, resuming session_id {}", handle_id, session_id);
-            
-            // Remove from cache
-            cache.retain(|ct| ct.handle_id != handle_id);
-            drop(cache);
-            
-            // Resume the torrent
-            if let Some(handle) = self.session.get(TorrentIdOrHash::Id(session_id)) {
-                // Resume if paused
-                if handle.is_paused() {
-                    self.session.unpause(&handle).await?;
-                }
-                
-                tracing::info!("Resumed cached torrent, session_id {} for handle_id {}", session_id, handle_id);
-                
-                // Update entry with session_id
-                drop(torrents);
-                let mut torrents = self.torrents.write().await;
-                if let Some(entry) = torrents.get_mut(&handle_id) {
-                    entry.session_id = Some(session_id);
+    let Some((first, last)) = spec.trim().split_once('-') else {
+        tracing::warn!("Ignoring malformed Range header: {}", spec);
+        return full();
+    };
+    let (first, last) = (first.trim(), last.trim());
+    let parse = |value: &str| value.parse::<u64>().ok();
+
+    let (start, end) = match (parse(first), parse(last)) {
+        // bytes=-500: the last 500 bytes
+        (None, Some(length)) if first.is_empty() => {
+            if length == 0 || file_size == 0 {
+                return Err(unsatisfiable());
+            }
+            (file_size.saturating_sub(length), file_size - 1)
+        }
+        // bytes=500-
+        (Some(start), None) if last.is_empty() => (start, file_size.saturating_sub(1)),
+        (Some(start), Some(end)) if start <= end => (start, end.min(file_size.saturating_sub(1))),
+        _ => {
+            tracing::warn!("Ignoring malformed Range header: {}", spec);
+            return full();
+        }
+    };
+
+    if start >= file_size {
+        return Err(unsatisfiable());
+    }
+    Ok((start, end, StatusCode::PARTIAL_CONTENT))
+}
+
+/// Status and headers of a ranged media response, shared by GET and HEAD. The body is
+/// sent as-is; compressing video would break byte ranges.
+fn media_response_builder(status_code: StatusCode, start: u64, end: u64, file_size: u64, content_type: &str) -> axum::http::response::Builder {
+    let mut response = Response::builder()
+        .status(status_code)
+        .header(header::CONTENT_TYPE, content_type)
+        .header(header::CONTENT_LENGTH, (end - start + 1).to_string())
+        .header(header::ACCEPT_RANGES, "bytes")
+        .header(header::CACHE_CONTROL, "no-transform");
+
+    if status_code == StatusCode::PARTIAL_CONTENT {
+        response = response.header(header::CONTENT_RANGE, format!("bytes {}-{}/{}", start, end, file_size));
+    }
+    response
+}
+
+fn torrent_file_size(state: &AppState, session_id: usize, file_id: usize) -> Result<u64, Response> {
+    let handle = state.session.get(TorrentIdOrHash::Id(session_id))
+        .ok_or_else(|| (StatusCode::NOT_FOUND, "Torrent not found").into_response())?;
+    match handle.with_metadata(|meta| meta.file_infos.get(file_id).map(|f| f.len)) {
+        Ok(Some(size)) => Ok(size),
+        _ => Err((StatusCode::NOT_FOUND, "File not found").into_response()),
+    }
+}
+
+// Players probe the length and range support with HEAD before seeking; answering it
+// without opening a torrent stream keeps them from falling back to a full download
+async fn stream_file_head(
+    Path((session_id, file_id)): Path<(usize, usize)>,
+    headers: HeaderMap,
+    axum::extract::State(state): axum::extract::State<AppState>,
+) -> Response {
+    let file_size = match torrent_file_size(&state, session_id, file_id) {
+        Ok(size) => size,
+        Err(response) => return response,
+    };
+    let (start, end, status_code) = match parse_byte_range(&headers, file_size) {
+        Ok(range) => range,
+        Err(response) => return response,
+    };
+    media_response_builder(status_code, start, end, file_size, "video/x-matroska")
+        .body(Body::empty())
+        .unwrap()
+}
+
+// CORS preflights are answered by the CORS layer; this covers plain OPTIONS probes
+async fn media_options() -> Response {
+    Response::builder()
+        .status(StatusCode::NO_CONTENT)
+        .header(header::ALLOW, "GET, HEAD, OPTIONS")
+        .header(header::ACCEPT_RANGES, "bytes")
+        .body(Body::empty())
+        .unwrap()
+}
+
+/// Turns away requests for torrents that haven't passed parental controls in
+/// prepare_stream, so stream URLs can't be opened around them
+async fn parental_gate(request: axum::extract::Request, next: axum::middleware::Next) -> Response {
+    let session_id = request.uri().path()
+        .strip_prefix("/torrents/")
+        .and_then(|rest| rest.split('/').next())
+        .and_then(|id| id.parse::<usize>().ok());
+    match session_id {
+        Some(session_id) if !crate::parental::session_allowed(session_id) => {
+            (StatusCode::FORBIDDEN, crate::i18n::t("error.parental_not_cleared")).into_response()
+        }
+        _ => next.run(request).await,
+    }
+}
+
+async fn stream_file(
+    Path((session_id, file_id)): Path<(usize, usize)>,
+    headers: HeaderMap,
+    axum::extract::State(state): axum::extract::State<AppState>,
+) -> impl IntoResponse {
+    use std::io::SeekFrom;
+    use tokio_util::io::ReaderStream;
+
+    let handle = match state.session.get(TorrentIdOrHash::Id(session_id)) {
+        Some(h) => h,
+        None => return (StatusCode::NOT_FOUND, "Torrent not found").into_response(),
+    };
+
+    let file_size = match torrent_file_size(&state, session_id, file_id) {
+        Ok(size) => size,
+        Err(response) => return response,
+    };
+
+    let (start, end, status_code) = match parse_byte_range(&headers, file_size) {
+        Ok(range) => range,
+        Err(response) => return response,
+    };
+
+    // Without peers the torrent can't deliver anything, so read from a web seed instead
+    let web_seeds = state.web_seeds.read().await.get(&session_id).cloned();
+    if let Some(seeds) = web_seeds {
+        let stats = handle.stats();
+        let live_peers = stats.live.as_ref().map(|l| l.snapshot.peer_stats.live).unwrap_or(0);
+        if live_peers == 0 && !stats.finished {
+            if let Some(response) = web_seed_response(&handle, &seeds, file_id, start, end, file_size, status_code).await {
+                return response;
+            }
+        }
+    }
+
+    let mut stream = match handle.stream(file_id) {
+        Ok(s) => s,
+        Err(e) => {
+            tracing::error!("Failed to create stream for file_id {}: {}", file_id, e);
+            return StreamError::internal("stream_open_failed", format!("Failed to stream: {}", e)).into_response();
+        }
+    };
+
+    if start > 0 {
+        if let Err(e) = stream.seek(SeekFrom::Start(start)).await {
+            tracing::error!("Failed to seek stream to {}: {}", start, e);
+            return StreamError::internal("seek_failed", format!("Failed to seek: {}", e)).into_response();
+        }
+    }
+
+    let content_length = end - start + 1;
+    let limited_stream = stream.take(content_length);
+    
+    let reader_stream = ReaderStream::new(limited_stream);
+    let body = Body::from_stream(reader_stream);
+
+    media_response_builder(status_code, start, end, file_size, "video/x-matroska")
+        .body(body)
+        .unwrap()
+        .into_response()
+}
+
+/// Proxies a byte range of a torrent file from the first web seed that serves it
+async fn web_seed_response(
+    handle: &librqbit::ManagedTorrent,
+    seeds: &Arc<WebSeeds>,
+    file_id: usize,
+    start: u64,
+    end: u64,
+    file_size: u64,
+    status_code: StatusCode,
+) -> Option<Response> {
+    use futures::StreamExt;
+
+    let torrent_name = handle.name()?;
+    let (relative_path, multi_file) = handle.with_metadata(|meta| {
+        let path: Vec<String> = meta.file_infos.get(file_id)?
+            .relative_filename
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy().to_string())
+            .collect();
+        Some((path, meta.file_infos.len() > 1))
+    }).ok()??;
+
+    let client = crate::search::http::HttpClient::new(std::time::Duration::from_secs(30));
+    let range = format!("bytes={}-{}", start, end);
+    for seed in &seeds.urls {
+        let url = web_seed::file_url(seed, &torrent_name, &relative_path, multi_file);
+        let response = match client.get_with_headers(&url, &[("Range", &range)]).await {
+            Ok(response) => response,
+            Err(e) => {
+                tracing::warn!("web seed {} failed: {}", url, e);
+                continue;
+            }
+        };
+        // A seed that ignores the range would send the wrong bytes
+        let usable = response.status() == reqwest::StatusCode::PARTIAL_CONTENT
+            || (response.status().is_success() && start == 0 && end + 1 == file_size);
+        if !usable {
+            tracing::warn!("web seed {} returned {}", url, response.status());
+            continue;
+        }
+
+        let counter = seeds.clone();
+        let body = response.bytes_stream().map(move |chunk| {
+            if let Ok(ref bytes) = chunk {
+                counter.add_bytes(bytes.len() as u64);
+            }
+            chunk.map_err(std::io::Error::other)
+        });
+
+        return media_response_builder(status_code, start, end, file_size, "video/x-matroska")
+            .body(Body::from_stream(body))
+            .ok();
+    }
+    None
+}
+
+// Remembers the port the media server got last time, so stream urls handed to
+// external players keep working across restarts when no port is configured
+const LAST_HTTP_PORT_FILE: &str = "http_port";
+// Attempts at the configured port; the previous instance may still be releasing it
+const HTTP_BIND_ATTEMPTS: u32 = 3;
+const HTTP_BIND_RETRY_DELAY: std::time::Duration = std::time::Duration::from_millis(500);
+
+/// Binds the media server to `http_port` when set (0 = auto), otherwise to the last
+/// used port, falling back to any free port
+async fn bind_http_listener(data_dir: &std::path::Path, http_port: u16) -> std::io::Result<tokio::net::TcpListener> {
+    if http_port != 0 {
+        for attempt in 1..=HTTP_BIND_ATTEMPTS {
+            match tokio::net::TcpListener::bind(("127.0.0.1", http_port)).await {
+                Ok(listener) => return Ok(listener),
+                Err(e) => {
+                    tracing::warn!("failed to bind HTTP server to port {} (attempt {}): {}", http_port, attempt, e);
+                    if attempt < HTTP_BIND_ATTEMPTS {
+                        tokio::time::sleep(HTTP_BIND_RETRY_DELAY).await;
+                    }
                 }
-                
-                return Ok(());
-            } else {
-                tracing::warn!("Cached session_id {} not found in session, adding fresh", session_id);
             }
-        } else {
-            drop(cache);
         }
+    }
+
+    let last_port = std::fs::read_to_string(data_dir.join(LAST_HTTP_PORT_FILE))
+        .ok()
+        .and_then(|p| p.trim().parse::<u16>().ok())
+        .filter(|p| *p != 0 && *p != http_port);
+    if let Some(port) = last_port {
+        match tokio::net::TcpListener::bind(("127.0.0.1", port)).await {
+            Ok(listener) => return Ok(listener),
+            Err(e) => tracing::warn!("last used HTTP port {} is unavailable: {}", port, e),
+        }
+    }
+
+    tokio::net::TcpListener::bind("127.0.0.1:0").await
+}
+
+impl TorrentManager {
+    /// `data_dir` is the app data directory, where state that isn't torrent data is kept
+    pub async fn new(download_dir: PathBuf, data_dir: PathBuf, http_port: u16, media_cache: MediaCache) -> Result<Self> {
+        tracing::info!("initializing TorrentManager with download_dir: {:?}", download_dir);
         
-        // Add the torrent with ONLY the specific file selected
-        let add_torrent = if entry.magnet_url.starts_with("magnet:") {
-            AddTorrent::from_url(&entry.magnet_url)
-        } else if entry.magnet_url.starts_with("http") {
-            AddTorrent::from_url(&entry.magnet_url)
-        } else {
-            AddTorrent::from_local_filename(&entry.magnet_url)?
+        if let Err(e) = std::fs::create_dir_all(&download_dir) {
+            tracing::error!("failed to create download directory: {}", e);
+            return Err(e.into());
+        }
+
+        // Create session with default options
+        tracing::info!("creating librqbit session...");
+        let session = match Session::new(download_dir.clone()).await {
+            Ok(s) => {
+                tracing::info!("librqbit session created successfully");
+                s
+            }
+            Err(e) => {
+                tracing::error!("failed to create librqbit session: {}", e);
+                return Err(anyhow::anyhow!("Failed to create librqbit session: {}", e));
+            }
         };
+
+        let torrents = Arc::new(RwLock::new(HashMap::new()));
+        let next_id = Arc::new(RwLock::new(0));
+
+        // Note: We don't load existing torrents from session since we store URLs separately
+        // and only add them to session when streaming starts
+        tracing::info!("TorrentManager initialized");
+
+        tracing::info!("binding HTTP server to localhost...");
+        let listener = match bind_http_listener(&data_dir, http_port).await {
+            Ok(l) => {
+                tracing::info!("HTTP server listener created successfully");
+                l
+            }
+            Err(e) => {
+                tracing::error!("failed to bind HTTP server: {}", e);
+                return Err(e.into());
+            }
+        };
+        let http_addr = listener.local_addr()?;
+        tracing::info!("HTTP server will run on: {}", http_addr);
+        if let Err(e) = std::fs::write(data_dir.join(LAST_HTTP_PORT_FILE), http_addr.port().to_string()) {
+            tracing::error!("failed to save HTTP server port: {}", e);
+        }
         
-        tracing::info!("Preparing stream for file index {}", file_index);
-        
-        let opts = AddTorrentOptions {
-            overwrite: true,
-            paused: false,
-            only_files: Some(vec![file_index]),
-            force_tracker_interval: Some(std::time::Duration::from_secs(5)), // Request peers faster
-            ..Default::default()
+        let streams = StreamSessions::new();
+        let local_files: Arc<RwLock<HashMap<String, PathBuf>>> =
+            Arc::new(RwLock::new(HashMap::new()));
+        let web_seeds: Arc<RwLock<HashMap<usize, Arc<WebSeeds>>>> =
+            Arc::new(RwLock::new(HashMap::new()));
+        let access_log = StreamAccessLog::new();
+        let chapter_thumbnails: ChapterThumbnails = Arc::new(RwLock::new(HashMap::new()));
+        let (probe_events, _) = tokio::sync::broadcast::channel(64);
+
+        let state = AppState {
+            session: session.clone(),
+            streams: streams.clone(),
+            download_dir: download_dir.clone(),
+            http_addr,
+            local_files: local_files.clone(),
+            web_seeds: web_seeds.clone(),
+            chapter_thumbnails: chapter_thumbnails.clone(),
+            audio_previews: Arc::new(RwLock::new(HashMap::new())),
+            probe_events: probe_events.clone(),
+            media_cache: Arc::new(media_cache),
+        };
+
+        let app = Router::new()
+            .route("/torrents/{session_id}/stream/{file_id}", get(stream_file).head(stream_file_head).options(media_options))
+            .route("/torrents/{session_id}/metadata/{file_id}", get(get_file_metadata))
+            .route("/torrents/{session_id}/availability/{file_id}", get(get_file_availability))
+            .route("/torrents/{session_id}/subtitles/{file_id}/{track_index}", get(get_subtitle_track))
+            .route("/torrents/{session_id}/attachments/{file_id}/{attachment_index}", get(get_attachment))
+            .route("/torrents/{session_id}/chapters/{file_id}/{chapter_index}/thumb", get(get_chapter_thumbnail))
+            .route("/torrents/{session_id}/audio-preview/{file_id}", get(get_audio_preview))
+            .route("/torrents/{session_id}/srt-stream/{file_id}/{track_index}", get(stream_srt_subtitles))
+            .route("/torrents/{session_id}/transcoded-audio-stream/{file_id}/{track_index}", get(stream_transcoded_audio))
+            .route("/torrents/{session_id}/transcoded-audio-stream/{file_id}", get(stream_transcoded_audio_default))
+            .route("/torrents/{session_id}/transcoded-audio/{file_id}", get(serve_transcoded_audio))
+            .route("/torrents/{session_id}/tonemapped-stream/{file_id}", get(stream_tonemapped_video))
+            .route("/torrents/{session_id}/remux/{file_id}", get(stream_remuxed_video))
+            .route("/local/{library_id}/stream", get(stream_local_file).head(stream_local_file_head).options(media_options))
+            .route("/local/{library_id}/metadata", get(get_local_file_metadata))
+            .route("/local/{library_id}/subtitles/{track_index}", get(get_local_subtitle_track))
+            .route("/local/{library_id}/transcoded-audio-stream/{track_index}", get(stream_local_transcoded_audio))
+            .route("/fonts/{filename}", get(serve_font))
+            .layer(axum::middleware::from_fn(parental_gate))
+            .layer(axum::middleware::from_fn_with_state(access_log.clone(), stream_log::record_access))
+            .layer(CorsLayer::permissive())
+            .with_state(state);
+
+        tokio::spawn(async move {
+            axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>()).await.ok();
+        });
+
+        let manager = Self {
+            session,
+            download_dir,
+            torrents,
+            next_id,
+            http_addr,
+            streams,
+            torrent_cache: Arc::new(RwLock::new(Vec::new())),
+            speed_averages: Arc::new(RwLock::new(HashMap::new())),
+            file_verifications: Arc::new(RwLock::new(HashMap::new())),
+            selected_files: Arc::new(RwLock::new(HashMap::new())),
+            listings: Arc::new(RwLock::new(HashMap::new())),
+            listing_fetches: Arc::new(RwLock::new(HashMap::new())),
+            local_files,
+            web_seeds,
+            chapter_thumbnails,
+            access_log,
+            probe_events,
+            speed_limits: std::sync::Mutex::new((None, None)),
+            playback_hold: std::sync::atomic::AtomicBool::new(false),
         };
         
-        let response = self.session.add_torrent(add_torrent, Some(opts)).await?;
-        let (session_id, _handle) = match response {
-            AddTorrentResponse::Added(id, h) => (id, h),
-            AddTorrentResponse::AlreadyManaged(id, h) => {
-                tracing::info!("Torrent already managed, reusing existing download");
-                if h.is_paused() {
-                    self.session.unpause(&h).await?;
+        // Load cached torrents from disk
+        if let Err(e) = manager.load_cache_from_disk().await {
+            tracing::warn!("Failed to load torrent cache from disk: {}", e);
+        }
+        
+        Ok(manager)
+    }
+
+    pub async fn add_torrent(&self, magnet_or_url: String) -> Result<usize> {
+        let magnet_or_url = expand_info_hash(magnet_or_url);
+        // A torrent already in the session (e.g. streaming) is used as is; otherwise a
+        // prefetched listing already has what the list_only fetch would wait for
+        let existing = self.session_by_hash(&magnet_or_url).map(|(id, _)| id);
+        let prefetched = match magnet_info_hash(&magnet_or_url) {
+            Some(hash) => self.listings.read().await.contains_key(&hash),
+            None => false,
+        };
+        let session_id = if let Some(id) = existing {
+            tracing::info!("Torrent already in session with id: {}", id);
+            Some(id)
+        } else if prefetched {
+            tracing::info!("Using prefetched metadata: {}", magnet_or_url);
+            None
+        } else {
+            tracing::info!("Adding torrent with list_only to fetch metadata: {}", magnet_or_url);
+
+            let (add_torrent, trackers, _) = torrent_source(&magnet_or_url).await?;
+
+            let opts = AddTorrentOptions {
+                list_only: true,
+                trackers,
+                ..Default::default()
+            };
+
+            let response = self.session.add_torrent(add_torrent, Some(opts)).await?;
+
+            // Extract session_id if it was added (shouldn't happen with list_only, but handle it)
+            match response {
+                AddTorrentResponse::Added(id, _) | AddTorrentResponse::AlreadyManaged(id, _) => {
+                    tracing::info!("Torrent was added to session with id: {}", id);
+                    Some(id)
+                }
+                AddTorrentResponse::ListOnly(_) => {
+                    tracing::info!("Got list-only response (metadata fetched)");
+                    None
                 }
-                (id, h)
-            }
-            AddTorrentResponse::ListOnly(_) => {
-                return Err(anyhow::anyhow!("Unexpected list_only response"));
             }
         };
+
+        let mut id_lock = self.next_id.write().await;
+        let our_id = *id_lock;
+        *id_lock += 1;
+        drop(id_lock);
         
-        tracing::info!("Setting session_id {} for handle_id {}", session_id, handle_id);
-        
-        drop(torrents);
         let mut torrents = self.torrents.write().await;
-        if let Some(entry) = torrents.get_mut(&handle_id) {
-            entry.session_id = Some(session_id);
-            tracing::info!("Successfully updated entry.session_id to {}", session_id);
-        }
+        torrents.insert(our_id, TorrentEntry {
+            magnet_url: magnet_or_url,
+            session_id,
+        });
         
-        Ok(())
+        tracing::info!("Stored torrent with our_id: {}", our_id);
+        Ok(our_id)
     }
 
-    pub async fn get_stream_status(&self, handle_id: usize, file_index: usize) -> Result<StreamStatus> {
-        println!("[Transcode] get_stream_status called: handle_id={}, file_index={}", handle_id, file_index);
-        
-        let torrents = self.torrents.read().await;
-        let entry = torrents
+    /// Hashes a file's pieces against the torrent's piece hashes. librqbit only rechecks
+    /// whole torrents when they are added, so the data is read back from disk here.
+    pub async fn verify_file(&self, handle_id: usize, file_index: usize) -> Result<FileVerification> {
+        let session_id = self.torrents.read().await
             .get(&handle_id)
-            .context("Torrent handle not found")?;
-            
-        let session_id = entry.session_id.context("Torrent not yet added to session")?;
-        tracing::info!("get_stream_status for handle_id={}, session_id={}, file_index={}", handle_id, session_id, file_index);
-        
+            .context("Torrent handle not found")?
+            .session_id
+            .context("Torrent not yet added to session")?;
+
+        self.start_file_verification(session_id, file_index).await?.await?
+    }
+
+    async fn start_file_verification(
+        &self,
+        session_id: usize,
+        file_index: usize,
+    ) -> Result<tokio::task::JoinHandle<Result<FileVerification>>> {
         let handle = self.session.get(TorrentIdOrHash::Id(session_id)).context("Session torrent not found")?;
-        let stats = handle.stats();
-        
-        let file_info = handle.with_metadata(|meta| {
-            meta.file_infos.get(file_index).map(|fi| (
-                fi.relative_filename.clone(),
-                fi.len
-            ))
-        })?.context("File index out of range")?;
 
-        let (file_name_path, file_size) = file_info;
-        let file_name = file_name_path
-            .file_name()
-            .and_then(|n| n.to_str())
-            .unwrap_or("unknown")
-            .to_string();
+        let (files, piece_length, piece_hashes) = handle.with_metadata(|meta| {
+            let files: Vec<(PathBuf, u64, u64)> = meta.file_infos
+                .iter()
+                .map(|f| (self.download_dir.join(&f.relative_filename), f.offset_in_torrent, f.len))
+                .collect();
+            (files, meta.lengths.default_piece_length() as u64, meta.info.pieces.as_ref().to_vec())
+        })?;
+        let have_pieces: std::collections::HashSet<u64> = handle
+            .with_chunk_tracker(|chunks| chunks.get_have_pieces().iter_ones().map(|p| p as u64).collect())
+            .unwrap_or_default();
 
-        // Check if ready
-        // We need to ensure:
-        // 1. The stream can be created (handle.stream succeeds)
-        // 2. We have enough data for headers (at least 2MB or finished)
-        let is_streamable = handle.clone().stream(file_index).is_ok();
-        let has_buffer = stats.progress_bytes > 2 * 1024 * 1024 || stats.finished;
-        
-        let is_ready = is_streamable && has_buffer;
-        
-        if !is_ready {
-            tracing::debug!(
-                "Stream not ready: streamable={}, buffer={} ({} bytes), finished={}", 
-                is_streamable, has_buffer, stats.progress_bytes, stats.finished
-            );
-        }
-        
-        // Check transcoding state
-        let transcode_progress = {
-            let states = self.transcode_states.read().await;
-            states.get(&(session_id, file_index)).map(|s| s.progress)
-        };
-        
-        let transcode_completed = {
-            let states = self.transcode_states.read().await;
-            states.get(&(session_id, file_index)).map(|s| s.completed).unwrap_or(false)
-        };
-        
-        let stream_info = if is_ready {
-             // Extract metadata for supported video formats
-            let lower = file_name.to_lowercase();
-            println!("[Transcode] File name: {}, stats: {}/{} bytes", file_name, stats.progress_bytes, stats.total_bytes);
-            let mut metadata = if lower.ends_with(".mkv") || lower.ends_with(".mp4") || lower.ends_with(".avi") || lower.ends_with(".mov") {
-                // If fully downloaded, use the actual file
-                if stats.progress_bytes >= stats.total_bytes && stats.total_bytes > 0 {
-                    println!("[Transcode] File fully downloaded, extracting metadata from disk");
-                    let file_path = self.download_dir.join(&file_name_path);
-                    println!("[Transcode] File path: {:?}", file_path);
-                    println!("[Transcode] File exists: {}", file_path.exists());
-                    if file_path.exists() {
-                        match extract_mkv_metadata_ffprobe(&file_path).await {
-                            Ok(meta) => {
-                                println!("[Transcode] Successfully extracted metadata from file");
-                                Some(meta)
-                            },
-                            Err(e) => {
-                                println!("[Transcode] Failed to extract metadata: {}", e);
-                                None
-                            }
-                        }
-                    } else {
-                        println!("[Transcode] File doesn't exist on disk yet, checking cache");
-                        let cache = self.metadata_cache.read().await;
-                        let cached = cache.get(&(session_id, file_index)).cloned();
-                        println!("[Transcode] Metadata cache contains entry: {}", cached.is_some());
-                        cached
-                    }
-                } else {
-                    println!("[Transcode] File not fully downloaded, checking metadata cache");
-                    // Try to get from metadata cache (populated by /metadata/ endpoint)
-                    let cache = self.metadata_cache.read().await;
-                    let cached = cache.get(&(session_id, file_index)).cloned();
-                    println!("[Transcode] Metadata cache contains entry: {}", cached.is_some());
-                    cached
+        tracing::info!("Verifying file {} of session_id={}", file_index, session_id);
+        self.file_verifications.write().await.insert((session_id, file_index), None);
+
+        let file_verifications = self.file_verifications.clone();
+        Ok(tokio::spawn(async move {
+            let result = tokio::task::spawn_blocking(move || {
+                hash_check_file(&files, file_index, piece_length, &piece_hashes, &have_pieces)
+            }).await?;
+
+            let mut verifications = file_verifications.write().await;
+            match &result {
+                Ok(verification) => {
+                    tracing::info!(
+                        "Verified file {}: {}/{} pieces ok, {} bad, {} skipped",
+                        file_index, verification.verified_pieces, verification.total_pieces,
+                        verification.bad_pieces.len(), verification.skipped_pieces
+                    );
+                    verifications.insert((session_id, file_index), Some(verification.clone()));
                 }
-            } else {
-                println!("[Transcode] File format not supported for metadata extraction");
-                None
-            };
-            println!("[Transcode] Metadata result: {}", if metadata.is_some() { "Some" } else { "None" });
-            
-            // If transcoding is needed and not yet started, start it
-            if let Some(ref mut meta) = metadata {
-                println!("[Transcode] Metadata needs_audio_transcoding: {}", meta.needs_audio_transcoding);
-                if meta.needs_audio_transcoding {
-                    let transcode_key = (session_id, file_index);
-                    let states = self.transcode_states.read().await;
-                    let transcoding_started = states.contains_key(&transcode_key);
-                    drop(states);
-                    
-                    if !transcoding_started {
-                        // Mark transcoding as started immediately - no waiting for download
-                        let mut states = self.transcode_states.write().await;
-                        states.insert((session_id, file_index), TranscodeState {
-                            progress: 0.0,
-                            output_path: None,
-                            completed: false,
-                            error: None,
-                        });
-                        drop(states);
-                        
-                        println!("[Transcode] Transcoding ready for immediate on-demand streaming at {}", file_name);
-                        
-                        // Add transcoded URLs for each audio track that needs transcoding
-                        for (track_idx, track) in meta.audio_tracks.iter_mut().enumerate() {
-                            if track.needs_transcoding {
-                                track.transcoded_url = Some(format!(
-                                    "http://{}/torrents/{}/transcoded-audio-stream/{}/{}",
-                                    self.http_addr,
-                                    session_id,
-                                    file_index,
-                                    track_idx
-                                ));
-                                println!("[Transcode] Track {} ({}) ready for immediate piped transcoding", 
-                                    track_idx, track.codec.as_deref().unwrap_or("unknown"));
-                            }
-                        }
-                        
-                        // Keep legacy field for backward compatibility (first track needing transcode)
-                        if meta.audio_tracks.first().map(|t| t.needs_transcoding).unwrap_or(false) {
-                            meta.transcoded_audio_url = Some(format!(
-                                "http://{}/torrents/{}/transcoded-audio-stream/{}/0",
-                                self.http_addr,
-                                session_id,
-                                file_index
-                            ));
-                        }
-                    }
+                Err(_) => {
+                    verifications.remove(&(session_id, file_index));
                 }
             }
 
-            Some(StreamInfo {
-                url: format!(
-                    "http://{}/torrents/{}/stream/{}",
-                    self.http_addr,
-                    session_id,
-                    file_index
-                ),
-                file_name,
-                file_size,
-                metadata,
-            })
-        } else {
-            None
+            result
+        }))
+    }
+
+    /// Adds a torrent to the session with all of its files, for downloads that aren't tied
+    /// to a stream (e.g. dropped into the watch folder)
+    pub async fn add_torrent_download(&self, magnet_or_url: String, paused: bool) -> Result<usize> {
+        let magnet_or_url = expand_info_hash(magnet_or_url);
+        tracing::info!("Adding torrent for download (paused={}): {}", paused, magnet_or_url);
+
+        let (add_torrent, trackers, web_seeds) = torrent_source(&magnet_or_url).await?;
+
+        let opts = AddTorrentOptions {
+            paused,
+            trackers,
+            ..Default::default()
         };
 
-        // Determine the current state
-        let state = if transcode_progress.is_some() && !transcode_completed {
-            "transcoding".to_string()
-        } else if stats.live.is_none() {
-            "checking".to_string()
-        } else {
-            "downloading".to_string()
+        let session_id = match self.session.add_torrent(add_torrent, Some(opts)).await? {
+            AddTorrentResponse::Added(id, _) => id,
+            AddTorrentResponse::AlreadyManaged(id, _) => {
+                // Hand back the handle that already tracks it rather than a second entry
+                let existing = self.torrents.read().await.iter()
+                    .find(|(_, entry)| entry.session_id == Some(id))
+                    .map(|(handle_id, _)| *handle_id);
+                if let Some(handle_id) = existing {
+                    tracing::info!("Torrent already managed as handle {}", handle_id);
+                    return Ok(handle_id);
+                }
+                id
+            }
+            AddTorrentResponse::ListOnly(_) => {
+                return Err(anyhow::anyhow!("Unexpected list_only response"));
+            }
         };
+        self.register_web_seeds(session_id, web_seeds).await;
+
+        let mut id_lock = self.next_id.write().await;
+        let our_id = *id_lock;
+        *id_lock += 1;
+        drop(id_lock);
+
+        self.torrents.write().await.insert(our_id, TorrentEntry {
+            magnet_url: magnet_or_url,
+            session_id: Some(session_id),
+        });
+
+        Ok(our_id)
+    }
+
+    /// Fetch the file list of a torrent without keeping it around, so search results can show
+    /// what's inside before the user commits to one
+    pub async fn preview_torrent_files(&self, magnet_or_url: String) -> Result<TorrentPreview> {
+        let magnet_or_url = expand_info_hash(magnet_or_url);
+        tracing::info!("Previewing torrent files: {}", magnet_or_url);
         
-        // Check if transcoding is needed from either stream_info metadata or the cache
-        let needs_transcoding_from_stream = stream_info.as_ref()
-            .and_then(|s| s.metadata.as_ref())
-            .map(|m| m.needs_audio_transcoding)
-            .unwrap_or(false);
+        let (add_torrent, trackers, _) = torrent_source(&magnet_or_url).await?;
         
-        let needs_transcoding_from_cache = {
-            let cache = self.metadata_cache.read().await;
-            cache.get(&(session_id, file_index))
-                .map(|m| m.needs_audio_transcoding)
-                .unwrap_or(false)
+        let opts = AddTorrentOptions {
+            list_only: true,
+            trackers,
+            ..Default::default()
         };
         
-        let needs_audio_transcoding = needs_transcoding_from_stream || needs_transcoding_from_cache;
+        let response = self.session.add_torrent(add_torrent, Some(opts)).await?;
         
-        // Check if on-demand transcoding is ready (state exists but not completed means streaming is ready)
-        let transcode_streaming_ready = transcode_progress.is_some() && !transcode_completed;
+        let list_info = match response {
+            AddTorrentResponse::ListOnly(list_info) => list_info,
+            AddTorrentResponse::Added(id, _) => {
+                // Shouldn't happen with list_only, but don't leave a stray download behind
+                tracing::warn!("Preview unexpectedly added torrent {}, removing it", id);
+                self.session.delete(TorrentIdOrHash::Id(id), true).await?;
+                return Err(anyhow::anyhow!("Expected list_only response"));
+            }
+            AddTorrentResponse::AlreadyManaged(_, handle) => {
+                // Already in the session (e.g. currently streaming), read the list from it
+                let name = handle.name().unwrap_or_else(|| "Unknown".to_string());
+                let files = handle.with_metadata(|meta| {
+                    meta.file_infos
+                        .iter()
+                        .enumerate()
+                        .map(|(index, file_info)| {
+                            build_file_preview(index, file_info.relative_filename.to_string_lossy().to_string(), file_info.len)
+                        })
+                        .collect::<Vec<_>>()
+                })?;
+                return Ok(TorrentPreview {
+                    name,
+                    total_size: files.iter().map(|f| f.size).sum(),
+                    files,
+                });
+            }
+        };
         
-        // Determine status
-        let status = if !is_ready {
-            "initializing".to_string()
-        } else if needs_audio_transcoding && !transcode_streaming_ready {
-            // Still waiting for minimum download before transcoding can start
-            "transcoding".to_string()
-        } else {
-            // Ready means either no transcoding needed, or on-demand transcoding is available
-            "ready".to_string()
+        let files: Vec<TorrentFilePreview> = list_info.info
+            .iter_file_details()?
+            .enumerate()
+            .filter_map(|(index, detail)| {
+                let path = detail.filename.to_string().ok()?;
+                Some(build_file_preview(index, path, detail.len))
+            })
+            .collect();
+        
+        let name = match &list_info.info.name {
+            Some(n) => n.to_string(),
+            None => "Unknown".to_string(),
         };
         
-        tracing::debug!("Stream status: is_ready={}, needs_transcoding={}, transcode_completed={}, status={}", 
-            is_ready, needs_audio_transcoding, transcode_completed, status);
-
-        Ok(StreamStatus {
-            status,
-            progress_bytes: stats.progress_bytes,
-            total_bytes: stats.total_bytes,
-            peers: stats.live.as_ref().map(|l| l.snapshot.peer_stats.live).unwrap_or(0),
-            download_speed: stats.live.as_ref().map(|l| l.download_speed.mbps as u64).unwrap_or(0),
-            stream_info,
-            state,
-            transcode_progress,
+        tracing::info!("Preview of {} has {} files", name, files.len());
+        
+        Ok(TorrentPreview {
+            name,
+            total_size: files.iter().map(|f| f.size).sum(),
+            files,
         })
     }
-    
-    pub async fn stop_stream(&self, handle_id: usize, delete_files: bool) -> Result<()> {
-        tracing::info!("Stopping stream for handle_id: {}, delete_files: {}", handle_id, delete_files);
-        
-        let mut torrents = self.torrents.write().await;
-        if let Some(entry) = torrents.get_mut(&handle_id) {
-            if let Some(session_id) = entry.session_id {
-                if delete_files {
-                    // Delete torrent completely from librqbit with all files
-                    tracing::info!("Deleting torrent session_id: {} completely with files", session_id);
-                    
-                    // First, manually delete the files to ensure they're removed
-                    if let Some(handle) = self.session.get(TorrentIdOrHash::Id(session_id)) {
-                        tracing::info!("Manually deleting files for session_id: {}", session_id);
-                        self.clear_torrent_files(session_id, &handle).await?;
-                    }
-                    
-                    // Then remove from librqbit
-                    entry.session_id = None;
-                    self.session.delete(TorrentIdOrHash::Id(session_id), true).await?;
-                    tracing::info!("Torrent session_id: {} deleted from librqbit", session_id);
-                } else {
-                    // Cache the torrent: pause it and clear file data
-                    tracing::info!("Caching torrent session_id: {} for handle_id: {}", session_id, handle_id);
-                    
-                    // Pause the torrent first
-                    if let Some(handle) = self.session.get(TorrentIdOrHash::Id(session_id)) {
-                        self.session.pause(&handle).await?;
-                        
-                        // Clear file data to save space
-                        self.clear_torrent_files(session_id, &handle).await?;
-                    }
-                    
-                    // Add to cache
-                    let cached_torrent = CachedTorrent {
-                        handle_id,
-                        session_id,
-                        magnet_url: entry.magnet_url.clone(),
-                        cached_at: std::time::SystemTime::now(),
-                    };
-                    
-                    let mut cache = self.torrent_cache.write().await;
-                    
-                    // Remove if already in cache (by handle_id or magnet_url)
-                    let magnet_url = entry.magnet_url.clone();
-                    cache.retain(|ct| ct.handle_id != handle_id && ct.magnet_url != magnet_url);
-                    
-                    // Add to front of cache
-                    cache.insert(0, cached_torrent);
-                    
-                    // Enforce 10 torrent limit
-                    while cache.len() > 10 {
-                        if let Some(oldest) = cache.pop() {
-                            tracing::info!("Cache limit reached, removing oldest cached torrent: handle_id={}, session_id={}", oldest.handle_id, oldest.session_id);
-                            // Remove from session completely
-                            self.session.delete(TorrentIdOrHash::Id(oldest.session_id), true).await?;
-                            
-                            // Clear session_id from torrent entry
-                            if let Some(old_entry) = torrents.get_mut(&oldest.handle_id) {
-                                old_entry.session_id = None;
-                            }
-                        }
-                    }
-                    
-                    tracing::info!("Torrent cached. Current cache size: {}", cache.len());
-                    drop(cache);
-                    
-                    // Save cache to disk
-                    if let Err(e) = self.save_cache_to_disk().await {
-                        tracing::error!("Failed to save cache to disk: {}", e);
-                    }
-                }
-            }
+
+    /// Name and video files of a torrent that isn't in the session, from the listing cache
+    /// or fetched with list_only. Opening a result while its prefetch is still running
+    /// waits for that fetch instead of starting a second one.
+    async fn listing(&self, magnet_url: &str) -> Result<(String, Vec<TorrentFile>)> {
+        let Some(hash) = magnet_info_hash(magnet_url) else {
+            return self.fetch_listing(magnet_url, None).await;
+        };
+        if let Some(listing) = self.cached_listing(&hash).await {
+            return Ok(listing);
         }
-        
-        Ok(())
+
+        let fetch = self.listing_fetches.write().await.entry(hash.clone()).or_default().clone();
+        let _fetching = fetch.lock().await;
+        if let Some(listing) = self.cached_listing(&hash).await {
+            return Ok(listing);
+        }
+        let result = self.fetch_listing(magnet_url, Some(hash.clone())).await;
+        self.listing_fetches.write().await.remove(&hash);
+        result
     }
-    
-    /// Clear file data for a cached torrent to save space while keeping metadata
-    async fn clear_torrent_files(&self, session_id: usize, handle: &librqbit::ManagedTorrent) -> Result<()> {
-        tracing::info!("Clearing file data for session_id: {}", session_id);
+
+    async fn cached_listing(&self, hash: &str) -> Option<(String, Vec<TorrentFile>)> {
+        self.listings.read().await
+            .get(hash)
+            .map(|listing| (listing.name.clone(), listing.files.clone()))
+    }
+
+    async fn fetch_listing(&self, magnet_url: &str, hash: Option<String>) -> Result<(String, Vec<TorrentFile>)> {
+        let (add_torrent, trackers, _) = torrent_source(magnet_url).await?;
+
+        let opts = AddTorrentOptions {
+            list_only: true,
+            trackers,
+            ..Default::default()
+        };
+
+        let response = self.session.add_torrent(add_torrent, Some(opts)).await?;
+
+        let list_info = match response {
+            AddTorrentResponse::ListOnly(list_info) => list_info,
+            _ => return Err(anyhow::anyhow!("Expected list_only response")),
+        };
+        let files: Vec<TorrentFile> = list_info.info
+            .iter_file_details()?
+            .enumerate()
+            .filter_map(|(index, detail)| {
+                let filename_str = detail.filename.to_string().ok()?;
+                let lower = filename_str.to_lowercase();
+                if lower.ends_with(".mkv") || lower.ends_with(".mp4") || lower.ends_with(".avi") || lower.ends_with(".mov") {
+                    let pathbuf = detail.filename.to_pathbuf().ok()?;
+                    let name = pathbuf
+                        .file_name()
+                        .and_then(|n| n.to_str())
+                        .unwrap_or("unknown")
+                        .to_string();
+
+                    Some(TorrentFile {
+                        index,
+                        name,
+                        size: detail.len,
+                        path: filename_str,
+                        downloaded_bytes: 0,
+                        progress: 0.0,
+                        selected: false,
+                    })
+                } else {
+                    None
+                }
+            })
+            .collect();
+
+        let name = match &list_info.info.name {
+            Some(n) => n.to_string(),
+            None => "Unknown".to_string(),
+        };
+
+        if let Some(hash) = hash {
+            let mut listings = self.listings.write().await;
+            if listings.len() >= LISTING_CACHE_LIMIT {
+                let oldest = listings.iter().min_by_key(|(_, l)| l.fetched_at).map(|(k, _)| k.clone());
+                if let Some(oldest) = oldest {
+                    listings.remove(&oldest);
+                }
+            }
+            listings.insert(hash, TorrentListing {
+                name: name.clone(),
+                files: files.clone(),
+                fetched_at: std::time::Instant::now(),
+            });
+        }
+        Ok((name, files))
+    }
+
+    /// Fetches a search result's file list in the background so opening it is instant.
+    /// Failures only mean the result is listed the usual way when it's opened.
+    pub async fn prefetch_listing(&self, magnet_url: &str) {
+        let Some(hash) = magnet_info_hash(magnet_url) else { return };
+        if self.listings.read().await.contains_key(&hash) {
+            return;
+        }
+        match tokio::time::timeout(PREFETCH_TIMEOUT, self.listing(magnet_url)).await {
+            Ok(Ok((name, files))) => tracing::info!("Prefetched metadata of {} ({} video files)", name, files.len()),
+            Ok(Err(e)) => tracing::debug!("Prefetching metadata of {} failed: {}", hash, e),
+            Err(_) => tracing::debug!("Prefetching metadata of {} timed out", hash),
+        }
+    }
+
+    pub async fn get_torrent_info(&self, handle_id: usize) -> Result<TorrentInfo> {
+        let torrents = self.torrents.read().await;
+        let entry = torrents
+            .get(&handle_id)
+            .context("Torrent handle not found")?;
         
-        // Get torrent name for the base directory
-        let torrent_name = handle.with_metadata(|meta| {
-            meta.info.name.as_ref()
-                .map(|n| n.to_string())
-                .unwrap_or_else(|| "unknown".to_string())
-        })?;
+        // If not yet added to session, fetch metadata via list_only
+        if entry.session_id.is_none() {
+            let magnet_url = entry.magnet_url.clone();
+            drop(torrents);
+
+            let (name, files) = self.listing(&magnet_url).await?;
+            return Ok(TorrentInfo {
+                handle_id,
+                name,
+                size: files.iter().map(|f| f.size).sum(),
+                files,
+                progress: 0.0,
+                download_speed: 0,
+                upload_speed: 0,
+                peers: 0,
+                is_paused: true,
+                state: "paused".to_string(),
+                eta_seconds: None,
+                streamable_eta_seconds: None,
+                web_seeds: 0,
+                web_seed_bytes: 0,
+            });
+        }
         
-        // Get file paths
-        let file_paths: Vec<PathBuf> = handle.with_metadata(|meta| {
-            meta.file_infos
-                .iter()
-                .map(|info| {
-                    let mut path = self.download_dir.clone();
-                    path.push(info.relative_filename.to_path_buf());
-                    path
-                })
-                .collect()
-        })?;
+        let session_id = entry.session_id.unwrap();
+
+        let handle = self
+            .session
+            .get(TorrentIdOrHash::Id(session_id))
+            .context("Session torrent not found")?;
+
+        let stats = handle.stats();
+        let only_files = handle.only_files();
+
+        // Get torrent metadata - filter to video files (.mkv, .mp4, .avi, .mov)
+        let files: Vec<TorrentFile> = handle
+            .with_metadata(|meta| {
+                meta.file_infos
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(index, file_info)| {
+                        let filename = file_info
+                            .relative_filename
+                            .to_string_lossy()
+                            .to_string();
+                        let lower = filename.to_lowercase();
+                        
+                        if lower.ends_with(".mkv") || lower.ends_with(".mp4") || lower.ends_with(".avi") || lower.ends_with(".mov") {
+                            let downloaded_bytes = stats.file_progress.get(index).copied().unwrap_or(0);
+                            Some(TorrentFile {
+                                index,
+                                name: file_info
+                                    .relative_filename
+                                    .file_name()
+                                    .and_then(|n| n.to_str())
+                                    .unwrap_or("unknown")
+                                    .to_string(),
+                                size: file_info.len,
+                                path: filename,
+                                downloaded_bytes,
+                                progress: file_percent(downloaded_bytes, file_info.len),
+                                selected: only_files.as_ref().map_or(true, |only| only.contains(&index)),
+                            })
+                        } else {
+                            None
+                        }
+                    })
+                    .collect()
+            })?;
+
+        let torrent_name = handle.name().unwrap_or_else(|| "Unknown".to_string());
+        let is_paused = handle.is_paused();
         
-        // Delete each file
-        let mut deleted_count = 0;
-        for path in &file_paths {
-            if path.exists() {
-                match tokio::fs::remove_file(&path).await {
-                    Ok(_) => {
-                        tracing::info!("Deleted file: {:?}", path);
-                        deleted_count += 1;
+        // Determine state: when stats.live is None, torrent is checking/hashing
+        let state = if is_paused {
+            "paused".to_string()
+        } else if stats.live.is_none() {
+            "checking".to_string()
+        } else {
+            "live".to_string()
+        };
+
+        let (download_speed, upload_speed) = self.smoothed_speeds(handle_id, &stats).await;
+        let web_seeds = self.web_seeds.read().await.get(&session_id).cloned();
+
+        // Progress of the files being downloaded rather than the whole torrent, which for a
+        // season pack with one episode selected would never get anywhere
+        let selected: Vec<&TorrentFile> = files.iter().filter(|f| f.selected).collect();
+        let (selected_downloaded, selected_size) = if selected.is_empty() {
+            (stats.progress_bytes, stats.total_bytes)
+        } else {
+            (
+                selected.iter().map(|f| f.downloaded_bytes).sum(),
+                selected.iter().map(|f| f.size).sum(),
+            )
+        };
+        let remaining_bytes = selected_size.saturating_sub(selected_downloaded);
+
+        let streamable_eta_seconds = match selected.iter().max_by_key(|f| f.size).copied().or_else(|| files.iter().max_by_key(|f| f.size)) {
+            Some(file) => {
+                let duration = self.streams.duration((session_id, file.index)).await;
+                let bytes_per_second = match duration {
+                    Some(d) => (file.size as f64 / d) as u64,
+                    None => FALLBACK_BYTES_PER_SECOND,
+                };
+                let required_buffer = startup_buffer_bytes(bytes_per_second, download_speed, file.size);
+                eta_seconds(required_buffer.saturating_sub(file.downloaded_bytes), download_speed)
+            }
+            None => None,
+        };
+
+        Ok(TorrentInfo {
+            handle_id,
+            name: torrent_name,
+            size: files.iter().map(|f| f.size).sum(),
+            files,
+            progress: file_percent(selected_downloaded, selected_size),
+            download_speed,
+            upload_speed,
+            peers: stats.live.as_ref().map(|l| l.snapshot.peer_stats.live).unwrap_or(0),
+            is_paused,
+            state,
+            eta_seconds: if stats.finished { Some(0) } else { eta_seconds(remaining_bytes, download_speed) },
+            streamable_eta_seconds,
+            web_seeds: web_seeds.as_ref().map(|w| w.urls.len()).unwrap_or(0),
+            web_seed_bytes: web_seeds.as_ref().map(|w| w.bytes()).unwrap_or(0),
+        })
+    }
+
+    /// Exponential moving average of the transfer speeds in bytes/sec, weighted by the time
+    /// since the last sample so callers polling at different rates see the same curve
+    async fn smoothed_speeds(&self, handle_id: usize, stats: &librqbit::TorrentStats) -> (u64, u64) {
+        let (download, upload) = stats.live.as_ref()
+            .map(|l| (mib_to_bytes(l.download_speed.mbps), mib_to_bytes(l.upload_speed.mbps)))
+            .unwrap_or((0.0, 0.0));
+
+        let now = std::time::Instant::now();
+        let mut averages = self.speed_averages.write().await;
+        let average = averages.entry(handle_id).or_insert(SpeedAverage {
+            download,
+            upload,
+            updated_at: now,
+        });
+
+        let elapsed = now.duration_since(average.updated_at).as_secs_f64();
+        let alpha = 1.0 - (-elapsed / SPEED_SMOOTHING_SECS).exp();
+        average.download += alpha * (download - average.download);
+        average.upload += alpha * (upload - average.upload);
+        average.updated_at = now;
+
+        (average.download as u64, average.upload as u64)
+    }
+
+    /// Paths of files in the session that are fully downloaded, for the local library
+    pub async fn completed_files(&self) -> Vec<PathBuf> {
+        let session_ids: Vec<usize> = self.torrents.read().await
+            .values()
+            .filter_map(|entry| entry.session_id)
+            .collect();
+
+        let mut completed = Vec::new();
+        for session_id in session_ids {
+            let Some(handle) = self.session.get(TorrentIdOrHash::Id(session_id)) else { continue };
+            let file_progress = handle.stats().file_progress;
+            let files = handle.with_metadata(|meta| {
+                meta.file_infos.iter()
+                    .enumerate()
+                    .filter(|(index, f)| f.len > 0 && file_progress.get(*index).copied() == Some(f.len))
+                    .map(|(_, f)| self.download_dir.join(&f.relative_filename))
+                    .collect::<Vec<_>>()
+            });
+            if let Ok(files) = files {
+                completed.extend(files);
+            }
+        }
+        completed
+    }
+
+    /// Torrents that have finished downloading, with their largest file which is usually
+    /// the episode or movie itself
+    pub async fn finished_torrents(&self) -> Vec<FinishedTorrent> {
+        let session_ids: Vec<(usize, usize)> = self.torrents.read().await
+            .iter()
+            .filter_map(|(handle_id, entry)| entry.session_id.map(|id| (*handle_id, id)))
+            .collect();
+
+        let mut finished = Vec::new();
+        for (handle_id, session_id) in session_ids {
+            let Some(handle) = self.session.get(TorrentIdOrHash::Id(session_id)) else { continue };
+            if !handle.stats().finished {
+                continue;
+            }
+            let main_file = handle.with_metadata(|meta| {
+                meta.file_infos.iter()
+                    .max_by_key(|f| f.len)
+                    .map(|f| self.download_dir.join(&f.relative_filename))
+            });
+            if let Ok(Some(main_file)) = main_file {
+                finished.push(FinishedTorrent {
+                    handle_id,
+                    name: handle.name().unwrap_or_else(|| "Unknown".to_string()),
+                    main_file,
+                });
+            }
+        }
+        finished
+    }
+
+    /// Aggregate numbers for a status bar. Cache sizes live outside the torrent manager
+    /// and are left at zero for the caller to fill in.
+    pub async fn get_session_stats(&self) -> Result<SessionStats> {
+        let session_ids: Vec<(usize, usize)> = self.torrents.read().await
+            .iter()
+            .filter_map(|(handle_id, entry)| entry.session_id.map(|id| (*handle_id, id)))
+            .collect();
+
+        let mut stats = SessionStats {
+            active_torrents: 0,
+            paused_torrents: 0,
+            download_speed: 0,
+            upload_speed: 0,
+            downloaded_bytes: 0,
+            uploaded_bytes: 0,
+            download_dir_bytes: 0,
+            cached_torrents: self.torrent_cache.read().await.len(),
+            audio_cache_bytes: 0,
+            subtitle_cache_bytes: 0,
+        };
+
+        for (handle_id, session_id) in session_ids {
+            let Some(handle) = self.session.get(TorrentIdOrHash::Id(session_id)) else {
+                continue;
+            };
+            let torrent_stats = handle.stats();
+
+            if handle.is_paused() {
+                stats.paused_torrents += 1;
+            } else {
+                stats.active_torrents += 1;
+            }
+
+            let (download_speed, upload_speed) = self.smoothed_speeds(handle_id, &torrent_stats).await;
+            stats.download_speed += download_speed;
+            stats.upload_speed += upload_speed;
+            stats.downloaded_bytes += torrent_stats.live.as_ref().map(|l| l.snapshot.fetched_bytes).unwrap_or(0);
+            stats.uploaded_bytes += torrent_stats.uploaded_bytes;
+        }
+
+        let download_dir = self.download_dir.clone();
+        stats.download_dir_bytes = tokio::task::spawn_blocking(move || {
+            crate::media_cache::MediaCache::get_dir_size(&download_dir)
+        }).await?;
+
+        Ok(stats)
+    }
+
+    pub async fn list_torrents(&self) -> Result<Vec<TorrentInfo>> {
+        let torrents = self.torrents.read().await;
+        let mut result = Vec::new();
+
+        for (our_id, _) in torrents.iter() {
+            if let Ok(info) = self.get_torrent_info(*our_id).await {
+                result.push(info);
+            }
+        }
+
+        Ok(result)
+    }
+
+    pub async fn prepare_stream(&self, handle_id: usize, file_index: usize) -> Result<()> {
+        // Other handles with an open stream count against the cap; this handle moving on
+        // to another file doesn't
+        let max_streams = MAX_CONCURRENT_STREAMS.load(std::sync::atomic::Ordering::Relaxed);
+        let open_handles: std::collections::HashSet<usize> = self.streams.open_streams().await
+            .into_iter()
+            .map(|(_, open_handle)| open_handle)
+            .filter(|open_handle| *open_handle != handle_id)
+            .collect();
+        if open_handles.len() >= max_streams {
+            return Err(anyhow::anyhow!(crate::i18n::t_args(
+                "error.too_many_streams",
+                &[("count", &open_handles.len())]
+            )));
+        }
+
+        let torrents = self.torrents.read().await;
+        let entry = torrents
+            .get(&handle_id)
+            .context("Torrent handle not found")?;
+        
+        // Check if this torrent is in the cache
+        let mut cache = self.torrent_cache.write().await;
+        let cached_session_id = cache.iter()
+            .find(|ct| ct.handle_id == handle_id)
+            .map(|ct| ct.session_id);
+        
+        let mut resumed = None;
+        if let Some(session_id) = cached_session_id {
+            tracing::info!("Found cached torrent for handle_id {}, resuming session_id {}", handle_id, session_id);
+            
+            // Remove from cache
+            cache.retain(|ct| ct.handle_id != handle_id);
+            drop(cache);
+            
+            // Resume the torrent
+            if let Some(handle) = self.session.get(TorrentIdOrHash::Id(session_id)) {
+                // Resume if paused
+                if handle.is_paused() {
+                    self.session.unpause(&handle).await?;
+                }
+                
+                tracing::info!("Resumed cached torrent, session_id {} for handle_id {}", session_id, handle_id);
+                resumed = Some((session_id, handle));
+            } else {
+                tracing::warn!("Cached session_id {} not found in session, adding fresh", session_id);
+            }
+        } else {
+            drop(cache);
+        }
+
+        // Another file of a torrent that's already in the session (the next episode of a
+        // batch, or the same release opened from another handle): keep that session and
+        // its data and only change which files download, below
+        if resumed.is_none() {
+            if let Some((session_id, handle)) = self.existing_session(&torrents, handle_id, &entry.magnet_url) {
+                tracing::info!("Reusing session_id {} for handle_id {} file {}", session_id, handle_id, file_index);
+                if handle.is_paused() {
+                    self.session.unpause(&handle).await?;
+                }
+                // It's in use again, so it mustn't be evicted as a cached torrent
+                self.torrent_cache.write().await.retain(|ct| ct.session_id != session_id);
+                resumed = Some((session_id, handle));
+            }
+        }
+        
+        let (session_id, handle) = match resumed {
+            Some(resumed) => resumed,
+            None => {
+                // Add the torrent with ONLY the specific file selected
+                let (add_torrent, trackers, web_seeds) = torrent_source(&entry.magnet_url).await?;
+                
+                tracing::info!("Preparing stream for file index {}", file_index);
+                
+                let opts = AddTorrentOptions {
+                    overwrite: true,
+                    paused: false,
+                    only_files: Some(vec![file_index]),
+                    force_tracker_interval: Some(std::time::Duration::from_secs(5)), // Request peers faster
+                    trackers,
+                    ..Default::default()
+                };
+                
+                let response = self.session.add_torrent(add_torrent, Some(opts)).await?;
+                let (session_id, handle) = match response {
+                    AddTorrentResponse::Added(id, h) => (id, h),
+                    AddTorrentResponse::AlreadyManaged(id, h) => {
+                        tracing::info!("Torrent already managed, reusing existing download");
+                        if h.is_paused() {
+                            self.session.unpause(&h).await?;
+                        }
+                        (id, h)
                     }
-                    Err(e) => {
-                        tracing::warn!("Failed to delete file {:?}: {}", path, e);
+                    AddTorrentResponse::ListOnly(_) => {
+                        return Err(anyhow::anyhow!("Unexpected list_only response"));
+                    }
+                };
+                self.register_web_seeds(session_id, web_seeds).await;
+                (session_id, handle)
+            }
+        };
+        
+        tracing::info!("Setting session_id {} for handle_id {}", session_id, handle_id);
+        drop(torrents);
+        let mut torrents = self.torrents.write().await;
+        if let Some(entry) = torrents.get_mut(&handle_id) {
+            entry.session_id = Some(session_id);
+            tracing::info!("Successfully updated entry.session_id to {}", session_id);
+        }
+        drop(torrents);
+
+        // A handle plays one file at a time; whatever it played before is released
+        let stream_key = (session_id, file_index);
+        self.streams.stop_matching(|key, s| s.handle_id == Some(handle_id) && key != stream_key).await;
+        self.streams.update(stream_key, |s| s.handle_id = Some(handle_id)).await;
+        self.select_stream_files(session_id, &handle).await?;
+        
+        Ok(())
+    }
+
+    /// The session already holding `magnet_url`'s torrent: the handle's own, another
+    /// handle's for the same magnet, or one with the same info hash
+    fn existing_session(
+        &self,
+        torrents: &HashMap<usize, TorrentEntry>,
+        handle_id: usize,
+        magnet_url: &str,
+    ) -> Option<(usize, librqbit::ManagedTorrentHandle)> {
+        let own = torrents.get(&handle_id).and_then(|e| e.session_id);
+        let shared = torrents.values()
+            .filter(|e| e.magnet_url == magnet_url)
+            .filter_map(|e| e.session_id);
+        if let Some(found) = own.into_iter().chain(shared)
+            .find_map(|id| self.session.get(TorrentIdOrHash::Id(id)).map(|h| (id, h)))
+        {
+            return Some(found);
+        }
+        self.session_by_hash(magnet_url)
+    }
+
+    /// The session's torrent with `magnet_url`'s info hash, if it holds one
+    fn session_by_hash(&self, magnet_url: &str) -> Option<(usize, librqbit::ManagedTorrentHandle)> {
+        use std::str::FromStr;
+        let hash = magnet_info_hash(magnet_url)?;
+        let id = librqbit::dht::Id20::from_str(&hash).ok()?;
+        let handle = self.session.get(TorrentIdOrHash::Hash(id))?;
+        Some((handle.id(), handle))
+    }
+
+    /// Downloads exactly the files open streams play from the torrent, plus any the user
+    /// picked with `update_file_selection`, so a second episode of a season pack doesn't
+    /// replace the first one's selection. Torrents that download every file are left alone.
+    async fn select_stream_files(&self, session_id: usize, handle: &librqbit::ManagedTorrentHandle) -> Result<()> {
+        let Some(current) = handle.only_files() else {
+            return Ok(());
+        };
+        let mut wanted: std::collections::HashSet<usize> = self.streams.open_streams().await
+            .into_iter()
+            .filter(|((id, _), _)| *id == session_id)
+            .map(|((_, file_index), _)| file_index)
+            .collect();
+        if let Some(selected) = self.selected_files.read().await.get(&session_id) {
+            wanted.extend(selected.iter().copied());
+        }
+        let current: std::collections::HashSet<usize> = current.into_iter().collect();
+        if wanted.is_empty() || wanted == current {
+            return Ok(());
+        }
+        tracing::info!("Streaming files {:?} of session_id {}", wanted, session_id);
+        self.session.update_only_files(handle, &wanted).await
+    }
+
+    /// Sets which files of a running torrent download, e.g. to queue the next episode of a
+    /// batch while the current one plays. Files being streamed stay selected whatever is
+    /// passed, and data already downloaded for a deselected file is kept. Returns the
+    /// files now downloading.
+    pub async fn update_file_selection(&self, handle_id: usize, file_indices: Vec<usize>) -> Result<Vec<usize>> {
+        let session_id = self.torrents.read().await
+            .get(&handle_id)
+            .context("Torrent handle not found")?
+            .session_id
+            .context("Torrent not yet added to session")?;
+        let handle = self.session.get(TorrentIdOrHash::Id(session_id)).context("Session torrent not found")?;
+
+        let file_count = handle.with_metadata(|meta| meta.file_infos.len())?;
+        if let Some(invalid) = file_indices.iter().find(|i| **i >= file_count) {
+            anyhow::bail!("File index {} is out of range, the torrent has {} files", invalid, file_count);
+        }
+
+        let selected: std::collections::BTreeSet<usize> = file_indices.into_iter().collect();
+        let mut wanted: std::collections::HashSet<usize> = self.streams.open_streams().await
+            .into_iter()
+            .filter(|((id, _), _)| *id == session_id)
+            .map(|((_, file_index), _)| file_index)
+            .collect();
+        wanted.extend(selected.iter().copied());
+        if wanted.is_empty() {
+            anyhow::bail!("Select at least one file to download");
+        }
+
+        tracing::info!("File selection for handle_id={} (session_id={}): {:?}", handle_id, session_id, selected);
+        self.selected_files.write().await.insert(session_id, selected);
+        self.session.update_only_files(&handle, &wanted).await?;
+
+        let mut files: Vec<usize> = wanted.into_iter().collect();
+        files.sort_unstable();
+        Ok(files)
+    }
+
+    pub async fn get_stream_status(&self, handle_id: usize, file_index: usize) -> Result<StreamStatus> {
+        tracing::info!("[Transcode] get_stream_status called: handle_id={}, file_index={}", handle_id, file_index);
+        
+        let torrents = self.torrents.read().await;
+        let entry = torrents
+            .get(&handle_id)
+            .context("Torrent handle not found")?;
+            
+        let session_id = entry.session_id.context("Torrent not yet added to session")?;
+        tracing::info!("get_stream_status for handle_id={}, session_id={}, file_index={}", handle_id, session_id, file_index);
+        
+        let handle = self.session.get(TorrentIdOrHash::Id(session_id)).context("Session torrent not found")?;
+        let stats = handle.stats();
+        
+        let file_info = handle.with_metadata(|meta| {
+            meta.file_infos.get(file_index).map(|fi| (
+                fi.relative_filename.clone(),
+                fi.len
+            ))
+        })?.context("File index out of range")?;
+
+        let (file_name_path, file_size) = file_info;
+        let file_name = file_name_path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or("unknown")
+            .to_string();
+
+        // Size the startup buffer from the file's bitrate and the current download speed
+        let duration = self.streams.duration((session_id, file_index)).await;
+        let bytes_per_second = match duration {
+            Some(d) => (file_size as f64 / d) as u64,
+            None => FALLBACK_BYTES_PER_SECOND,
+        };
+        let (download_bytes_per_second, _) = self.smoothed_speeds(handle_id, &stats).await;
+        // The stream server falls back to web seeds when peers can't keep up, so a slow
+        // swarm doesn't need the extra buffer; some data must still be downloaded
+        let has_web_seeds = self.web_seeds.read().await.contains_key(&session_id);
+        let required_buffer = if has_web_seeds {
+            std::cmp::min(MIN_STARTUP_BUFFER_BYTES, file_size)
+        } else {
+            startup_buffer_bytes(bytes_per_second, download_bytes_per_second, file_size)
+        };
+
+        // Check if ready
+        // We need to ensure:
+        // 1. The stream can be created (handle.stream succeeds)
+        // 2. We have enough data to start without stalling right away (or finished)
+        let is_streamable = handle.clone().stream(file_index).is_ok();
+        // Readiness goes by the file being played; other files of a batch don't help it start
+        let file_downloaded = stats.file_progress.get(file_index).copied().unwrap_or(0);
+        let fully_downloaded = file_size > 0 && file_downloaded >= file_size;
+        let has_buffer = file_downloaded >= required_buffer || fully_downloaded || stats.finished;
+        
+        let is_ready = is_streamable && has_buffer;
+
+        let estimated_seconds_until_ready = if has_buffer {
+            None
+        } else if download_bytes_per_second > 0 {
+            Some(required_buffer.saturating_sub(file_downloaded) as f64 / download_bytes_per_second as f64)
+        } else {
+            None
+        };
+        
+        if !is_ready {
+            tracing::debug!(
+                "Stream not ready: streamable={}, buffer={} ({}/{} bytes), finished={}", 
+                is_streamable, has_buffer, file_downloaded, required_buffer, stats.finished
+            );
+        }
+        
+        // Check transcoding state
+        let stream_key = (session_id, file_index);
+        let transcode = self.streams.read(stream_key, |s| s.transcode.as_ref().map(|t| (t.progress, t.completed))).await.flatten();
+        let transcode_progress = transcode.map(|(progress, _)| progress);
+        let transcode_completed = transcode.map(|(_, completed)| completed).unwrap_or(false);
+        
+        // Completed files are hash checked before they're treated as downloaded
+        let verification = if fully_downloaded {
+            let existing = self.file_verifications.read().await.get(&(session_id, file_index)).cloned();
+            match existing {
+                Some(Some(v)) if v.ok => "verified",
+                Some(Some(_)) => "corrupt",
+                Some(None) => "verifying",
+                None => {
+                    if let Err(e) = self.start_file_verification(session_id, file_index).await {
+                        tracing::warn!("Failed to start verification: {}", e);
+                    }
+                    "verifying"
+                }
+            }
+        } else {
+            "incomplete"
+        };
+
+        let stream_info = if is_ready {
+             // Extract metadata for supported video formats
+            let lower = file_name.to_lowercase();
+            tracing::info!("[Transcode] File name: {}, stats: {}/{} bytes", file_name, file_downloaded, file_size);
+            let mut metadata = if lower.ends_with(".mkv") || lower.ends_with(".mp4") || lower.ends_with(".avi") || lower.ends_with(".mov") {
+                // If fully downloaded and verified, use the actual file
+                if fully_downloaded && verification == "verified" {
+                    tracing::info!("[Transcode] File fully downloaded, extracting metadata from disk");
+                    let file_path = self.download_dir.join(&file_name_path);
+                    tracing::info!("[Transcode] File path: {:?}", file_path);
+                    tracing::info!("[Transcode] File exists: {}", file_path.exists());
+                    if file_path.exists() {
+                        match extract_mkv_metadata_ffprobe(&file_path).await {
+                            Ok(meta) => {
+                                tracing::info!("[Transcode] Successfully extracted metadata from file");
+                                Some(meta)
+                            },
+                            Err(e) => {
+                                tracing::warn!("[Transcode] Failed to extract metadata: {}", e);
+                                None
+                            }
+                        }
+                    } else {
+                        tracing::info!("[Transcode] File doesn't exist on disk yet, checking cache");
+                        let cached = self.streams.metadata(stream_key).await;
+                        tracing::info!("[Transcode] Metadata cache contains entry: {}", cached.is_some());
+                        cached
+                    }
+                } else {
+                    tracing::info!("[Transcode] File not fully downloaded, checking metadata cache");
+                    // Try to get from the stream session (populated by /metadata/ endpoint)
+                    let cached = self.streams.metadata(stream_key).await;
+                    tracing::info!("[Transcode] Metadata cache contains entry: {}", cached.is_some());
+                    cached
+                }
+            } else {
+                tracing::info!("[Transcode] File format not supported for metadata extraction");
+                None
+            };
+            tracing::info!("[Transcode] Metadata result: {}", if metadata.is_some() { "Some" } else { "None" });
+            
+            // If transcoding is needed and not yet started, start it
+            if let Some(ref mut meta) = metadata {
+                tracing::info!("[Transcode] Metadata needs_audio_transcoding: {}", meta.needs_audio_transcoding);
+                if meta.needs_audio_transcoding {
+                    // Mark transcoding as started immediately - no waiting for download
+                    let transcoding_started = self.streams.update(stream_key, |s| {
+                        let started = s.transcode.is_some();
+                        if !started {
+                            s.transcode = Some(TranscodeState {
+                                progress: 0.0,
+                                output_path: None,
+                                completed: false,
+                                error: None,
+                            });
+                        }
+                        started
+                    }).await;
+                    
+                    if !transcoding_started {
+                        tracing::info!("[Transcode] Transcoding ready for immediate on-demand streaming at {}", file_name);
+                        
+                        // Add transcoded URLs for each audio track that needs transcoding
+                        for (track_idx, track) in meta.audio_tracks.iter_mut().enumerate() {
+                            if track.needs_transcoding {
+                                track.transcoded_url = Some(format!(
+                                    "http://{}/torrents/{}/transcoded-audio-stream/{}/{}",
+                                    self.http_addr,
+                                    session_id,
+                                    file_index,
+                                    track_idx
+                                ));
+                                tracing::info!("[Transcode] Track {} ({}) ready for immediate piped transcoding", 
+                                    track_idx, track.codec.as_deref().unwrap_or("unknown"));
+                            }
+                        }
+                        
+                        // Keep legacy field for backward compatibility (first track needing transcode)
+                        if meta.audio_tracks.first().map(|t| t.needs_transcoding).unwrap_or(false) {
+                            meta.transcoded_audio_url = Some(format!(
+                                "http://{}/torrents/{}/transcoded-audio-stream/{}/0",
+                                self.http_addr,
+                                session_id,
+                                file_index
+                            ));
+                        }
+                    }
+                }
+            }
+
+            Some(StreamInfo {
+                url: format!(
+                    "http://{}/torrents/{}/stream/{}",
+                    self.http_addr,
+                    session_id,
+                    file_index
+                ),
+                file_name,
+                file_size,
+                metadata,
+            })
+        } else {
+            None
+        };
+
+        // Determine the current state
+        let state = if transcode_progress.is_some() && !transcode_completed {
+            "transcoding".to_string()
+        } else if stats.live.is_none() {
+            "checking".to_string()
+        } else {
+            "downloading".to_string()
+        };
+        
+        // Check if transcoding is needed from either stream_info metadata or the cache
+        let needs_transcoding_from_stream = stream_info.as_ref()
+            .and_then(|s| s.metadata.as_ref())
+            .map(|m| m.needs_audio_transcoding)
+            .unwrap_or(false);
+        
+        let needs_transcoding_from_cache = self.streams.metadata(stream_key).await
+            .map(|m| m.needs_audio_transcoding)
+            .unwrap_or(false);
+        
+        let needs_audio_transcoding = needs_transcoding_from_stream || needs_transcoding_from_cache;
+        
+        // Check if on-demand transcoding is ready (state exists but not completed means streaming is ready)
+        let transcode_streaming_ready = transcode_progress.is_some() && !transcode_completed;
+        
+        // Determine status
+        let status = if !is_ready {
+            "initializing".to_string()
+        } else if needs_audio_transcoding && !transcode_streaming_ready {
+            // Still waiting for minimum download before transcoding can start
+            "transcoding".to_string()
+        } else {
+            // Ready means either no transcoding needed, or on-demand transcoding is available
+            "ready".to_string()
+        };
+        
+        tracing::debug!("Stream status: is_ready={}, needs_transcoding={}, transcode_completed={}, status={}", 
+            is_ready, needs_audio_transcoding, transcode_completed, status);
+
+        // Buffering from the first status check; the player's position reports move it on to playing
+        let stream_url = stream_info.as_ref().map(|info| info.url.clone());
+        self.streams.update(stream_key, |s| {
+            s.handle_id = Some(handle_id);
+            s.advance(StreamPhase::Buffering);
+            if stream_url.is_some() {
+                s.url = stream_url;
+            }
+        }).await;
+
+        Ok(StreamStatus {
+            status,
+            progress_bytes: file_downloaded,
+            total_bytes: file_size,
+            peers: stats.live.as_ref().map(|l| l.snapshot.peer_stats.live).unwrap_or(0),
+            download_speed: download_bytes_per_second,
+            stream_info,
+            state,
+            transcode_progress,
+            buffer_target_bytes: required_buffer,
+            estimated_seconds_until_ready,
+            eta_seconds: if stats.finished || fully_downloaded {
+                Some(0)
+            } else {
+                eta_seconds(file_size.saturating_sub(file_downloaded), download_bytes_per_second)
+            },
+            verification: verification.to_string(),
+        })
+    }
+    
+    /// Probes the header of a prepared file with ffprobe so the player can choose between
+    /// direct play, audio transcode and full transcode before starting playback
+    pub async fn probe_stream_compatibility(&self, handle_id: usize, file_index: usize) -> Result<StreamCompatibility> {
+        let session_id = {
+            let torrents = self.torrents.read().await;
+            let entry = torrents.get(&handle_id).context("Torrent handle not found")?;
+            entry.session_id.context("Stream not prepared yet")?
+        };
+
+        let handle = self.session
+            .get(TorrentIdOrHash::Id(session_id))
+            .context("Torrent not found in session")?;
+
+        let (file_size, file_name) = handle
+            .with_metadata(|meta| {
+                meta.file_infos.get(file_index).map(|f| (f.len, f.relative_filename.to_string_lossy().to_string()))
+            })?
+            .context("File not found")?;
+
+        // Container headers and the first frames are enough for stream info, unlike chapters/tags
+        let probe_size = std::cmp::min(file_size, 16 * 1024 * 1024) as usize;
+        let mut stream = handle.stream(file_index)?;
+
+        let extension = std::path::Path::new(&file_name)
+            .extension()
+            .and_then(|e| e.to_str())
+            .unwrap_or("mkv")
+            .to_lowercase();
+        // Removed when dropped, whichever way this returns
+        let probe_file = tempfile::Builder::new()
+            .prefix("magnolia_probe_")
+            .suffix(&format!(".{}", extension))
+            .tempfile()?;
+        let mut temp_file = tokio::fs::File::from_std(probe_file.reopen()?);
+
+        let mut buffer = vec![0u8; 1024 * 1024];
+        let mut total_read = 0usize;
+        let mut consecutive_empty_reads = 0;
+        while total_read < probe_size {
+            let n = stream.read(&mut buffer[..std::cmp::min(buffer.len(), probe_size - total_read)]).await?;
+            if n == 0 {
+                consecutive_empty_reads += 1;
+                if consecutive_empty_reads >= 100 {
+                    break;
+                }
+                tokio::time::sleep(tokio::time::Duration::from_millis(200)).await;
+                continue;
+            }
+            consecutive_empty_reads = 0;
+            tokio::io::AsyncWriteExt::write_all(&mut temp_file, &buffer[..n]).await?;
+            total_read += n;
+        }
+        temp_file.sync_all().await?;
+        drop(temp_file);
+
+        tracing::info!("Probing {} bytes of {} for compatibility", total_read, file_name);
+
+        let result = if total_read < std::cmp::min(probe_size, 2 * 1024 * 1024) {
+            Err(anyhow::anyhow!("Not enough data available yet to probe the file"))
+        } else {
+            probe_compatibility_ffprobe(probe_file.path()).await
+        };
+        drop(probe_file);
+
+        let mut compatibility = result?;
+        if compatibility.hdr.is_some() {
+            compatibility.tonemapped_url = Some(format!(
+                "http://{}/torrents/{}/tonemapped-stream/{}",
+                self.http_addr,
+                session_id,
+                file_index
+            ));
+        }
+        Ok(compatibility)
+    }
+
+    /// Keeps the pieces just ahead of the playhead prioritized. librqbit prioritizes pieces
+    /// that open file streams are waiting on, so a background stream reads through the
+    /// window ahead of the current position and is restarted whenever the user seeks.
+    pub async fn report_playback_position(&self, handle_id: usize, file_index: usize, seconds: f64) -> Result<()> {
+        let session_id = {
+            let torrents = self.torrents.read().await;
+            let entry = torrents.get(&handle_id).context("Torrent handle not found")?;
+            entry.session_id.context("Stream not prepared yet")?
+        };
+
+        let handle = self.session
+            .get(TorrentIdOrHash::Id(session_id))
+            .context("Torrent not found in session")?;
+
+        let file_size = handle
+            .with_metadata(|meta| meta.file_infos.get(file_index).map(|f| f.len))?
+            .context("File not found")?;
+
+        // Map time to bytes using the average bitrate from the probed duration
+        let duration = self.streams.duration((session_id, file_index)).await;
+        let bytes_per_second = match duration {
+            Some(d) => (file_size as f64 / d) as u64,
+            None => FALLBACK_BYTES_PER_SECOND,
+        };
+
+        let position = std::cmp::min((seconds.max(0.0) * bytes_per_second as f64) as u64, file_size);
+        let window = std::cmp::max(bytes_per_second * READAHEAD_SECONDS, MIN_READAHEAD_BYTES);
+        let end = std::cmp::min(position + window, file_size);
+
+        let stream_key = (session_id, file_index);
+        let still_ahead = self.streams.read(stream_key, |s| {
+            s.playback_window.as_ref().is_some_and(|current| {
+                // Still inside the first half of the current window, nothing to do
+                position >= current.start
+                    && position < current.start + (current.end - current.start) / 2
+                    && !current.task.is_finished()
+            })
+        }).await.unwrap_or(false);
+        if still_ahead {
+            return Ok(());
+        }
+
+        let mut stream = handle.stream(file_index)?;
+        let task = tokio::spawn(async move {
+            if let Err(e) = stream.seek(std::io::SeekFrom::Start(position)).await {
+                tracing::warn!("Readahead seek to {} failed: {}", position, e);
+                return;
+            }
+            let mut buffer = vec![0u8; 256 * 1024];
+            let mut remaining = end - position;
+            while remaining > 0 {
+                let len = std::cmp::min(buffer.len() as u64, remaining) as usize;
+                match stream.read(&mut buffer[..len]).await {
+                    Ok(0) => break,
+                    Ok(n) => remaining -= n as u64,
+                    Err(e) => {
+                        tracing::warn!("Readahead read failed: {}", e);
+                        break;
+                    }
+                }
+            }
+        });
+
+        tracing::info!("Readahead window for handle_id={} file={} at {:.1}s: bytes {}-{}", handle_id, file_index, seconds, position, end);
+        self.streams.update(stream_key, |s| {
+            if let Some(previous) = s.playback_window.replace(PlaybackWindow { start: position, end, task }) {
+                previous.task.abort();
+            }
+            s.handle_id = Some(handle_id);
+            s.advance(StreamPhase::Playing);
+        }).await;
+
+        Ok(())
+    }
+
+    /// Called periodically by the stall watchdog. Streams that are being played and still
+    /// need data (see `waiting_for_data`) but receive nothing get their peer discovery
+    /// restarted, and are returned once they stay stalled long enough to be worth
+    /// switching releases.
+    pub async fn check_stalled_streams(&self) -> Vec<StalledStream> {
+        let mut stalled_streams = Vec::new();
+        for ((session_id, file_index), handle_id) in self.streams.stall_candidates().await {
+            let stream_key = (session_id, file_index);
+            let Some(handle) = self.session.get(TorrentIdOrHash::Id(session_id)) else {
+                continue;
+            };
+            // Nothing arrives while paused, which isn't a stall
+            if handle.is_paused() {
+                self.streams.update_existing(stream_key, |s| s.stall = None).await;
+                continue;
+            }
+
+            let stats = handle.stats();
+            let speed = stats.live.as_ref().map(|l| l.download_speed.mbps).unwrap_or(0.0);
+            let file_size = handle.with_metadata(|meta| meta.file_infos.get(file_index).map(|fi| fi.len)).ok().flatten();
+            let file_complete = file_size.is_some_and(|size| {
+                stats.file_progress.get(file_index).is_some_and(|&downloaded| downloaded >= size)
+            });
+            if stats.finished || file_complete || speed > 0.0 {
+                self.streams.update_existing(stream_key, |s| s.stall = None).await;
+                continue;
+            }
+
+            // (stalled seconds, restart peer discovery now, report now)
+            let Some((stalled_seconds, reannounce, report)) = self.streams.update_existing(stream_key, |s| {
+                let state = s.stall.get_or_insert_with(|| StallState {
+                    since: std::time::Instant::now(),
+                    reannounced: false,
+                    reported: false,
+                });
+                let stalled_seconds = state.since.elapsed().as_secs();
+                let reannounce = stalled_seconds >= STALL_REANNOUNCE_SECS && !state.reannounced;
+                let report = stalled_seconds >= STALL_REPORT_SECS && !state.reported;
+                state.reannounced |= reannounce;
+                state.reported |= report;
+                (stalled_seconds, reannounce, report)
+            }).await else {
+                continue;
+            };
+
+            if reannounce {
+                tracing::info!("Stream handle_id={} stalled for {}s, restarting peer discovery", handle_id, stalled_seconds);
+                if let Err(e) = self.restart_peer_discovery(&handle).await {
+                    tracing::warn!("Failed to restart stalled torrent: {}", e);
+                }
+            }
+
+            if report {
+                stalled_streams.push(StalledStream { handle_id, file_index, stalled_seconds });
+            }
+        }
+
+        stalled_streams
+    }
+
+    pub async fn stop_stream(&self, handle_id: usize, delete_files: bool) -> Result<()> {
+        tracing::info!("Stopping stream for handle_id: {}, delete_files: {}", handle_id, delete_files);
+        
+        // Readahead, metadata reads, transcodes and temp files all end with the stream
+        let session_id = self.torrents.read().await.get(&handle_id).and_then(|e| e.session_id);
+        if let Some(session_id) = session_id {
+            let shared = self.streams.open_streams().await
+                .iter()
+                .any(|((id, _), open_handle)| *id == session_id && *open_handle != handle_id);
+            if shared {
+                // Another player is still on this torrent, so it stays running
+                tracing::info!("session_id {} is still streaming elsewhere, only closing handle_id {}", session_id, handle_id);
+                return self.close_stream(handle_id).await;
+            }
+            self.streams.stop_torrent(session_id).await;
+            self.chapter_thumbnails.write().await.retain(|(id, _, _), _| *id != session_id);
+        }
+        self.speed_averages.write().await.remove(&handle_id);
+        
+        let mut torrents = self.torrents.write().await;
+        if let Some(entry) = torrents.get_mut(&handle_id) {
+            if let Some(session_id) = entry.session_id {
+                if delete_files {
+                    // Delete torrent completely from librqbit with all files
+                    tracing::info!("Deleting torrent session_id: {} completely with files", session_id);
+                    
+                    // First, manually delete the files to ensure they're removed
+                    if let Some(handle) = self.session.get(TorrentIdOrHash::Id(session_id)) {
+                        tracing::info!("Manually deleting files for session_id: {}", session_id);
+                        self.clear_torrent_files(session_id, &handle).await?;
+                    }
+                    
+                    // Then remove from librqbit
+                    entry.session_id = None;
+                    self.session.delete(TorrentIdOrHash::Id(session_id), true).await?;
+                    tracing::info!("Torrent session_id: {} deleted from librqbit", session_id);
+                } else {
+                    // Cache the torrent: pause it and clear file data
+                    tracing::info!("Caching torrent session_id: {} for handle_id: {}", session_id, handle_id);
+                    
+                    // Pause the torrent first
+                    if let Some(handle) = self.session.get(TorrentIdOrHash::Id(session_id)) {
+                        self.session.pause(&handle).await?;
+                        
+                        // Clear file data to save space
+                        self.clear_torrent_files(session_id, &handle).await?;
+                    }
+                    
+                    // Add to cache
+                    let cached_torrent = CachedTorrent {
+                        handle_id,
+                        session_id,
+                        magnet_url: entry.magnet_url.clone(),
+                        cached_at: std::time::SystemTime::now(),
+                    };
+                    
+                    let mut cache = self.torrent_cache.write().await;
+                    
+                    // Remove if already in cache (by handle_id or magnet_url)
+                    let magnet_url = entry.magnet_url.clone();
+                    cache.retain(|ct| ct.handle_id != handle_id && ct.magnet_url != magnet_url);
+                    
+                    // Add to front of cache
+                    cache.insert(0, cached_torrent);
+                    
+                    // Enforce 10 torrent limit
+                    while cache.len() > 10 {
+                        if let Some(oldest) = cache.pop() {
+                            tracing::info!("Cache limit reached, removing oldest cached torrent: handle_id={}, session_id={}", oldest.handle_id, oldest.session_id);
+                            // Remove from session completely
+                            self.session.delete(TorrentIdOrHash::Id(oldest.session_id), true).await?;
+                            
+                            // Clear session_id from torrent entry
+                            if let Some(old_entry) = torrents.get_mut(&oldest.handle_id) {
+                                old_entry.session_id = None;
+                            }
+                        }
+                    }
+                    
+                    tracing::info!("Torrent cached. Current cache size: {}", cache.len());
+                    drop(cache);
+                    
+                    // Save cache to disk
+                    if let Err(e) = self.save_cache_to_disk().await {
+                        tracing::error!("Failed to save cache to disk: {}", e);
+                    }
+                }
+            }
+        }
+        
+        Ok(())
+    }
+    
+    /// Ends the handle's stream without touching the torrent, freeing its slot under the
+    /// concurrent stream cap. Its file is deselected if other streams share the torrent.
+    pub async fn close_stream(&self, handle_id: usize) -> Result<()> {
+        let stopped = self.streams.stop_handle(handle_id).await;
+        self.speed_averages.write().await.remove(&handle_id);
+
+        let session_ids: std::collections::HashSet<usize> = stopped.iter().map(|(session_id, _)| *session_id).collect();
+        for session_id in session_ids {
+            if let Some(handle) = self.session.get(TorrentIdOrHash::Id(session_id)) {
+                self.select_stream_files(session_id, &handle).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Every open stream with what it's using: readahead, temp files and its file's download
+    pub async fn active_streams(&self) -> Vec<ActiveStream> {
+        let mut active = Vec::new();
+        for usage in self.streams.usage().await {
+            let Some(handle) = self.session.get(TorrentIdOrHash::Id(usage.session_id)) else {
+                continue;
+            };
+            let stats = handle.stats();
+            let file_size = handle
+                .with_metadata(|meta| meta.file_infos.get(usage.file_index).map(|fi| fi.len))
+                .ok()
+                .flatten()
+                .unwrap_or(0);
+            active.push(ActiveStream {
+                file_size,
+                downloaded_bytes: stats.file_progress.get(usage.file_index).copied().unwrap_or(0),
+                peers: stats.live.as_ref().map(|l| l.snapshot.peer_stats.live).unwrap_or(0),
+                usage,
+            });
+        }
+        active
+    }
+    
+    /// Clear file data for a cached torrent to save space while keeping metadata
+    async fn clear_torrent_files(&self, session_id: usize, handle: &librqbit::ManagedTorrent) -> Result<()> {
+        tracing::info!("Clearing file data for session_id: {}", session_id);
+        
+        // Get torrent name for the base directory
+        let torrent_name = handle.with_metadata(|meta| {
+            meta.info.name.as_ref()
+                .map(|n| n.to_string())
+                .unwrap_or_else(|| "unknown".to_string())
+        })?;
+        
+        // Get file paths
+        let file_paths: Vec<PathBuf> = handle.with_metadata(|meta| {
+            meta.file_infos
+                .iter()
+                .map(|info| {
+                    let mut path = self.download_dir.clone();
+                    path.push(info.relative_filename.to_path_buf());
+                    path
+                })
+                .collect()
+        })?;
+        
+        // Delete each file
+        let mut deleted_count = 0;
+        for path in &file_paths {
+            if path.exists() {
+                match tokio::fs::remove_file(&path).await {
+                    Ok(_) => {
+                        tracing::info!("Deleted file: {:?}", path);
+                        deleted_count += 1;
+                    }
+                    Err(e) => {
+                        tracing::warn!("Failed to delete file {:?}: {}", path, e);
+                    }
+                }
+            }
+        }
+        
+        // Delete the torrent's base directory if it exists and is now empty
+        let torrent_dir = self.download_dir.join(&torrent_name);
+        if torrent_dir.exists() && torrent_dir.is_dir() {
+            match tokio::fs::remove_dir_all(&torrent_dir).await {
+                Ok(_) => {
+                    tracing::info!("Deleted torrent directory: {:?}", torrent_dir);
+                }
+                Err(e) => {
+                    tracing::warn!("Failed to delete torrent directory {:?}: {}", torrent_dir, e);
+                }
+            }
+        }
+        
+        tracing::info!("Deleted {} files for session_id: {}", deleted_count, session_id);
+        Ok(())
+    }
+    
+    /// Save torrent cache to disk
+    async fn save_cache_to_disk(&self) -> Result<()> {
+        let cache = self.torrent_cache.read().await;
+        let cache_file = self.download_dir.join("torrent_cache.json");
+        
+        let json = serde_json::to_string_pretty(&*cache)?;
+        tokio::fs::write(&cache_file, json).await?;
+        
+        tracing::debug!("Saved {} cached torrents to disk", cache.len());
+        Ok(())
+    }
+    
+    /// Load torrent cache from disk and restore sessions
+    async fn load_cache_from_disk(&self) -> Result<()> {
+        let cache_file = self.download_dir.join("torrent_cache.json");
+        
+        if !cache_file.exists() {
+            return Ok(());
+        }
+        
+        let json = tokio::fs::read_to_string(&cache_file).await?;
+        let cached_torrents: Vec<CachedTorrent> = serde_json::from_str(&json)?;
+        
+        tracing::info!("Loading {} cached torrents from disk", cached_torrents.len());
+        
+        let mut restored_cache = Vec::new();
+        
+        for cached in cached_torrents {
+            // Restore the torrent session in paused state
+            // Don't use list_only - we want the torrent in the session with 0-byte files
+            let (add_torrent, trackers, web_seeds) = match torrent_source(&cached.magnet_url).await {
+                Ok(source) => source,
+                Err(e) => {
+                    tracing::warn!("Failed to load cached torrent handle_id={}: {}", cached.handle_id, e);
+                    continue;
+                }
+            };
+            
+            let opts = AddTorrentOptions {
+                overwrite: false,
+                paused: true, // Start paused to avoid downloading
+                only_files: None, // No specific files selected yet
+                trackers,
+                ..Default::default()
+            };
+            
+            match self.session.add_torrent(add_torrent, Some(opts)).await {
+                Ok(response) => {
+                    let session_id = match response {
+                        AddTorrentResponse::Added(id, _) => {
+                            tracing::info!("Restored cached torrent (newly added): handle_id={}, session_id={}", cached.handle_id, id);
+                            id
+                        }
+                        AddTorrentResponse::AlreadyManaged(id, _) => {
+                            tracing::info!("Restored cached torrent (already managed): handle_id={}, session_id={}", cached.handle_id, id);
+                            id
+                        }
+                        AddTorrentResponse::ListOnly(_) => {
+                            tracing::warn!("Got ListOnly response when trying to restore cached torrent handle_id={}", cached.handle_id);
+                            continue;
+                        }
+                    };
+                    
+                    self.register_web_seeds(session_id, web_seeds).await;
+                    
+                    // Add to restored cache regardless of session_id match
+                    // (session_id might be different if librqbit reassigns IDs)
+                    let mut updated_cached = cached.clone();
+                    updated_cached.session_id = session_id;
+                    restored_cache.push(updated_cached);
+                }
+                Err(e) => {
+                    tracing::warn!("Failed to restore cached torrent handle_id={}: {}", cached.handle_id, e);
+                }
+            }
+        }
+        
+        let mut cache = self.torrent_cache.write().await;
+        *cache = restored_cache;
+        
+        tracing::info!("Successfully restored {} cached torrents", cache.len());
+        
+        // Re-save cache with updated session IDs
+        drop(cache);
+        if let Err(e) = self.save_cache_to_disk().await {
+            tracing::error!("Failed to save updated cache: {}", e);
+        }
+        
+        Ok(())
+    }
+
+    pub async fn pause_torrent(&self, handle_id: usize) -> Result<()> {
+        let torrents = self.torrents.read().await;
+        let entry = torrents.get(&handle_id).context("Torrent not found")?;
+        if let Some(session_id) = entry.session_id {
+            let handle = self
+                .session
+                .get(TorrentIdOrHash::Id(session_id))
+                .context("Session torrent not found")?;
+            self.session.pause(&handle).await?;
+        }
+        Ok(())
+    }
+
+    pub async fn resume_torrent(&self, handle_id: usize) -> Result<()> {
+        let torrents = self.torrents.read().await;
+        let entry = torrents.get(&handle_id).context("Torrent not found")?;
+        if let Some(session_id) = entry.session_id {
+            let handle = self
+                .session
+                .get(TorrentIdOrHash::Id(session_id))
+                .context("Session torrent not found")?;
+            self.session.unpause(&handle).await?;
+        }
+        Ok(())
+    }
+
+    // librqbit has no explicit re-announce; restarting the torrent re-runs the tracker
+    // announces and the DHT peer lookup. Paused torrents are left paused: whoever paused
+    // them (the user, pause-all, the idle timeout) meant it.
+    async fn restart_peer_discovery(&self, handle: &librqbit::ManagedTorrentHandle) -> Result<()> {
+        if handle.is_paused() {
+            return Ok(());
+        }
+        self.session.pause(handle).await?;
+        self.session.unpause(handle).await
+    }
+
+    /// Asks the trackers and the DHT for peers again, for a torrent that's stuck without any
+    pub async fn reannounce(&self, handle_id: usize) -> Result<()> {
+        let session_id = self.torrents.read().await
+            .get(&handle_id)
+            .context("Torrent not found")?
+            .session_id
+            .context("Torrent not yet added to session")?;
+        let handle = self.session.get(TorrentIdOrHash::Id(session_id)).context("Session torrent not found")?;
+        if handle.is_paused() {
+            anyhow::bail!("Resume the torrent to re-announce it");
+        }
+        tracing::info!("Re-announcing handle_id={} (session_id={})", handle_id, session_id);
+        self.restart_peer_discovery(&handle).await
+    }
+
+    /// Hashes everything on disk again and re-downloads whatever doesn't match. librqbit
+    /// only checks existing data when a torrent is added, so the torrent is taken out of
+    /// the session (files kept) and added back with the same file selection, under a new
+    /// session id. Refused while the torrent is being streamed, as the stream URLs carry
+    /// the old id.
+    pub async fn force_recheck(&self, handle_id: usize) -> Result<()> {
+        let (session_id, magnet_url) = {
+            let torrents = self.torrents.read().await;
+            let entry = torrents.get(&handle_id).context("Torrent not found")?;
+            (entry.session_id.context("Torrent not yet added to session")?, entry.magnet_url.clone())
+        };
+        if self.streams.open_streams().await.iter().any(|((id, _), _)| *id == session_id) {
+            anyhow::bail!("Stop playback before rechecking this torrent");
+        }
+
+        let handle = self.session.get(TorrentIdOrHash::Id(session_id)).context("Session torrent not found")?;
+        let only_files = handle.only_files();
+        let paused = handle.is_paused();
+        drop(handle);
+
+        // Loaded before the torrent leaves the session, so a .torrent that can't be fetched
+        // any more doesn't leave the handle without one
+        let (add_torrent, trackers, web_seeds) = torrent_source(&magnet_url).await?;
+
+        tracing::info!("Rechecking handle_id={} (session_id={})", handle_id, session_id);
+        self.session.delete(TorrentIdOrHash::Id(session_id), false).await?;
+        self.web_seeds.write().await.remove(&session_id);
+        self.file_verifications.write().await.retain(|(id, _), _| *id != session_id);
+        let selected = self.selected_files.write().await.remove(&session_id);
+
+        let opts = AddTorrentOptions {
+            overwrite: true,
+            paused,
+            only_files,
+            trackers,
+            ..Default::default()
+        };
+        let added = match self.session.add_torrent(add_torrent, Some(opts)).await {
+            Ok(AddTorrentResponse::Added(id, _) | AddTorrentResponse::AlreadyManaged(id, _)) => Ok(id),
+            Ok(AddTorrentResponse::ListOnly(_)) => Err(anyhow::anyhow!("Unexpected list_only response")),
+            Err(e) => Err(e),
+        };
+        // Every handle on the old session moves to the new one, or back to not having one
+        // when the re-add failed, so the next stream adds the torrent again
+        let moved_to = added.as_ref().ok().copied();
+        for entry in self.torrents.write().await.values_mut().filter(|e| e.session_id == Some(session_id)) {
+            entry.session_id = moved_to;
+        }
+        let new_session_id = match added {
+            Ok(id) => id,
+            Err(e) => {
+                tracing::warn!("Re-adding handle_id={} for recheck failed: {}", handle_id, e);
+                let mut cache = self.torrent_cache.write().await;
+                let before = cache.len();
+                cache.retain(|c| c.session_id != session_id);
+                let changed = cache.len() != before;
+                drop(cache);
+                if changed {
+                    if let Err(e) = self.save_cache_to_disk().await {
+                        tracing::warn!("Failed to save torrent cache: {}", e);
+                    }
+                }
+                return Err(e);
+            }
+        };
+        self.register_web_seeds(new_session_id, web_seeds).await;
+        if let Some(selected) = selected {
+            self.selected_files.write().await.insert(new_session_id, selected);
+        }
+
+        let mut recached = false;
+        for cached in self.torrent_cache.write().await.iter_mut().filter(|c| c.session_id == session_id) {
+            cached.session_id = new_session_id;
+            recached = true;
+        }
+        if recached {
+            if let Err(e) = self.save_cache_to_disk().await {
+                tracing::warn!("Failed to save torrent cache: {}", e);
+            }
+        }
+        tracing::info!("handle_id={} re-added as session_id={} for recheck", handle_id, new_session_id);
+        Ok(())
+    }
+
+    /// The handle's librqbit session id, waiting up to 5 seconds for prepare_stream to add it
+    async fn wait_for_session(&self, handle_id: usize) -> Result<usize, String> {
+        for attempt in 1..=10 {
+            let session_id = self.torrents.read().await
+                .get(&handle_id)
+                .ok_or("Torrent handle not found")?
+                .session_id;
+            if let Some(session_id) = session_id {
+                return Ok(session_id);
+            }
+            tracing::info!("Session ID not yet available, waiting... (attempt {}/10)", attempt);
+            tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
+        }
+        Err("Torrent session not ready after 5 seconds. Please wait for video to start loading.".to_string())
+    }
+
+    /// Hex info hash of a handle's torrent, once it's been added to the session
+    pub async fn info_hash(&self, handle_id: usize) -> Option<String> {
+        let session_id = self.torrents.read().await.get(&handle_id)?.session_id?;
+        let handle = self.session.get(TorrentIdOrHash::Id(session_id))?;
+        Some(handle.info_hash().as_string())
+    }
+
+    async fn register_web_seeds(&self, session_id: usize, urls: Vec<String>) {
+        if urls.is_empty() {
+            return;
+        }
+        tracing::info!("torrent session_id={} has {} web seeds", session_id, urls.len());
+        self.web_seeds.write().await.insert(session_id, Arc::new(WebSeeds::new(urls)));
+    }
+
+    /// Distinct peers the DHT returns for `info_hash` (hex) within `timeout`, as a check on
+    /// the seed counts trackers report. None when the DHT is off or the hash is invalid.
+    pub async fn count_dht_peers(&self, info_hash: &str, timeout: std::time::Duration) -> Option<u32> {
+        use futures::StreamExt;
+        use std::str::FromStr;
+
+        let dht = self.session.get_dht()?;
+        let id = librqbit::dht::Id20::from_str(info_hash).ok()?;
+        let mut peers_stream = dht.get_peers(id, None).ok()?;
+
+        let mut peers = std::collections::HashSet::new();
+        let deadline = tokio::time::Instant::now() + timeout;
+        while let Ok(Some(peer)) = tokio::time::timeout_at(deadline, peers_stream.next()).await {
+            peers.insert(peer);
+        }
+        Some(peers.len() as u32)
+    }
+
+    /// Session-wide speed limits in bytes/sec, None for unlimited
+    pub fn set_speed_limits(&self, download_bps: Option<u32>, upload_bps: Option<u32>) {
+        if let Ok(mut limits) = self.speed_limits.lock() {
+            *limits = (download_bps, upload_bps);
+        }
+        self.apply_speed_limits();
+    }
+
+    // The configured limits, lowered to the trickle while playback is held
+    fn apply_speed_limits(&self) {
+        let (mut download_bps, mut upload_bps) = self.speed_limits.lock().map(|l| *l).unwrap_or((None, None));
+        if self.playback_hold.load(std::sync::atomic::Ordering::Relaxed) {
+            let trickle = |limit: Option<u32>| Some(limit.map_or(HOLD_TRICKLE_BPS, |l| l.min(HOLD_TRICKLE_BPS)));
+            download_bps = trickle(download_bps);
+            upload_bps = trickle(upload_bps);
+        }
+        self.session.ratelimits.set_download_bps(download_bps.and_then(std::num::NonZeroU32::new));
+        self.session.ratelimits.set_upload_bps(upload_bps.and_then(std::num::NonZeroU32::new));
+        tracing::info!("Speed limits set: download={:?} upload={:?} bytes/sec", download_bps, upload_bps);
+    }
+
+    /// Called by the player while it's open. Resumes a torrent paused for being idle.
+    pub async fn stream_keep_alive(&self, handle_id: usize, file_index: usize) -> Result<()> {
+        let session_id = {
+            let torrents = self.torrents.read().await;
+            let entry = torrents.get(&handle_id).context("Torrent handle not found")?;
+            entry.session_id.context("Stream not prepared yet")?
+        };
+        let was_paused = self.streams.update_existing((session_id, file_index), |s| {
+            s.last_ping = std::time::Instant::now();
+            s.idle_stage.take() == Some(IdleStage::Paused)
+        }).await.context("Stream was stopped")?;
+        if was_paused {
+            if let Some(handle) = self.session.get(TorrentIdOrHash::Id(session_id)) {
+                if handle.is_paused() {
+                    tracing::info!("Player for handle_id={} is back, resuming its torrent", handle_id);
+                    self.session.unpause(&handle).await?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Called periodically by the stall watchdog. Streams whose player stopped sending
+    /// keep-alives get a warning, then their torrent paused at the idle timeout and the
+    /// stream stopped at twice that. Returns the streams that moved to a new stage.
+    pub async fn check_idle_streams(&self) -> Vec<IdleStream> {
+        let timeout = STREAM_IDLE_TIMEOUT_SECS.load(std::sync::atomic::Ordering::Relaxed);
+        if timeout == 0 {
+            return Vec::new();
+        }
+
+        let mut idle_streams = Vec::new();
+        for ((session_id, file_index), handle_id) in self.streams.open_streams().await {
+            let stream_key = (session_id, file_index);
+            let Some(Some((idle_seconds, stage))) = self.streams.update_existing(stream_key, |s| {
+                let idle_seconds = s.last_ping.elapsed().as_secs();
+                let stage = if idle_seconds >= timeout * 2 {
+                    IdleStage::Stopped
+                } else if idle_seconds >= timeout {
+                    IdleStage::Paused
+                } else if idle_seconds + IDLE_WARNING_SECS >= timeout {
+                    IdleStage::Warned
+                } else {
+                    return None;
+                };
+                if s.idle_stage >= Some(stage) {
+                    return None;
+                }
+                s.idle_stage = Some(stage);
+                Some((idle_seconds, stage))
+            }).await else {
+                continue;
+            };
+
+            match stage {
+                IdleStage::Warned => {
+                    tracing::warn!("No keep-alive for handle_id={} in {}s, pausing it soon", handle_id, idle_seconds);
+                }
+                IdleStage::Paused => {
+                    // Another player still pinging on the same torrent keeps it running
+                    let mut others_active = false;
+                    for ((id, other_index), _) in self.streams.open_streams().await {
+                        if id == session_id && other_index != file_index {
+                            let stage = self.streams.read((id, other_index), |s| s.idle_stage).await.flatten();
+                            others_active |= stage < Some(IdleStage::Paused);
+                        }
+                    }
+                    if !others_active {
+                        if let Some(handle) = self.session.get(TorrentIdOrHash::Id(session_id)) {
+                            tracing::warn!("No keep-alive for handle_id={} in {}s, pausing its torrent", handle_id, idle_seconds);
+                            if let Err(e) = self.session.pause(&handle).await {
+                                tracing::warn!("Failed to pause idle torrent: {}", e);
+                            }
+                        }
+                    }
+                }
+                IdleStage::Stopped => {
+                    tracing::warn!("No keep-alive for handle_id={} in {}s, stopping the stream", handle_id, idle_seconds);
+                    if let Err(e) = self.stop_stream(handle_id, false).await {
+                        tracing::warn!("Failed to stop idle stream: {}", e);
+                    }
+                }
+            }
+            idle_streams.push(IdleStream { handle_id, file_index, idle_seconds, stage });
+        }
+        idle_streams
+    }
+
+    /// The player paused or resumed. Resuming lifts a playback hold right away; holding
+    /// only starts from the watchdog once the pause has lasted long enough.
+    pub async fn report_playback_paused(&self, handle_id: usize, file_index: usize, paused: bool) -> Result<()> {
+        let session_id = {
+            let torrents = self.torrents.read().await;
+            let entry = torrents.get(&handle_id).context("Torrent handle not found")?;
+            entry.session_id.context("Stream not prepared yet")?
+        };
+        self.streams.update_existing((session_id, file_index), |s| {
+            s.paused_since = if paused { s.paused_since.or(Some(std::time::Instant::now())) } else { None };
+        }).await;
+        if !paused {
+            self.update_playback_hold().await;
+        }
+        Ok(())
+    }
+
+    /// Drops every torrent to a trickle once all streams being played have been paused
+    /// for the hold time, and back to full speed when one plays again or stops. Readahead
+    /// windows stay prioritized, so the trickle goes to what playback needs next. librqbit
+    /// only has session-wide limits, which is why background downloads slow down too.
+    pub async fn update_playback_hold(&self) {
+        let hold_secs = PAUSE_HOLD_SECS.load(std::sync::atomic::Ordering::Relaxed);
+        let hold = hold_secs > 0 && self.streams.all_paused_for(std::time::Duration::from_secs(hold_secs)).await;
+        if self.playback_hold.swap(hold, std::sync::atomic::Ordering::Relaxed) != hold {
+            tracing::info!("Playback hold {}", if hold { "started, downloads slowed to a trickle" } else { "lifted" });
+            self.apply_speed_limits();
+        }
+    }
+
+    /// Pauses every running torrent except the ones being streamed, and returns the
+    /// handles that were actually paused so automation can resume just those later. A
+    /// torrent that fails to pause doesn't stop the rest; the call only fails when
+    /// nothing could be paused.
+    pub async fn pause_all(&self) -> Result<Vec<usize>> {
+        let streaming: std::collections::HashSet<usize> = self.streams.open_streams().await
+            .into_iter()
+            .map(|((session_id, _), _)| session_id)
+            .collect();
+        let torrents = self.torrents.read().await;
+        let mut paused = Vec::new();
+        let mut errors = Vec::new();
+        for (handle_id, entry) in torrents.iter() {
+            let Some(session_id) = entry.session_id else { continue };
+            if streaming.contains(&session_id) {
+                continue;
+            }
+            let Some(handle) = self.session.get(TorrentIdOrHash::Id(session_id)) else { continue };
+            if handle.is_paused() {
+                continue;
+            }
+            match self.session.pause(&handle).await {
+                Ok(()) => paused.push(*handle_id),
+                Err(e) => {
+                    tracing::warn!("Failed to pause handle_id={}: {}", handle_id, e);
+                    errors.push(format!("{}: {}", handle_id, e));
+                }
+            }
+        }
+        tracing::info!("Paused {} torrents", paused.len());
+        if paused.is_empty() && !errors.is_empty() {
+            anyhow::bail!("Failed to pause torrents: {}", errors.join("; "));
+        }
+        Ok(paused)
+    }
+
+    /// Resumes every paused torrent except the ones parked in the torrent cache, which
+    /// are paused on purpose with their data cleared
+    pub async fn resume_all(&self) -> Result<Vec<usize>> {
+        let cached: Vec<usize> = self.torrent_cache.read().await.iter().map(|ct| ct.handle_id).collect();
+        let torrents = self.torrents.read().await;
+        let mut resumed = Vec::new();
+        for (handle_id, entry) in torrents.iter() {
+            if cached.contains(handle_id) {
+                continue;
+            }
+            let Some(session_id) = entry.session_id else { continue };
+            let Some(handle) = self.session.get(TorrentIdOrHash::Id(session_id)) else { continue };
+            if !handle.is_paused() {
+                continue;
+            }
+            self.session.unpause(&handle).await?;
+            resumed.push(*handle_id);
+        }
+        tracing::info!("Resumed {} torrents", resumed.len());
+        Ok(resumed)
+    }
+
+    pub async fn remove_torrent(&self, handle_id: usize, delete_files: bool) -> Result<()> {
+        let mut torrents = self.torrents.write().await;
+        if let Some(entry) = torrents.remove(&handle_id) {
+            if let Some(session_id) = entry.session_id {
+                self.web_seeds.write().await.remove(&session_id);
+                self.session.delete(TorrentIdOrHash::Id(session_id), delete_files).await?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn get_download_dir(&self) -> PathBuf {
+        self.download_dir.clone()
+    }
+
+    /// Makes a library file reachable through the streaming server and returns its stream URL
+    pub async fn register_local_file(&self, library_id: String, path: PathBuf) -> String {
+        let url = format!("http://{}/local/{}/stream", self.http_addr, library_id);
+        self.local_files.write().await.insert(library_id, path);
+        url
+    }
+
+    pub async fn wipe_all_files(&self) -> Result<()> {
+        tracing::info!("Wiping all torrent files from download directory");
+        
+        let download_dir = self.download_dir.clone();
+        
+        // Delete everything in the download directory except cache files
+        if download_dir.exists() {
+            let mut entries = tokio::fs::read_dir(&download_dir).await?;
+            let mut deleted_count = 0;
+            
+            while let Some(entry) = entries.next_entry().await? {
+                let path = entry.path();
+                let file_name = path.file_name()
+                    .and_then(|n| n.to_str())
+                    .unwrap_or("");
+                
+                // Skip cache files
+                if file_name == "torrent_cache.json" {
+                    continue;
+                }
+                
+                if path.is_dir() {
+                    match tokio::fs::remove_dir_all(&path).await {
+                        Ok(_) => {
+                            tracing::info!("Deleted directory: {:?}", path);
+                            deleted_count += 1;
+                        }
+                        Err(e) => {
+                            tracing::warn!("Failed to delete directory {:?}: {}", path, e);
+                        }
+                    }
+                } else {
+                    match tokio::fs::remove_file(&path).await {
+                        Ok(_) => {
+                            tracing::info!("Deleted file: {:?}", path);
+                            deleted_count += 1;
+                        }
+                        Err(e) => {
+                            tracing::warn!("Failed to delete file {:?}: {}", path, e);
+                        }
+                    }
+                }
+            }
+            
+            tracing::info!("Wiped {} items from download directory", deleted_count);
+        }
+        
+        Ok(())
+    }
+
+    pub async fn cleanup_all(&self) -> Result<()> {
+        tracing::info!("Cleaning up all torrents on app close");
+        let torrents = self.torrents.read().await;
+        let session_ids: Vec<usize> = torrents.values()
+            .filter_map(|entry| entry.session_id)
+            .collect();
+        drop(torrents);
+
+        for session_id in session_ids {
+            tracing::info!("Deleting torrent session_id: {} with files", session_id);
+            if let Err(e) = self.session.delete(TorrentIdOrHash::Id(session_id), true).await {
+                tracing::error!("Error deleting torrent {}: {}", session_id, e);
+            }
+        }
+        
+        // Also delete the cache file
+        let cache_path = self.download_dir.join("torrent_cache.json");
+        if cache_path.exists() {
+            if let Err(e) = std::fs::remove_file(&cache_path) {
+                tracing::error!("Failed to remove cache file: {}", e);
+            } else {
+                tracing::info!("Removed torrent cache file");
+            }
+        }
+        
+        Ok(())
+    }
+
+    pub async fn get_http_port(&self) -> Result<u16, String> {
+        Ok(self.http_addr.port())
+    }
+
+    pub fn stream_access_log(&self) -> Vec<StreamAccess> {
+        self.access_log.entries()
+    }
+
+    pub fn subscribe_metadata_probes(&self) -> tokio::sync::broadcast::Receiver<MetadataProbeProgress> {
+        self.probe_events.subscribe()
+    }
+
+    pub fn subscribe_stream_lifecycle(&self) -> tokio::sync::broadcast::Receiver<StreamLifecycleEvent> {
+        self.streams.subscribe()
+    }
+
+    /// Gives a running metadata read `seconds` more to gather data
+    pub async fn extend_metadata_probe(&self, session_id: usize, file_id: usize, seconds: u64) -> Result<(), String> {
+        let extended = self.streams.update_existing((session_id, file_id), |s| {
+            s.probe.as_mut().map(|probe| {
+                let now = tokio::time::Instant::now();
+                probe.deadline = probe.deadline.max(now) + std::time::Duration::from_secs(seconds);
+            })
+        }).await.flatten();
+        extended.ok_or_else(|| "No metadata read in progress".to_string())
+    }
+
+    /// Stops waiting on a metadata read; whatever was gathered is used if it's enough
+    pub async fn cancel_metadata_probe(&self, session_id: usize, file_id: usize) -> Result<(), String> {
+        let cancelled = self.streams.update_existing((session_id, file_id), |s| {
+            s.probe.as_mut().map(|probe| probe.cancelled = true)
+        }).await.flatten();
+        cancelled.ok_or_else(|| "No metadata read in progress".to_string())
+    }
+
+    pub async fn get_transcoded_audio(&self, session_id: usize, file_index: usize) -> Result<Option<Vec<u8>>, String> {
+        // Check if transcoding is complete and get the output path
+        let output_path = match self.streams.read((session_id, file_index), |s| s.transcode.clone()).await.flatten() {
+            Some(transcode_state) => {
+                if !transcode_state.completed {
+                    return Err("Transcoding not complete".to_string());
+                }
+                transcode_state.output_path
+            }
+            None => return Err("No transcoding in progress for this file".to_string()),
+        };
+
+        let output_path = match output_path {
+            Some(p) => p,
+            None => return Err("Transcoded file path not found".to_string()),
+        };
+
+        if !output_path.exists() {
+            return Err("Transcoded file not found on disk".to_string());
+        }
+
+        // Read the entire file into memory
+        match tokio::fs::read(&output_path).await {
+            Ok(data) => {
+                tracing::info!("Loaded transcoded audio: {} bytes from {:?}", data.len(), output_path);
+                Ok(Some(data))
+            }
+            Err(e) => Err(format!("Failed to read transcoded audio file: {}", e)),
+        }
+    }
+}
+
+fn build_file_preview(index: usize, path: String, size: u64) -> TorrentFilePreview {
+    let name = std::path::Path::new(&path)
+        .file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or("unknown")
+        .to_string();
+    let lower = name.to_lowercase();
+    let is_video = lower.ends_with(".mkv") || lower.ends_with(".mp4") || lower.ends_with(".avi") || lower.ends_with(".mov");
+    let (season, episode) = if is_video {
+        crate::search::parse_season_episode(&name)
+    } else {
+        (None, None)
+    };
+    
+    TorrentFilePreview {
+        index,
+        name,
+        path,
+        size,
+        is_video,
+        season,
+        episode,
+    }
+}
+
+// Browser support check for an audio stream, based on ffprobe's codec name, long name and profile
+fn audio_needs_transcoding(codec_name: &str, codec_long_name: &str, profile: &str) -> bool {
+    // Check if this codec needs transcoding (check codec name, long name, and profile)
+    let codec_lower = codec_name.to_lowercase();
+    let long_name_lower = codec_long_name.to_lowercase();
+    let profile_lower = profile.to_lowercase();
+    
+    // Special check for AC3/EAC3 variants to ensure proper detection
+    let is_ac3_variant = codec_lower == "ac3" 
+        || codec_lower == "eac3" 
+        || codec_lower == "ac-3" 
+        || codec_lower == "e-ac-3"
+        || codec_lower.starts_with("ac3")
+        || codec_lower.starts_with("eac3")
+        || long_name_lower.contains("ac-3")
+        || long_name_lower.contains("ac3")
+        || long_name_lower.contains("e-ac-3")
+        || long_name_lower.contains("eac3")
+        || long_name_lower.contains("dolby digital");
+    
+    // Whitelist of browser-supported codecs
+    let is_known_supported = matches!(codec_lower.as_str(), 
+        "aac" | "mp3" | "opus" | "vorbis" | "mp2" | "mp1" | "flac"
+    ) && !long_name_lower.contains("truehd") 
+      && !long_name_lower.contains("dts")
+      && !long_name_lower.contains("atmos")
+      && !is_ac3_variant;
+    
+    // Check against blacklist of known unsupported codecs
+    let is_known_unsupported = is_ac3_variant || UNSUPPORTED_AUDIO_CODECS.iter().any(|unsupported| {
+        codec_lower == *unsupported 
+            || codec_lower.contains(unsupported)
+            || long_name_lower.contains(unsupported)
+            || profile_lower.contains(unsupported)
+    });
+    
+    // Transcode if explicitly unsupported OR if not in the supported whitelist
+    is_known_unsupported || !is_known_supported
+}
+
+async fn extract_mkv_metadata_ffprobe(file_path: &std::path::Path) -> Result<MkvMetadata> {
+    
+    tracing::info!("Extracting metadata with ffprobe: {:?}", file_path);
+    
+    // Check if file exists
+    if !file_path.exists() {
+        tracing::error!("File does not exist: {:?}", file_path);
+        return Err(anyhow::anyhow!("File does not exist"));
+    }
+    
+    let file_size = std::fs::metadata(file_path)?.len();
+    tracing::info!("File size: {} bytes", file_size);
+    
+    let mut cmd = ffmpeg_tools::ffprobe_command();
+    cmd.args(&[
+            "-v", "error",
+            "-print_format", "json",
+            "-show_format",
+            "-show_streams",
+            "-show_chapters",
+            file_path.to_str().unwrap(),
+        ]);
+
+    let output = ffmpeg_tools::output(&mut cmd)
+        .await
+        .context("Failed to run ffprobe command")?;
+    
+    tracing::info!("ffprobe exit status: {}", output.status);
+    
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        tracing::error!("ffprobe failed with status: {}", output.status);
+        tracing::error!("ffprobe stderr: {}", stderr);
+        tracing::error!("ffprobe stdout: {}", stdout);
+        return Err(anyhow::anyhow!("ffprobe failed: {}", stderr));
+    }
+    
+    let stdout_str = String::from_utf8_lossy(&output.stdout);
+    tracing::debug!("ffprobe output: {}", stdout_str);
+    
+    let probe_data: serde_json::Value = serde_json::from_slice(&output.stdout)
+        .context("Failed to parse ffprobe JSON output")?;
+    
+    let mut audio_tracks = Vec::new();
+    let mut subtitle_tracks = Vec::new();
+    let mut chapters = Vec::new();
+    let mut attachments = Vec::new();
+    
+    // Extract streams
+    if let Some(streams) = probe_data.get("streams").and_then(|s| s.as_array()) {
+        let mut audio_index = 0;
+        let mut subtitle_index = 0;
+        
+        for (position, stream) in streams.iter().enumerate() {
+            let codec_type = stream.get("codec_type").and_then(|t| t.as_str());
+            let is_cover = stream.get("disposition")
+                .and_then(|d| d.get("attached_pic"))
+                .and_then(|a| a.as_i64())
+                == Some(1);
+            let is_attachment = codec_type == Some("attachment") || (codec_type == Some("video") && is_cover);
+            
+            match codec_type {
+                // Fonts are attachment streams; cover art shows up as an attached-pic video stream
+                _ if is_attachment => {
+                    let tag = |name: &str| stream.get("tags")
+                        .and_then(|t| t.get(name))
+                        .and_then(|v| v.as_str())
+                        .map(|v| v.to_string());
+                    let stream_index = stream.get("index")
+                        .and_then(|i| i.as_u64())
+                        .map(|i| i as usize)
+                        .unwrap_or(position);
+                    
+                    attachments.push(Attachment {
+                        index: attachments.len(),
+                        stream_index,
+                        filename: tag("filename"),
+                        mimetype: tag("mimetype"),
+                        is_cover,
+                    });
+                }
+                Some("audio") => {
+                    let codec_name = stream.get("codec_name").and_then(|c| c.as_str()).unwrap_or("unknown");
+                    let codec_long_name = stream.get("codec_long_name").and_then(|c| c.as_str()).unwrap_or("");
+                    let profile = stream.get("profile").and_then(|p| p.as_str()).unwrap_or("");
+                    let language = stream.get("tags")
+                        .and_then(|t| t.get("language"))
+                        .and_then(|l| l.as_str())
+                        .unwrap_or("und")
+                        .to_string();
+                    let title = stream.get("tags")
+                        .and_then(|t| t.get("title"))
+                        .and_then(|t| t.as_str())
+                        .map(|s| s.to_string());
+                    
+                    let needs_transcoding = audio_needs_transcoding(codec_name, codec_long_name, profile);
+                    
+                    tracing::info!("Audio track {}: codec='{}' ({}), profile='{}', needs_transcoding={}", 
+                        audio_index, codec_name, codec_long_name, profile, needs_transcoding);
+                    
+                    audio_tracks.push(AudioTrack {
+                        index: audio_index,
+                        language: Some(language),
+                        codec: Some(codec_name.to_string()),
+                        name: title,
+                        needs_transcoding,
+                        transcoded_url: None,
+                    });
+                    audio_index += 1;
+                }
+                Some("subtitle") => {
+                    let codec_name = stream.get("codec_name").and_then(|c| c.as_str()).unwrap_or("unknown");
+                    let language = stream.get("tags")
+                        .and_then(|t| t.get("language"))
+                        .and_then(|l| l.as_str())
+                        .unwrap_or("und")
+                        .to_string();
+                    let title = stream.get("tags")
+                        .and_then(|t| t.get("title"))
+                        .and_then(|t| t.as_str())
+                        .map(|s| s.to_string());
+                    
+                    let is_forced = stream.get("disposition")
+                        .and_then(|d| d.get("forced"))
+                        .and_then(|f| f.as_i64())
+                        == Some(1);
+                    
+                    subtitle_tracks.push(SubtitleTrack {
+                        index: subtitle_index,
+                        language: Some(language),
+                        codec: Some(codec_name.to_string()),
+                        name: title,
+                        is_forced,
+                        url: None,
+                        mime_type: None,
+                    });
+                    subtitle_index += 1;
+                }
+                _ => {}
+            }
+        }
+    }
+    
+    // Extract chapters
+    if let Some(chapters_data) = probe_data.get("chapters").and_then(|c| c.as_array()) {
+        for (index, chapter) in chapters_data.iter().enumerate() {
+            let start_str = chapter.get("start_time").and_then(|s| s.as_str());
+            let end_str = chapter.get("end_time").and_then(|e| e.as_str());
+            let title = chapter.get("tags")
+                .and_then(|t| t.get("title"))
+                .and_then(|t| t.as_str())
+                .map(|s| s.to_string());
+            
+            if let (Some(start), Some(end)) = (start_str, end_str) {
+                if let (Ok(start_time), Ok(end_time)) = (start.parse::<f64>(), end.parse::<f64>()) {
+                    chapters.push(Chapter {
+                        index,
+                        title,
+                        start_time,
+                        end_time,
+                    });
+                }
+            }
+        }
+    }
+    
+    tracing::info!("Extracted {} audio tracks, {} subtitle tracks, {} chapters, {} attachments", 
+        audio_tracks.len(), subtitle_tracks.len(), chapters.len(), attachments.len());
+    
+    // Check if ANY audio track needs transcoding (check all tracks, not just first)
+    let needs_audio_transcoding = audio_tracks.iter()
+        .any(|t| t.needs_transcoding);
+    
+    // Log all audio codecs for debugging
+    for track in &audio_tracks {
+        tracing::info!("Audio track {}: codec={:?}, needs_transcoding={}", 
+            track.index, track.codec, track.needs_transcoding);
+    }
+    
+    let duration = probe_data.get("format")
+        .and_then(|f| f.get("duration"))
+        .and_then(|d| d.as_str())
+        .and_then(|s| s.parse::<f64>().ok());
+
+    if needs_audio_transcoding {
+        tracing::info!("Audio transcoding required - at least one track has unsupported codec");
+    } else {
+        tracing::info!("No audio transcoding required - all tracks have supported codecs");
+    }
+    
+    Ok(MkvMetadata {
+        audio_tracks,
+        subtitle_tracks,
+        chapters,
+        attachments,
+        needs_audio_transcoding,
+        transcoded_audio_url: None,
+        duration,
+    })
+}
+
+async fn probe_compatibility_ffprobe(file_path: &std::path::Path) -> Result<StreamCompatibility> {
+
+    let mut cmd = ffmpeg_tools::ffprobe_command();
+    cmd.args(&[
+            "-v", "error",
+            "-print_format", "json",
+            "-show_format",
+            "-show_streams",
+        ])
+        .arg(file_path);
+
+    let output = ffmpeg_tools::output(&mut cmd)
+        .await
+        .context("Failed to run ffprobe command")?;
+
+    if !output.status.success() {
+        return Err(anyhow::anyhow!("ffprobe failed: {}", String::from_utf8_lossy(&output.stderr)));
+    }
+
+    let probe_data: serde_json::Value = serde_json::from_slice(&output.stdout)
+        .context("Failed to parse ffprobe JSON output")?;
+
+    let container = probe_data.get("format")
+        .and_then(|f| f.get("format_name"))
+        .and_then(|n| n.as_str())
+        .unwrap_or("unknown")
+        .to_string();
+
+    let mut video = None;
+    let mut hdr = None;
+    let mut audio_codecs = Vec::new();
+    let mut audio_compatible = true;
+
+    if let Some(streams) = probe_data.get("streams").and_then(|s| s.as_array()) {
+        for stream in streams {
+            let str_field = |name: &str| stream.get(name).and_then(|v| v.as_str()).map(|s| s.to_string());
+
+            match stream.get("codec_type").and_then(|t| t.as_str()) {
+                // Cover art shows up as an attached-pic video stream
+                Some("video") if video.is_none() && stream.get("disposition").and_then(|d| d.get("attached_pic")).and_then(|a| a.as_i64()) != Some(1) => {
+                    let pix_fmt = str_field("pix_fmt");
+                    let bit_depth = str_field("bits_per_raw_sample")
+                        .and_then(|b| b.parse().ok())
+                        .or_else(|| pix_fmt.as_deref().map(|p| if p.contains("10") { 10 } else if p.contains("12") { 12 } else { 8 }));
+
+                    let has_side_data = |kind: &str| stream.get("side_data_list")
+                        .and_then(|l| l.as_array())
+                        .map(|l| l.iter().any(|d| d.get("side_data_type").and_then(|t| t.as_str()).map(|t| t.contains(kind)).unwrap_or(false)))
+                        .unwrap_or(false);
+
+                    hdr = if has_side_data("DOVI") {
+                        Some("Dolby Vision".to_string())
+                    } else {
+                        match str_field("color_transfer").as_deref() {
+                            Some("smpte2084") if has_side_data("HDR Dynamic Metadata") => Some("HDR10+".to_string()),
+                            Some("smpte2084") => Some("HDR10".to_string()),
+                            Some("arib-std-b67") => Some("HLG".to_string()),
+                            _ => None,
+                        }
+                    };
+
+                    video = Some(VideoStreamInfo {
+                        codec: str_field("codec_name").unwrap_or_else(|| "unknown".to_string()),
+                        profile: str_field("profile"),
+                        width: stream.get("width").and_then(|w| w.as_u64()).map(|w| w as u32),
+                        height: stream.get("height").and_then(|h| h.as_u64()).map(|h| h as u32),
+                        bit_depth,
+                        pix_fmt,
+                    });
+                }
+                Some("audio") => {
+                    let codec_name = str_field("codec_name").unwrap_or_else(|| "unknown".to_string());
+                    let codec_long_name = str_field("codec_long_name").unwrap_or_default();
+                    let profile = str_field("profile").unwrap_or_default();
+                    if audio_needs_transcoding(&codec_name, &codec_long_name, &profile) {
+                        audio_compatible = false;
+                    }
+                    audio_codecs.push(codec_name);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    // 8-bit H.264, VP9 and AV1 decode everywhere; HEVC, 10-bit H.264 and HDR don't reliably
+    let video_compatible = match &video {
+        Some(v) => {
+            let codec_ok = match v.codec.as_str() {
+                "h264" => v.bit_depth.unwrap_or(8) <= 8,
+                "vp8" | "vp9" | "av1" => true,
+                _ => false,
+            };
+            codec_ok && hdr.is_none()
+        }
+        None => true,
+    };
+
+    // HDR needs tone-mapping rather than a plain transcode unless the display handles it,
+    // which only the frontend can tell (e.g. the dynamic-range media query)
+    let recommended_mode = if hdr.is_some() {
+        "tonemap"
+    } else if !video_compatible {
+        "full_transcode"
+    } else if !audio_compatible {
+        "audio_transcode"
+    } else {
+        "direct"
+    };
+
+    tracing::info!("Compatibility probe: container={}, video={:?}, audio={:?}, hdr={:?}, mode={}",
+        container, video.as_ref().map(|v| &v.codec), audio_codecs, hdr, recommended_mode);
+
+    Ok(StreamCompatibility {
+        container,
+        video,
+        audio_codecs,
+        hdr,
+        video_compatible,
+        audio_compatible,
+        recommended_mode: recommended_mode.to_string(),
+        tonemapped_url: None,
+    })
+}
+
+// Transcode audio to AAC using ffmpeg-sidecar
+#[allow(dead_code)]
+async fn transcode_audio_track(
+    input_path: &std::path::Path,
+    output_path: &std::path::Path,
+    audio_track_index: usize,
+    streams: StreamSessions,
+    session_id: usize,
+    file_id: usize,
+) -> Result<()> {
+    use std::process::Stdio;
+    use tokio::io::{AsyncBufReadExt, BufReader};
+    
+    tracing::info!("[Transcode] Starting audio transcoding: {:?} -> {:?} (track {})", 
+        input_path, output_path, audio_track_index);
+    
+    // Verify input file exists
+    if !input_path.exists() {
+        let err = format!("Input file does not exist: {:?}", input_path);
+        tracing::error!("[Transcode] ERROR: {}", err);
+        return Err(anyhow::anyhow!(err));
+    }
+    
+    let file_size = std::fs::metadata(input_path)
+        .map(|m| m.len())
+        .unwrap_or(0);
+    tracing::info!("[Transcode] Input file size: {} MB", file_size / 1_048_576);
+    
+    // Get duration for progress calculation
+    let duration = get_media_duration(input_path).await.unwrap_or(0.0);
+    tracing::info!("[Transcode] Media duration: {} seconds", duration);
+    
+    // Initialize transcode state
+    streams.update((session_id, file_id), |s| {
+        s.transcode = Some(TranscodeState {
+            progress: 0.0,
+            output_path: Some(output_path.to_path_buf()),
+            completed: false,
+            error: None,
+        });
+    }).await;
+    
+    // Use ffmpeg-sidecar to get the ffmpeg path
+    
+    let mut cmd = ffmpeg_tools::ffmpeg_command();
+
+    cmd.args(&[
+        "-y",  // Overwrite output
+        "-i", input_path.to_str().unwrap(),
+        "-map", &format!("0:a:{}", audio_track_index), // Select specific audio track
+    ])
+    .args(aac_encode_args()) // Transcode to AAC
+    .args(&[
+        "-progress", "pipe:1", // Output progress to stdout
+        "-nostats",
+        output_path.to_str().unwrap(),
+    ])
+    .stdout(Stdio::piped())
+    .stderr(Stdio::piped());
+    
+    let mut child = ffmpeg_tools::spawn(&mut cmd).context("Failed to spawn ffmpeg")?;
+    
+    let stdout = child.stdout.take().context("Failed to get stdout")?;
+    let mut reader = BufReader::new(stdout).lines();
+    
+    // Parse progress output
+    while let Ok(Some(line)) = reader.next_line().await {
+        if line.starts_with("out_time_ms=") {
+            if let Ok(time_ms) = line.trim_start_matches("out_time_ms=").parse::<i64>() {
+                let current_time = time_ms as f64 / 1_000_000.0;
+                let progress = if duration > 0.0 {
+                    ((current_time / duration) * 100.0).min(99.0)
+                } else {
+                    0.0
+                };
+                
+                // Update progress
+                streams.update_existing((session_id, file_id), |s| {
+                    if let Some(state) = s.transcode.as_mut() {
+                        state.progress = progress as f32;
                     }
+                }).await;
+                if progress as u32 % 10 == 0 { // Log every 10%
+                    tracing::info!("[Transcode] Progress: {:.1}%", progress);
                 }
             }
         }
-        
-        // Delete the torrent's base directory if it exists and is now empty
-        let torrent_dir = self.download_dir.join(&torrent_name);
-        if torrent_dir.exists() && torrent_dir.is_dir() {
-            match tokio::fs::remove_dir_all(&torrent_dir).await {
-                Ok(_) => {
-                    tracing::info!("Deleted torrent directory: {:?}", torrent_dir);
-                }
-                Err(e) => {
-                    tracing::warn!("Failed to delete torrent directory {:?}: {}", torrent_dir, e);
-                }
-            }
-        }
-        
-        tracing::info!("Deleted {} files for session_id: {}", deleted_count, session_id);
-        Ok(())
     }
     
-    /// Save torrent cache to disk
-    async fn save_cache_to_disk(&self) -> Result<()> {
-        let cache = self.torrent_cache.read().await;
-        let cache_file = self.download_dir.join("torrent_cache.json");
-        
-        let json = serde_json::to_string_pretty(&*cache)?;
-        tokio::fs::write(&cache_file, json).await?;
-        
-        tracing::debug!("Saved {} cached torrents to disk", cache.len());
-        Ok(())
-    }
+    // Wait for completion
+    let status = child.wait().await.context("Failed to wait for ffmpeg")?;
     
-    /// Load torrent cache from disk and restore sessions
-    async fn load_cache_from_disk(&self) -> Result<()> {
-        let cache_file = self.download_dir.join("torrent_cache.json");
-        
-        if !cache_file.exists() {
-            return Ok(());
-        }
-        
-        let json = tokio::fs::read_to_string(&cache_file).await?;
-        let cached_torrents: Vec<CachedTorrent> = serde_json::from_str(&json)?;
-        
-        tracing::info!("Loading {} cached torrents from disk", cached_torrents.len());
-        
-        let mut restored_cache = Vec::new();
-        
-        for cached in cached_torrents {
-            // Restore the torrent session in paused state
-            // Don't use list_only - we want the torrent in the session with 0-byte files
-            let add_torrent = AddTorrent::from_url(&cached.magnet_url);
-            
-            let opts = AddTorrentOptions {
-                overwrite: false,
-                paused: true, // Start paused to avoid downloading
-                only_files: None, // No specific files selected yet
-                ..Default::default()
-            };
-            
-            match self.session.add_torrent(add_torrent, Some(opts)).await {
-                Ok(response) => {
-                    let session_id = match response {
-                        AddTorrentResponse::Added(id, _) => {
-                            tracing::info!("Restored cached torrent (newly added): handle_id={}, session_id={}", cached.handle_id, id);
-                            id
-                        }
-                        AddTorrentResponse::AlreadyManaged(id, _) => {
-                            tracing::info!("Restored cached torrent (already managed): handle_id={}, session_id={}", cached.handle_id, id);
-                            id
-                        }
-                        AddTorrentResponse::ListOnly(_) => {
-                            tracing::warn!("Got ListOnly response when trying to restore cached torrent handle_id={}", cached.handle_id);
-                            continue;
-                        }
-                    };
-                    
-                    // Add to restored cache regardless of session_id match
-                    // (session_id might be different if librqbit reassigns IDs)
-                    let mut updated_cached = cached.clone();
-                    updated_cached.session_id = session_id;
-                    restored_cache.push(updated_cached);
-                }
-                Err(e) => {
-                    tracing::warn!("Failed to restore cached torrent handle_id={}: {}", cached.handle_id, e);
-                }
+    if status.success() {
+        tracing::info!("[Transcode] Completed successfully!");
+        streams.update_existing((session_id, file_id), |s| {
+            if let Some(state) = s.transcode.as_mut() {
+                state.progress = 100.0;
+                state.completed = true;
             }
-        }
-        
-        let mut cache = self.torrent_cache.write().await;
-        *cache = restored_cache;
-        
-        tracing::info!("Successfully restored {} cached torrents", cache.len());
-        
-        // Re-save cache with updated session IDs
-        drop(cache);
-        if let Err(e) = self.save_cache_to_disk().await {
-            tracing::error!("Failed to save updated cache: {}", e);
-        }
-        
+        }).await;
         Ok(())
+    } else {
+        let error_msg = "FFmpeg transcoding failed".to_string();
+        tracing::error!("[Transcode] ERROR: {}", error_msg);
+        streams.update_existing((session_id, file_id), |s| {
+            if let Some(state) = s.transcode.as_mut() {
+                state.error = Some(error_msg.clone());
+            }
+        }).await;
+        Err(anyhow::anyhow!(error_msg))
     }
+}
 
-    pub async fn pause_torrent(&self, handle_id: usize) -> Result<()> {
-        let torrents = self.torrents.read().await;
-        let entry = torrents.get(&handle_id).context("Torrent not found")?;
-        if let Some(session_id) = entry.session_id {
-            let handle = self
-                .session
-                .get(TorrentIdOrHash::Id(session_id))
-                .context("Session torrent not found")?;
-            self.session.pause(&handle).await?;
-        }
-        Ok(())
-    }
+// Get media duration using ffprobe
+#[allow(dead_code)]
+async fn get_media_duration(path: &std::path::Path) -> Result<f64> {
+    
+    let mut cmd = ffmpeg_tools::ffprobe_command();
+    cmd.args(&[
+            "-v", "error",
+            "-show_entries", "format=duration",
+            "-of", "default=noprint_wrappers=1:nokey=1",
+            path.to_str().unwrap(),
+        ]);
 
-    pub async fn resume_torrent(&self, handle_id: usize) -> Result<()> {
-        let torrents = self.torrents.read().await;
-        let entry = torrents.get(&handle_id).context("Torrent not found")?;
-        if let Some(session_id) = entry.session_id {
-            let handle = self
-                .session
-                .get(TorrentIdOrHash::Id(session_id))
-                .context("Session torrent not found")?;
-            self.session.unpause(&handle).await?;
-        }
-        Ok(())
+    let output = ffmpeg_tools::output(&mut cmd)
+        .await
+        .context("Failed to run ffprobe")?;
+    
+    if output.status.success() {
+        let duration_str = String::from_utf8_lossy(&output.stdout);
+        duration_str.trim().parse::<f64>().context("Failed to parse duration")
+    } else {
+        Err(anyhow::anyhow!("ffprobe failed"))
     }
+}
 
-    pub async fn remove_torrent(&self, handle_id: usize, delete_files: bool) -> Result<()> {
-        let mut torrents = self.torrents.write().await;
-        if let Some(entry) = torrents.remove(&handle_id) {
-            if let Some(session_id) = entry.session_id {
-                self.session.delete(TorrentIdOrHash::Id(session_id), delete_files).await?;
+// HTTP handler for backward compatibility - defaults to track 0
+async fn stream_transcoded_audio_default(
+    Path((session_id, file_id)): Path<(usize, usize)>,
+    headers: HeaderMap,
+    axum::extract::State(state): axum::extract::State<AppState>,
+) -> impl IntoResponse {
+    // Forward to main handler with track_index = 0
+    stream_transcoded_audio(Path((session_id, file_id, 0)), headers, axum::extract::State(state)).await
+}
+
+// Cached transcodes are told apart by the settings that shaped them, so changing the
+// downmix or normalization doesn't bring back the old output
+fn aac_cache_extension() -> String {
+    let normalization = AUDIO_NORMALIZATION.read().map(|n| *n).unwrap_or_default();
+    let downmix = AUDIO_DOWNMIX.read().map(|d| *d).unwrap_or_default();
+    format!("{:?}-{:?}.aac", downmix, normalization).to_lowercase()
+}
+
+// HTTP handler to stream transcoded audio live (starts playing before transcoding is complete).
+// A transcode the player read to the end is kept in the media cache under the torrent's
+// info hash, so re-watching the file, even after a restart, is served from disk.
+async fn stream_transcoded_audio(
+    Path((session_id, file_id, track_index)): Path<(usize, usize, usize)>,
+    _headers: HeaderMap,
+    axum::extract::State(state): axum::extract::State<AppState>,
+) -> impl IntoResponse {
+    use std::process::Stdio;
+    
+    tracing::info!("Live transcoded audio stream request: session_id={}, file_id={}, track_index={}", session_id, file_id, track_index);
+    
+    let Some(handle) = state.session.get(TorrentIdOrHash::Id(session_id)) else {
+        return (StatusCode::NOT_FOUND, "Failed to create torrent stream").into_response();
+    };
+    let cache_id = handle.info_hash().as_string();
+    let extension = aac_cache_extension();
+
+    if let Some(path) = state.media_cache.cached_format(TrackType::Transcode, &cache_id, file_id, track_index, &extension) {
+        match tokio::fs::File::open(&path).await {
+            Ok(file) => {
+                tracing::info!("Serving cached transcode {:?}", path);
+                let size = file.metadata().await.map(|m| m.len()).unwrap_or(0);
+                return Response::builder()
+                    .status(StatusCode::OK)
+                    .header(header::CONTENT_TYPE, "audio/aac")
+                    .header(header::CONTENT_LENGTH, size)
+                    .header(header::CACHE_CONTROL, "no-cache")
+                    .body(Body::from_stream(tokio_util::io::ReaderStream::new(file)))
+                    .unwrap()
+                    .into_response();
             }
+            Err(e) => tracing::warn!("Failed to open cached transcode {:?}: {}", path, e),
         }
-        Ok(())
     }
 
-    pub fn get_download_dir(&self) -> PathBuf {
-        self.download_dir.clone()
-    }
+    // Get torrent stream to pipe directly to ffmpeg
+    let mut torrent_stream = match handle.stream(file_id) {
+        Ok(stream) => stream,
+        Err(e) => {
+            tracing::error!("Failed to create torrent stream: {}", e);
+            return (StatusCode::NOT_FOUND, "Failed to create torrent stream").into_response();
+        }
+    };
+    
+    tracing::info!("Starting real-time transcode with piped torrent stream");
+    
+    let Some(job) = start_transcode_job("audio").await else {
+        return transcode_busy_response();
+    };
 
-    pub async fn wipe_all_files(&self) -> Result<()> {
-        tracing::info!("Wiping all torrent files from download directory");
+    // Start ffmpeg transcoding with piped input from torrent stream
+    let mut cmd = ffmpeg_tools::ffmpeg_command();
+    // Only errors on stderr, so what ends up in an error response is the failure
+    cmd.args(["-hide_banner", "-loglevel", "error"]);
+    
+    let audio_map = format!("0:a:{}", track_index);
+    cmd.args(&[
+        "-i", "pipe:0",  // Read from stdin
+        "-map", &audio_map,
+    ])
+    .args(aac_encode_args())
+    .args(&[
+        "-f", "adts",
+        "pipe:1",
+    ])
+    .stdin(Stdio::piped())
+    .stdout(Stdio::piped())
+    .stderr(Stdio::piped());
+    
+    let mut child = match ffmpeg_tools::spawn(&mut cmd) {
+        Ok(c) => c,
+        Err(e) => {
+            tracing::error!("Failed to spawn ffmpeg: {}", e);
+            return StreamError::internal("ffmpeg_spawn_failed", format!("Failed to start transcoding: {}", e)).into_response();
+        }
+    };
+    
+    let mut stdin = match child.stdin.take() {
+        Some(s) => s,
+        None => return StreamError::internal("ffmpeg_pipe_failed", "Failed to get ffmpeg stdin").into_response(),
+    };
+    
+    let mut stdout = match child.stdout.take() {
+        Some(s) => s,
+        None => return StreamError::internal("ffmpeg_pipe_failed", "Failed to get ffmpeg output").into_response(),
+    };
+    
+    // Spawn task to pipe torrent stream to ffmpeg stdin
+    tokio::spawn(async move {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        let mut buffer = vec![0u8; 256 * 1024]; // 256KB chunks
+        let mut total_piped = 0u64;
         
-        let download_dir = self.download_dir.clone();
+        tracing::info!("Starting to pipe torrent stream to ffmpeg stdin");
         
-        // Delete everything in the download directory except cache files
-        if download_dir.exists() {
-            let mut entries = tokio::fs::read_dir(&download_dir).await?;
-            let mut deleted_count = 0;
-            
-            while let Some(entry) = entries.next_entry().await? {
-                let path = entry.path();
-                let file_name = path.file_name()
-                    .and_then(|n| n.to_str())
-                    .unwrap_or("");
-                
-                // Skip cache files
-                if file_name == "torrent_cache.json" {
-                    continue;
+        loop {
+            match torrent_stream.read(&mut buffer).await {
+                Ok(0) => {
+                    tracing::info!("Torrent stream EOF, piped {} MB total", total_piped / 1_048_576);
+                    break;
                 }
-                
-                if path.is_dir() {
-                    match tokio::fs::remove_dir_all(&path).await {
-                        Ok(_) => {
-                            tracing::info!("Deleted directory: {:?}", path);
-                            deleted_count += 1;
-                        }
-                        Err(e) => {
-                            tracing::warn!("Failed to delete directory {:?}: {}", path, e);
-                        }
+                Ok(n) => {
+                    if let Err(e) = stdin.write_all(&buffer[..n]).await {
+                        tracing::error!("Failed to write to ffmpeg stdin: {}", e);
+                        break;
                     }
-                } else {
-                    match tokio::fs::remove_file(&path).await {
-                        Ok(_) => {
-                            tracing::info!("Deleted file: {:?}", path);
-                            deleted_count += 1;
-                        }
-                        Err(e) => {
-                            tracing::warn!("Failed to delete file {:?}: {}", path, e);
-                        }
+                    total_piped += n as u64;
+                    if total_piped % (50 * 1024 * 1024) == 0 {
+                        tracing::info!("Piped {} MB to ffmpeg", total_piped / 1_048_576);
                     }
                 }
+                Err(e) => {
+                    tracing::error!("Failed to read from torrent stream: {}", e);
+                    break;
+                }
             }
-            
-            tracing::info!("Wiped {} items from download directory", deleted_count);
         }
         
-        Ok(())
-    }
-
-    pub async fn cleanup_all(&self) -> Result<()> {
-        tracing::info!("Cleaning up all torrents on app close");
-        let torrents = self.torrents.read().await;
-        let session_ids: Vec<usize> = torrents.values()
-            .filter_map(|entry| entry.session_id)
-            .collect();
-        drop(torrents);
+        drop(stdin);
+    });
+    
+    // Send the transcoded audio to the client while writing it to a temp file, which goes
+    // into the cache if ffmpeg finishes and the client read all of it
+    let id = EXTRACTION_COUNTER.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+    let part_path = std::env::temp_dir().join(format!("magnolia_transcode_{}_{}_{}_{}.part", session_id, file_id, track_index, id));
+    let (tx, mut rx) = tokio::sync::mpsc::channel::<std::io::Result<axum::body::Bytes>>(16);
+    let media_cache = state.media_cache.clone();
+    let stderr = child.stderr.take().map(|stderr| tokio::spawn(stderr_tail(stderr)));
+    tokio::spawn(async move {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        let mut part = tokio::fs::File::create(&part_path).await.ok();
+        let mut buffer = vec![0u8; 64 * 1024];
+        let mut delivered = true;
 
-        for session_id in session_ids {
-            tracing::info!("Deleting torrent session_id: {} with files", session_id);
-            if let Err(e) = self.session.delete(TorrentIdOrHash::Id(session_id), true).await {
-                tracing::error!("Error deleting torrent {}: {}", session_id, e);
+        loop {
+            let read = tokio::select! {
+                read = stdout.read(&mut buffer) => read,
+                // The client went away, or nothing came in time and the request gave up
+                _ = tx.closed() => {
+                    delivered = false;
+                    break;
+                }
+            };
+            match read {
+                Ok(0) => break,
+                Ok(n) => {
+                    let chunk = axum::body::Bytes::copy_from_slice(&buffer[..n]);
+                    if let Some(file) = part.as_mut() {
+                        if file.write_all(&chunk).await.is_err() {
+                            part = None;
+                        }
+                    }
+                    // Client went away; dropping the child stops ffmpeg
+                    if tx.send(Ok(chunk)).await.is_err() {
+                        delivered = false;
+                        break;
+                    }
+                }
+                Err(e) => {
+                    let _ = tx.send(Err(e)).await;
+                    delivered = false;
+                    break;
+                }
             }
         }
-        
-        // Also delete the cache file
-        let cache_path = self.download_dir.join("torrent_cache.json");
-        if cache_path.exists() {
-            if let Err(e) = std::fs::remove_file(&cache_path) {
-                tracing::error!("Failed to remove cache file: {}", e);
-            } else {
-                tracing::info!("Removed torrent cache file");
+
+        if !delivered {
+            let _ = child.start_kill();
+        }
+        let finished = delivered && child.wait().await.map(|status| status.success()).unwrap_or(false);
+        drop(child);
+        drop(job);
+        let cached = match part {
+            Some(mut file) if finished => {
+                let _ = file.flush().await;
+                drop(file);
+                media_cache.store_file_as(TrackType::Transcode, &cache_id, file_id, track_index, &extension, &part_path)
+            }
+            _ => Err("transcode didn't finish".to_string()),
+        };
+        match cached {
+            Ok(path) => tracing::info!("Cached transcode at {:?}", path),
+            Err(e) => {
+                tracing::info!("Transcode not cached: {}", e);
+                let _ = tokio::fs::remove_file(&part_path).await;
             }
         }
-        
-        Ok(())
-    }
+    });
 
-    pub async fn get_http_port(&self) -> Result<u16, String> {
-        Ok(self.http_addr.port())
+    // Like child_output_body, a transcode that fails or stalls before its first bytes is
+    // answered with ffmpeg's stderr. Dropping the receiver makes the task kill ffmpeg and
+    // give back the transcode slot.
+    let first = match tokio::time::timeout(FIRST_OUTPUT_TIMEOUT, rx.recv()).await {
+        Ok(Some(Ok(first))) => first,
+        Err(_) => {
+            drop(rx);
+            let stderr = match stderr {
+                Some(task) => task.await.unwrap_or_default(),
+                None => String::new(),
+            };
+            tracing::error!("ffmpeg produced no output in {}s, killed it", FIRST_OUTPUT_TIMEOUT.as_secs());
+            return StreamError::ffmpeg("ffmpeg produced no output in time", &stderr).into_response();
+        }
+        Ok(first) => {
+            let stderr = match stderr {
+                Some(task) => task.await.unwrap_or_default(),
+                None => String::new(),
+            };
+            tracing::error!("ffmpeg failed before producing output: {}", stderr);
+            let message = match first {
+                Some(Err(e)) => format!("Failed to read ffmpeg output: {}", e),
+                _ => "ffmpeg exited without producing output".to_string(),
+            };
+            return StreamError::ffmpeg(message, &stderr).into_response();
+        }
+    };
+    let stream = futures::stream::unfold((Some(first), rx), |(first, mut rx)| async move {
+        match first {
+            Some(first) => Some((Ok(first), (None, rx))),
+            None => rx.recv().await.map(|chunk| (chunk, (None, rx))),
+        }
+    });
+    let body = Body::from_stream(stream);
+    
+    Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, "audio/aac")
+        .header(header::TRANSFER_ENCODING, "chunked")
+        .header(header::CACHE_CONTROL, "no-cache")
+        .body(body)
+        .unwrap()
+        .into_response()
+}
+
+// Local library files are served from disk under /local/{library_id}/..., mirroring the
+// torrent routes so the player can treat both the same way
+
+async fn local_file_path(state: &AppState, library_id: &str) -> Option<PathBuf> {
+    state.local_files.read().await.get(library_id).cloned()
+}
+
+fn video_content_type(path: &std::path::Path) -> &'static str {
+    match path.extension().and_then(|e| e.to_str()).map(|e| e.to_lowercase()).as_deref() {
+        Some("mp4") | Some("m4v") => "video/mp4",
+        Some("webm") => "video/webm",
+        Some("mov") => "video/quicktime",
+        Some("avi") => "video/x-msvideo",
+        _ => "video/x-matroska",
     }
+}
 
-    pub async fn get_transcoded_audio(&self, session_id: usize, file_index: usize) -> Result<Option<Vec<u8>>, String> {
-        // Check if transcoding is complete and get the output path
-        let output_path = {
-            let states = self.transcode_states.read().await;
-            if let Some(transcode_state) = states.get(&(session_id, file_index)) {
-                if !transcode_state.completed {
-                    return Err("Transcoding not complete".to_string());
-                }
-                transcode_state.output_path.clone()
-            } else {
-                return Err("No transcoding in progress for this file".to_string());
-            }
-        };
+async fn stream_local_file(
+    Path(library_id): Path<String>,
+    headers: HeaderMap,
+    axum::extract::State(state): axum::extract::State<AppState>,
+) -> impl IntoResponse {
+    use std::io::SeekFrom;
+    use tokio_util::io::ReaderStream;
 
-        let output_path = match output_path {
-            Some(p) => p,
-            None => return Err("Transcoded file path not found".to_string()),
-        };
+    let Some(path) = local_file_path(&state, &library_id).await else {
+        return (StatusCode::NOT_FOUND, "Library entry not found").into_response();
+    };
 
-        if !output_path.exists() {
-            return Err("Transcoded file not found on disk".to_string());
+    let mut file = match tokio::fs::File::open(&path).await {
+        Ok(f) => f,
+        Err(e) => {
+            tracing::error!("Failed to open local file {:?}: {}", path, e);
+            return (StatusCode::NOT_FOUND, "File not found").into_response();
         }
+    };
 
-        // Read the entire file into memory
-        match tokio::fs::read(&output_path).await {
-            Ok(data) => {
-                tracing::info!("Loaded transcoded audio: {} bytes from {:?}", data.len(), output_path);
-                Ok(Some(data))
-            }
-            Err(e) => Err(format!("Failed to read transcoded audio file: {}", e)),
+    let file_size = match file.metadata().await {
+        Ok(m) if m.len() > 0 => m.len(),
+        _ => return StreamError::internal("file_size_unavailable", "Could not get file size").into_response(),
+    };
+
+    let (start, end, status_code) = match parse_byte_range(&headers, file_size) {
+        Ok(range) => range,
+        Err(response) => return response,
+    };
+
+    if start > 0 {
+        if let Err(e) = file.seek(SeekFrom::Start(start)).await {
+            tracing::error!("Failed to seek local file to {}: {}", start, e);
+            return StreamError::internal("seek_failed", format!("Failed to seek: {}", e)).into_response();
         }
     }
+
+    let content_length = end - start + 1;
+    let body = Body::from_stream(ReaderStream::new(file.take(content_length)));
+
+    media_response_builder(status_code, start, end, file_size, video_content_type(&path))
+        .body(body)
+        .unwrap()
+        .into_response()
 }
 
-async fn extract_mkv_metadata_ffprobe(file_path: &std::path::Path) -> Result<MkvMetadata> {
-    use tokio::process::Command;
-    
-    tracing::info!("Extracting metadata with ffprobe: {:?}", file_path);
-    
-    // Check if file exists
-    if !file_path.exists() {
-        tracing::error!("File does not exist: {:?}", file_path);
-        return Err(anyhow::anyhow!("File does not exist"));
-    }
-    
-    let file_size = std::fs::metadata(file_path)?.len();
-    tracing::info!("File size: {} bytes", file_size);
-    
-    let mut cmd = Command::new("ffprobe");
-    cmd.args(&[
-            "-v", "error",
-            "-print_format", "json",
-            "-show_format",
-            "-show_streams",
-            "-show_chapters",
-            file_path.to_str().unwrap(),
-        ]);
+async fn stream_local_file_head(
+    Path(library_id): Path<String>,
+    headers: HeaderMap,
+    axum::extract::State(state): axum::extract::State<AppState>,
+) -> Response {
+    let Some(path) = local_file_path(&state, &library_id).await else {
+        return (StatusCode::NOT_FOUND, "Library entry not found").into_response();
+    };
+    let file_size = match tokio::fs::metadata(&path).await {
+        Ok(m) if m.len() > 0 => m.len(),
+        _ => return (StatusCode::NOT_FOUND, "File not found").into_response(),
+    };
+    let (start, end, status_code) = match parse_byte_range(&headers, file_size) {
+        Ok(range) => range,
+        Err(response) => return response,
+    };
+    media_response_builder(status_code, start, end, file_size, video_content_type(&path))
+        .body(Body::empty())
+        .unwrap()
+}
 
-    #[cfg(target_os = "windows")]
-    cmd.creation_flags(0x08000000);
+async fn get_local_file_metadata(
+    Path(library_id): Path<String>,
+    axum::extract::State(state): axum::extract::State<AppState>,
+) -> impl IntoResponse {
+    tracing::info!("Local metadata request: library_id={}", library_id);
 
-    let output = cmd.output()
-        .await
-        .context("Failed to run ffprobe command")?;
-    
-    tracing::info!("ffprobe exit status: {}", output.status);
-    
-    if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        let stdout = String::from_utf8_lossy(&output.stdout);
-        tracing::error!("ffprobe failed with status: {}", output.status);
-        tracing::error!("ffprobe stderr: {}", stderr);
-        tracing::error!("ffprobe stdout: {}", stdout);
-        return Err(anyhow::anyhow!("ffprobe failed: {}", stderr));
-    }
-    
-    let stdout_str = String::from_utf8_lossy(&output.stdout);
-    tracing::debug!("ffprobe output: {}", stdout_str);
-    
-    let probe_data: serde_json::Value = serde_json::from_slice(&output.stdout)
-        .context("Failed to parse ffprobe JSON output")?;
-    
-    let mut audio_tracks = Vec::new();
-    let mut subtitle_tracks = Vec::new();
-    let mut chapters = Vec::new();
-    
-    // Extract streams
-    if let Some(streams) = probe_data.get("streams").and_then(|s| s.as_array()) {
-        let mut audio_index = 0;
-        let mut subtitle_index = 0;
-        
-        for stream in streams {
-            let codec_type = stream.get("codec_type").and_then(|t| t.as_str());
-            
-            match codec_type {
-                Some("audio") => {
-                    let codec_name = stream.get("codec_name").and_then(|c| c.as_str()).unwrap_or("unknown");
-                    let codec_long_name = stream.get("codec_long_name").and_then(|c| c.as_str()).unwrap_or("");
-                    let profile = stream.get("profile").and_then(|p| p.as_str()).unwrap_or("");
-                    let language = stream.get("tags")
-                        .and_then(|t| t.get("language"))
-                        .and_then(|l| l.as_str())
-                        .unwrap_or("und")
-                        .to_string();
-                    let title = stream.get("tags")
-                        .and_then(|t| t.get("title"))
-                        .and_then(|t| t.as_str())
-                        .map(|s| s.to_string());
-                    
-                    // Check if this codec needs transcoding (check codec name, long name, and profile)
-                    let codec_lower = codec_name.to_lowercase();
-                    let long_name_lower = codec_long_name.to_lowercase();
-                    let profile_lower = profile.to_lowercase();
-                    
-                    // Special check for AC3/EAC3 variants to ensure proper detection
-                    let is_ac3_variant = codec_lower == "ac3" 
-                        || codec_lower == "eac3" 
-                        || codec_lower == "ac-3" 
-                        || codec_lower == "e-ac-3"
-                        || codec_lower.starts_with("ac3")
-                        || codec_lower.starts_with("eac3")
-                        || long_name_lower.contains("ac-3")
-                        || long_name_lower.contains("ac3")
-                        || long_name_lower.contains("e-ac-3")
-                        || long_name_lower.contains("eac3")
-                        || long_name_lower.contains("dolby digital");
-                    
-                    // Whitelist of browser-supported codecs
-                    let is_known_supported = matches!(codec_lower.as_str(), 
-                        "aac" | "mp3" | "opus" | "vorbis" | "mp2" | "mp1" | "flac"
-                    ) && !long_name_lower.contains("truehd") 
-                      && !long_name_lower.contains("dts")
-                      && !long_name_lower.contains("atmos")
-                      && !is_ac3_variant;
-                    
-                    // Check against blacklist of known unsupported codecs
-                    let is_known_unsupported = is_ac3_variant || UNSUPPORTED_AUDIO_CODECS.iter().any(|unsupported| {
-                        codec_lower == *unsupported 
-                            || codec_lower.contains(unsupported)
-                            || long_name_lower.contains(unsupported)
-                            || profile_lower.contains(unsupported)
-                    });
-                    
-                    // Transcode if explicitly unsupported OR if not in the supported whitelist
-                    let needs_transcoding = is_known_unsupported || !is_known_supported;
-                    
-                    tracing::info!("Audio track {}: codec='{}' ({}), profile='{}', needs_transcoding={}", 
-                        audio_index, codec_name, codec_long_name, profile, needs_transcoding);
-                    
-                    audio_tracks.push(AudioTrack {
-                        index: audio_index,
-                        language: Some(language),
-                        codec: Some(codec_name.to_string()),
-                        name: title,
-                        needs_transcoding,
-                        transcoded_url: None,
-                    });
-                    audio_index += 1;
-                }
-                Some("subtitle") => {
-                    let codec_name = stream.get("codec_name").and_then(|c| c.as_str()).unwrap_or("unknown");
-                    let language = stream.get("tags")
-                        .and_then(|t| t.get("language"))
-                        .and_then(|l| l.as_str())
-                        .unwrap_or("und")
-                        .to_string();
-                    let title = stream.get("tags")
-                        .and_then(|t| t.get("title"))
-                        .and_then(|t| t.as_str())
-                        .map(|s| s.to_string());
-                    
-                    subtitle_tracks.push(SubtitleTrack {
-                        index: subtitle_index,
-                        language: Some(language),
-                        codec: Some(codec_name.to_string()),
-                        name: title,
-                    });
-                    subtitle_index += 1;
-                }
-                _ => {}
-            }
+    let Some(path) = local_file_path(&state, &library_id).await else {
+        return (StatusCode::NOT_FOUND, "Library entry not found").into_response();
+    };
+
+    // The whole file is on disk, so ffprobe can read it directly instead of a temp copy
+    let mut metadata = match extract_mkv_metadata_ffprobe(&path).await {
+        Ok(m) => m,
+        Err(e) => {
+            tracing::error!("Failed to extract metadata from {:?}: {}", path, e);
+            return StreamError::internal("metadata_failed", format!("Failed to extract metadata: {}", e)).into_response();
         }
-    }
-    
-    // Extract chapters
-    if let Some(chapters_data) = probe_data.get("chapters").and_then(|c| c.as_array()) {
-        for (index, chapter) in chapters_data.iter().enumerate() {
-            let start_str = chapter.get("start_time").and_then(|s| s.as_str());
-            let end_str = chapter.get("end_time").and_then(|e| e.as_str());
-            let title = chapter.get("tags")
-                .and_then(|t| t.get("title"))
-                .and_then(|t| t.as_str())
-                .map(|s| s.to_string());
-            
-            if let (Some(start), Some(end)) = (start_str, end_str) {
-                if let (Ok(start_time), Ok(end_time)) = (start.parse::<f64>(), end.parse::<f64>()) {
-                    chapters.push(Chapter {
-                        index,
-                        title,
-                        start_time,
-                        end_time,
-                    });
-                }
-            }
+    };
+
+    for (track_idx, track) in metadata.audio_tracks.iter_mut().enumerate() {
+        if track.needs_transcoding {
+            track.transcoded_url = Some(format!(
+                "http://{}/local/{}/transcoded-audio-stream/{}",
+                state.http_addr, library_id, track_idx
+            ));
         }
     }
-    
-    tracing::info!("Extracted {} audio tracks, {} subtitle tracks, {} chapters", 
-        audio_tracks.len(), subtitle_tracks.len(), chapters.len());
-    
-    // Check if ANY audio track needs transcoding (check all tracks, not just first)
-    let needs_audio_transcoding = audio_tracks.iter()
-        .any(|t| t.needs_transcoding);
-    
-    // Log all audio codecs for debugging
-    for track in &audio_tracks {
-        tracing::info!("Audio track {}: codec={:?}, needs_transcoding={}", 
-            track.index, track.codec, track.needs_transcoding);
+    if metadata.audio_tracks.first().map(|t| t.needs_transcoding).unwrap_or(false) {
+        metadata.transcoded_audio_url = Some(format!(
+            "http://{}/local/{}/transcoded-audio-stream/0",
+            state.http_addr, library_id
+        ));
     }
-    
-    let duration = probe_data.get("format")
-        .and_then(|f| f.get("duration"))
-        .and_then(|d| d.as_str())
-        .and_then(|s| s.parse::<f64>().ok());
 
-    if needs_audio_transcoding {
-        tracing::info!("Audio transcoding required - at least one track has unsupported codec");
-    } else {
-        tracing::info!("No audio transcoding required - all tracks have supported codecs");
-    }
-    
-    Ok(MkvMetadata {
-        audio_tracks,
-        subtitle_tracks,
-        chapters,
-        needs_audio_transcoding,
-        transcoded_audio_url: None,
-        duration,
-    })
+    add_subtitle_urls(&mut metadata, &format!("http://{}/local/{}/subtitles", state.http_addr, library_id));
+
+    axum::Json(metadata).into_response()
 }
 
-// Transcode audio to AAC using ffmpeg-sidecar
-#[allow(dead_code)]
-async fn transcode_audio_track(
-    input_path: &std::path::Path,
-    output_path: &std::path::Path,
-    audio_track_index: usize,
-    transcode_states: Arc<RwLock<HashMap<(usize, usize), TranscodeState>>>,
-    session_id: usize,
-    file_id: usize,
-) -> Result<()> {
-    use std::process::Stdio;
-    use tokio::io::{AsyncBufReadExt, BufReader};
-    
-    println!("[Transcode] Starting audio transcoding: {:?} -> {:?} (track {})", 
-        input_path, output_path, audio_track_index);
-    
-    // Verify input file exists
-    if !input_path.exists() {
-        let err = format!("Input file does not exist: {:?}", input_path);
-        println!("[Transcode] ERROR: {}", err);
-        return Err(anyhow::anyhow!(err));
+async fn get_local_subtitle_track(
+    Path((library_id, track_index)): Path<(String, usize)>,
+    axum::extract::Query(window): axum::extract::Query<SubtitleWindow>,
+    axum::extract::State(state): axum::extract::State<AppState>,
+) -> impl IntoResponse {
+    tracing::info!("Local subtitle request: library_id={}, track={}, format={:?}", library_id, track_index, window.format);
+
+    let format = window.format.unwrap_or(TrackFormat::Ass);
+    if !format.is_subtitle() {
+        return (StatusCode::BAD_REQUEST, "Not a subtitle format").into_response();
     }
-    
-    let file_size = std::fs::metadata(input_path)
-        .map(|m| m.len())
-        .unwrap_or(0);
-    println!("[Transcode] Input file size: {} MB", file_size / 1_048_576);
-    
-    // Get duration for progress calculation
-    let duration = get_media_duration(input_path).await.unwrap_or(0.0);
-    println!("[Transcode] Media duration: {} seconds", duration);
-    
-    // Initialize transcode state
-    {
-        let mut states = transcode_states.write().await;
-        states.insert((session_id, file_id), TranscodeState {
-            progress: 0.0,
-            output_path: Some(output_path.to_path_buf()),
-            completed: false,
-            error: None,
-        });
+    let Some(path) = local_file_path(&state, &library_id).await else {
+        return (StatusCode::NOT_FOUND, "Library entry not found").into_response();
+    };
+
+    match extract_track(&path, track_index, format).await {
+        Ok(data) if format != TrackFormat::Ass => text_subtitle_response(data, format, true),
+        // Library files are complete, so only broken output is turned away
+        Ok(data) => {
+            let check = check_ass(&data, true, None);
+            ass_response(data, &check, true, None)
+        }
+        Err(e) => StreamError::ffmpeg("Subtitle extraction failed", &e).into_response(),
     }
-    
-    // Use ffmpeg-sidecar to get the ffmpeg path
-    use tokio::process::Command;
-    
-    let mut cmd = Command::new(ffmpeg_path());
-    
-    #[cfg(target_os = "windows")]
-    cmd.creation_flags(0x08000000);
+}
+
+async fn stream_local_transcoded_audio(
+    Path((library_id, track_index)): Path<(String, usize)>,
+    axum::extract::State(state): axum::extract::State<AppState>,
+) -> impl IntoResponse {
+    use std::process::Stdio;
+
+    tracing::info!("Local transcoded audio request: library_id={}, track_index={}", library_id, track_index);
+
+    let Some(path) = local_file_path(&state, &library_id).await else {
+        return (StatusCode::NOT_FOUND, "Library entry not found").into_response();
+    };
+
+    // Same AAC/ADTS output as the torrent route, but ffmpeg reads the file itself
+    let Some(job) = start_transcode_job("audio").await else {
+        return transcode_busy_response();
+    };
+
+    let mut cmd = ffmpeg_tools::ffmpeg_command();
+    // Only errors on stderr, so what ends up in an error response is the failure
+    cmd.args(["-hide_banner", "-loglevel", "error"]);
+
+    let audio_map = format!("0:a:{}", track_index);
+    cmd.arg("-i")
+        .arg(&path)
+        .args(["-map", &audio_map])
+        .args(aac_encode_args())
+        .args(["-f", "adts", "pipe:1"])
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+
+    let mut child = match ffmpeg_tools::spawn(&mut cmd) {
+        Ok(c) => c,
+        Err(e) => {
+            tracing::error!("Failed to spawn ffmpeg: {}", e);
+            return StreamError::internal("ffmpeg_spawn_failed", format!("Failed to start transcoding: {}", e)).into_response();
+        }
+    };
+
+    let stdout = match child.stdout.take() {
+        Some(s) => s,
+        None => return StreamError::internal("ffmpeg_pipe_failed", "Failed to get ffmpeg output").into_response(),
+    };
+
+    let body = match child_output_body(child, stdout, Some(job)).await {
+        Ok(body) => body,
+        Err(e) => return e.into_response(),
+    };
+
+    Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, "audio/aac")
+        .header(header::TRANSFER_ENCODING, "chunked")
+        .header(header::CACHE_CONTROL, "no-cache")
+        .body(body)
+        .unwrap()
+        .into_response()
+}
+
+// Live HDR -> SDR transcode: linearize with zscale, tone-map, then convert to BT.709 8-bit
+// H.264 in fragmented MP4 so the webview can play it while it's being produced.
+// Accepts ?tonemap=hable|mobius|reinhard (default hable) and ?audio=<track index>.
+// Keyframes are forced on the configured interval, so every fragment lasts that long;
+// X-Fragment-Duration tells the player.
+async fn stream_tonemapped_video(
+    Path((session_id, file_id)): Path<(usize, usize)>,
+    axum::extract::Query(params): axum::extract::Query<HashMap<String, String>>,
+    axum::extract::State(state): axum::extract::State<AppState>,
+) -> impl IntoResponse {
+    use std::process::Stdio;
+
+    let algorithm = match params.get("tonemap").map(|s| s.as_str()) {
+        Some("mobius") => "mobius",
+        Some("reinhard") => "reinhard",
+        _ => "hable",
+    };
+    let audio_track: usize = params.get("audio").and_then(|a| a.parse().ok()).unwrap_or(0);
+
+    tracing::info!("Tone-mapped stream request: session_id={}, file_id={}, tonemap={}, audio={}", session_id, file_id, algorithm, audio_track);
+
+    let mut torrent_stream = match state.session.get(TorrentIdOrHash::Id(session_id)).map(|h| h.stream(file_id)) {
+        Some(Ok(stream)) => stream,
+        Some(Err(e)) => {
+            tracing::error!("Failed to create torrent stream: {}", e);
+            return (StatusCode::NOT_FOUND, "Failed to create torrent stream").into_response();
+        }
+        None => return (StatusCode::NOT_FOUND, "Torrent not found").into_response(),
+    };
+
+    let filter = format!(
+        "zscale=t=linear:npl=100,format=gbrpf32le,zscale=p=bt709,tonemap=tonemap={}:desat=0,zscale=t=bt709:m=bt709:r=tv,format=yuv420p",
+        algorithm
+    );
+    let audio_map = format!("0:a:{}?", audio_track);
+    let keyframe_secs = TRANSCODE_KEYFRAME_SECS.load(std::sync::atomic::Ordering::Relaxed);
+    let keyframes = format!("expr:gte(t,n_forced*{})", keyframe_secs);
+
+    let Some(job) = start_transcode_job("tonemap").await else {
+        return transcode_busy_response();
+    };
+
+    let mut cmd = ffmpeg_tools::ffmpeg_command();
+    // Only errors on stderr, so what ends up in an error response is the failure
+    cmd.args(["-hide_banner", "-loglevel", "error"]);
 
     cmd.args(&[
-        "-y",  // Overwrite output
-        "-i", input_path.to_str().unwrap(),
-        "-map", &format!("0:a:{}", audio_track_index), // Select specific audio track
-        "-c:a", "aac",  // Transcode to AAC
-        "-b:a", "192k", // Good quality
-        "-progress", "pipe:1", // Output progress to stdout
-        "-nostats",
-        output_path.to_str().unwrap(),
+        "-i", "pipe:0",
+        "-map", "0:v:0",
+        "-map", &audio_map,
+        "-vf", &filter,
+        "-c:v", "libx264",
+        "-preset", "veryfast",
+        "-crf", "20",
+        "-force_key_frames", &keyframes,
+        // Scene cuts would add keyframes between the forced ones and split fragments unevenly
+        "-sc_threshold", "0",
+    ])
+    .args(aac_encode_args())
+    .args(&[
+        "-f", "mp4",
+        "-movflags", "frag_keyframe+empty_moov+default_base_moof",
+        "pipe:1",
     ])
+    .stdin(Stdio::piped())
     .stdout(Stdio::piped())
     .stderr(Stdio::piped());
-    
-    let mut child = cmd.spawn().context("Failed to spawn ffmpeg")?;
-    
-    let stdout = child.stdout.take().context("Failed to get stdout")?;
-    let mut reader = BufReader::new(stdout).lines();
-    
-    // Parse progress output
-    while let Ok(Some(line)) = reader.next_line().await {
-        if line.starts_with("out_time_ms=") {
-            if let Ok(time_ms) = line.trim_start_matches("out_time_ms=").parse::<i64>() {
-                let current_time = time_ms as f64 / 1_000_000.0;
-                let progress = if duration > 0.0 {
-                    ((current_time / duration) * 100.0).min(99.0)
-                } else {
-                    0.0
-                };
-                
-                // Update progress
-                let mut states = transcode_states.write().await;
-                if let Some(state) = states.get_mut(&(session_id, file_id)) {
-                    state.progress = progress as f32;
-                    if progress as u32 % 10 == 0 { // Log every 10%
-                        println!("[Transcode] Progress: {:.1}%", progress);
+
+    let mut child = match ffmpeg_tools::spawn(&mut cmd) {
+        Ok(c) => c,
+        Err(e) => {
+            tracing::error!("Failed to spawn ffmpeg: {}", e);
+            return StreamError::internal("ffmpeg_spawn_failed", format!("Failed to start tone-mapping: {}", e)).into_response();
+        }
+    };
+
+    let mut stdin = match child.stdin.take() {
+        Some(s) => s,
+        None => return StreamError::internal("ffmpeg_pipe_failed", "Failed to get ffmpeg stdin").into_response(),
+    };
+
+    let stdout = match child.stdout.take() {
+        Some(s) => s,
+        None => return StreamError::internal("ffmpeg_pipe_failed", "Failed to get ffmpeg output").into_response(),
+    };
+
+    tokio::spawn(async move {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        let mut buffer = vec![0u8; 256 * 1024];
+
+        loop {
+            match torrent_stream.read(&mut buffer).await {
+                Ok(0) => break,
+                Ok(n) => {
+                    // Client disconnected and ffmpeg exited
+                    if stdin.write_all(&buffer[..n]).await.is_err() {
+                        break;
                     }
                 }
+                Err(e) => {
+                    tracing::error!("Failed to read from torrent stream: {}", e);
+                    break;
+                }
             }
         }
-    }
-    
-    // Wait for completion
-    let status = child.wait().await.context("Failed to wait for ffmpeg")?;
-    
-    if status.success() {
-        println!("[Transcode] Completed successfully!");
-        let mut states = transcode_states.write().await;
-        if let Some(state) = states.get_mut(&(session_id, file_id)) {
-            state.progress = 100.0;
-            state.completed = true;
-        }
-        Ok(())
-    } else {
-        let error_msg = "FFmpeg transcoding failed".to_string();
-        println!("[Transcode] ERROR: {}", error_msg);
-        let mut states = transcode_states.write().await;
-        if let Some(state) = states.get_mut(&(session_id, file_id)) {
-            state.error = Some(error_msg.clone());
-        }
-        Err(anyhow::anyhow!(error_msg))
-    }
-}
 
-// Get media duration using ffprobe
-#[allow(dead_code)]
-async fn get_media_duration(path: &std::path::Path) -> Result<f64> {
-    use tokio::process::Command;
-    
-    let mut cmd = Command::new("ffprobe");
-    cmd.args(&[
-            "-v", "error",
-            "-show_entries", "format=duration",
-            "-of", "default=noprint_wrappers=1:nokey=1",
-            path.to_str().unwrap(),
-        ]);
+        drop(stdin);
+    });
 
-    #[cfg(target_os = "windows")]
-    cmd.creation_flags(0x08000000);
+    let body = match child_output_body(child, stdout, Some(job)).await {
+        Ok(body) => body,
+        Err(e) => return e.into_response(),
+    };
 
-    let output = cmd.output()
-        .await
-        .context("Failed to run ffprobe")?;
-    
-    if output.status.success() {
-        let duration_str = String::from_utf8_lossy(&output.stdout);
-        duration_str.trim().parse::<f64>().context("Failed to parse duration")
-    } else {
-        Err(anyhow::anyhow!("ffprobe failed"))
-    }
+    Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, "video/mp4")
+        .header("X-Fragment-Duration", keyframe_secs.to_string())
+        .header(header::TRANSFER_ENCODING, "chunked")
+        .header(header::CACHE_CONTROL, "no-cache")
+        .body(body)
+        .unwrap()
+        .into_response()
 }
 
-// HTTP handler for backward compatibility - defaults to track 0
-async fn stream_transcoded_audio_default(
-    Path((session_id, file_id)): Path<(usize, usize)>,
-    headers: HeaderMap,
-    axum::extract::State(state): axum::extract::State<AppState>,
-) -> impl IntoResponse {
-    // Forward to main handler with track_index = 0
-    stream_transcoded_audio(Path((session_id, file_id, 0)), headers, axum::extract::State(state)).await
+// Where a seeking remux reads from: the file on disk once it's completely downloaded,
+// otherwise the stream route, which fetches pieces as ffmpeg reads them. A partly
+// downloaded file on disk has zeros wherever pieces are missing, and ffmpeg reads well
+// past the seek point, so it's only used when nothing is missing.
+async fn remux_seek_input(
+    state: &AppState,
+    handle: &librqbit::ManagedTorrent,
+    session_id: usize,
+    file_id: usize,
+) -> std::ffi::OsString {
+    let stream_url: std::ffi::OsString = format!("http://{}/torrents/{}/stream/{}", state.http_addr, session_id, file_id).into();
+    let Ok(Some((relative_path, file_size))) = handle.with_metadata(|meta| {
+        meta.file_infos.get(file_id).map(|f| (f.relative_filename.clone(), f.len))
+    }) else {
+        return stream_url;
+    };
+
+    let path = state.download_dir.join(relative_path);
+    let complete = handle.stats().file_progress.get(file_id).is_some_and(|&downloaded| downloaded >= file_size);
+    if complete && tokio::fs::try_exists(&path).await.unwrap_or(false) {
+        path.into_os_string()
+    } else {
+        stream_url
+    }
 }
 
-// HTTP handler to stream transcoded audio live (starts playing before transcoding is complete)
-async fn stream_transcoded_audio(
-    Path((session_id, file_id, track_index)): Path<(usize, usize, usize)>,
-    _headers: HeaderMap,
+// Live MKV -> fragmented MP4 remux for webviews that decode H.264/AAC but can't open
+// Matroska. Video is copied as is; the audio track (?audio=<track index>, default 0) is
+// copied too unless the metadata says the webview can't play it, then it's encoded to AAC.
+// ?t=<seconds> starts a fresh MP4 at the keyframe before that time, read from the
+// download rather than the pipe; X-Remux-Start echoes it, as the output's timestamps
+// start from zero.
+async fn stream_remuxed_video(
+    Path((session_id, file_id)): Path<(usize, usize)>,
+    axum::extract::Query(params): axum::extract::Query<HashMap<String, String>>,
     axum::extract::State(state): axum::extract::State<AppState>,
 ) -> impl IntoResponse {
     use std::process::Stdio;
-    use tokio::process::Command;
-    
-    tracing::info!("Live transcoded audio stream request: session_id={}, file_id={}, track_index={}", session_id, file_id, track_index);
-    
-    // Get torrent stream to pipe directly to ffmpeg
-    let torrent_stream = {
-        let handle = state.session.get(TorrentIdOrHash::Id(session_id));
-        if let Some(h) = handle {
-            match h.stream(file_id) {
-                Ok(stream) => Some(stream),
-                Err(e) => {
-                    tracing::error!("Failed to create torrent stream: {}", e);
-                    None
-                }
+
+    let audio_track: usize = params.get("audio").and_then(|a| a.parse().ok()).unwrap_or(0);
+    let seek_time: Option<f64> = params.get("t").and_then(|t| t.parse().ok()).filter(|t: &f64| *t > 0.0);
+    let transcode_audio = state.streams.metadata((session_id, file_id)).await
+        .and_then(|m| m.audio_tracks.into_iter().find(|t| t.index == audio_track))
+        .map(|t| t.needs_transcoding)
+        .unwrap_or(false);
+
+    tracing::info!("Remux request: session_id={}, file_id={}, audio={}, t={:?}, transcode_audio={}", session_id, file_id, audio_track, seek_time, transcode_audio);
+
+    let handle = match state.session.get(TorrentIdOrHash::Id(session_id)) {
+        Some(h) => h,
+        None => return (StatusCode::NOT_FOUND, "Torrent not found").into_response(),
+    };
+    let seek_input = match seek_time {
+        Some(_) => Some(remux_seek_input(&state, &handle, session_id, file_id).await),
+        None => None,
+    };
+    let torrent_stream = if seek_input.is_none() {
+        match handle.stream(file_id) {
+            Ok(stream) => Some(stream),
+            Err(e) => {
+                tracing::error!("Failed to create torrent stream: {}", e);
+                return (StatusCode::NOT_FOUND, "Failed to create torrent stream").into_response();
             }
-        } else {
-            None
         }
+    } else {
+        None
     };
-    
-    if torrent_stream.is_none() {
-        return (StatusCode::NOT_FOUND, "Failed to create torrent stream").into_response();
+
+    let audio_map = format!("0:a:{}?", audio_track);
+    let audio_args = if transcode_audio {
+        aac_encode_args()
+    } else {
+        vec!["-c:a".to_string(), "copy".to_string()]
+    };
+
+    let Some(job) = start_transcode_job("remux").await else {
+        return transcode_busy_response();
+    };
+
+    let mut cmd = ffmpeg_tools::ffmpeg_command();
+    // Only errors on stderr, so what ends up in an error response is the failure
+    cmd.args(["-hide_banner", "-loglevel", "error"]);
+
+    match (&seek_input, seek_time) {
+        (Some(input), Some(seek_time)) => {
+            tracing::info!("Remux seeking to {:.3}s in {:?}", seek_time, input);
+            cmd.args(["-ss", &format!("{:.3}", seek_time)]).arg("-i").arg(input);
+        }
+        _ => {
+            cmd.args(["-i", "pipe:0"]);
+        }
     }
-    
-    let mut torrent_stream = torrent_stream.unwrap();
-    
-    tracing::info!("Starting real-time transcode with piped torrent stream");
-    
-    // Start ffmpeg transcoding with piped input from torrent stream
-    let mut cmd = Command::new(ffmpeg_path());
-    
-    #[cfg(target_os = "windows")]
-    cmd.creation_flags(0x08000000);
-    
-    let audio_map = format!("0:a:{}", track_index);
     cmd.args(&[
-        "-i", "pipe:0",  // Read from stdin
+        "-map", "0:v:0",
         "-map", &audio_map,
-        "-c:a", "aac",
-        "-b:a", "192k",
-        "-f", "adts",
+        "-c:v", "copy",
+    ])
+    .args(audio_args)
+    .args(&[
+        "-f", "mp4",
+        "-movflags", "frag_keyframe+empty_moov+default_base_moof",
         "pipe:1",
     ])
-    .stdin(Stdio::piped())
+    .stdin(if torrent_stream.is_some() { Stdio::piped() } else { Stdio::null() })
     .stdout(Stdio::piped())
-    .stderr(Stdio::null());
-    
-    let mut child = match cmd.spawn() {
+    .stderr(Stdio::piped());
+
+    let mut child = match ffmpeg_tools::spawn(&mut cmd) {
         Ok(c) => c,
         Err(e) => {
             tracing::error!("Failed to spawn ffmpeg: {}", e);
-            return (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to start transcoding: {}", e)).into_response();
+            return StreamError::internal("ffmpeg_spawn_failed", format!("Failed to start remuxing: {}", e)).into_response();
         }
     };
-    
-    let mut stdin = match child.stdin.take() {
-        Some(s) => s,
-        None => return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to get ffmpeg stdin").into_response(),
-    };
-    
+
     let stdout = match child.stdout.take() {
         Some(s) => s,
-        None => return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to get ffmpeg output").into_response(),
+        None => return StreamError::internal("ffmpeg_pipe_failed", "Failed to get ffmpeg output").into_response(),
     };
-    
-    // Spawn task to pipe torrent stream to ffmpeg stdin
-    tokio::spawn(async move {
-        use tokio::io::{AsyncReadExt, AsyncWriteExt};
-        let mut buffer = vec![0u8; 256 * 1024]; // 256KB chunks
-        let mut total_piped = 0u64;
-        
-        tracing::info!("Starting to pipe torrent stream to ffmpeg stdin");
-        
-        loop {
-            match torrent_stream.read(&mut buffer).await {
-                Ok(0) => {
-                    tracing::info!("Torrent stream EOF, piped {} MB total", total_piped / 1_048_576);
-                    break;
-                }
-                Ok(n) => {
-                    if let Err(e) = stdin.write_all(&buffer[..n]).await {
-                        tracing::error!("Failed to write to ffmpeg stdin: {}", e);
-                        break;
+
+    if let Some(mut torrent_stream) = torrent_stream {
+        let mut stdin = match child.stdin.take() {
+            Some(s) => s,
+            None => return StreamError::internal("ffmpeg_pipe_failed", "Failed to get ffmpeg stdin").into_response(),
+        };
+
+        tokio::spawn(async move {
+            use tokio::io::{AsyncReadExt, AsyncWriteExt};
+            let mut buffer = vec![0u8; 256 * 1024];
+
+            loop {
+                match torrent_stream.read(&mut buffer).await {
+                    Ok(0) => break,
+                    Ok(n) => {
+                        // Client disconnected and ffmpeg exited
+                        if stdin.write_all(&buffer[..n]).await.is_err() {
+                            break;
+                        }
                     }
-                    total_piped += n as u64;
-                    if total_piped % (50 * 1024 * 1024) == 0 {
-                        tracing::info!("Piped {} MB to ffmpeg", total_piped / 1_048_576);
+                    Err(e) => {
+                        tracing::error!("Failed to read from torrent stream: {}", e);
+                        break;
                     }
                 }
-                Err(e) => {
-                    tracing::error!("Failed to read from torrent stream: {}", e);
-                    break;
-                }
             }
-        }
-        
-        drop(stdin);
-    });
-    
-    // Stream the transcoded audio to the client
-    let stream = tokio_util::io::ReaderStream::new(stdout);
-    let body = Body::from_stream(stream);
-    
-    // Spawn task to wait for ffmpeg completion (non-blocking)
-    tokio::spawn(async move {
-        let _ = child.wait().await;
-    });
-    
+
+            drop(stdin);
+        });
+    }
+
+    let body = match child_output_body(child, stdout, Some(job)).await {
+        Ok(body) => body,
+        Err(e) => return e.into_response(),
+    };
+
     Response::builder()
         .status(StatusCode::OK)
-        .header(header::CONTENT_TYPE, "audio/aac")
+        .header(header::CONTENT_TYPE, "video/mp4")
         .header(header::TRANSFER_ENCODING, "chunked")
         .header(header::CACHE_CONTROL, "no-cache")
+        .header("X-Remux-Start", format!("{:.3}", seek_time.unwrap_or(0.0)))
         .body(body)
         .unwrap()
         .into_response()
@@ -1972,7 +5963,6 @@ async fn stream_srt_subtitles(
     axum::extract::State(state): axum::extract::State<AppState>,
 ) -> impl IntoResponse {
     use std::process::Stdio;
-    use tokio::process::Command;
     
     tracing::info!("========================================");
     tracing::info!("SRT subtitle stream request received:");
@@ -2005,7 +5995,7 @@ async fn stream_srt_subtitles(
     
     if !file_path.exists() {
         tracing::warn!("File not yet available for subtitle extraction: {:?}", file_path);
-        return (StatusCode::SERVICE_UNAVAILABLE, "File not yet downloaded").into_response();
+        return StreamError::unavailable("not_downloaded", "File not yet downloaded", None).into_response();
     }
     
     // Parse time window from headers
@@ -2022,10 +6012,9 @@ async fn stream_srt_subtitles(
     tracing::info!("Extracting SRT subtitles from {}s to {}s", start_time, end_time);
     
     // Extract subtitle track using ffmpeg
-    let mut cmd = Command::new(ffmpeg_path());
-    
-    #[cfg(target_os = "windows")]
-    cmd.creation_flags(0x08000000);
+    let mut cmd = ffmpeg_tools::ffmpeg_command();
+    // Only errors on stderr, so what ends up in an error response is the failure
+    cmd.args(["-hide_banner", "-loglevel", "error"]);
 
     cmd.args(&[
         "-ss", &start_time.to_string(),
@@ -2036,29 +6025,26 @@ async fn stream_srt_subtitles(
         "pipe:1",
     ])
     .stdout(Stdio::piped())
-    .stderr(Stdio::null());
+    .stderr(Stdio::piped());
     
-    let mut child = match cmd.spawn() {
+    let mut child = match ffmpeg_tools::spawn(&mut cmd) {
         Ok(c) => c,
         Err(e) => {
             tracing::error!("Failed to spawn ffmpeg for SRT extraction: {}", e);
-            return (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to extract subtitles: {}", e)).into_response();
+            return StreamError::internal("ffmpeg_spawn_failed", format!("Failed to extract subtitles: {}", e)).into_response();
         }
     };
     
     let stdout = match child.stdout.take() {
         Some(s) => s,
-        None => return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to get ffmpeg output").into_response(),
+        None => return StreamError::internal("ffmpeg_pipe_failed", "Failed to get ffmpeg output").into_response(),
     };
     
-    // Stream the extracted subtitles to the client
-    let stream = tokio_util::io::ReaderStream::new(stdout);
-    let body = Body::from_stream(stream);
-    
-    // Spawn task to wait for ffmpeg completion (non-blocking)
-    tokio::spawn(async move {
-        let _ = child.wait().await;
-    });
+    // Stream the extracted subtitles to the client; ffmpeg stops if the client leaves
+    let body = match child_output_body(child, stdout, None).await {
+        Ok(body) => body,
+        Err(e) => return e.into_response(),
+    };
     
     Response::builder()
         .status(StatusCode::OK)
@@ -2080,7 +6066,7 @@ async fn serve_font(
     
     let app_data = match dirs::data_dir() {
         Some(dir) => dir.join("com.chair.magnolia").join("fonts"),
-        None => return (StatusCode::INTERNAL_SERVER_ERROR, "Could not find app data directory").into_response(),
+        None => return StreamError::internal("app_data_missing", "Could not find app data directory").into_response(),
     };
     
     let font_path = app_data.join(&filename);
@@ -2095,6 +6081,8 @@ async fn serve_font(
         Ok(data) => data,
         Err(_) => return (StatusCode::NOT_FOUND, "Font not found").into_response(),
     };
+    // Keeps fonts of releases still being watched from being cleaned up
+    crate::font_manager::touch_font(&font_path);
     
     // Determine content type based on extension
     let content_type = if filename.ends_with(".ttf") {
@@ -2126,16 +6114,14 @@ async fn serve_transcoded_audio(
     tracing::info!("Transcoded audio request: session_id={}, file_id={}", session_id, file_id);
     
     // Check if transcoding is complete
-    let output_path = {
-        let states = state.transcode_states.read().await;
-        if let Some(transcode_state) = states.get(&(session_id, file_id)) {
+    let output_path = match state.streams.read((session_id, file_id), |s| s.transcode.clone()).await.flatten() {
+        Some(transcode_state) => {
             if !transcode_state.completed {
-                return (StatusCode::SERVICE_UNAVAILABLE, "Transcoding not complete").into_response();
+                return StreamError::unavailable("transcode_incomplete", "Transcoding not complete", None).into_response();
             }
-            transcode_state.output_path.clone()
-        } else {
-            return (StatusCode::NOT_FOUND, "No transcoding in progress").into_response();
+            transcode_state.output_path
         }
+        None => return (StatusCode::NOT_FOUND, "No transcoding in progress").into_response(),
     };
     
     let output_path = match output_path {
@@ -2150,7 +6136,7 @@ async fn serve_transcoded_audio(
     // Get file size
     let file_size = match tokio::fs::metadata(&output_path).await {
         Ok(m) => m.len(),
-        Err(_) => return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to get file size").into_response(),
+        Err(_) => return StreamError::internal("file_size_unavailable", "Failed to get file size").into_response(),
     };
     
     // Handle range requests
@@ -2174,12 +6160,12 @@ async fn serve_transcoded_audio(
     // Open file and seek
     let mut file = match tokio::fs::File::open(&output_path).await {
         Ok(f) => f,
-        Err(_) => return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to open file").into_response(),
+        Err(_) => return StreamError::internal("file_read_failed", "Failed to open file").into_response(),
     };
     
     if start > 0 {
         if let Err(_) = file.seek(std::io::SeekFrom::Start(start)).await {
-            return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to seek").into_response();
+            return StreamError::internal("seek_failed", "Failed to seek").into_response();
         }
     }
     
@@ -2215,6 +6201,17 @@ pub async fn add_torrent(
         .map_err(|e| e.to_string())
 }
 
+#[tauri::command]
+pub async fn preview_torrent_files(
+    manager: State<'_, Arc<TorrentManager>>,
+    magnet_or_url: String,
+) -> Result<TorrentPreview, String> {
+    manager
+        .preview_torrent_files(magnet_or_url)
+        .await
+        .map_err(|e| e.to_string())
+}
+
 #[tauri::command]
 pub async fn get_torrent_info(
     manager: State<'_, Arc<TorrentManager>>,
@@ -2227,82 +6224,266 @@ pub async fn get_torrent_info(
 }
 
 #[tauri::command]
-pub async fn list_torrents(
-    manager: State<'_, Arc<TorrentManager>>,
-) -> Result<Vec<TorrentInfo>, String> {
-    manager.list_torrents().await.map_err(|e| e.to_string())
+pub async fn list_torrents(
+    manager: State<'_, Arc<TorrentManager>>,
+) -> Result<Vec<TorrentInfo>, String> {
+    manager.list_torrents().await.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn prepare_stream(
+    manager: State<'_, Arc<TorrentManager>>,
+    settings_manager: State<'_, crate::settings::SettingsManager>,
+    imdb_resolver: State<'_, crate::imdb_resolver::ImdbResolver>,
+    cache_metadata: State<'_, std::sync::Mutex<crate::cache_metadata::CacheMetadataManager>>,
+    handle_id: usize,
+    file_index: usize,
+    tmdb_id: Option<u32>,
+    media_type: Option<String>, // "tv" or "movie"
+    season: Option<u32>,
+    episode: Option<u32>,
+) -> Result<(), String> {
+    let parental = settings_manager.get().await.parental;
+    if let Some(tmdb_id) = tmdb_id {
+        crate::parental::check_title(&parental, &imdb_resolver, tmdb_id, media_type.as_deref().unwrap_or("tv")).await?;
+    }
+    // The title comes from the player, so the torrent itself is checked too
+    if parental.block_adult {
+        let info = manager.get_torrent_info(handle_id).await.map_err(|e| e.to_string())?;
+        crate::parental::check_torrent_name(&parental, &info.name)?;
+        if let Some(file) = info.files.iter().find(|f| f.index == file_index) {
+            crate::parental::check_torrent_name(&parental, &file.name)?;
+        }
+    }
+
+    manager
+        .prepare_stream(handle_id, file_index)
+        .await
+        .map_err(|e| e.to_string())?;
+    if let Ok(session_id) = manager.wait_for_session(handle_id).await {
+        crate::parental::clear_session(session_id);
+    }
+
+    // Remember which title the torrent belongs to, so cached files can be grouped by it
+    if let (Some(tmdb_id), Some(info_hash)) = (tmdb_id, manager.info_hash(handle_id).await) {
+        let media_type = media_type.unwrap_or_else(|| "tv".to_string());
+        let result = match cache_metadata.lock() {
+            Ok(mut mappings) => mappings.set_episode_mapping(&info_hash, tmdb_id, media_type, season, episode),
+            Err(_) => Err("cache metadata lock poisoned".to_string()),
+        };
+        if let Err(e) = result {
+            tracing::warn!("Failed to record cache metadata for {}: {}", info_hash, e);
+        }
+    }
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn get_stream_status(
+    manager: State<'_, Arc<TorrentManager>>,
+    handle_id: usize,
+    file_index: usize,
+) -> Result<StreamStatus, String> {
+    manager
+        .get_stream_status(handle_id, file_index)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn probe_stream_compatibility(
+    manager: State<'_, Arc<TorrentManager>>,
+    handle_id: usize,
+    file_index: usize,
+) -> Result<StreamCompatibility, String> {
+    manager
+        .probe_stream_compatibility(handle_id, file_index)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn pause_torrent(
+    manager: State<'_, Arc<TorrentManager>>,
+    handle_id: usize,
+) -> Result<(), String> {
+    manager
+        .pause_torrent(handle_id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn resume_torrent(
+    manager: State<'_, Arc<TorrentManager>>,
+    handle_id: usize,
+) -> Result<(), String> {
+    manager
+        .resume_torrent(handle_id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn update_file_selection(
+    manager: State<'_, Arc<TorrentManager>>,
+    handle_id: usize,
+    file_indices: Vec<usize>,
+) -> Result<Vec<usize>, String> {
+    manager
+        .update_file_selection(handle_id, file_indices)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn reannounce(
+    manager: State<'_, Arc<TorrentManager>>,
+    handle_id: usize,
+) -> Result<(), String> {
+    manager
+        .reannounce(handle_id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn force_recheck(
+    manager: State<'_, Arc<TorrentManager>>,
+    handle_id: usize,
+) -> Result<(), String> {
+    manager
+        .force_recheck(handle_id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn verify_file(
+    manager: State<'_, Arc<TorrentManager>>,
+    handle_id: usize,
+    file_index: usize,
+) -> Result<FileVerification, String> {
+    manager
+        .verify_file(handle_id, file_index)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn get_stream_access_log(manager: State<'_, Arc<TorrentManager>>) -> Result<Vec<StreamAccess>, String> {
+    Ok(manager.stream_access_log())
+}
+
+#[tauri::command]
+pub async fn extend_metadata_probe(
+    manager: State<'_, Arc<TorrentManager>>,
+    session_id: usize,
+    file_id: usize,
+    seconds: u64,
+) -> Result<(), String> {
+    manager.extend_metadata_probe(session_id, file_id, seconds).await
+}
+
+#[tauri::command]
+pub async fn cancel_metadata_probe(
+    manager: State<'_, Arc<TorrentManager>>,
+    session_id: usize,
+    file_id: usize,
+) -> Result<(), String> {
+    manager.cancel_metadata_probe(session_id, file_id).await
+}
+
+#[tauri::command]
+pub async fn pause_all_torrents(manager: State<'_, Arc<TorrentManager>>) -> Result<Vec<usize>, String> {
+    manager.pause_all().await.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn resume_all_torrents(manager: State<'_, Arc<TorrentManager>>) -> Result<Vec<usize>, String> {
+    manager.resume_all().await.map_err(|e| e.to_string())
 }
 
 #[tauri::command]
-pub async fn prepare_stream(
+pub async fn remove_torrent(
     manager: State<'_, Arc<TorrentManager>>,
     handle_id: usize,
-    file_index: usize,
+    delete_files: bool,
 ) -> Result<(), String> {
     manager
-        .prepare_stream(handle_id, file_index)
+        .remove_torrent(handle_id, delete_files)
         .await
         .map_err(|e| e.to_string())
 }
 
 #[tauri::command]
-pub async fn get_stream_status(
+pub async fn report_playback_position(
     manager: State<'_, Arc<TorrentManager>>,
     handle_id: usize,
     file_index: usize,
-) -> Result<StreamStatus, String> {
+    seconds: f64,
+) -> Result<(), String> {
     manager
-        .get_stream_status(handle_id, file_index)
+        .report_playback_position(handle_id, file_index, seconds)
         .await
         .map_err(|e| e.to_string())
 }
 
 #[tauri::command]
-pub async fn pause_torrent(
+pub async fn stream_keep_alive(
     manager: State<'_, Arc<TorrentManager>>,
     handle_id: usize,
+    file_index: usize,
 ) -> Result<(), String> {
     manager
-        .pause_torrent(handle_id)
+        .stream_keep_alive(handle_id, file_index)
         .await
         .map_err(|e| e.to_string())
 }
 
 #[tauri::command]
-pub async fn resume_torrent(
+pub async fn report_playback_paused(
     manager: State<'_, Arc<TorrentManager>>,
     handle_id: usize,
+    file_index: usize,
+    paused: bool,
 ) -> Result<(), String> {
     manager
-        .resume_torrent(handle_id)
+        .report_playback_paused(handle_id, file_index, paused)
         .await
         .map_err(|e| e.to_string())
 }
 
 #[tauri::command]
-pub async fn remove_torrent(
+pub async fn stop_stream(
     manager: State<'_, Arc<TorrentManager>>,
     handle_id: usize,
     delete_files: bool,
 ) -> Result<(), String> {
     manager
-        .remove_torrent(handle_id, delete_files)
+        .stop_stream(handle_id, delete_files)
         .await
         .map_err(|e| e.to_string())
 }
 
 #[tauri::command]
-pub async fn stop_stream(
+pub async fn close_stream(
     manager: State<'_, Arc<TorrentManager>>,
     handle_id: usize,
-    delete_files: bool,
 ) -> Result<(), String> {
     manager
-        .stop_stream(handle_id, delete_files)
+        .close_stream(handle_id)
         .await
         .map_err(|e| e.to_string())
 }
 
+#[tauri::command]
+pub async fn list_active_streams(
+    manager: State<'_, Arc<TorrentManager>>,
+) -> Result<Vec<ActiveStream>, String> {
+    Ok(manager.active_streams().await)
+}
+
 #[tauri::command]
 pub async fn wipe_all_torrent_files(
     manager: State<'_, Arc<TorrentManager>>,
@@ -2321,454 +6502,308 @@ pub async fn get_download_dir(manager: State<'_, Arc<TorrentManager>>) -> Result
         .to_string())
 }
 
+/// A track extract_subtitle or extract_audio_track wrote into the media cache
+#[derive(Debug, Clone, Serialize)]
+pub struct ExtractedTrack {
+    pub path: String,
+    pub format: TrackFormat,
+    // False when only the downloaded start of the file was read; the path then points at a
+    // temp file and the complete track is cached in the background
+    pub complete: bool,
+}
+
+// How much of a file that's still downloading is read for the first subtitle extraction
+const PARTIAL_SUBTITLE_READ_BYTES: u64 = 150 * 1024 * 1024;
+
+/// Extracts a subtitle track into the media cache and returns its path. Files that are
+/// still downloading get a quick extraction from their start, and the full track follows
+/// once the whole file could be read.
 #[tauri::command]
 pub async fn extract_subtitle(
+    app: tauri::AppHandle,
     manager: State<'_, Arc<TorrentManager>>,
+    cache: State<'_, MediaCache>,
     handle_id: usize,
     file_index: usize,
     track_index: usize,
-) -> Result<String, String> {
-    use tokio::process::Command;
-    use tokio::io::AsyncReadExt;
-    
-    tracing::info!("Extracting subtitle chunk: handle_id={}, file_index={}, track_index={}", handle_id, file_index, track_index);
-    
-    // Get the torrent entry with retry for session_id
-    let session_id = {
-        let mut retries = 0;
-        loop {
-            let torrents = manager.torrents.read().await;
-            let entry = torrents
-                .get(&handle_id)
-                .ok_or("Torrent handle not found")?;
-            
-            if let Some(sid) = entry.session_id {
-                drop(torrents);
-                break sid;
-            }
-            
-            drop(torrents);
-            
-            if retries >= 10 {
-                return Err("Torrent session not ready after 5 seconds. Please wait for video to start loading.".to_string());
-            }
-            
-            retries += 1;
-            tracing::info!("Session ID not yet available, waiting... (attempt {}/10)", retries);
-            tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
-        }
-    };
-    
-    // Get handle from librqbit session
+    format: Option<TrackFormat>,
+) -> Result<ExtractedTrack, String> {
+    let format = format.unwrap_or(TrackFormat::Srt);
+    if !format.is_subtitle() {
+        return Err(format!("{:?} is not a subtitle format", format));
+    }
+    tracing::info!("Extracting subtitle: handle_id={}, file_index={}, track_index={}, format={:?}", handle_id, file_index, track_index, format);
+
+    let session_id = manager.wait_for_session(handle_id).await?;
     let handle = manager.session.get(TorrentIdOrHash::Id(session_id))
         .ok_or("Session not found")?;
-    
-    // Get file info and size
-    let (file_info, file_size) = handle.with_metadata(|meta| {
-        meta.file_infos.get(file_index).map(|fi| (fi.relative_filename.clone(), fi.len))
-    }).map_err(|e| e.to_string())?
-        .ok_or("File index out of range")?;
-    
-    let file_path = manager.download_dir.join(&file_info);
-    
-    // Check if file is fully downloaded on disk
-    if file_path.exists() {
-        let metadata = tokio::fs::metadata(&file_path).await
-            .map_err(|e| format!("Failed to get file metadata: {}", e))?;
-        
-        if metadata.len() == file_size {
-            tracing::info!("File fully downloaded, extracting complete subtitles from disk");
-            
-            // Extract subtitle using ffmpeg directly from file
-            let mut cmd = Command::new(ffmpeg_path());
-            cmd.args(&[
-                    "-i", file_path.to_str().unwrap(),
-                    "-map", &format!("0:s:{}", track_index),
-                    "-f", "srt",
-                    "-"
-                ]);
-
-            #[cfg(target_os = "windows")]
-            cmd.creation_flags(0x08000000);
-
-            let output = cmd.output()
-                .await
-                .map_err(|e| format!("Failed to run ffmpeg: {}", e))?;
-            
-            if !output.status.success() {
-                let stderr = String::from_utf8_lossy(&output.stderr);
-                tracing::error!("ffmpeg subtitle extraction failed: {}", stderr);
-                return Err(format!("Subtitle extraction failed: {}", stderr));
-            }
-            
-            let subtitle_text = String::from_utf8(output.stdout)
-                .map_err(|e| format!("Invalid UTF-8 in subtitle data: {}", e))?;
-            
-            tracing::info!("Successfully extracted complete subtitle, {} bytes", subtitle_text.len());
-            return Ok(subtitle_text);
-        }
+    let cache_id = handle.info_hash().as_string();
+    if let Some(path) = cache.cached_format(TrackType::Subtitle, &cache_id, file_index, track_index, format.extension()) {
+        return Ok(ExtractedTrack { path: path.to_string_lossy().to_string(), format, complete: true });
     }
-    
-    // File not fully downloaded, extract from first 150MB for initial subtitles
-    // (enough to get subtitles from beginning and throughout most movies)
-    tracing::info!("Extracting initial subtitle chunk from streaming data");
-    
-    // Create a torrent reader for streaming
-    let mut reader = handle.stream(file_index)
-        .map_err(|e| format!("Failed to create stream: {}", e))?;
-    
-    // Read to a temporary location
-    let temp_dir = std::env::temp_dir();
-    let temp_file_path = temp_dir.join(format!("magnolia_sub_{}_{}.mkv", session_id, file_index));
-    
-    let mut temp_file = tokio::fs::File::create(&temp_file_path).await
-        .map_err(|e| format!("Failed to create temp file: {}", e))?;
-    
-    // Read first 150MB or until EOF (whichever comes first)
-    let mut buffer = vec![0u8; 1024 * 1024]; // 1MB chunks
-    let mut total_read = 0u64;
-    let initial_read_limit = 150 * 1024 * 1024; // 150MB
-    
-    tracing::info!("Reading initial {} MB for subtitle extraction", initial_read_limit / (1024 * 1024));
-    
-    while total_read < initial_read_limit {
-        match reader.read(&mut buffer).await {
-            Ok(0) => {
-                tracing::info!("Reached EOF at {} bytes", total_read);
-                break; // EOF
-            }
-            Ok(n) => {
-                tokio::io::AsyncWriteExt::write_all(&mut temp_file, &buffer[..n]).await
-                    .map_err(|e| format!("Failed to write temp file: {}", e))?;
-                total_read += n as u64;
-            }
-            Err(e) => {
-                tracing::warn!("Error reading stream for subtitles: {}, have {} bytes", e, total_read);
-                break;
+
+    let source = ExtractionSource::open(&handle, &manager.download_dir, session_id, file_index, Some(PARTIAL_SUBTITLE_READ_BYTES)).await?;
+    let complete = source.complete;
+    let data = extract_track(&source.path, track_index, format).await;
+    source.cleanup().await;
+    let data = data?;
+    if format == TrackFormat::Ass {
+        let duration = manager.streams.duration((session_id, file_index)).await;
+        match check_ass(&data, complete, duration).status {
+            SubtitleStatus::Invalid if complete => return Err("Extracted subtitle is not valid ASS".to_string()),
+            SubtitleStatus::Empty | SubtitleStatus::Invalid if !complete => {
+                return Err("Subtitle data isn't downloaded yet, try again later".to_string());
             }
+            _ => {}
         }
     }
-    
-    temp_file.sync_all().await.map_err(|e| format!("Failed to sync temp file: {}", e))?;
-    drop(temp_file);
-    
-    tracing::info!("Finished reading {} bytes, extracting subtitles with ffmpeg", total_read);
-    
-    // Extract subtitle using ffmpeg
-    let mut cmd = Command::new(ffmpeg_path());
-    cmd.args(&[
-            "-i", temp_file_path.to_str().unwrap(),
-            "-map", &format!("0:s:{}", track_index),
-            "-f", "srt",
-            "-"
-        ]);
 
-    #[cfg(target_os = "windows")]
-    cmd.creation_flags(0x08000000);
-
-    let output = cmd.output()
-        .await
-        .map_err(|e| format!("Failed to run ffmpeg: {}", e))?;
-    
-    // Clean up temp file
-    let _ = tokio::fs::remove_file(&temp_file_path).await;
-    
-    if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        tracing::error!("ffmpeg subtitle extraction failed: {}", stderr);
-        return Err(format!("Subtitle extraction failed: {}", stderr));
+    if complete {
+        let path = cache.save_track_as(TrackType::Subtitle, &cache_id, file_index, track_index, format.extension(), data).await?;
+        return Ok(ExtractedTrack { path: path.to_string_lossy().to_string(), format, complete: true });
     }
-    
-    let subtitle_text = String::from_utf8(output.stdout)
-        .map_err(|e| format!("Invalid UTF-8 in subtitle data: {}", e))?;
-    
-    tracing::info!("Successfully extracted initial subtitle chunk, {} bytes", subtitle_text.len());
-    
-    // Start background task to extract complete subtitles
-    let manager_clone = manager.inner().clone();
-    let handle_clone = handle_id;
-    let file_clone = file_index;
-    let track_clone = track_index;
-    
-    tokio::spawn(async move {
-        if let Err(e) = extract_complete_subtitle_background(
-            manager_clone,
-            handle_clone,
-            file_clone,
-            track_clone
-        ).await {
-            tracing::error!("Background subtitle extraction failed: {}", e);
+
+    // Hand out what the start of the file had while the rest is read in the background
+    let partial_path = std::env::temp_dir().join(format!("magnolia_partial_{}_{}_{}.{}", cache_id, file_index, track_index, format.extension()));
+    tokio::fs::write(&partial_path, data).await
+        .map_err(|e| format!("Failed to write subtitle: {}", e))?;
+    tracing::info!("Extracted subtitle from the start of the file, caching the complete track in the background");
+
+    let manager = manager.inner().clone();
+    crate::crash_report::spawn_logged("subtitle extraction", async move {
+        use tauri::Manager;
+        let cache = app.state::<MediaCache>();
+        match extract_to_cache(&manager, &cache, handle_id, file_index, track_index, format).await {
+            Ok((track, _)) => tracing::info!("[Background] Complete subtitle cached at {}", track.path),
+            Err(e) => tracing::error!("[Background] Subtitle extraction failed: {}", e),
         }
+        let _ = tokio::fs::remove_file(&partial_path).await;
     });
-    
-    Ok(subtitle_text)
+
+    Ok(ExtractedTrack { path: partial_path.to_string_lossy().to_string(), format, complete: false })
 }
 
-async fn extract_complete_subtitle_background(
-    manager: Arc<TorrentManager>,
+/// Path of a subtitle track the media cache already holds in full, without extracting it
+pub async fn cached_subtitle(
+    manager: &TorrentManager,
+    cache: &MediaCache,
     handle_id: usize,
     file_index: usize,
     track_index: usize,
-) -> Result<(), String> {
-    use tokio::process::Command;
-    use tokio::io::AsyncReadExt;
-    
-    tracing::info!("[Background] Starting complete subtitle extraction for handle={}, file={}, track={}", 
-        handle_id, file_index, track_index);
-    
-    // Small delay to let initial playback start
-    tokio::time::sleep(tokio::time::Duration::from_secs(2)).await;
-    
-    // Get the torrent entry
-    let torrents = manager.torrents.read().await;
-    let entry = torrents
-        .get(&handle_id)
-        .ok_or("Torrent handle not found")?;
-        
-    let session_id = entry.session_id.ok_or("Torrent not yet added to session")?;
-    drop(torrents);
-    
-    // Get handle from librqbit session
-    let handle = manager.session.get(TorrentIdOrHash::Id(session_id))
-        .ok_or("Session not found")?;
-    
-    // Get file info and size
-    let (file_info, file_size) = handle.with_metadata(|meta| {
-        meta.file_infos.get(file_index).map(|fi| (fi.relative_filename.clone(), fi.len))
-    }).map_err(|e| e.to_string())?
-        .ok_or("File index out of range")?;
-    
-    let file_path = manager.download_dir.join(&file_info);
-    
-    // Check if already on disk
-    if file_path.exists() {
-        let metadata = tokio::fs::metadata(&file_path).await
-            .map_err(|e| format!("Failed to get file metadata: {}", e))?;
-        
-        if metadata.len() == file_size {
-            tracing::info!("[Background] File already complete on disk, no need to extract");
-            return Ok(());
-        }
-    }
-    
-    // Create a torrent reader for streaming
-    let mut reader = handle.stream(file_index)
-        .map_err(|e| format!("Failed to create stream: {}", e))?;
-    
-    // Read entire file to temp location
-    let temp_dir = std::env::temp_dir();
-    let temp_file_path = temp_dir.join(format!("magnolia_sub_full_{}_{}.mkv", session_id, file_index));
-    
-    let mut temp_file = tokio::fs::File::create(&temp_file_path).await
-        .map_err(|e| format!("Failed to create temp file: {}", e))?;
-    
-    let mut buffer = vec![0u8; 1024 * 1024];
-    let mut total_read = 0u64;
-    
-    tracing::info!("[Background] Reading complete file ({} bytes) for full subtitle extraction", file_size);
-    
-    loop {
-        match reader.read(&mut buffer).await {
-            Ok(0) => {
-                tracing::info!("[Background] Reached EOF at {} bytes", total_read);
-                break;
-            }
-            Ok(n) => {
-                tokio::io::AsyncWriteExt::write_all(&mut temp_file, &buffer[..n]).await
-                    .map_err(|e| format!("Failed to write temp file: {}", e))?;
-                total_read += n as u64;
-                
-                // Log progress every 100MB
-                if total_read % (100 * 1024 * 1024) < (n as u64) {
-                    tracing::info!("[Background] Progress: {} / {} bytes ({:.1}%)", 
-                        total_read, file_size, (total_read as f64 / file_size as f64) * 100.0);
-                }
-            }
-            Err(e) => {
-                tracing::error!("[Background] Error reading stream: {}", e);
-                let _ = tokio::fs::remove_file(&temp_file_path).await;
-                return Err(format!("Failed to read stream: {}", e));
-            }
-        }
+    format: TrackFormat,
+) -> Option<PathBuf> {
+    let session_id = manager.wait_for_session(handle_id).await.ok()?;
+    let handle = manager.session.get(TorrentIdOrHash::Id(session_id))?;
+    cache.cached_format(TrackType::Subtitle, &handle.info_hash().as_string(), file_index, track_index, format.extension())
+}
+
+/// Extracts an audio track into the media cache and returns its path, remuxed as Matroska
+/// by default. Reads the whole file, so it can take a while on a torrent still downloading.
+#[tauri::command]
+pub async fn extract_audio_track(
+    app: tauri::AppHandle,
+    manager: State<'_, Arc<TorrentManager>>,
+    cache: State<'_, MediaCache>,
+    handle_id: usize,
+    file_index: usize,
+    track_index: usize,
+    format: Option<TrackFormat>,
+) -> Result<ExtractedTrack, String> {
+    let format = format.unwrap_or(TrackFormat::Mka);
+    if format.is_subtitle() {
+        return Err(format!("{:?} is not an audio format", format));
     }
-    
-    temp_file.sync_all().await.map_err(|e| format!("Failed to sync: {}", e))?;
-    drop(temp_file);
-    
-    tracing::info!("[Background] Extracting complete subtitles with ffmpeg");
-    
-    // Extract complete subtitle
-    let mut cmd = Command::new(ffmpeg_path());
-    cmd.args(&[
-            "-i", temp_file_path.to_str().unwrap(),
-            "-map", &format!("0:s:{}", track_index),
-            "-f", "srt",
-            "-"
-        ]);
+    tracing::info!("Extracting audio track: handle_id={}, file_index={}, track_index={}, format={:?}", handle_id, file_index, track_index, format);
 
-    #[cfg(target_os = "windows")]
-    cmd.creation_flags(0x08000000);
+    let (track, cached) = extract_to_cache(&manager, &cache, handle_id, file_index, track_index, format).await?;
 
-    let output = cmd.output()
-        .await
-        .map_err(|e| format!("Failed to run ffmpeg: {}", e))?;
-    
-    let _ = tokio::fs::remove_file(&temp_file_path).await;
-    
-    if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        tracing::error!("[Background] ffmpeg extraction failed: {}", stderr);
-        return Err(format!("Extraction failed: {}", stderr));
-    }
-    
-    let subtitle_text = String::from_utf8(output.stdout)
-        .map_err(|e| format!("Invalid UTF-8: {}", e))?;
-    
-    tracing::info!("[Background] Complete subtitle extracted, {} bytes. Saving to cache...", subtitle_text.len());
-    
-    // Save to cache
-    // Use the magnet hash as cache key
-    let torrents = manager.torrents.read().await;
-    if let Some(entry) = torrents.get(&handle_id) {
-        // Extract info hash from magnet link for stable cache ID
-        if let Some(hash_start) = entry.magnet_url.find("btih:") {
-            let hash_part = &entry.magnet_url[hash_start + 5..];
-            let hash_end = hash_part.find('&').unwrap_or(hash_part.len());
-            let info_hash = &hash_part[..hash_end];
-            
-            let cache_key = format!("{}-{}-{}", info_hash, file_index, track_index);
-            
-            // Save using the subtitle cache system
-            if let Err(e) = tokio::fs::write(
-                std::env::temp_dir().join(format!("magnolia_subtitle_cache_{}.srt", cache_key)),
-                &subtitle_text
-            ).await {
-                tracing::error!("[Background] Failed to save subtitle cache: {}", e);
-            } else {
-                tracing::info!("[Background] Complete subtitle saved to cache");
-            }
-        }
+    // Reading a file that's still downloading takes long enough that the user may have moved on
+    if !cached {
+        let name = std::path::Path::new(&track.path).file_name().and_then(|n| n.to_str()).unwrap_or("audio track").to_string();
+        crate::notifications::notify(&app, crate::notifications::NotificationKind::TranscodeComplete, &crate::i18n::t("notification.audio_track_ready.title"), &name).await;
     }
-    
-    Ok(())
+    Ok(track)
+}
+
+// Picture-based subtitles ffmpeg can't turn into text
+const BITMAP_SUBTITLE_CODECS: &[&str] = &["hdmv_pgs_subtitle", "pgssub", "dvd_subtitle", "dvdsub", "dvb_subtitle", "xsub"];
+
+/// A subtitle track cached by extract_all_subtitles
+#[derive(Debug, Clone, Serialize)]
+pub struct ExtractedSubtitle {
+    pub track_index: usize,
+    pub language: Option<String>,
+    pub name: Option<String>,
+    pub codec: Option<String>,
+    pub path: String,
+    pub format: TrackFormat,
 }
 
+/// Extracts every text subtitle track of the file into the media cache in one ffmpeg
+/// pass: ASS/SSA tracks as ASS, the rest as SRT. Waits until the whole file can be read,
+/// then switching between tracks is just a cache read. Picture-based tracks are left out.
 #[tauri::command]
-pub async fn extract_audio_track(
+pub async fn extract_all_subtitles(
     manager: State<'_, Arc<TorrentManager>>,
+    cache: State<'_, MediaCache>,
     handle_id: usize,
     file_index: usize,
-    track_index: usize,
-) -> Result<Vec<u8>, String> {
-    tracing::info!("extracting audio track: handle_id={}, file_index={}, track_index={}", handle_id, file_index, track_index);
-    
-    // Get the torrent entry with retry for session_id
-    let session_id = {
-        let mut retries = 0;
-        loop {
-            let torrents = manager.torrents.read().await;
-            let entry = torrents
-                .get(&handle_id)
-                .ok_or("Torrent handle not found")?;
-            
-            if let Some(sid) = entry.session_id {
-                drop(torrents);
-                break sid;
-            }
-            
-            drop(torrents);
-            
-            if retries >= 10 {
-                return Err("Torrent session not ready after 5 seconds".to_string());
-            }
-            
-            retries += 1;
-            tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
+) -> Result<Vec<ExtractedSubtitle>, String> {
+    tracing::info!("Extracting all subtitles: handle_id={}, file_index={}", handle_id, file_index);
+
+    let session_id = manager.wait_for_session(handle_id).await?;
+    let handle = manager.session.get(TorrentIdOrHash::Id(session_id))
+        .ok_or("Session not found")?;
+    let cache_id = handle.info_hash().as_string();
+
+    let source = ExtractionSource::open(&handle, &manager.download_dir, session_id, file_index, None).await?;
+    let metadata = match manager.streams.metadata((session_id, file_index)).await {
+        Some(metadata) => Ok(metadata),
+        None => extract_mkv_metadata_ffprobe(&source.path).await.map_err(|e| e.to_string()),
+    };
+    let metadata = match metadata {
+        Ok(metadata) => metadata,
+        Err(e) => {
+            source.cleanup().await;
+            return Err(format!("Failed to read subtitle tracks: {}", e));
         }
     };
-    
-    // Get handle from librqbit session
+
+    let tracks: Vec<(SubtitleTrack, TrackFormat)> = metadata.subtitle_tracks
+        .into_iter()
+        .filter(|track| !track.codec.as_deref().is_some_and(|c| BITMAP_SUBTITLE_CODECS.contains(&c)))
+        .map(|track| {
+            let format = match track.codec.as_deref() {
+                Some("ass") | Some("ssa") => TrackFormat::Ass,
+                _ => TrackFormat::Srt,
+            };
+            (track, format)
+        })
+        .collect();
+
+    // Only tracks that aren't cached yet go through ffmpeg
+    let missing: Vec<(usize, TrackFormat)> = tracks
+        .iter()
+        .filter(|(track, format)| cache.cached_format(TrackType::Subtitle, &cache_id, file_index, track.index, format.extension()).is_none())
+        .map(|(track, format)| (track.index, *format))
+        .collect();
+    let extracted = if missing.is_empty() {
+        Ok(Vec::new())
+    } else {
+        extract_tracks(&source.path, &missing).await
+    };
+    source.cleanup().await;
+
+    for ((track_index, format), data) in missing.iter().zip(extracted?) {
+        if data.is_empty() {
+            tracing::warn!("Subtitle track {} came out empty, not caching it", track_index);
+            continue;
+        }
+        if *format == TrackFormat::Ass && check_ass(&data, true, None).status == SubtitleStatus::Invalid {
+            tracing::warn!("Subtitle track {} is not valid ASS, not caching it", track_index);
+            continue;
+        }
+        cache.save_track_as(TrackType::Subtitle, &cache_id, file_index, *track_index, format.extension(), data).await?;
+    }
+
+    let subtitles = tracks
+        .into_iter()
+        .filter_map(|(track, format)| {
+            let path = cache.format_path(TrackType::Subtitle, &cache_id, file_index, track.index, format.extension());
+            path.is_file().then(|| ExtractedSubtitle {
+                track_index: track.index,
+                language: track.language,
+                name: track.name,
+                codec: track.codec,
+                path: path.to_string_lossy().to_string(),
+                format,
+            })
+        })
+        .collect();
+    Ok(subtitles)
+}
+
+// Reads the whole file (from disk or the torrent) and caches the track in `format`. The
+// flag is true when the track was already cached and nothing was extracted.
+async fn extract_to_cache(
+    manager: &Arc<TorrentManager>,
+    cache: &MediaCache,
+    handle_id: usize,
+    file_index: usize,
+    track_index: usize,
+    format: TrackFormat,
+) -> Result<(ExtractedTrack, bool), String> {
+    let session_id = manager.wait_for_session(handle_id).await?;
     let handle = manager.session.get(TorrentIdOrHash::Id(session_id))
         .ok_or("Session not found")?;
-    
-    // Get file info
-    let (file_info, file_size) = handle.with_metadata(|meta| {
-        meta.file_infos.get(file_index).map(|fi| (fi.relative_filename.clone(), fi.len))
-    }).map_err(|e| e.to_string())?
-        .ok_or("File index out of range")?;
-    
-    let file_path = manager.download_dir.join(&file_info);
-    
-    // Check if file is fully downloaded - if so, extract from disk
-    if file_path.exists() {
-        let metadata = tokio::fs::metadata(&file_path).await
-            .map_err(|e| format!("Failed to get file metadata: {}", e))?;
-        
-        if metadata.len() == file_size {
-            tracing::info!("File fully downloaded, extracting audio from disk");
-            return extract_audio_from_file(&file_path, track_index).await;
-        }
+    let cache_id = handle.info_hash().as_string();
+    let track_type = format.track_type();
+    if let Some(path) = cache.cached_format(track_type, &cache_id, file_index, track_index, format.extension()) {
+        return Ok((ExtractedTrack { path: path.to_string_lossy().to_string(), format, complete: true }, true));
     }
-    
-    // File not fully downloaded, stream it to temp location first
-    tracing::info!("File not fully downloaded, streaming to temp location");
-    
-    let mut reader = handle.stream(file_index)
-        .map_err(|e| format!("Failed to create stream: {}", e))?;
-    
-    let temp_dir = std::env::temp_dir();
-    let temp_file_path = temp_dir.join(format!("magnolia_audio_{}_{}.tmp", session_id, file_index));
-    
-    let mut temp_file = tokio::fs::File::create(&temp_file_path).await
-        .map_err(|e| format!("Failed to create temp file: {}", e))?;
-    
-    // Copy entire file to temp location
-    tracing::info!("Streaming file to temp location...");
-    tokio::io::copy(&mut reader, &mut temp_file).await
-        .map_err(|e| format!("Failed to stream file: {}", e))?;
-    
-    temp_file.sync_all().await
-        .map_err(|e| format!("Failed to sync temp file: {}", e))?;
-    drop(temp_file);
-    
-    // Extract audio from temp file
-    let result = extract_audio_from_file(&temp_file_path, track_index).await;
-    
-    // Clean up temp file
-    let _ = tokio::fs::remove_file(&temp_file_path).await;
-    
-    result
+
+    let source = ExtractionSource::open(&handle, &manager.download_dir, session_id, file_index, None).await?;
+    let data = extract_track(&source.path, track_index, format).await;
+    source.cleanup().await;
+    let data = data?;
+    // Truncated ASS would otherwise stay in the cache for good
+    if format == TrackFormat::Ass && check_ass(&data, true, None).status == SubtitleStatus::Invalid {
+        return Err("Extracted subtitle is not valid ASS".to_string());
+    }
+
+    let path = cache.save_track_as(track_type, &cache_id, file_index, track_index, format.extension(), data).await?;
+    Ok((ExtractedTrack { path: path.to_string_lossy().to_string(), format, complete: true }, false))
 }
 
-async fn extract_audio_from_file(file_path: &std::path::Path, track_index: usize) -> Result<Vec<u8>, String> {
-    use tokio::process::Command;
-    
-    tracing::info!("Extracting audio track {} from {:?}", track_index, file_path);
-    
-    // Use ffmpeg to extract and remux audio track
-    // Output to matroska format which can contain any codec properly
-    let mut cmd = Command::new(ffmpeg_path());
-    cmd.args(&[
-            "-i", file_path.to_str().unwrap(),
-            "-map", &format!("0:a:{}", track_index),
-            "-c:a", "copy", // Copy codec without re-encoding
-            "-f", "matroska", // Output as MKV which supports all codecs
-            "-"
-        ]);
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    #[cfg(target_os = "windows")]
-    cmd.creation_flags(0x08000000);
+    fn range(value: &str, file_size: u64) -> Result<(u64, u64, StatusCode), StatusCode> {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::RANGE, value.parse().unwrap());
+        parse_byte_range(&headers, file_size).map_err(|response| response.status())
+    }
 
-    let output = cmd.output()
-        .await
-        .map_err(|e| format!("Failed to run ffmpeg: {}", e))?;
-    
-    if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        tracing::error!("ffmpeg audio extraction failed: {}", stderr);
-        return Err(format!("Audio extraction failed: {}", stderr));
+    #[test]
+    fn no_range_serves_whole_file() {
+        assert_eq!(parse_byte_range(&HeaderMap::new(), 1000).unwrap(), (0, 999, StatusCode::OK));
+    }
+
+    #[test]
+    fn closed_range_is_clamped_to_file() {
+        assert_eq!(range("bytes=100-199", 1000), Ok((100, 199, StatusCode::PARTIAL_CONTENT)));
+        assert_eq!(range("bytes=900-5000", 1000), Ok((900, 999, StatusCode::PARTIAL_CONTENT)));
+    }
+
+    #[test]
+    fn suffix_range_serves_the_tail() {
+        assert_eq!(range("bytes=-500", 1000), Ok((500, 999, StatusCode::PARTIAL_CONTENT)));
+        assert_eq!(range("bytes=-5000", 1000), Ok((0, 999, StatusCode::PARTIAL_CONTENT)));
+        assert_eq!(range("bytes=-0", 1000), Err(StatusCode::RANGE_NOT_SATISFIABLE));
+    }
+
+    #[test]
+    fn open_range_runs_to_the_end() {
+        assert_eq!(range("bytes=500-", 1000), Ok((500, 999, StatusCode::PARTIAL_CONTENT)));
+        assert_eq!(range("bytes=0-", 1000), Ok((0, 999, StatusCode::PARTIAL_CONTENT)));
+    }
+
+    #[test]
+    fn start_beyond_end_is_unsatisfiable() {
+        assert_eq!(range("bytes=1000-", 1000), Err(StatusCode::RANGE_NOT_SATISFIABLE));
+        assert_eq!(range("bytes=2000-3000", 1000), Err(StatusCode::RANGE_NOT_SATISFIABLE));
+    }
+
+    #[test]
+    fn multi_range_is_rejected() {
+        assert_eq!(range("bytes=0-99,200-299", 1000), Err(StatusCode::RANGE_NOT_SATISFIABLE));
+    }
+
+    #[test]
+    fn malformed_range_is_ignored() {
+        for value in ["bytes=abc-def", "bytes=500", "bytes=-", "bytes=300-200", "items=0-99"] {
+            assert_eq!(range(value, 1000), Ok((0, 999, StatusCode::OK)), "{}", value);
+        }
     }
-    
-    tracing::info!("Successfully extracted audio track, {} bytes", output.stdout.len());
-    Ok(output.stdout)
 }
@@ -19,10 +19,20 @@ pub struct SeasonTorrent {
     // For now, we'll just map each episode individually, even if they share the same magnet link
 }
 
+// What the user picked last time for a show, used to bias later searches
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReleasePreference {
+    pub provider: String,
+    pub release_group: Option<String>,
+    pub quality: Option<String>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct ShowHistory {
     // Map season number to season info
     pub seasons: HashMap<u32, SeasonTorrent>,
+    #[serde(default)]
+    pub release_preference: Option<ReleasePreference>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
@@ -101,6 +111,27 @@ impl TrackingManager {
             .cloned()
     }
 
+    pub async fn save_release_preference(&self, show_id: u32, provider: String, title: &str, quality: Option<String>) {
+        let mut data = self.data.write().await;
+
+        let show = data.shows.entry(show_id).or_default();
+        show.release_preference = Some(ReleasePreference {
+            provider,
+            release_group: crate::search::parse_release_group(title),
+            quality,
+        });
+
+        // Persist to disk
+        if let Ok(content) = serde_json::to_string_pretty(&*data) {
+            let _ = fs::write(&self.file_path, content);
+        }
+    }
+
+    pub async fn get_release_preference(&self, show_id: u32) -> Option<ReleasePreference> {
+        let data = self.data.read().await;
+        data.shows.get(&show_id).and_then(|show| show.release_preference.clone())
+    }
+
     pub async fn get_all_selections(&self, show_id: u32) -> Option<ShowHistory> {
         let data = self.data.read().await;
         data.shows.get(&show_id).cloned()
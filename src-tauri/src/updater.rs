@@ -0,0 +1,335 @@
+use crate::settings::{SettingsManager, UpdateChannel};
+use crate::{log_error, log_info};
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::cmp::Ordering;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use tauri::{AppHandle, Emitter, State};
+use tokio::io::AsyncWriteExt;
+
+const RELEASES_URL: &str = "https://api.github.com/repos/chwair/magnolia/releases?per_page=20";
+// Progress events are sent at most this often so a fast download doesn't flood the UI
+const PROGRESS_INTERVAL: std::time::Duration = std::time::Duration::from_millis(250);
+
+#[derive(Debug, Deserialize)]
+struct GithubRelease {
+    tag_name: String,
+    #[serde(default)]
+    name: Option<String>,
+    #[serde(default)]
+    body: Option<String>,
+    #[serde(default)]
+    draft: bool,
+    #[serde(default)]
+    prerelease: bool,
+    #[serde(default)]
+    published_at: Option<String>,
+    #[serde(default)]
+    assets: Vec<GithubAsset>,
+}
+
+#[derive(Debug, Deserialize)]
+struct GithubAsset {
+    name: String,
+    browser_download_url: String,
+    #[serde(default)]
+    size: u64,
+    // "sha256:<hex>", computed by GitHub for assets uploaded since mid 2025
+    #[serde(default)]
+    digest: Option<String>,
+}
+
+// The installer `check_for_updates` found; the only thing `download_update` will fetch
+struct PendingUpdate {
+    url: String,
+    file_name: String,
+    sha256: String,
+}
+
+// What `download_update` saved and verified; the only thing `install_update` will run
+struct DownloadedUpdate {
+    path: PathBuf,
+    sha256: String,
+}
+
+static PENDING: Mutex<Option<PendingUpdate>> = Mutex::new(None);
+static DOWNLOADED: Mutex<Option<DownloadedUpdate>> = Mutex::new(None);
+
+/// A release newer than the running version
+#[derive(Debug, Clone, Serialize)]
+pub struct UpdateInfo {
+    pub current_version: String,
+    pub version: String,
+    pub name: Option<String>,
+    // Markdown, straight from the GitHub release
+    pub notes: String,
+    pub published_at: Option<String>,
+    pub prerelease: bool,
+    pub download_url: String,
+    pub size: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct DownloadProgress {
+    downloaded: u64,
+    total: Option<u64>,
+}
+
+// "v1.3.0-beta.2" -> ([1, 3, 0], Some("beta.2"))
+fn parse_version(version: &str) -> (Vec<u64>, Option<String>) {
+    let version = version.trim().trim_start_matches('v');
+    let (core, pre) = match version.split_once('-') {
+        Some((core, pre)) => (core, Some(pre.to_string())),
+        None => (version, None),
+    };
+    let numbers = core.split('.').map(|part| part.parse().unwrap_or(0)).collect();
+    (numbers, pre)
+}
+
+/// Semver-style ordering: numeric parts first, then a release sorts above its pre-releases
+fn compare_versions(a: &str, b: &str) -> Ordering {
+    let (mut a_core, a_pre) = parse_version(a);
+    let (mut b_core, b_pre) = parse_version(b);
+    let len = a_core.len().max(b_core.len());
+    a_core.resize(len, 0);
+    b_core.resize(len, 0);
+
+    a_core.cmp(&b_core).then_with(|| match (a_pre, b_pre) {
+        (None, None) => Ordering::Equal,
+        (None, Some(_)) => Ordering::Greater,
+        (Some(_), None) => Ordering::Less,
+        (Some(a), Some(b)) => compare_prerelease(&a, &b),
+    })
+}
+
+// "beta.10" sorts above "beta.9"
+fn compare_prerelease(a: &str, b: &str) -> Ordering {
+    for (a, b) in a.split('.').zip(b.split('.')) {
+        let ordering = match (a.parse::<u64>(), b.parse::<u64>()) {
+            (Ok(a), Ok(b)) => a.cmp(&b),
+            _ => a.cmp(b),
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+    a.split('.').count().cmp(&b.split('.').count())
+}
+
+// install_update runs the NSIS installer, so that's the only asset worth offering
+fn installer_asset(assets: &[GithubAsset]) -> Option<&GithubAsset> {
+    assets.iter().find(|a| a.name.ends_with(".exe"))
+}
+
+// The installer's published SHA-256: GitHub's asset digest, or else a "<installer>.sha256"
+// asset next to it (sha256sum format, the hash first)
+async fn installer_sha256(installer: &GithubAsset, assets: &[GithubAsset]) -> Option<String> {
+    if let Some(hex) = installer.digest.as_deref().and_then(|d| d.strip_prefix("sha256:")) {
+        return Some(hex.to_lowercase());
+    }
+    let checksum_name = format!("{}.sha256", installer.name);
+    let checksum = assets.iter().find(|a| a.name == checksum_name)?;
+    let text = reqwest::get(&checksum.browser_download_url)
+        .await
+        .and_then(|r| r.error_for_status())
+        .ok()?
+        .text()
+        .await
+        .ok()?;
+    text.split_whitespace()
+        .next()
+        .filter(|hex| hex.len() == 64 && hex.chars().all(|c| c.is_ascii_hexdigit()))
+        .map(|hex| hex.to_lowercase())
+}
+
+async fn file_sha256(path: &std::path::Path) -> Result<String, String> {
+    let data = tokio::fs::read(path).await.map_err(|e| format!("failed to read installer: {}", e))?;
+    Ok(format!("{:x}", Sha256::digest(&data)))
+}
+
+async fn fetch_releases() -> Result<Vec<GithubRelease>, String> {
+    // GitHub rejects API requests without a user agent
+    let client = reqwest::Client::builder()
+        .user_agent(concat!("magnolia/", env!("CARGO_PKG_VERSION")))
+        .timeout(std::time::Duration::from_secs(30))
+        .build()
+        .map_err(|e| e.to_string())?;
+    client
+        .get(RELEASES_URL)
+        .header(reqwest::header::ACCEPT, "application/vnd.github+json")
+        .send()
+        .await
+        .and_then(|r| r.error_for_status())
+        .map_err(|e| format!("failed to fetch releases: {}", e))?
+        .json()
+        .await
+        .map_err(|e| format!("failed to read releases: {}", e))
+}
+
+/// The newest release on the configured channel, if it's newer than the running version
+/// and ships an installer
+#[tauri::command]
+pub async fn check_for_updates(
+    app_handle: AppHandle,
+    settings_manager: State<'_, SettingsManager>,
+) -> Result<Option<UpdateInfo>, String> {
+    let channel = settings_manager.get().await.update_channel;
+    let current_version = app_handle.package_info().version.to_string();
+
+    let releases = fetch_releases().await?;
+    let latest = releases
+        .into_iter()
+        .filter(|r| !r.draft)
+        .filter(|r| channel == UpdateChannel::Beta || !r.prerelease)
+        .max_by(|a, b| compare_versions(&a.tag_name, &b.tag_name));
+
+    let Some(release) = latest else {
+        log_info!("Updater: no releases on the {:?} channel", channel);
+        return Ok(None);
+    };
+    let version = release.tag_name.trim_start_matches('v').to_string();
+    if compare_versions(&version, &current_version) != Ordering::Greater {
+        log_info!("Updater: {} is up to date ({:?} channel)", current_version, channel);
+        return Ok(None);
+    }
+    let Some(asset) = installer_asset(&release.assets) else {
+        log_info!("Updater: {} has no installer", version);
+        return Ok(None);
+    };
+    // An installer that can't be checked isn't offered at all
+    let Some(sha256) = installer_sha256(asset, &release.assets).await else {
+        log_info!("Updater: {} publishes no checksum for {}", version, asset.name);
+        return Ok(None);
+    };
+    if let Ok(mut pending) = PENDING.lock() {
+        *pending = Some(PendingUpdate {
+            url: asset.browser_download_url.clone(),
+            file_name: asset.name.clone(),
+            sha256,
+        });
+    }
+
+    log_info!("Updater: {} available ({:?} channel)", version, channel);
+    Ok(Some(UpdateInfo {
+        current_version,
+        version,
+        name: release.name.filter(|n| !n.is_empty()),
+        notes: release.body.unwrap_or_default(),
+        published_at: release.published_at,
+        prerelease: release.prerelease,
+        download_url: asset.browser_download_url.clone(),
+        size: asset.size,
+    }))
+}
+
+/// Downloads the installer `check_for_updates` found to the temp folder, emitting
+/// "update-download-progress" along the way, and checks it against the published hash
+#[tauri::command]
+pub async fn download_update(app_handle: AppHandle) -> Result<(), String> {
+    let (url, file_name, expected) = {
+        let pending = PENDING.lock().map_err(|e| e.to_string())?;
+        let pending = pending.as_ref().ok_or("no update to download; check for updates first")?;
+        (pending.url.clone(), pending.file_name.clone(), pending.sha256.clone())
+    };
+    let dest_path = std::env::temp_dir().join(&file_name);
+    log_info!("Updater: downloading {} to {:?}", url, dest_path);
+
+    let response = reqwest::get(&url)
+        .await
+        .and_then(|r| r.error_for_status())
+        .map_err(|e| format!("failed to download: {}", e))?;
+    let total = response.content_length();
+
+    let mut file = tokio::fs::File::create(&dest_path)
+        .await
+        .map_err(|e| format!("failed to create file: {}", e))?;
+    let mut downloaded = 0u64;
+    let mut hasher = Sha256::new();
+    let mut last_progress = std::time::Instant::now();
+    let mut stream = response.bytes_stream();
+
+    while let Some(chunk) = stream.next().await {
+        let chunk = match chunk {
+            Ok(chunk) => chunk,
+            Err(e) => {
+                drop(file);
+                let _ = tokio::fs::remove_file(&dest_path).await;
+                log_error!("Updater: download failed: {}", e);
+                return Err(format!("failed to read response: {}", e));
+            }
+        };
+        file.write_all(&chunk)
+            .await
+            .map_err(|e| format!("failed to write file: {}", e))?;
+        hasher.update(&chunk);
+        downloaded += chunk.len() as u64;
+
+        if last_progress.elapsed() >= PROGRESS_INTERVAL {
+            last_progress = std::time::Instant::now();
+            let _ = app_handle.emit("update-download-progress", DownloadProgress { downloaded, total });
+        }
+    }
+    file.flush().await.map_err(|e| format!("failed to write file: {}", e))?;
+    let _ = app_handle.emit("update-download-progress", DownloadProgress { downloaded, total });
+
+    drop(file);
+
+    let actual = format!("{:x}", hasher.finalize());
+    if actual != expected {
+        let _ = tokio::fs::remove_file(&dest_path).await;
+        log_error!("Updater: checksum mismatch for {}: expected {}, got {}", file_name, expected, actual);
+        return Err("downloaded installer doesn't match the published checksum".to_string());
+    }
+
+    log_info!("Updater: download complete and verified ({} bytes)", downloaded);
+    if let Ok(mut slot) = DOWNLOADED.lock() {
+        *slot = Some(DownloadedUpdate { path: dest_path, sha256: actual });
+    }
+    Ok(())
+}
+
+/// Runs the installer `download_update` saved, after hashing it again in case the file
+/// in the temp folder was swapped in the meantime
+#[tauri::command]
+pub async fn install_update(_app_handle: AppHandle) -> Result<(), String> {
+    let (installer_path, expected) = {
+        let downloaded = DOWNLOADED.lock().map_err(|e| e.to_string())?;
+        let downloaded = downloaded.as_ref().ok_or("no verified update downloaded")?;
+        (downloaded.path.clone(), downloaded.sha256.clone())
+    };
+    if file_sha256(&installer_path).await? != expected {
+        return Err("installer changed since it was downloaded".to_string());
+    }
+    log_info!("Updater: running installer {:?}", installer_path);
+
+    #[cfg(target_os = "windows")]
+    {
+        use std::os::windows::process::CommandExt;
+        use std::process::Command;
+
+        // Run NSIS installer with /S flag for silent install
+        let status = Command::new(&installer_path)
+            .arg("/S")
+            .creation_flags(0x08000000) // CREATE_NO_WINDOW
+            .status()
+            .map_err(|e| format!("failed to run installer: {}", e))?;
+
+        if !status.success() {
+            return Err("installer failed".to_string());
+        }
+
+        // Delete the installer after successful install
+        tokio::time::sleep(tokio::time::Duration::from_secs(2)).await;
+        let _ = std::fs::remove_file(&installer_path);
+
+        // Exit the app so the new version can start
+        std::process::exit(0);
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    {
+        Err("auto-update only supported on windows".to_string())
+    }
+}
@@ -0,0 +1,117 @@
+use crate::settings::SettingsManager;
+use crate::torrent::{TorrentFile, TorrentManager};
+use notify::event::ModifyKind;
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use tauri::{AppHandle, Emitter, Manager};
+use tokio::sync::mpsc;
+
+#[derive(Clone, serde::Serialize)]
+struct WatchFolderTorrentPayload {
+    path: String,
+    handle_id: usize,
+    name: String,
+    files: Vec<TorrentFile>,
+}
+
+/// Watches a user-chosen directory and adds any .torrent file dropped into it
+pub struct WatchFolder {
+    watcher: Mutex<Option<RecommendedWatcher>>,
+    sender: mpsc::UnboundedSender<PathBuf>,
+}
+
+impl WatchFolder {
+    pub fn new(app: AppHandle) -> Self {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        tauri::async_runtime::spawn(process_torrent_files(app, receiver));
+
+        Self {
+            watcher: Mutex::new(None),
+            sender,
+        }
+    }
+
+    /// Replaces the current watcher; None stops watching
+    pub fn watch(&self, folder: Option<String>) {
+        let mut current = self.watcher.lock().unwrap();
+        *current = None;
+
+        let Some(folder) = folder.filter(|f| !f.trim().is_empty()) else {
+            return;
+        };
+
+        let sender = self.sender.clone();
+        let watcher = notify::recommended_watcher(move |res: notify::Result<notify::Event>| {
+            let Ok(event) = res else { return };
+            // Files copied in show up as creates, files moved in as renames
+            if matches!(event.kind, EventKind::Create(_) | EventKind::Modify(ModifyKind::Name(_))) {
+                for path in event.paths {
+                    let _ = sender.send(path);
+                }
+            }
+        });
+
+        match watcher {
+            Ok(mut watcher) => match watcher.watch(PathBuf::from(&folder).as_path(), RecursiveMode::NonRecursive) {
+                Ok(_) => {
+                    tracing::info!("watching {} for .torrent files", folder);
+                    *current = Some(watcher);
+                }
+                Err(e) => tracing::warn!("failed to watch folder {}: {}", folder, e),
+            },
+            Err(e) => tracing::warn!("failed to create folder watcher: {}", e),
+        }
+    }
+}
+
+async fn process_torrent_files(app: AppHandle, mut receiver: mpsc::UnboundedReceiver<PathBuf>) {
+    let mut seen: HashSet<PathBuf> = HashSet::new();
+
+    while let Some(path) = receiver.recv().await {
+        let is_torrent = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.eq_ignore_ascii_case("torrent"))
+            .unwrap_or(false);
+        // A single copy fires several events
+        if !is_torrent || !seen.insert(path.clone()) {
+            continue;
+        }
+
+        // Give whatever is writing the file a moment to finish
+        tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
+        if !path.exists() {
+            seen.remove(&path);
+            continue;
+        }
+
+        let autostart = app.state::<SettingsManager>().get().await.watch_folder_autostart;
+        let manager = app.state::<Arc<TorrentManager>>().inner().clone();
+        let path_str = path.to_string_lossy().to_string();
+
+        let handle_id = match manager.add_torrent_download(path_str.clone(), !autostart).await {
+            Ok(id) => id,
+            Err(e) => {
+                tracing::warn!("failed to add torrent from watch folder {}: {}", path_str, e);
+                // Let a later event (e.g. the file being rewritten) try again
+                seen.remove(&path);
+                continue;
+            }
+        };
+
+        match manager.get_torrent_info(handle_id).await {
+            Ok(info) => {
+                tracing::info!("added {} from watch folder ({} files)", info.name, info.files.len());
+                let _ = app.emit("watch-folder-torrent-added", WatchFolderTorrentPayload {
+                    path: path_str,
+                    handle_id,
+                    name: info.name,
+                    files: info.files,
+                });
+            }
+            Err(e) => tracing::warn!("failed to read torrent info for {}: {}", path_str, e),
+        }
+    }
+}
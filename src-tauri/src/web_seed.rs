@@ -0,0 +1,82 @@
+use serde_bencode::value::Value;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// BEP 19 web seeds of one torrent. librqbit only downloads from peers, so the stream
+/// server reads ranges straight from a web seed while the torrent has none.
+#[derive(Debug, Default)]
+pub struct WebSeeds {
+    pub urls: Vec<String>,
+    // Bytes served to the player from the web seeds
+    bytes: AtomicU64,
+}
+
+impl WebSeeds {
+    pub fn new(urls: Vec<String>) -> Self {
+        Self {
+            urls,
+            bytes: AtomicU64::new(0),
+        }
+    }
+
+    pub fn add_bytes(&self, bytes: u64) {
+        self.bytes.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    pub fn bytes(&self) -> u64 {
+        self.bytes.load(Ordering::Relaxed)
+    }
+}
+
+fn is_http_url(url: &str) -> bool {
+    url.starts_with("http://") || url.starts_with("https://")
+}
+
+/// The `ws=` parameters of a magnet link
+pub fn magnet_web_seeds(magnet: &str) -> Vec<String> {
+    let Some((_, query)) = magnet.split_once('?') else { return Vec::new() };
+    query
+        .split('&')
+        .filter_map(|part| part.strip_prefix("ws="))
+        .filter_map(|url| urlencoding::decode(url).ok().map(|u| u.into_owned()))
+        .filter(|url| is_http_url(url))
+        .collect()
+}
+
+/// The `url-list` of a .torrent file, which is either one url or a list of them
+pub fn torrent_web_seeds(torrent: &[u8]) -> Vec<String> {
+    let Ok(Value::Dict(root)) = serde_bencode::from_bytes::<Value>(torrent) else {
+        return Vec::new();
+    };
+    let as_url = |value: &Value| match value {
+        Value::Bytes(bytes) => String::from_utf8(bytes.clone()).ok(),
+        _ => None,
+    };
+
+    let urls = match root.get(b"url-list".as_slice()) {
+        Some(Value::List(list)) => list.iter().filter_map(as_url).collect(),
+        Some(value) => as_url(value).into_iter().collect(),
+        None => Vec::new(),
+    };
+    urls.into_iter().filter(|url: &String| is_http_url(url)).collect()
+}
+
+/// Where a file of the torrent lives on a web seed. Per BEP 19 a url ending in "/" is a
+/// folder holding the torrent (by its name); otherwise a single-file torrent's url is the
+/// file itself.
+pub fn file_url(seed: &str, torrent_name: &str, relative_path: &[String], multi_file: bool) -> String {
+    let encode = |segment: &str| urlencoding::encode(segment).into_owned();
+    if !multi_file {
+        return if seed.ends_with('/') {
+            format!("{}{}", seed, encode(torrent_name))
+        } else {
+            seed.to_string()
+        };
+    }
+
+    let mut url = seed.trim_end_matches('/').to_string();
+    for segment in std::iter::once(torrent_name.to_string()).chain(relative_path.iter().cloned()) {
+        url.push('/');
+        url.push_str(&encode(&segment));
+    }
+    url
+}
@@ -719,6 +719,13 @@ export class MKVDemuxer {
     return this.attachments || [];
   }
 
+  // SubtitlesOctopus can't see system fonts, so fonts left out of the cache
+  // because the system has them are still handed to it from memory
+  fontBlobUrl(data) {
+    if (!data) return null;
+    return URL.createObjectURL(new Blob([data], { type: 'font/ttf' }));
+  }
+
   async extractAndSaveFonts() {
     const savedFonts = [];
     
@@ -749,10 +756,11 @@ export class MKVDemuxer {
         });
         
         if (isInstalled) {
-          console.log(`[Font Extractor] ${attachment.filename} already on system, skipping`);
+          console.log(`[Font Extractor] ${attachment.filename} already on system, not caching`);
           savedFonts.push({
             filename: attachment.filename,
             location: 'system',
+            url: this.fontBlobUrl(attachment.data),
             skipped: true
           });
           continue;
@@ -778,12 +786,26 @@ export class MKVDemuxer {
           data: dataArray
         });
         
+        if (savedPath.startsWith('system:')) {
+          console.log(`[Font Extractor] ${attachment.filename} family already on system, not caching`);
+          savedFonts.push({
+            filename: attachment.filename,
+            location: 'system',
+            url: this.fontBlobUrl(fontData),
+            skipped: true
+          });
+          continue;
+        }
+
         console.log(`[Font Extractor] ✓ Saved ${attachment.filename} to ${savedPath}`);
         
+        // The same font may already be stored under another name, so serve the stored file
+        const storedName = savedPath.split(/[\\/]/).pop();
+        
         // Get the HTTP server port from torrent manager
         // Font will be served at http://localhost:{port}/fonts/{filename}
         const httpPort = await invoke('get_http_port');
-        const httpUrl = `http://localhost:${httpPort}/fonts/${encodeURIComponent(attachment.filename)}`;
+        const httpUrl = `http://localhost:${httpPort}/fonts/${encodeURIComponent(storedName)}`;
         
         console.log(`[Font Extractor] Font URL: ${httpUrl}`);
         
@@ -0,0 +1,42 @@
+import { invoke } from "@tauri-apps/api/core";
+import { getTVDetails } from "../tmdb.js";
+
+const STORAGE_KEY = "lastAiredEpisodes";
+
+function loadLastAired() {
+  try {
+    return JSON.parse(localStorage.getItem(STORAGE_KEY)) || {};
+  } catch {
+    return {};
+  }
+}
+
+// Compares each listed show's latest aired episode with the one seen last time and
+// notifies about the difference. Shows seen for the first time are only recorded.
+export async function checkNewEpisodes(list) {
+  const lastAired = loadLastAired();
+  const shows = list.filter((item) => item.media_type === "tv");
+
+  for (const show of shows) {
+    try {
+      const details = await getTVDetails(show.id);
+      const latest = details?.last_episode_to_air;
+      if (!latest) continue;
+
+      const previous = lastAired[show.id];
+      lastAired[show.id] = latest.id;
+      if (previous === undefined || previous === latest.id) continue;
+
+      const name = details.name || show.name;
+      await invoke("send_notification", {
+        kind: "new_episode",
+        title: `New episode of ${name}`,
+        body: `S${latest.season_number}E${latest.episode_number} - ${latest.name}`,
+      });
+    } catch (error) {
+      console.error("failed to check new episodes for", show.id, error);
+    }
+  }
+
+  localStorage.setItem(STORAGE_KEY, JSON.stringify(lastAired));
+}